@@ -2,30 +2,20 @@ use std::rc::Rc;
 
 use glam::{Affine3A, Vec3};
 use iris_engine::renderer::{
-    bind_group::{BindGroup, BindGroupBuilder},
-    buffer::{IndexBuffer, StorageBufferArray, UniformBuffer, VertexBuffer},
-    camera::OrbitCamera,
     color::Color,
-    gui::{color_edit, lights_gui},
-    light::{DirectionalLight, Light},
-    material::{MaterialPipelineBuilder, PbrMaterialBuilder},
-    mesh::{Mesh, Vertex},
+    environment::Environment,
+    light::DirectionalLight,
+    material::PbrMaterialBuilder,
+    mesh::Mesh,
     model::Model,
-    render_pipeline::{RenderPassBuilder, RenderPipelineWire},
+    render_graph::RenderGraph,
+    scene_renderer::SceneRenderer,
     texture::Texture,
-    wgpu_renderer::Renderer,
+    wgpu_renderer::{Renderer, RendererSettings, ShadingPath},
 };
 
 struct Example {
-    vertex_buffer: VertexBuffer<Vertex>,
-    index_buffer: IndexBuffer,
-    bind_group: BindGroup,
-    camera_uniform: UniformBuffer<OrbitCamera>,
-    pipeline: wgpu::RenderPipeline,
-    pipeline_wire: Option<wgpu::RenderPipeline>,
-    depth_texture: Texture,
-    light_storage: StorageBufferArray<Light>,
-    clear_color: Color,
+    scene: SceneRenderer,
     model: Model,
 }
 
@@ -37,18 +27,9 @@ impl iris_engine::renderer::app::App for Example {
             .default_open(false)
             .show(gui, |ui| {
                 if self.model.gui(ui, &r.device, &r.queue) {
-                    self.pipeline = MaterialPipelineBuilder::new(self.model.material())
-                        .add_bind_group(&self.model.transform_bind_group().layout)
-                        .add_bind_group(&self.bind_group.layout)
-                        .depth(self.depth_texture.texture.format())
-                        .build::<Vertex>(&r.device, r.config.format);
+                    self.scene.batcher.invalidate_pipelines();
                 }
-
-                if lights_gui(ui, &mut self.light_storage.data) {
-                    self.light_storage.update(&r.queue);
-                }
-
-                color_edit(ui, &mut self.clear_color, "Clear Color");
+                self.scene.gui(ui, r);
             });
     }
     fn gui_register(
@@ -59,28 +40,28 @@ impl iris_engine::renderer::app::App for Example {
         self.model.gui_register(egui_renderer, &r.device);
     }
 
-    fn init(r: &mut Renderer) -> Self {
-        let plane = Mesh::from_obj("examples/plane/plane.obj");
-        let vertices = plane.vertices();
-        let indices = plane.indices();
-        let vertex_buffer = VertexBuffer::new(vertices, &r.device);
-        let index_buffer = IndexBuffer::new(indices, &r.device);
-        let aspect_ratio = r.config.width as f32 / r.config.height as f32;
-        let camera = OrbitCamera::new(2.0, aspect_ratio);
+    fn shaders_changed(&mut self, _r: &Renderer) {
+        self.scene.batcher.reload_pipelines();
+    }
 
-        let camera_uniform = UniformBuffer::new(camera, &r.device);
+    fn renderer_settings() -> RendererSettings {
+        RendererSettings {
+            shading: ShadingPath::Deferred,
+            hot_reload: cfg!(debug_assertions),
+            ..Default::default()
+        }
+    }
 
+    fn init(r: &mut Renderer) -> Self {
+        let plane = Mesh::from_obj("examples/plane/plane.obj").expect("Failed to load OBJ file");
         let directional_light = DirectionalLight {
             direction: Vec3::NEG_ONE,
             ..Default::default()
         };
-        let light_storage =
-            StorageBufferArray::new(&[directional_light.into()], &r.device, &r.queue, 16);
+        let environment =
+            Environment::from_color(Color::new(0.01, 0.01, 0.01), &r.device, &r.queue);
+        let scene = SceneRenderer::new(&[directional_light.into()], environment, r);
 
-        let bind_group = BindGroupBuilder::new()
-            .uniform(&camera_uniform.buffer)
-            .storage_buffer(&light_storage.buffer)
-            .build(&r.device);
         let texture =
             Texture::from_path("examples/plane/diffuse.jpg", &r.device, &r.queue).unwrap();
         let normal = Texture::from_path("examples/plane/normal.png", &r.device, &r.queue).unwrap();
@@ -88,88 +69,20 @@ impl iris_engine::renderer::app::App for Example {
             .diffuse_texture(texture)
             .normal_texture(normal)
             .build(&r.device, &r.queue);
-        let model = Model::new(Affine3A::IDENTITY, Rc::new(plane), material, &r.device);
-        let depth_texture = Texture::depth(&r.device, r.config.width, r.config.height);
-        let pipeline = model
-            .pipeline()
-            .add_bind_group(&bind_group.layout)
-            .depth(depth_texture.texture.format())
-            .build::<Vertex>(&r.device, r.config.format);
-
-        let pipeline_wire = r
-            .device
-            .features()
-            .contains(wgpu::Features::POLYGON_MODE_LINE)
-            .then(|| {
-                RenderPipelineWire::new()
-                    .add_bind_group(&model.transform_bind_group().layout)
-                    .add_bind_group(&bind_group.layout)
-                    .polygon_mode(wgpu::PolygonMode::Line)
-                    .depth(depth_texture.texture.format())
-                    .cull_mode(None)
-                    .build::<Vertex>(&r.device, r.config.format)
-            });
-
-        let clear_color = Color {
-            r: 0.1,
-            g: 0.2,
-            b: 0.3,
-        };
-        // Done
-        Self {
-            vertex_buffer,
-            index_buffer,
-            bind_group,
-            camera_uniform,
-            pipeline,
-            pipeline_wire,
-
-            depth_texture,
-            light_storage,
-            clear_color,
-            model,
-        }
+        let model = Model::new(Affine3A::IDENTITY, Rc::new(plane), material);
+        Self { scene, model }
     }
 
     fn input(&mut self, event: winit::event::WindowEvent, r: &mut Renderer) {
-        if self.camera_uniform.data.input(&event) {
-            self.camera_uniform.update(&r.queue);
-        }
+        self.scene.input(&event, r);
     }
 
     fn resize(&mut self, r: &mut Renderer) {
-        let aspect_ratio = r.config.width as f32 / r.config.height as f32;
-        self.camera_uniform.data.set_aspect_ratio(aspect_ratio);
-        self.camera_uniform.update(&r.queue);
-        self.depth_texture = Texture::depth(&r.device, r.config.width, r.config.height);
+        self.scene.resize(r);
     }
 
-    fn render(&mut self, view: &wgpu::TextureView, r: &mut Renderer) {
-        let mut encoder = r
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut rpass = RenderPassBuilder::new()
-                .depth(&self.depth_texture.view)
-                .clear_color(self.clear_color.into())
-                .build(&mut encoder, view);
-            rpass.set_pipeline(&self.pipeline);
-            rpass.set_bind_group(0, &self.model.material().bind_group().bind_group, &[]);
-            rpass.set_bind_group(1, &self.model.transform_bind_group().bind_group, &[]);
-            rpass.set_bind_group(2, &self.bind_group.bind_group, &[]);
-            rpass.set_index_buffer(
-                self.index_buffer.buffer.slice(..),
-                wgpu::IndexFormat::Uint32,
-            );
-            rpass.set_vertex_buffer(0, self.vertex_buffer.buffer.slice(..));
-            rpass.draw_indexed(0..self.index_buffer.indices.len() as u32, 0, 0..1);
-            if let Some(ref pipe) = self.pipeline_wire {
-                rpass.set_pipeline(pipe);
-                rpass.draw_indexed(0..self.index_buffer.indices.len() as u32, 0, 0..1);
-            }
-        }
-
-        r.queue.submit(Some(encoder.finish()));
+    fn render<'a>(&'a mut self, graph: &mut RenderGraph<'a>, r: &Renderer) {
+        self.scene.add_to_graph(graph, [&self.model], r);
     }
 }
 
@@ -0,0 +1,314 @@
+//! Derive macros of iris-engine, re-exported next to the traits they implement.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
+use syn::{
+    parse::ParseStream, parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields,
+    Ident, LitInt, LitStr, Meta, Result, Token,
+};
+
+/// Implements `core::bind_group::AsBindGroup` from field attributes giving the binding of each field:
+/// - `#[texture(n)]` and `#[sampler(n)]` on a `ResourceHandle<Image>`, usually both on the same field
+/// - `#[uniform(n)]` on a `GpuSendable` value
+/// - `#[storage(n)]` or `#[storage(n, read_only)]` on a `GpuSendable` value
+///
+/// Bindings are numbered from 0 without gaps. The struct attribute
+/// `#[bind_group(label = "...", visibility = VERTEX_FRAGMENT)]` replaces the default label, the struct name,
+/// and the default `FRAGMENT` visibility, naming a `wgpu::ShaderStages` constant.
+#[proc_macro_derive(
+    AsBindGroup,
+    attributes(bind_group, texture, sampler, uniform, storage)
+)]
+pub fn derive_as_bind_group(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    as_bind_group(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implements `core::shader_type::ShaderType` for a struct of `ShaderType` fields, laid out like the WGSL
+/// struct with the same members in the same order.
+///
+/// On `#[repr(C)]` structs, usually `Pod` ones also uploaded with `bytemuck`, every field is checked at
+/// compile time to be at its WGSL offset. Fields marked `#[padding]` only fill such structs where WGSL
+/// pads implicitly, they are not members of the WGSL struct.
+#[proc_macro_derive(ShaderType, attributes(padding))]
+pub fn derive_shader_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    shader_type(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum Kind {
+    Texture,
+    Sampler,
+    Uniform,
+    Storage { read_only: bool },
+}
+
+struct Binding {
+    index: u32,
+    kind: Kind,
+    field: Ident,
+    span: Span,
+}
+
+fn as_bind_group(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let mut label = LitStr::new(&name.to_string(), name.span());
+    let mut visibility = format_ident!("FRAGMENT");
+    for attribute in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("bind_group"))
+    {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("label") {
+                label = meta.value()?.parse()?;
+            } else if meta.path.is_ident("visibility") {
+                visibility = meta.value()?.parse()?;
+            } else {
+                return Err(meta.error("expected `label` or `visibility`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let Data::Struct(ref data) = input.data else {
+        return Err(Error::new(
+            name.span(),
+            "AsBindGroup can only be derived for structs",
+        ));
+    };
+    let Fields::Named(ref fields) = data.fields else {
+        return Err(Error::new(
+            name.span(),
+            "AsBindGroup needs a struct with named fields",
+        ));
+    };
+    let mut bindings = vec![];
+    for field in &fields.named {
+        let ident = field.ident.clone().expect("Named field");
+        for attribute in &field.attrs {
+            if let Some(kind) = binding_kind(&attribute.meta) {
+                let (index, kind) = parse_binding(&attribute.meta, kind)?;
+                bindings.push(Binding {
+                    index,
+                    kind,
+                    field: ident.clone(),
+                    span: attribute.span(),
+                });
+            }
+        }
+    }
+    bindings.sort_by_key(|binding| binding.index);
+    for (expected, binding) in (0..).zip(&bindings) {
+        if binding.index != expected {
+            return Err(Error::new(
+                binding.span,
+                format!("expected binding {expected}, bindings are numbered from 0 without gaps or repeats"),
+            ));
+        }
+    }
+
+    let images = image_fields(&bindings);
+    let mut buffers = vec![];
+    let mut resources = vec![];
+    for binding in &bindings {
+        let field = &binding.field;
+        let image = images
+            .iter()
+            .position(|&image| image == field)
+            .unwrap_or_default();
+        let buffer = buffers.len();
+        resources.push(match binding.kind {
+            Kind::Texture => {
+                quote! { ::wgpu::BindingResource::TextureView(&images[#image].texture_view) }
+            }
+            Kind::Sampler => quote! { ::wgpu::BindingResource::Sampler(&images[#image].sampler) },
+            Kind::Uniform => {
+                buffers.push(quote! { BufferContents::uniform(&self.#field) });
+                quote! { buffers[#buffer].as_entire_binding() }
+            }
+            Kind::Storage { .. } => {
+                buffers.push(quote! { BufferContents::storage(&self.#field) });
+                quote! { buffers[#buffer].as_entire_binding() }
+            }
+        });
+    }
+    let layout = bindings.iter().map(|binding| match binding.kind {
+        Kind::Texture => quote! { .texture_2d() },
+        Kind::Sampler => quote! { .sampler(::wgpu::SamplerBindingType::Filtering) },
+        Kind::Uniform => quote! { .uniform() },
+        Kind::Storage { read_only } => quote! { .storage(#read_only) },
+    });
+
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::iris_engine::core::bind_group::AsBindGroup for #name #type_generics #where_clause {
+            fn label() -> Option<&'static str> {
+                Some(#label)
+            }
+            fn images(&self) -> Vec<::iris_engine::core::resources::ResourceHandle<::iris_engine::core::image::Image>> {
+                vec![#(self.#images),*]
+            }
+            fn buffers(&self) -> Vec<::iris_engine::core::bind_group::BufferContents> {
+                use ::iris_engine::core::bind_group::BufferContents;
+                vec![#(#buffers),*]
+            }
+            #[allow(unused_variables)]
+            fn bindings<'a>(
+                images: &[&'a ::iris_engine::core::image::GpuImage],
+                buffers: &'a [::wgpu::Buffer],
+            ) -> Vec<::wgpu::BindingResource<'a>> {
+                vec![#(#resources),*]
+            }
+            fn bind_group_layout(device: &::wgpu::Device) -> ::wgpu::BindGroupLayout {
+                ::iris_engine::core::bind_group::BindGroupLayoutBuilder::new(::wgpu::ShaderStages::#visibility)
+                    #(#layout)*
+                    .build(device)
+            }
+        }
+    })
+}
+
+fn binding_kind(meta: &Meta) -> Option<&'static str> {
+    ["texture", "sampler", "uniform", "storage"]
+        .into_iter()
+        .find(|&kind| meta.path().is_ident(kind))
+}
+
+/// Binding index and flags of a field attribute, like `storage(3, read_only)`
+fn parse_binding(meta: &Meta, kind: &str) -> Result<(u32, Kind)> {
+    let (index, flags) = meta.require_list()?.parse_args_with(|input: ParseStream| {
+        let index: LitInt = input.parse()?;
+        let mut flags: Vec<Ident> = vec![];
+        while input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            flags.push(input.parse()?);
+        }
+        Ok((index, flags))
+    })?;
+    let mut read_only = false;
+    for flag in flags {
+        if kind == "storage" && flag == "read_only" {
+            read_only = true;
+        } else {
+            return Err(Error::new(flag.span(), format!("unexpected `{kind}` flag")));
+        }
+    }
+    let kind = match kind {
+        "texture" => Kind::Texture,
+        "sampler" => Kind::Sampler,
+        "uniform" => Kind::Uniform,
+        _ => Kind::Storage { read_only },
+    };
+    Ok((index.base10_parse()?, kind))
+}
+
+/// Fields whose image is uploaded for a texture or sampler binding, once each
+fn image_fields(bindings: &[Binding]) -> Vec<&Ident> {
+    let mut fields: Vec<&Ident> = vec![];
+    for binding in bindings {
+        if matches!(binding.kind, Kind::Texture | Kind::Sampler)
+            && !fields.contains(&&binding.field)
+        {
+            fields.push(&binding.field);
+        }
+    }
+    fields
+}
+
+fn shader_type(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let Data::Struct(ref data) = input.data else {
+        return Err(Error::new(
+            name.span(),
+            "ShaderType can only be derived for structs",
+        ));
+    };
+    let Fields::Named(ref fields) = data.fields else {
+        return Err(Error::new(
+            name.span(),
+            "ShaderType needs a struct with named fields",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "ShaderType can't be derived for generic structs",
+        ));
+    }
+    let fields: Vec<_> = fields
+        .named
+        .iter()
+        .filter(|field| !field.attrs.iter().any(|a| a.path().is_ident("padding")))
+        .map(|field| (field.ident.clone().expect("Named field"), &field.ty))
+        .collect();
+    let idents: Vec<_> = fields.iter().map(|(ident, _)| ident).collect();
+    let indices = 0..fields.len();
+    let names = idents.iter().map(|ident| ident.to_string());
+    let types: Vec<_> = fields.iter().map(|(_, ty)| ty).collect();
+    let members = quote! {
+        &[#((#names, <#types as ::iris_engine::core::shader_type::ShaderType>::LAYOUT)),*]
+    };
+    let layouts = quote! {
+        [#(<#types as ::iris_engine::core::shader_type::ShaderType>::LAYOUT),*]
+    };
+
+    let check = is_repr_c(input)?.then(|| {
+        let indices = indices.clone();
+        quote! {
+            const _: () = {
+                let offsets = ::iris_engine::core::shader_type::member_offsets(#layouts);
+                #(
+                    assert!(
+                        ::std::mem::offset_of!(#name, #idents) == offsets[#indices],
+                        concat!("`", stringify!(#name), "::", stringify!(#idents), "` is not at its WGSL offset"),
+                    );
+                )*
+                assert!(
+                    ::std::mem::size_of::<#name>()
+                        == <#name as ::iris_engine::core::shader_type::ShaderType>::LAYOUT.size,
+                    concat!("`", stringify!(#name), "` is not the size of its WGSL struct"),
+                );
+            };
+        }
+    });
+    Ok(quote! {
+        impl ::iris_engine::core::shader_type::ShaderType for #name {
+            const LAYOUT: ::iris_engine::core::shader_type::TypeLayout =
+                ::iris_engine::core::shader_type::TypeLayout::structure(&#layouts);
+            const MEMBERS: &'static [(&'static str, ::iris_engine::core::shader_type::TypeLayout)] =
+                #members;
+
+            fn write(&self, bytes: &mut [u8]) {
+                let offsets = ::iris_engine::core::shader_type::member_offsets(#layouts);
+                #(
+                    ::iris_engine::core::shader_type::ShaderType::write(
+                        &self.#idents,
+                        &mut bytes[offsets[#indices]..],
+                    );
+                )*
+            }
+        }
+        #check
+    })
+}
+
+/// Whether the struct has `#[repr(C)]`, so its fields are in declaration order
+fn is_repr_c(input: &DeriveInput) -> Result<bool> {
+    let mut repr_c = false;
+    for attribute in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attribute.parse_nested_meta(|meta| {
+            repr_c |= meta.path.is_ident("C");
+            // Skips the arguments of `align(n)` and `packed(n)`
+            if !meta.input.is_empty() && !meta.input.peek(Token![,]) {
+                meta.input.parse::<TokenTree>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(repr_c)
+}
@@ -1,4 +1,5 @@
 pub mod app;
+pub mod assets;
 pub mod bind_group;
 pub mod buffer;
 pub mod component;
@@ -9,6 +10,11 @@ pub mod gpu_sendable;
 pub mod image;
 pub mod material;
 pub mod material_renderer;
+pub mod mesh_renderer;
+pub mod query;
+pub mod render_assets;
 pub mod renderer;
 pub mod resources;
+pub mod scene;
+pub mod shader_type;
 pub mod transform;
@@ -1,6 +1,7 @@
 use std::{result::Result, sync::Arc, time::Instant};
 
 use egui_wgpu::ScreenDescriptor;
+use image::RgbaImage;
 use winit::{
     error::EventLoopError,
     event::{Event, KeyEvent, WindowEvent},
@@ -206,3 +207,19 @@ async fn start<A: App>() -> Result<(), EventLoopError> {
 pub fn run<A: App>() -> Result<(), EventLoopError> {
     pollster::block_on(start::<A>())
 }
+
+/// Drives an [`App`] for `frames` frames without a window and returns the last frame.
+///
+/// Returns `None` if no adapter is available. The gui is not drawn in headless mode.
+pub fn render_headless<A: App>(width: u32, height: u32, frames: u32) -> Option<RgbaImage> {
+    // Several headless runs can share a process (e.g. tests), so the subscriber may already be set
+    tracing_subscriber::fmt().try_init().ok();
+    let mut renderer =
+        pollster::block_on(Renderer::headless(width, height, A::renderer_settings()))?;
+    let mut app = A::init(&mut renderer);
+    for _ in 0..frames {
+        let view = renderer.offscreen_view()?;
+        app.render(&view, &mut renderer);
+    }
+    renderer.render_to_image()
+}
@@ -0,0 +1,136 @@
+use std::{borrow::Cow, io::BufReader};
+
+use assets_manager::{loader, Asset, BoxedError};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use crate::renderer::{
+    color::Color,
+    mesh::Mesh,
+    resources::{mesh_from_obj_models, OBJ_LOAD_OPTIONS},
+};
+
+use super::{
+    image::Image,
+    material::StandardMaterial,
+    resources::{Resource, ResourceManager},
+};
+
+/// A resource created from an asset loaded through the `AssetCache` of a [`ResourceManager`].
+pub trait AssetResource: Resource + Sized {
+    type Asset: Asset;
+    fn from_asset(asset: &Self::Asset, resources: &mut ResourceManager)
+        -> Result<Self, BoxedError>;
+}
+
+/// `A` read only from its `N`th extension, so loading `bricks.jpg` never reads `bricks.png`.
+pub struct WithExtension<A, const N: usize>(pub A);
+
+impl<A: Asset, const N: usize> Asset for WithExtension<A, N> {
+    const EXTENSIONS: &'static [&'static str] = if N < A::EXTENSIONS.len() {
+        &[A::EXTENSIONS[N]]
+    } else {
+        &[]
+    };
+    type Loader = loader::LoadFrom<A, A::Loader>;
+    const HOT_RELOADED: bool = A::HOT_RELOADED;
+}
+
+impl<A, const N: usize> From<A> for WithExtension<A, N> {
+    fn from(value: A) -> Self {
+        Self(value)
+    }
+}
+
+pub struct ImageAsset(pub DynamicImage);
+
+impl Asset for ImageAsset {
+    const EXTENSIONS: &'static [&'static str] = &["png", "jpg", "jpeg"];
+    type Loader = ImageLoader;
+}
+
+pub struct ImageLoader;
+
+impl loader::Loader<ImageAsset> for ImageLoader {
+    fn load(content: Cow<[u8]>, _ext: &str) -> Result<ImageAsset, BoxedError> {
+        Ok(ImageAsset(image::load_from_memory(&content)?))
+    }
+}
+
+impl AssetResource for Image {
+    type Asset = ImageAsset;
+    fn from_asset(
+        asset: &Self::Asset,
+        _resources: &mut ResourceManager,
+    ) -> Result<Self, BoxedError> {
+        Ok(Self::new(asset.0.clone()))
+    }
+}
+
+pub struct ObjAsset(pub Mesh);
+
+impl Asset for ObjAsset {
+    const EXTENSION: &'static str = "obj";
+    type Loader = ObjLoader;
+}
+
+pub struct ObjLoader;
+
+impl loader::Loader<ObjAsset> for ObjLoader {
+    fn load(content: Cow<[u8]>, _ext: &str) -> Result<ObjAsset, BoxedError> {
+        let (models, _) = tobj::load_obj_buf(
+            &mut BufReader::new(content.as_ref()),
+            &OBJ_LOAD_OPTIONS,
+            // Materials are not part of the mesh
+            |_| Err(tobj::LoadError::OpenFileFailed),
+        )?;
+        Ok(ObjAsset(mesh_from_obj_models(&models)))
+    }
+}
+
+impl Resource for Mesh {}
+
+impl AssetResource for Mesh {
+    type Asset = ObjAsset;
+    fn from_asset(
+        asset: &Self::Asset,
+        _resources: &mut ResourceManager,
+    ) -> Result<Self, BoxedError> {
+        Ok(asset.0.clone())
+    }
+}
+
+/// RON definition of a [`StandardMaterial`], textures are asset paths.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StandardMaterialDefinition {
+    pub diffuse_texture: String,
+    pub diffuse_color: Color,
+    pub normal_map: String,
+    pub specular: f32,
+    pub ior: f32,
+    pub roughness: f32,
+    pub ambient: Color,
+}
+
+impl Asset for StandardMaterialDefinition {
+    const EXTENSION: &'static str = "ron";
+    type Loader = loader::RonLoader;
+}
+
+impl AssetResource for StandardMaterial {
+    type Asset = StandardMaterialDefinition;
+    fn from_asset(
+        asset: &Self::Asset,
+        resources: &mut ResourceManager,
+    ) -> Result<Self, BoxedError> {
+        Ok(Self {
+            diffuse_texture: resources.load(&asset.diffuse_texture)?,
+            diffuse_color: asset.diffuse_color,
+            normal_map: resources.load(&asset.normal_map)?,
+            specular: asset.specular,
+            ior: asset.ior,
+            roughness: asset.roughness,
+            ambient: asset.ambient,
+        })
+    }
+}
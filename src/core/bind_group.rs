@@ -1,25 +1,48 @@
-use wgpu::BindGroupEntry;
+use std::sync::Arc;
 
-use super::resources::ResourceManager;
+pub use iris_engine_derive::AsBindGroup;
+use wgpu::util::DeviceExt;
 
+use crate::GpuSendable;
+
+use super::{
+    image::{GpuImage, Image},
+    resources::ResourceHandle,
+    shader_type::ShaderType,
+};
+
+/// Bind group made from the fields of a struct, usually derived.
+///
+/// Images and buffers are listed apart from the bindings so they can be uploaded once and shared,
+/// see [`RenderAssets`](super::render_assets::RenderAssets).
 pub trait AsBindGroup {
     fn label() -> Option<&'static str> {
         None
     }
-    fn as_bind_group(
-        &self,
+    /// Images of the texture and sampler bindings, each once, in binding order
+    fn images(&self) -> Vec<ResourceHandle<Image>>;
+    /// Contents of the uniform and storage bindings, in binding order
+    fn buffers(&self) -> Vec<BufferContents>;
+    /// Resource of every binding, from [`Self::images`] and [`Self::buffers`] once on the GPU
+    fn bindings<'a>(
+        images: &[&'a GpuImage],
+        buffers: &'a [wgpu::Buffer],
+    ) -> Vec<wgpu::BindingResource<'a>>;
+    /// Made once per type by [`RenderAssets`](super::render_assets::RenderAssets) and shared by its bind groups
+    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout;
+
+    fn create_bind_group(
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        resources: &ResourceManager,
+        layout: Arc<wgpu::BindGroupLayout>,
+        images: &[&GpuImage],
+        buffers: &[wgpu::Buffer],
     ) -> BindGroup {
-        let layout = Self::bind_group_layout(device);
-        let bindings = self.bindings(device, queue, resources);
-        let entries = bindings
-            .iter()
+        let entries = Self::bindings(images, buffers)
+            .into_iter()
             .enumerate()
-            .map(|(index, binding)| BindGroupEntry {
+            .map(|(index, resource)| wgpu::BindGroupEntry {
                 binding: index as u32,
-                resource: binding.get_binding(),
+                resource,
             })
             .collect::<Vec<_>>();
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -30,44 +53,67 @@ pub trait AsBindGroup {
 
         BindGroup::new(layout, bind_group)
     }
-
-    fn bindings(
-        &self,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        resources: &ResourceManager,
-    ) -> Vec<OwnedBindingResource>;
-
-    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout;
 }
 
-#[derive(Debug)]
-pub enum OwnedBindingResource {
-    Buffer(wgpu::Buffer),
-    TextureView(wgpu::TextureView),
-    Sampler(wgpu::Sampler),
+/// Bytes of a uniform or storage binding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferContents {
+    pub usage: wgpu::BufferUsages,
+    pub bytes: Vec<u8>,
 }
 
-impl OwnedBindingResource {
-    pub fn get_binding(&self) -> wgpu::BindingResource {
-        match self {
-            Self::Buffer(buffer) => buffer.as_entire_binding(),
-            Self::TextureView(view) => wgpu::BindingResource::TextureView(view),
-            Self::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+impl BufferContents {
+    pub fn uniform<T, U>(data: &T) -> Self
+    where
+        T: GpuSendable<U>,
+        U: ShaderType,
+    {
+        const {
+            assert!(
+                U::LAYOUT.uniform,
+                "Type breaks the layout rules of uniform buffers"
+            );
+        };
+        Self {
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            bytes: data.to_gpu().to_bytes(),
         }
     }
+    pub fn storage<T, U>(data: &T) -> Self
+    where
+        T: GpuSendable<U>,
+        U: ShaderType,
+    {
+        Self {
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            bytes: data.to_gpu().to_bytes(),
+        }
+    }
+    pub fn create_buffer(&self, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &self.bytes,
+            usage: self.usage,
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct BindGroup {
-    layout: wgpu::BindGroupLayout,
+    layout: Arc<wgpu::BindGroupLayout>,
     bind_group: wgpu::BindGroup,
 }
 
 impl BindGroup {
-    fn new(layout: wgpu::BindGroupLayout, bind_group: wgpu::BindGroup) -> Self {
+    const fn new(layout: Arc<wgpu::BindGroupLayout>, bind_group: wgpu::BindGroup) -> Self {
         Self { layout, bind_group }
     }
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+    pub const fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
 }
 
 #[derive(Debug, Clone)]
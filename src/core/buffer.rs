@@ -1,10 +1,12 @@
-use std::{fmt::Debug, mem};
+use std::fmt::Debug;
 
 use bytemuck::{Pod, Zeroable};
 use wgpu::util::DeviceExt;
 
 use crate::GpuSendable;
 
+use super::shader_type::{array_bytes, ShaderType};
+
 pub trait VertexAttributeLayout {
     fn layout() -> wgpu::VertexBufferLayout<'static>;
 }
@@ -55,12 +57,18 @@ pub struct UniformBuffer<T> {
 impl<T> UniformBuffer<T> {
     pub fn new<U>(data: T, device: &wgpu::Device) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
+        const {
+            assert!(
+                U::LAYOUT.uniform,
+                "Type breaks the layout rules of uniform buffers"
+            );
+        };
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&[data.to_gpu()]),
+            contents: &data.to_gpu().to_bytes(),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
         });
         Self { data, buffer }
@@ -68,12 +76,13 @@ impl<T> UniformBuffer<T> {
 
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data.to_gpu()]));
+        queue.write_buffer(&self.buffer, 0, &self.data.to_gpu().to_bytes());
     }
 }
+
 #[derive(Debug)]
 pub struct UniformBufferArray<T> {
     pub data: Vec<T>,
@@ -83,13 +92,19 @@ pub struct UniformBufferArray<T> {
 impl<T> UniformBufferArray<T> {
     pub fn new<U>(data: &[T], device: &wgpu::Device) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U> + Clone + Copy,
     {
+        const {
+            assert!(
+                U::LAYOUT.array(1).uniform,
+                "Type breaks the layout rules of uniform buffer arrays"
+            );
+        };
         let gpu_data: Vec<U> = data.iter().map(GpuSendable::to_gpu).collect();
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&gpu_data),
+            contents: &array_bytes(&gpu_data),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
         });
         Self {
@@ -99,24 +114,24 @@ impl<T> UniformBufferArray<T> {
     }
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         let gpu_data: Vec<U> = self.data.iter().map(GpuSendable::to_gpu).collect();
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&gpu_data));
+        queue.write_buffer(&self.buffer, 0, &array_bytes(&gpu_data));
     }
     pub fn update_at<U>(&self, index: usize, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         assert!(index < self.data.len(), "Updated buffer at index > length");
 
-        let gpu_data = self.data[index].to_gpu();
+        let offset = index * U::LAYOUT.stride();
         queue.write_buffer(
             &self.buffer,
-            index as u64,
-            bytemuck::cast_slice(&[gpu_data]),
+            offset as u64,
+            &self.data[index].to_gpu().to_bytes(),
         );
     }
 }
@@ -130,12 +145,12 @@ pub struct StorageBuffer<T> {
 impl<T> StorageBuffer<T> {
     pub fn new<U>(data: T, device: &wgpu::Device) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&[data.to_gpu()]),
+            contents: &data.to_gpu().to_bytes(),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
         });
         Self { data, buffer }
@@ -143,10 +158,10 @@ impl<T> StorageBuffer<T> {
 
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data.to_gpu()]));
+        queue.write_buffer(&self.buffer, 0, &self.data.to_gpu().to_bytes());
     }
 }
 
@@ -159,17 +174,17 @@ pub struct StorageBufferArray<T> {
 impl<T> StorageBufferArray<T> {
     pub fn new<U>(data: &[T], device: &wgpu::Device, queue: &wgpu::Queue, size: u64) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable + Debug,
+        U: ShaderType,
         T: GpuSendable<U> + Clone + Copy,
     {
         let gpu_data: Vec<U> = data.iter().map(GpuSendable::to_gpu).collect();
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: mem::size_of::<T>() as u64 * size,
+            size: U::LAYOUT.stride() as u64 * size,
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
-        queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&gpu_data));
+        queue.write_buffer(&buffer, 0, &array_bytes(&gpu_data));
         Self {
             data: data.to_vec(),
             buffer,
@@ -177,31 +192,32 @@ impl<T> StorageBufferArray<T> {
     }
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         if self.data.is_empty() {
-            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[U::zeroed()]));
+            queue.write_buffer(&self.buffer, 0, &vec![0; U::LAYOUT.stride()]);
         } else {
             let gpu_data: Vec<U> = self.data.iter().map(GpuSendable::to_gpu).collect();
-            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&gpu_data));
+            queue.write_buffer(&self.buffer, 0, &array_bytes(&gpu_data));
         }
     }
     pub fn update_at<U>(&self, index: usize, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         assert!(index < self.data.len(), "Updated buffer at index > length");
 
-        let gpu_data = self.data[index].to_gpu();
+        let offset = index * U::LAYOUT.stride();
         queue.write_buffer(
             &self.buffer,
-            index as u64,
-            bytemuck::cast_slice(&[gpu_data]),
+            offset as u64,
+            &self.data[index].to_gpu().to_bytes(),
         );
     }
 }
+
 #[derive(Debug)]
 pub struct Buffer {
     pub buffer: wgpu::Buffer,
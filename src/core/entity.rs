@@ -1,8 +1,13 @@
-use std::{any::TypeId, collections::HashMap};
+use std::{any::TypeId, collections::HashMap, mem};
 
+use glam::{Affine3A, Mat4};
 use slotmap::{new_key_type, DenseSlotMap, SlotMap};
 
-use super::{component::Component, transform::Transform};
+use super::{
+    component::Component,
+    query::{Components, Query, QueryFilter, ReadOnlyQuery},
+    transform::Transform,
+};
 
 new_key_type! {
     pub struct ComponentKey;
@@ -24,13 +29,174 @@ impl EntityHierarchy {
         let entity = Entity::new(name);
         self.entities.insert(entity)
     }
+    pub fn add_child_entity(&mut self, parent: EntityKey, name: String) -> EntityKey {
+        let child = self.add_entity(name);
+        self.set_parent(child, Some(parent));
+        child
+    }
+    /// Removes the entity together with all its descendants.
     pub fn remove_entity(&mut self, entity: EntityKey) -> Option<Entity> {
-        self.entities.remove(entity)
+        self.detach(entity);
+        let removed = self.entities.remove(entity)?;
+        let mut stack = removed.children.clone();
+        while let Some(key) = stack.pop() {
+            if let Some(descendant) = self.entities.remove(key) {
+                stack.extend(descendant.children);
+            }
+        }
+        Some(removed)
+    }
+
+    pub fn parent(&self, entity: EntityKey) -> Option<EntityKey> {
+        self.entities.get(entity)?.parent
+    }
+    pub fn children(&self, entity: EntityKey) -> &[EntityKey] {
+        self.entities
+            .get(entity)
+            .map_or(&[], |e| e.children.as_slice())
+    }
+    pub fn roots(&self) -> impl Iterator<Item = EntityKey> + '_ {
+        self.entities
+            .iter()
+            .filter(|(_, e)| e.parent.is_none())
+            .map(|(key, _)| key)
+    }
+    pub fn is_ancestor(&self, ancestor: EntityKey, entity: EntityKey) -> bool {
+        let mut current = self.parent(entity);
+        while let Some(key) = current {
+            if key == ancestor {
+                return true;
+            }
+            current = self.parent(key);
+        }
+        false
     }
 
+    /// Sets the parent of `child`, keeping its local transform.
+    /// Returns false if either entity doesn't exist or the new parent is `child` or one of its descendants.
+    pub fn set_parent(&mut self, child: EntityKey, parent: Option<EntityKey>) -> bool {
+        if !self.entities.contains_key(child) {
+            return false;
+        }
+        if let Some(parent) = parent {
+            if parent == child
+                || !self.entities.contains_key(parent)
+                || self.is_ancestor(child, parent)
+            {
+                return false;
+            }
+        }
+        self.detach(child);
+        if let Some(parent) = parent {
+            self.entities[parent].children.push(child);
+        }
+        let entity = &mut self.entities[child];
+        entity.parent = parent;
+        entity.parent_changed = true;
+        true
+    }
+
+    /// Sets the parent of `child`, changing its local transform so that its world transform stays the same.
+    pub fn reparent(&mut self, child: EntityKey, parent: Option<EntityKey>) -> bool {
+        let world_transform = self.world_transform(child);
+        let parent_world_transform = self.frame(parent);
+        if !self.set_parent(child, parent) {
+            return false;
+        }
+        if let Some(world_transform) = world_transform {
+            if let Some(transform) = self.entities[child].get_component_mut::<Transform>() {
+                transform.set_local_transform(parent_world_transform.inverse() * world_transform);
+            }
+        }
+        true
+    }
+
+    /// World transform of the entity, computed from the local transforms of its ancestors.
+    ///
+    /// Unlike `Transform::global_transform` this is up to date even before `update_transform_hierarchies`.
+    /// Entities without a `Transform` return `None`; ancestors without one are skipped.
+    pub fn world_transform(&self, entity: EntityKey) -> Option<Affine3A> {
+        let local_transform = self
+            .entities
+            .get(entity)?
+            .get_component::<Transform>()?
+            .local_transform();
+        Some(self.frame(self.parent(entity)) * local_transform)
+    }
+    /// Transform of the space children of `entity` live in, the identity for `None`.
+    /// Entities without a `Transform` are skipped, like in [`Self::world_transform`].
+    fn frame(&self, entity: Option<EntityKey>) -> Affine3A {
+        let mut frame = Affine3A::IDENTITY;
+        let mut current = entity;
+        while let Some(entity) = current.and_then(|key| self.entities.get(key)) {
+            if let Some(transform) = entity.get_component::<Transform>() {
+                frame = transform.local_transform() * frame;
+            }
+            current = entity.parent;
+        }
+        frame
+    }
+    pub fn world_matrix(&self, entity: EntityKey) -> Option<Mat4> {
+        self.world_transform(entity).map(Mat4::from)
+    }
+
+    /// Recomputes the global transform of every entity whose transform, or that of an ancestor, changed.
     pub fn update_transform_hierarchies(&mut self) {
-        for (_, entity) in &mut self.entities {
-            entity.update_transform_hierarchies();
+        let mut stack: Vec<(EntityKey, Affine3A, bool)> = self
+            .roots()
+            .map(|key| (key, Affine3A::IDENTITY, false))
+            .collect();
+        while let Some((key, parent_transform, parent_changed)) = stack.pop() {
+            let entity = &mut self.entities[key];
+            let parent_changed = parent_changed || mem::take(&mut entity.parent_changed);
+            // Entities without a transform pass their parent's down unchanged
+            let (global_transform, changed) = entity.get_component_mut::<Transform>().map_or(
+                (parent_transform, parent_changed),
+                |transform| {
+                    let changed = parent_changed || transform.is_dirty();
+                    if changed {
+                        transform.update_global_transform_from(parent_transform);
+                    }
+                    (transform.global_transform(), changed)
+                },
+            );
+            stack.extend(
+                entity
+                    .children
+                    .iter()
+                    .map(|&child| (child, global_transform, changed)),
+            );
+        }
+    }
+
+    /// Iterates all entities matching `Q`, e.g. `query::<(&Transform, Option<&MaterialRenderer>)>()`.
+    pub fn query<Q: ReadOnlyQuery>(&self) -> impl Iterator<Item = (EntityKey, Q::Item<'_>)> {
+        self.query_filtered::<Q, ()>()
+    }
+    pub fn query_filtered<Q: ReadOnlyQuery, F: QueryFilter>(
+        &self,
+    ) -> impl Iterator<Item = (EntityKey, Q::Item<'_>)> {
+        self.entities
+            .iter()
+            .filter_map(|(key, entity)| Some((key, entity.query_filtered::<Q, F>()?)))
+    }
+    pub fn query_mut<Q: Query>(&mut self) -> impl Iterator<Item = (EntityKey, Q::Item<'_>)> {
+        self.query_mut_filtered::<Q, ()>()
+    }
+    pub fn query_mut_filtered<Q: Query, F: QueryFilter>(
+        &mut self,
+    ) -> impl Iterator<Item = (EntityKey, Q::Item<'_>)> {
+        self.entities
+            .iter_mut()
+            .filter_map(|(key, entity)| Some((key, entity.query_mut_filtered::<Q, F>()?)))
+    }
+
+    fn detach(&mut self, child: EntityKey) {
+        let Some(parent) = self.entities.get_mut(child).and_then(|e| e.parent.take()) else {
+            return;
+        };
+        if let Some(parent) = self.entities.get_mut(parent) {
+            parent.children.retain(|&c| c != child);
         }
     }
 }
@@ -40,7 +206,10 @@ pub struct Entity {
     pub name: String,
     components: SlotMap<ComponentKey, Box<dyn Component>>,
     type_map: HashMap<TypeId, ComponentKey>,
-    children: Vec<Entity>,
+    parent: Option<EntityKey>,
+    children: Vec<EntityKey>,
+    /// Set when the entity is moved in the hierarchy, its descendants' global transforms are recomputed
+    parent_changed: bool,
 }
 
 impl Entity {
@@ -48,7 +217,9 @@ impl Entity {
         Self {
             name,
             components: SlotMap::default(),
+            parent: None,
             children: Vec::default(),
+            parent_changed: false,
             type_map: HashMap::default(),
         }
     }
@@ -72,28 +243,191 @@ impl Entity {
         component.downcast_mut()
     }
     pub fn remove_component<T: Component>(&mut self) -> Option<Box<T>> {
-        let id = self.type_map.get(&TypeId::of::<T>())?;
-        let pop = self.components.remove(*id)?;
+        let id = self.type_map.remove(&TypeId::of::<T>())?;
+        let pop = self.components.remove(id)?;
         pop.downcast().ok()
     }
     pub fn has_component<T: Component>(&self) -> bool {
         self.type_map.contains_key(&TypeId::of::<T>())
     }
+    pub fn query<Q: ReadOnlyQuery>(&self) -> Option<Q::Item<'_>> {
+        self.query_filtered::<Q, ()>()
+    }
+    pub fn query_filtered<Q: ReadOnlyQuery, F: QueryFilter>(&self) -> Option<Q::Item<'_>> {
+        if !(Q::matches(self) && F::matches(self)) {
+            return None;
+        }
+        Q::fetch_ref(self)
+    }
+    pub fn query_mut<Q: Query>(&mut self) -> Option<Q::Item<'_>> {
+        self.query_mut_filtered::<Q, ()>()
+    }
+    pub fn query_mut_filtered<Q: Query, F: QueryFilter>(&mut self) -> Option<Q::Item<'_>> {
+        if !(Q::matches(self) && F::matches(self)) {
+            return None;
+        }
+        let type_map = &self.type_map;
+        // Components replaced by `add_component` of the same type are no longer reachable
+        let components = self
+            .components
+            .iter_mut()
+            .map(|(key, component)| (key, component.as_mut()))
+            .filter(|(key, component)| type_map.get(&component.as_any().type_id()) == Some(key))
+            .map(|(_, component)| component);
+        Q::fetch(&mut Components::new::<Q>(components))
+    }
+    pub const fn parent(&self) -> Option<EntityKey> {
+        self.parent
+    }
+    pub fn children(&self) -> &[EntityKey] {
+        &self.children
+    }
+}
 
-    pub fn update_transform_hierarchies(&mut self) {
-        // let Some(transform) = self.get_component::<Transform>() else {
-        //     return;
-        // };
-        // for child_transform in self
-        //     .children
-        //     .iter_mut()
-        //     .filter_map(|c| c.get_component_mut::<Transform>())
-        // {
-        //     child_transform.update_global_transform(*transform);
-        // }
-    }
-
-    pub fn add_child(&mut self, child: Self) {
-        self.children.push(child);
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use glam::{Quat, Vec3};
+
+    use super::{EntityHierarchy, EntityKey};
+    use crate::core::transform::Transform;
+
+    fn entity_at(hierarchy: &mut EntityHierarchy, name: &str, position: Vec3) -> EntityKey {
+        let key = hierarchy.add_entity(name.into());
+        hierarchy.entities[key].add_component(Transform::new(position, Quat::IDENTITY, Vec3::ONE));
+        key
+    }
+    fn global_position(hierarchy: &EntityHierarchy, key: EntityKey) -> Vec3 {
+        let transform = hierarchy.entities[key]
+            .get_component::<Transform>()
+            .unwrap();
+        transform.global_transform().translation.into()
+    }
+
+    #[test]
+    fn children_follow_parent() {
+        let mut hierarchy = EntityHierarchy::new();
+        let parent = entity_at(&mut hierarchy, "parent", Vec3::X);
+        let child = entity_at(&mut hierarchy, "child", Vec3::Y);
+        let grandchild = entity_at(&mut hierarchy, "grandchild", Vec3::Z);
+        assert!(hierarchy.set_parent(child, Some(parent)), "Valid parent");
+        assert!(
+            hierarchy.set_parent(grandchild, Some(child)),
+            "Valid parent"
+        );
+        hierarchy.update_transform_hierarchies();
+        assert_abs_diff_eq!(global_position(&hierarchy, grandchild), Vec3::ONE);
+
+        let transform = hierarchy.entities[parent]
+            .get_component_mut::<Transform>()
+            .unwrap();
+        transform.position = Vec3::ZERO;
+        hierarchy.update_transform_hierarchies();
+        assert_abs_diff_eq!(global_position(&hierarchy, child), Vec3::Y);
+        assert_abs_diff_eq!(global_position(&hierarchy, grandchild), Vec3::Y + Vec3::Z);
+    }
+
+    #[test]
+    fn reparent_keeps_world_transform() {
+        let mut hierarchy = EntityHierarchy::new();
+        let first = entity_at(&mut hierarchy, "first", Vec3::X);
+        let second = entity_at(&mut hierarchy, "second", Vec3::new(0.0, 2.0, 0.0));
+        hierarchy.entities[second]
+            .get_component_mut::<Transform>()
+            .unwrap()
+            .scale = Vec3::splat(2.0);
+        let child = hierarchy.add_child_entity(first, "child".into());
+        hierarchy.entities[child].add_component(Transform::new(Vec3::Z, Quat::IDENTITY, Vec3::ONE));
+        hierarchy.update_transform_hierarchies();
+        let before = global_position(&hierarchy, child);
+
+        assert!(hierarchy.reparent(child, Some(second)), "Valid parent");
+        hierarchy.update_transform_hierarchies();
+        assert_eq!(hierarchy.parent(child), Some(second), "Parent changed");
+        assert!(
+            hierarchy.children(first).is_empty(),
+            "Removed from old parent"
+        );
+        assert_abs_diff_eq!(global_position(&hierarchy, child), before, epsilon = 1e-5);
+        assert_abs_diff_eq!(
+            hierarchy.world_matrix(child).unwrap(),
+            hierarchy.entities[child]
+                .get_component::<Transform>()
+                .unwrap()
+                .world_matrix(),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn reparent_under_entity_without_transform() {
+        let mut hierarchy = EntityHierarchy::new();
+        let grandparent = entity_at(&mut hierarchy, "grandparent", Vec3::X);
+        let parent = hierarchy.add_child_entity(grandparent, "parent".into());
+        let child = entity_at(&mut hierarchy, "child", Vec3::Y);
+        hierarchy.update_transform_hierarchies();
+
+        assert!(hierarchy.reparent(child, Some(parent)), "Valid parent");
+        hierarchy.update_transform_hierarchies();
+        assert_abs_diff_eq!(global_position(&hierarchy, child), Vec3::Y, epsilon = 1e-5);
+        assert_abs_diff_eq!(
+            hierarchy.entities[child]
+                .get_component::<Transform>()
+                .unwrap()
+                .position,
+            Vec3::Y - Vec3::X,
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn moving_entity_without_transform_moves_its_children() {
+        let mut hierarchy = EntityHierarchy::new();
+        let first = entity_at(&mut hierarchy, "first", Vec3::X);
+        let second = entity_at(&mut hierarchy, "second", Vec3::Z);
+        let parent = hierarchy.add_child_entity(first, "parent".into());
+        let child = entity_at(&mut hierarchy, "child", Vec3::Y);
+        assert!(hierarchy.set_parent(child, Some(parent)), "Valid parent");
+        hierarchy.update_transform_hierarchies();
+        assert_abs_diff_eq!(global_position(&hierarchy, child), Vec3::X + Vec3::Y);
+
+        assert!(hierarchy.set_parent(parent, Some(second)), "Valid parent");
+        hierarchy.update_transform_hierarchies();
+        assert_abs_diff_eq!(global_position(&hierarchy, child), Vec3::Z + Vec3::Y);
+
+        assert!(hierarchy.set_parent(parent, None), "Detached");
+        hierarchy.update_transform_hierarchies();
+        assert_abs_diff_eq!(global_position(&hierarchy, child), Vec3::Y);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut hierarchy = EntityHierarchy::new();
+        let parent = hierarchy.add_entity("parent".into());
+        let child = hierarchy.add_child_entity(parent, "child".into());
+        assert!(
+            !hierarchy.set_parent(parent, Some(child)),
+            "Would create a cycle"
+        );
+        assert!(!hierarchy.set_parent(parent, Some(parent)), "Own parent");
+        assert_eq!(
+            hierarchy.roots().collect::<Vec<_>>(),
+            vec![parent],
+            "Single root"
+        );
+    }
+
+    #[test]
+    fn removing_entity_removes_descendants() {
+        let mut hierarchy = EntityHierarchy::new();
+        let root = hierarchy.add_entity("root".into());
+        let parent = hierarchy.add_child_entity(root, "parent".into());
+        let child = hierarchy.add_child_entity(parent, "child".into());
+        assert!(hierarchy.remove_entity(parent).is_some(), "Entity exists");
+        assert!(
+            !hierarchy.entities.contains_key(child),
+            "Descendant removed"
+        );
+        assert!(hierarchy.children(root).is_empty(), "Detached from parent");
     }
 }
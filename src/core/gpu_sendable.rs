@@ -1,15 +1,16 @@
-use bytemuck::{Pod, Zeroable};
+use super::shader_type::ShaderType;
 
+/// Converts to the type uploaded to the GPU, whose [`ShaderType`] layout matches the shaders.
 pub trait GpuSendable<T>
 where
-    T: Clone + Copy + Pod + Zeroable,
+    T: ShaderType,
 {
     fn to_gpu(&self) -> T;
 }
 
 impl<T> GpuSendable<T> for T
 where
-    T: Clone + Copy + Pod + Zeroable,
+    T: Clone + Copy + ShaderType,
 {
     fn to_gpu(&self) -> T {
         *self
@@ -1,5 +1,5 @@
 use image::{DynamicImage, ImageError};
-use wgpu::{util::DeviceExt, Extent3d};
+use wgpu::Extent3d;
 
 use crate::{core::compute::ComputePipelineBuilder, renderer::resources::get_max_mip_level_count};
 
@@ -168,17 +168,35 @@ impl Image {
 
         Ok(Self::new(image))
     }
+    /// Uploads the image as 8-bit RGBA and generates its other mip levels from the first.
     pub fn to_gpu(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> GpuImage {
-        let binding = self.image.as_rgb8().unwrap();
-        let data = binding.as_raw();
-        let texture = device.create_texture_with_data(
-            queue,
-            &self.texture_descriptor,
-            wgpu::util::TextureDataOrder::MipMajor,
-            data,
+        let size = self.texture_descriptor.size;
+        let mip_level_count = self.texture_descriptor.mip_level_count;
+        let mut usage = self.texture_descriptor.usage;
+        if mip_level_count > 1 {
+            // Mip levels are written as storage textures
+            usage |= wgpu::TextureUsages::STORAGE_BINDING;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            usage,
+            ..self.texture_descriptor.clone()
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.image.to_rgba8(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
         );
 
-        let size = self.texture_descriptor.size;
         let texture_view_descriptor = self.texture_view_descriptor.clone().unwrap_or_default();
 
         let texture_view = texture.create_view(&texture_view_descriptor);
@@ -190,10 +208,10 @@ impl Image {
             texture_format: self.texture_descriptor.format,
             sampler,
             size,
-            mip_level_count: self.texture_descriptor.mip_level_count,
+            mip_level_count,
         };
-        if image.texture.dimension() == wgpu::TextureDimension::D2 {
-            for layer in 0..=size.depth_or_array_layers {
+        if image.texture.dimension() == wgpu::TextureDimension::D2 && mip_level_count > 1 {
+            for layer in 0..size.depth_or_array_layers {
                 image.generate_mipmaps(device, queue, layer);
             }
         }
@@ -1,64 +1,83 @@
-use crate::{core::buffer::UniformBuffer, renderer::color::Color};
+use crate::renderer::color::Color;
 
 use super::{
-    bind_group::{AsBindGroup, BindGroupLayoutBuilder, OwnedBindingResource},
+    bind_group::AsBindGroup,
     image::Image,
-    resources::{Resource, ResourceHandle, ResourceManager},
+    resources::{Resource, ResourceHandle},
 };
 
 pub trait Material: Resource {}
 impl<T: Material> Resource for T {}
-#[derive(Debug)]
+#[derive(Debug, AsBindGroup)]
 pub struct StandardMaterial {
+    #[texture(0)]
+    #[sampler(1)]
     pub diffuse_texture: ResourceHandle<Image>,
+    #[uniform(2)]
     pub diffuse_color: Color,
+    #[texture(3)]
+    #[sampler(4)]
     pub normal_map: ResourceHandle<Image>,
+    #[uniform(5)]
     pub specular: f32,
+    #[uniform(6)]
     pub ior: f32,
+    #[uniform(7)]
     pub roughness: f32,
+    #[uniform(8)]
     pub ambient: Color,
 }
 
 impl Material for StandardMaterial {}
 
-impl AsBindGroup for StandardMaterial {
-    fn label() -> Option<&'static str> {
-        Some("StandardMaterial")
+impl<T: Material> From<ResourceHandle<T>> for ResourceHandle<dyn Material> {
+    fn from(value: ResourceHandle<T>) -> Self {
+        Self::from_key(value.key())
     }
-    fn bindings(
-        &self,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        resources: &ResourceManager,
-    ) -> Vec<OwnedBindingResource> {
-        let diffuse_texture = resources.load_resource(&self.diffuse_texture).unwrap();
-        let normal_texture = resources.load_resource(&self.normal_map).unwrap();
-        let diffuse_view = diffuse_texture.to_gpu(device, queue);
-        let normal_view = normal_texture.to_gpu(device, queue);
-        vec![
-            OwnedBindingResource::TextureView(diffuse_view.texture_view),
-            OwnedBindingResource::Sampler(diffuse_view.sampler),
-            OwnedBindingResource::Buffer(UniformBuffer::new(self.diffuse_color, device).buffer),
-            OwnedBindingResource::TextureView(normal_view.texture_view),
-            OwnedBindingResource::Sampler(normal_view.sampler),
-            OwnedBindingResource::Buffer(UniformBuffer::new(self.specular, device).buffer),
-            OwnedBindingResource::Buffer(UniformBuffer::new(self.ior, device).buffer),
-            OwnedBindingResource::Buffer(UniformBuffer::new(self.roughness, device).buffer),
-            OwnedBindingResource::Buffer(UniformBuffer::new(self.ambient, device).buffer),
-        ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Material, StandardMaterial};
+    use crate::{
+        core::{bind_group::AsBindGroup, render_assets::RenderAssets, resources::ResourceManager},
+        tests::device,
+    };
+
+    #[derive(AsBindGroup)]
+    #[bind_group(label = "Parameters", visibility = VERTEX_FRAGMENT)]
+    struct Parameters {
+        #[storage(1, read_only)]
+        weights: [f32; 4],
+        #[uniform(0)]
+        scale: f32,
     }
+    impl Material for Parameters {}
+
+    #[test]
+    fn derived_bindings_match_the_derived_layout() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut resources = ResourceManager::from_directory(std::env::temp_dir()).unwrap();
+        let parameters = resources.save_resource(Parameters {
+            weights: [0.25; 4],
+            scale: 2.0,
+        });
+        let mut assets = RenderAssets::new();
 
-    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        BindGroupLayoutBuilder::new(wgpu::ShaderStages::FRAGMENT)
-            .texture_2d()
-            .sampler(wgpu::SamplerBindingType::Filtering)
-            .uniform()
-            .texture_2d()
-            .sampler(wgpu::SamplerBindingType::Filtering)
-            .uniform()
-            .uniform()
-            .uniform()
-            .uniform()
-            .build(device)
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        assets.bind_group_layout::<StandardMaterial>(&device);
+        assets
+            .bind_group(&parameters, &device, &queue, &resources)
+            .unwrap();
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+        assert_eq!(Parameters::label(), Some("Parameters"), "Label attribute");
+        assert_eq!(
+            StandardMaterial::label(),
+            Some("StandardMaterial"),
+            "Struct name by default"
+        );
     }
 }
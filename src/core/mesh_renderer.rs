@@ -0,0 +1,8 @@
+use crate::renderer::mesh::Mesh;
+
+use super::{component::Component, resources::ResourceHandle};
+
+pub struct MeshRenderer {
+    pub mesh: ResourceHandle<Mesh>,
+}
+impl Component for MeshRenderer {}
@@ -0,0 +1,306 @@
+use std::{any::TypeId, marker::PhantomData};
+
+use super::{component::Component, entity::Entity};
+
+/// Most components a single query can borrow through [`Entity::query_mut`]
+const MAX_COMPONENTS: usize = 8;
+
+/// Components of an entity requested by a query and not yet handed out, each borrowed at most once.
+#[derive(Default)]
+pub struct Components<'a> {
+    slots: [Option<&'a mut dyn Component>; MAX_COMPONENTS],
+}
+
+impl<'a> Components<'a> {
+    /// Keeps the components `Q` asks for, without allocating.
+    pub(crate) fn new<Q: Query>(components: impl Iterator<Item = &'a mut dyn Component>) -> Self {
+        let mut slots = Self::default();
+        for component in components.filter(|c| Q::wants(c.as_any().type_id())) {
+            let slot = slots
+                .slots
+                .iter_mut()
+                .find(|slot| slot.is_none())
+                .expect("A query borrows at most 8 components");
+            *slot = Some(component);
+        }
+        slots
+    }
+
+    fn contains<T: Component>(&self) -> bool {
+        self.slots.iter().flatten().any(|c| c.is::<T>())
+    }
+
+    fn take<T: Component>(&mut self) -> Option<&'a mut T> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.as_ref().is_some_and(|c| c.is::<T>()))?;
+        slot.take()?.downcast_mut()
+    }
+}
+
+/// A typed request for components of an entity: `&T`, `&mut T`, `Option<_>` of those, or tuples of them.
+///
+/// Through [`Entity::query_mut`] each component is handed out once, so a query requesting the same
+/// component twice never matches there. Read-only queries can read it twice.
+pub trait Query {
+    type Item<'a>;
+    fn matches(entity: &Entity) -> bool;
+    /// Whether the query borrows components of this type
+    fn wants(type_id: TypeId) -> bool;
+    /// Whether [`Self::fetch`] would succeed, without taking anything
+    fn available(components: &Components<'_>) -> bool;
+    fn fetch<'a>(components: &mut Components<'a>) -> Option<Self::Item<'a>>;
+}
+
+/// A [`Query`] that only reads, so it can run through a shared reference.
+pub trait ReadOnlyQuery: Query {
+    fn fetch_ref(entity: &Entity) -> Option<Self::Item<'_>>;
+}
+
+/// Restricts which entities a query visits without borrowing their components.
+pub trait QueryFilter {
+    fn matches(entity: &Entity) -> bool;
+}
+
+pub struct With<T: Component>(PhantomData<T>);
+pub struct Without<T: Component>(PhantomData<T>);
+
+impl<T: Component> Query for &T {
+    type Item<'a> = &'a T;
+    fn matches(entity: &Entity) -> bool {
+        entity.has_component::<T>()
+    }
+    fn wants(type_id: TypeId) -> bool {
+        type_id == TypeId::of::<T>()
+    }
+    fn available(components: &Components<'_>) -> bool {
+        components.contains::<T>()
+    }
+    fn fetch<'a>(components: &mut Components<'a>) -> Option<Self::Item<'a>> {
+        components.take::<T>().map(|c| &*c)
+    }
+}
+impl<T: Component> ReadOnlyQuery for &T {
+    fn fetch_ref(entity: &Entity) -> Option<Self::Item<'_>> {
+        entity.get_component()
+    }
+}
+
+impl<T: Component> Query for &mut T {
+    type Item<'a> = &'a mut T;
+    fn matches(entity: &Entity) -> bool {
+        entity.has_component::<T>()
+    }
+    fn wants(type_id: TypeId) -> bool {
+        type_id == TypeId::of::<T>()
+    }
+    fn available(components: &Components<'_>) -> bool {
+        components.contains::<T>()
+    }
+    fn fetch<'a>(components: &mut Components<'a>) -> Option<Self::Item<'a>> {
+        components.take()
+    }
+}
+
+impl<Q: Query> Query for Option<Q> {
+    type Item<'a> = Option<Q::Item<'a>>;
+    fn matches(_entity: &Entity) -> bool {
+        true
+    }
+    fn wants(type_id: TypeId) -> bool {
+        Q::wants(type_id)
+    }
+    fn available(_components: &Components<'_>) -> bool {
+        true
+    }
+    /// Takes nothing unless all of `Q` is there, so a partial match leaves its components to the rest of the query.
+    fn fetch<'a>(components: &mut Components<'a>) -> Option<Self::Item<'a>> {
+        Some(if Q::available(components) {
+            Q::fetch(components)
+        } else {
+            None
+        })
+    }
+}
+impl<Q: ReadOnlyQuery> ReadOnlyQuery for Option<Q> {
+    fn fetch_ref(entity: &Entity) -> Option<Self::Item<'_>> {
+        Some(Q::fetch_ref(entity))
+    }
+}
+
+impl QueryFilter for () {
+    fn matches(_entity: &Entity) -> bool {
+        true
+    }
+}
+impl<T: Component> QueryFilter for With<T> {
+    fn matches(entity: &Entity) -> bool {
+        entity.has_component::<T>()
+    }
+}
+impl<T: Component> QueryFilter for Without<T> {
+    fn matches(entity: &Entity) -> bool {
+        !entity.has_component::<T>()
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Query),+> Query for ($($name,)+) {
+            type Item<'a> = ($($name::Item<'a>,)+);
+            fn matches(entity: &Entity) -> bool {
+                $($name::matches(entity))&&+
+            }
+            fn wants(type_id: TypeId) -> bool {
+                $($name::wants(type_id))||+
+            }
+            fn available(components: &Components<'_>) -> bool {
+                $($name::available(components))&&+
+            }
+            fn fetch<'a>(components: &mut Components<'a>) -> Option<Self::Item<'a>> {
+                Some(($($name::fetch(components)?,)+))
+            }
+        }
+        impl<$($name: ReadOnlyQuery),+> ReadOnlyQuery for ($($name,)+) {
+            fn fetch_ref(entity: &Entity) -> Option<Self::Item<'_>> {
+                Some(($($name::fetch_ref(entity)?,)+))
+            }
+        }
+        impl<$($name: QueryFilter),+> QueryFilter for ($($name,)+) {
+            fn matches(entity: &Entity) -> bool {
+                $($name::matches(entity))&&+
+            }
+        }
+    };
+}
+
+impl_query_tuple!(A);
+impl_query_tuple!(A, B);
+impl_query_tuple!(A, B, C);
+impl_query_tuple!(A, B, C, D);
+impl_query_tuple!(A, B, C, D, E);
+impl_query_tuple!(A, B, C, D, E, F);
+impl_query_tuple!(A, B, C, D, E, F, G);
+impl_query_tuple!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{With, Without};
+    use crate::core::{component::Component, entity::EntityHierarchy, transform::Transform};
+
+    struct Velocity(Vec3);
+    impl Component for Velocity {}
+    struct Frozen;
+    impl Component for Frozen {}
+
+    fn hierarchy() -> EntityHierarchy {
+        let mut hierarchy = EntityHierarchy::new();
+        let moving = hierarchy.add_entity("moving".into());
+        hierarchy.entities[moving].new_component::<Transform>();
+        hierarchy.entities[moving].add_component(Velocity(Vec3::X));
+        let frozen = hierarchy.add_entity("frozen".into());
+        hierarchy.entities[frozen].new_component::<Transform>();
+        hierarchy.entities[frozen].add_component(Velocity(Vec3::Y));
+        hierarchy.entities[frozen].add_component(Frozen);
+        let still = hierarchy.add_entity("still".into());
+        hierarchy.entities[still].new_component::<Transform>();
+        hierarchy.add_entity("empty".into());
+        hierarchy
+    }
+
+    #[test]
+    fn query_requires_all_components() {
+        let hierarchy = hierarchy();
+        assert_eq!(
+            hierarchy.query::<&Transform>().count(),
+            3,
+            "Three transforms"
+        );
+        assert_eq!(
+            hierarchy.query::<(&Transform, &Velocity)>().count(),
+            2,
+            "Two moving entities"
+        );
+        let optional = hierarchy
+            .query::<(&Transform, Option<&Velocity>)>()
+            .filter(|(_, (_, velocity))| velocity.is_none())
+            .count();
+        assert_eq!(optional, 1, "One entity without velocity");
+    }
+
+    #[test]
+    fn filters_restrict_entities() {
+        let hierarchy = hierarchy();
+        let names: Vec<_> = hierarchy
+            .query_filtered::<&Velocity, Without<Frozen>>()
+            .map(|(key, _)| hierarchy.entities[key].name.as_str())
+            .collect();
+        assert_eq!(names, ["moving"], "Frozen entity excluded");
+        assert_eq!(
+            hierarchy
+                .query_filtered::<&Transform, (With<Velocity>, With<Frozen>)>()
+                .count(),
+            1,
+            "Only the frozen entity"
+        );
+    }
+
+    #[test]
+    fn query_mut_writes_components() {
+        let mut hierarchy = hierarchy();
+        for (_, (transform, velocity)) in
+            hierarchy.query_mut_filtered::<(&mut Transform, &Velocity), Without<Frozen>>()
+        {
+            transform.position += velocity.0;
+        }
+        let positions: Vec<_> = hierarchy
+            .query::<&Transform>()
+            .map(|(_, transform)| transform.position)
+            .collect();
+        assert!(positions.contains(&Vec3::X), "Moving entity moved");
+        assert_eq!(
+            positions.iter().filter(|&&p| p == Vec3::ZERO).count(),
+            2,
+            "Other entities untouched"
+        );
+    }
+
+    #[test]
+    fn partial_optional_match_leaves_components() {
+        let mut hierarchy = hierarchy();
+        let still: Vec<_> = hierarchy
+            .query_mut::<(Option<(&mut Transform, &Velocity)>, &mut Transform)>()
+            .filter(|(_, (moving, _))| moving.is_none())
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            still.len(),
+            1,
+            "Transform not taken by the unmatched option"
+        );
+        assert_eq!(
+            hierarchy.entities[still[0]].name, "still",
+            "Entity without velocity"
+        );
+    }
+
+    #[test]
+    fn duplicate_mutable_access_never_matches() {
+        let mut hierarchy = hierarchy();
+        assert_eq!(
+            hierarchy
+                .query_mut::<(&mut Transform, &mut Transform)>()
+                .count(),
+            0,
+            "A component can only be borrowed once"
+        );
+        assert_eq!(
+            hierarchy.query::<(&Transform, &Transform)>().count(),
+            3,
+            "Reading a component twice is fine"
+        );
+    }
+}
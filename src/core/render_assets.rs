@@ -0,0 +1,424 @@
+use std::{any::TypeId, collections::HashMap, sync::Arc};
+
+use slotmap::SecondaryMap;
+
+use crate::renderer::{
+    buffer::{IndexBuffer, VertexBuffer},
+    mesh::{Mesh, Vertex},
+};
+
+use super::{
+    bind_group::{AsBindGroup, BindGroup, BufferContents},
+    image::{GpuImage, Image},
+    resources::{Resource, ResourceHandle, ResourceKey, ResourceManager},
+};
+
+/// Vertex and index buffers of a [`Mesh`] resource
+#[derive(Debug)]
+pub struct GpuMesh {
+    pub vertex_buffer: VertexBuffer<Vertex>,
+    pub index_buffer: IndexBuffer,
+}
+
+#[derive(Debug)]
+struct Prepared<T> {
+    gpu: T,
+    /// [`ResourceManager::version`] of the resource when it was uploaded
+    version: u32,
+}
+
+#[derive(Debug)]
+struct PreparedBindGroup {
+    bind_group: BindGroup,
+    buffers: Vec<wgpu::Buffer>,
+    /// [`ResourceManager::version`] of the resource when its buffers were written
+    version: u32,
+    /// Images and their versions the bind group was made with
+    images: Vec<(ResourceKey, u32)>,
+}
+
+/// GPU copies of resources, by the handle of the resource.
+///
+/// Images and meshes are uploaded once and shared by everything using them, the buffers of a bind group are
+/// written again only when its resource changed. All are uploaded again when their
+/// [`ResourceManager::version`] changes and dropped by [`Self::evict`] once the resource is removed.
+/// Bind groups of the same type share one layout.
+#[derive(Debug, Default)]
+pub struct RenderAssets {
+    images: SecondaryMap<ResourceKey, Prepared<GpuImage>>,
+    meshes: SecondaryMap<ResourceKey, Prepared<GpuMesh>>,
+    bind_groups: SecondaryMap<ResourceKey, PreparedBindGroup>,
+    layouts: HashMap<TypeId, Arc<wgpu::BindGroupLayout>>,
+}
+
+impl RenderAssets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The image on the GPU, uploaded if it wasn't yet or changed since. `None` if it isn't loaded.
+    pub fn image(
+        &mut self,
+        handle: &ResourceHandle<Image>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resources: &ResourceManager,
+    ) -> Option<&GpuImage> {
+        self.prepare_image(*handle, device, queue, resources)?;
+        Some(&self.images[handle.key()].gpu)
+    }
+
+    /// The mesh on the GPU, uploaded if it wasn't yet or changed since. `None` if it isn't loaded.
+    pub fn mesh(
+        &mut self,
+        handle: &ResourceHandle<Mesh>,
+        device: &wgpu::Device,
+        resources: &ResourceManager,
+    ) -> Option<&GpuMesh> {
+        let mesh = resources.load_resource(handle)?;
+        let version = resources.version(handle);
+        match self.meshes.get(handle.key()) {
+            Some(prepared) if prepared.version == version => {}
+            _ => {
+                self.meshes.insert(
+                    handle.key(),
+                    Prepared {
+                        gpu: GpuMesh {
+                            vertex_buffer: VertexBuffer::new(mesh.vertices.clone(), device),
+                            index_buffer: IndexBuffer::new(mesh.indices.clone(), device),
+                        },
+                        version,
+                    },
+                );
+            }
+        }
+        Some(&self.meshes[handle.key()].gpu)
+    }
+
+    /// Reloads the resources whose files changed on disk, see [`ResourceManager::hot_reload`], and uploads
+    /// the images and meshes among them that were on the GPU. Returns the keys of the reloaded resources.
+    ///
+    /// Bind groups are refreshed the next time [`Self::bind_group`] is called for their resource.
+    pub fn hot_reload(
+        &mut self,
+        resources: &mut ResourceManager,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<ResourceKey> {
+        let reloaded = resources.hot_reload();
+        for &key in &reloaded {
+            if self.images.contains_key(key) {
+                self.prepare_image(ResourceHandle::from_key(key), device, queue, resources);
+            }
+            if self.meshes.contains_key(key) {
+                self.mesh(&ResourceHandle::from_key(key), device, resources);
+            }
+        }
+        reloaded
+    }
+
+    /// Uploads the image if needed and returns its version.
+    fn prepare_image(
+        &mut self,
+        handle: ResourceHandle<Image>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resources: &ResourceManager,
+    ) -> Option<u32> {
+        let image = resources.load_resource(&handle)?;
+        let version = resources.version(&handle);
+        match self.images.get(handle.key()) {
+            Some(prepared) if prepared.version == version => {}
+            _ => {
+                self.images.insert(
+                    handle.key(),
+                    Prepared {
+                        gpu: image.to_gpu(device, queue),
+                        version,
+                    },
+                );
+            }
+        }
+        Some(version)
+    }
+
+    /// Bind group of the resource, made from its cached images and buffers.
+    ///
+    /// A change to the resource only writes its buffers again, the bind group is made again when
+    /// one of its images changed. `None` if the resource or one of its images isn't loaded.
+    pub fn bind_group<T: AsBindGroup + Resource>(
+        &mut self,
+        handle: &ResourceHandle<T>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resources: &ResourceManager,
+    ) -> Option<&BindGroup> {
+        let resource = resources.load_resource(handle)?;
+        let version = resources.version(handle);
+        let mut images = vec![];
+        for image in resource.images() {
+            let image_version = self.prepare_image(image, device, queue, resources)?;
+            images.push((image.key(), image_version));
+        }
+
+        let prepared = if let Some(mut prepared) = self.bind_groups.remove(handle.key()) {
+            let mut rebuild = prepared.images != images;
+            if prepared.version != version {
+                let contents = resource.buffers();
+                if same_sizes(&prepared.buffers, &contents) {
+                    for (buffer, contents) in prepared.buffers.iter().zip(&contents) {
+                        queue.write_buffer(buffer, 0, &contents.bytes);
+                    }
+                } else {
+                    prepared.buffers = create_buffers(&contents, device);
+                    rebuild = true;
+                }
+                prepared.version = version;
+            }
+            if rebuild {
+                prepared.bind_group =
+                    self.create_bind_group::<T>(&images, &prepared.buffers, device);
+                prepared.images = images;
+            }
+            prepared
+        } else {
+            let buffers = create_buffers(&resource.buffers(), device);
+            PreparedBindGroup {
+                bind_group: self.create_bind_group::<T>(&images, &buffers, device),
+                buffers,
+                version,
+                images,
+            }
+        };
+        self.bind_groups.insert(handle.key(), prepared);
+        Some(&self.bind_groups[handle.key()].bind_group)
+    }
+
+    /// Layout of the bind groups of `T`, made the first time it is asked for.
+    /// Pipelines drawing with them should be made with it.
+    pub fn bind_group_layout<T: AsBindGroup + 'static>(
+        &mut self,
+        device: &wgpu::Device,
+    ) -> &wgpu::BindGroupLayout {
+        self.layout::<T>(device)
+    }
+
+    fn layout<T: AsBindGroup + 'static>(
+        &mut self,
+        device: &wgpu::Device,
+    ) -> &Arc<wgpu::BindGroupLayout> {
+        self.layouts
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(T::bind_group_layout(device)))
+    }
+
+    fn create_bind_group<T: AsBindGroup + 'static>(
+        &mut self,
+        images: &[(ResourceKey, u32)],
+        buffers: &[wgpu::Buffer],
+        device: &wgpu::Device,
+    ) -> BindGroup {
+        let layout = Arc::clone(self.layout::<T>(device));
+        let images: Vec<_> = images
+            .iter()
+            .map(|&(key, _)| &self.images[key].gpu)
+            .collect();
+        T::create_bind_group(device, layout, &images, buffers)
+    }
+
+    /// Drops the GPU copies of resources removed from `resources`.
+    pub fn evict(&mut self, resources: &ResourceManager) {
+        self.images.retain(|key, _| resources.contains_key(key));
+        self.meshes.retain(|key, _| resources.contains_key(key));
+        self.bind_groups
+            .retain(|key, _| resources.contains_key(key));
+    }
+}
+
+fn create_buffers(contents: &[BufferContents], device: &wgpu::Device) -> Vec<wgpu::Buffer> {
+    contents
+        .iter()
+        .map(|contents| contents.create_buffer(device))
+        .collect()
+}
+
+/// Whether `contents` can be written to `buffers`, like when a storage binding didn't grow.
+fn same_sizes(buffers: &[wgpu::Buffer], contents: &[BufferContents]) -> bool {
+    buffers.len() == contents.len()
+        && buffers
+            .iter()
+            .zip(contents)
+            .all(|(buffer, contents)| buffer.size() == contents.bytes.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, thread,
+        time::{Duration, Instant},
+    };
+
+    use image::{Rgba, RgbaImage};
+
+    use super::RenderAssets;
+    use crate::{
+        core::{
+            image::Image,
+            material::StandardMaterial,
+            resources::{ResourceHandle, ResourceManager},
+        },
+        renderer::{color::Color, mesh::Mesh},
+        tests::device,
+    };
+
+    #[test]
+    fn uploads_are_shared_and_refreshed_on_change() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut resources = ResourceManager::from_directory(std::env::temp_dir()).unwrap();
+        let mut texture = resources.save_resource(Image::new(
+            RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])).into(),
+        ));
+        let material = |resources: &mut ResourceManager| {
+            resources.save_resource(StandardMaterial {
+                diffuse_texture: texture,
+                diffuse_color: Color::WHITE,
+                normal_map: texture,
+                specular: 0.5,
+                ior: 1.5,
+                roughness: 0.5,
+                ambient: Color::new(0.1, 0.1, 0.1),
+            })
+        };
+        let mut first = material(&mut resources);
+        let second = material(&mut resources);
+        let mut assets = RenderAssets::new();
+        let bind_group = |assets: &mut RenderAssets,
+                          material: ResourceHandle<StandardMaterial>,
+                          resources: &ResourceManager| {
+            assets
+                .bind_group(&material, &device, &queue, resources)
+                .unwrap()
+                .bind_group()
+                .global_id()
+        };
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let first_id = bind_group(&mut assets, first, &resources);
+        bind_group(&mut assets, second, &resources);
+        let image_id = assets.images[texture.key()].gpu.texture.global_id();
+        assert_eq!(assets.images.len(), 1, "Materials share the image");
+        assert_eq!(
+            assets.bind_groups[first.key()]
+                .bind_group
+                .layout()
+                .global_id(),
+            assets.bind_groups[second.key()]
+                .bind_group
+                .layout()
+                .global_id(),
+            "Materials share the layout"
+        );
+        assert_eq!(
+            bind_group(&mut assets, first, &resources),
+            first_id,
+            "Bind group is reused"
+        );
+
+        resources.load_resource_mut(&mut first).unwrap().roughness = 1.0;
+        assert_eq!(
+            bind_group(&mut assets, first, &resources),
+            first_id,
+            "Changed uniforms are written to the same buffers"
+        );
+        assert_eq!(
+            assets.images[texture.key()].gpu.texture.global_id(),
+            image_id,
+            "Unchanged image is not uploaded again"
+        );
+
+        resources.load_resource_mut(&mut texture).unwrap();
+        assert_ne!(
+            bind_group(&mut assets, first, &resources),
+            first_id,
+            "Bind group is made again with the new image"
+        );
+        assert_ne!(
+            assets.images[texture.key()].gpu.texture.global_id(),
+            image_id,
+            "Changed image is uploaded again"
+        );
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+
+        resources.remove_resource(first);
+        assets.evict(&resources);
+        assert_eq!(assets.bind_groups.len(), 1, "Removed material is evicted");
+        resources.remove_resource(texture);
+        resources.remove_resource(second);
+        assets.evict(&resources);
+        assert!(
+            assets.images.is_empty() && assets.bind_groups.is_empty(),
+            "Everything is evicted"
+        );
+    }
+
+    #[test]
+    fn changed_files_are_uploaded_again() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let dir = std::env::temp_dir().join(format!("iris_render_assets_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n";
+        RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]))
+            .save(dir.join("texture.png"))
+            .unwrap();
+        fs::write(
+            dir.join("mesh.obj"),
+            format!("{triangle}f 1/1/1 2/2/1 3/3/1\n"),
+        )
+        .unwrap();
+        let mut resources = ResourceManager::from_directory(&dir).unwrap();
+        let texture = resources.load::<Image>("texture.png").unwrap();
+        let mesh = resources.load::<Mesh>("mesh.obj").unwrap();
+        let mut assets = RenderAssets::new();
+        assets.image(&texture, &device, &queue, &resources).unwrap();
+        assets.mesh(&mesh, &device, &resources).unwrap();
+        let version = resources.version(&texture);
+
+        RgbaImage::from_pixel(4, 4, Rgba([0, 255, 0, 255]))
+            .save(dir.join("texture.png"))
+            .unwrap();
+        fs::write(
+            dir.join("mesh.obj"),
+            format!("{triangle}f 1/1/1 2/2/1 3/3/1\nf 3/3/1 2/2/1 1/1/1\n"),
+        )
+        .unwrap();
+        // The file watcher reports changes on its own thread
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut reloaded = vec![];
+        while !(reloaded.contains(&texture.key()) && reloaded.contains(&mesh.key()))
+            && Instant::now() < deadline
+        {
+            reloaded.extend(assets.hot_reload(&mut resources, &device, &queue));
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(
+            reloaded.contains(&texture.key()) && reloaded.contains(&mesh.key()),
+            "Both files reloaded"
+        );
+        assert!(resources.version(&texture) > version, "Version went up");
+        assert_eq!(
+            assets.images[texture.key()].gpu.size.width,
+            4,
+            "New image uploaded"
+        );
+        assert_eq!(
+            assets.meshes[mesh.key()].gpu.index_buffer.indices.len(),
+            6,
+            "New mesh uploaded"
+        );
+    }
+}
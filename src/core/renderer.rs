@@ -1,15 +1,59 @@
 use std::sync::Arc;
 
+use image::RgbaImage;
 use winit::dpi::PhysicalSize;
 
+use crate::renderer::resources::get_texture_data;
+
 #[derive(Debug)]
 pub struct Renderer {
     pub instance: wgpu::Instance,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface<'static>,
+    pub surface: Option<wgpu::Surface<'static>>,
     pub config: wgpu::SurfaceConfiguration,
+    pub offscreen: Option<OffscreenTarget>,
+}
+
+/// Color and depth textures a headless [`Renderer`] draws into instead of a swapchain.
+#[derive(Debug)]
+pub struct OffscreenTarget {
+    pub color: wgpu::Texture,
+    pub depth: wgpu::Texture,
+}
+
+impl OffscreenTarget {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+        let color = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Color Target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &config.view_formats,
+        });
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Depth Target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth24Plus,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        Self { color, depth }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +63,8 @@ pub struct RendererSettings {
     pub required_features: wgpu::Features,
     pub required_downlevel_capabilities: wgpu::DownlevelCapabilities,
     pub required_limits: wgpu::Limits,
+    /// Only used by headless renderers, requests a software adapter.
+    pub force_fallback_adapter: bool,
 }
 
 impl Default for RendererSettings {
@@ -32,6 +78,7 @@ impl Default for RendererSettings {
                 ..wgpu::DownlevelCapabilities::default()
             },
             required_limits: wgpu::Limits::downlevel_defaults(),
+            force_fallback_adapter: false,
         }
     }
 }
@@ -46,16 +93,7 @@ impl Renderer {
     ) -> Self {
         tracing::info!("Initializing wgpu...");
 
-        let backends = wgpu::util::backend_bits_from_env().unwrap_or_default();
-        let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
-        let gles_minor_version = wgpu::util::gles_minor_version_from_env().unwrap_or_default();
-
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends,
-            flags: wgpu::InstanceFlags::from_build_config().with_env(),
-            dx12_shader_compiler,
-            gles_minor_version,
-        });
+        let instance = create_instance();
 
         // Window size is only actually valid after we enter the event loop.
         let window_size = window.inner_size();
@@ -84,72 +122,97 @@ impl Renderer {
             config.format = format;
             config.view_formats.push(format);
         };
-        let adapter_info = adapter.get_info();
-        tracing::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
-
-        let optional_features = settings.optional_features;
-        let required_features = settings.required_features;
-        let adapter_features = adapter.features();
-        assert!(
-            adapter_features.contains(required_features),
-            "Adapter does not support required features for this example: {:?}",
-            required_features - adapter_features
-        );
-
-        let required_downlevel_capabilities = settings.required_downlevel_capabilities;
-        let downlevel_capabilities = adapter.get_downlevel_capabilities();
-        assert!(
-            downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
-            "Adapter does not support the minimum shader model required to run this example: {:?}",
-            required_downlevel_capabilities.shader_model
-        );
-        assert!(
-            downlevel_capabilities
-                .flags
-                .contains(required_downlevel_capabilities.flags),
-            "Adapter does not support the downlevel capabilities required to run this example: {:?}",
-            required_downlevel_capabilities.flags - downlevel_capabilities.flags
-        );
-
-        // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the surface.
-        let needed_limits = settings.required_limits.using_resolution(adapter.limits());
-
-        let trace_dir = std::env::var("WGPU_TRACE");
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: (optional_features & adapter_features) | required_features,
-                    required_limits: needed_limits,
-                },
-                trace_dir.ok().as_ref().map(std::path::Path::new),
-            )
-            .await
-            .expect("Unable to find a suitable GPU adapter!");
+        let (device, queue) = request_device(&adapter, &settings).await;
 
         Self {
             instance,
             adapter,
             device,
             queue,
-            surface,
+            surface: Some(surface),
             config,
+            offscreen: None,
         }
     }
+
+    /// Creates a renderer without a window, drawing into an [`OffscreenTarget`].
+    ///
+    /// Returns `None` if no adapter is available, so callers on machines without a GPU can skip.
+    pub async fn headless(width: u32, height: u32, settings: RendererSettings) -> Option<Self> {
+        tracing::info!("Initializing headless wgpu...");
+
+        let instance = create_instance();
+        let adapter = match wgpu::util::initialize_adapter_from_env(&instance, None) {
+            Some(adapter) => adapter,
+            None => {
+                instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference: wgpu::util::power_preference_from_env()
+                            .unwrap_or_default(),
+                        force_fallback_adapter: settings.force_fallback_adapter,
+                        compatible_surface: None,
+                    })
+                    .await?
+            }
+        };
+
+        // Readback in `render_to_image` expects 4 bytes per pixel in RGBA order
+        let format = if settings.srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::AutoNoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![format],
+        };
+        let (device, queue) = request_device(&adapter, &settings).await;
+        let offscreen = OffscreenTarget::new(&device, &config);
+
+        Some(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            surface: None,
+            config,
+            offscreen: Some(offscreen),
+        })
+    }
+
+    pub const fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
     /// Resize the surface, making sure to not resize to zero.
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
         tracing::info!("Surface resize {size:?}");
 
         self.config.width = size.width.max(1);
         self.config.height = size.height.max(1);
-        self.surface.configure(&self.device, &self.config);
+        if let Some(ref surface) = self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+        if self.offscreen.is_some() {
+            self.offscreen = Some(OffscreenTarget::new(&self.device, &self.config));
+        }
     }
     /// Acquire the next surface texture.
     pub fn acquire(&mut self) -> wgpu::SurfaceTexture {
-        match self.surface.get_current_texture() {
+        let surface = self
+            .surface
+            .as_ref()
+            .expect("Headless renderers have no surface to acquire from!");
+        match surface.get_current_texture() {
             Ok(frame) => frame,
             // If we timed out, just try again
-            Err(wgpu::SurfaceError::Timeout) => self.surface
+            Err(wgpu::SurfaceError::Timeout) => surface
                 .get_current_texture()
                 .expect("Failed to acquire next surface texture!"),
             Err(
@@ -159,11 +222,92 @@ impl Renderer {
                 // If OutOfMemory happens, reconfiguring may not help, but we might as well try
                 | wgpu::SurfaceError::OutOfMemory,
             ) => {
-                self.surface.configure(&self.device, &self.config);
-                self.surface
+                surface.configure(&self.device, &self.config);
+                surface
                     .get_current_texture()
                     .expect("Failed to acquire next surface texture!")
             }
         }
     }
+
+    /// View of the offscreen color target, in the same format a swapchain view would have.
+    pub fn offscreen_view(&self) -> Option<wgpu::TextureView> {
+        let offscreen = self.offscreen.as_ref()?;
+        Some(offscreen.color.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.config.view_formats[0]),
+            ..wgpu::TextureViewDescriptor::default()
+        }))
+    }
+
+    /// Reads the offscreen color target back to the CPU.
+    pub fn render_to_image(&self) -> Option<RgbaImage> {
+        let offscreen = self.offscreen.as_ref()?;
+        let (buffer, height, width) =
+            get_texture_data(&offscreen.color, &self.device, &self.queue, 0);
+        RgbaImage::from_raw(width, height, buffer.samples)
+    }
+}
+
+fn create_instance() -> wgpu::Instance {
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or_default();
+    let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
+    let gles_minor_version = wgpu::util::gles_minor_version_from_env().unwrap_or_default();
+
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        flags: wgpu::InstanceFlags::from_build_config().with_env(),
+        dx12_shader_compiler,
+        gles_minor_version,
+    })
+}
+
+async fn request_device(
+    adapter: &wgpu::Adapter,
+    settings: &RendererSettings,
+) -> (wgpu::Device, wgpu::Queue) {
+    let adapter_info = adapter.get_info();
+    tracing::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
+
+    let optional_features = settings.optional_features;
+    let required_features = settings.required_features;
+    let adapter_features = adapter.features();
+    assert!(
+        adapter_features.contains(required_features),
+        "Adapter does not support required features for this example: {:?}",
+        required_features - adapter_features
+    );
+
+    let required_downlevel_capabilities = &settings.required_downlevel_capabilities;
+    let downlevel_capabilities = adapter.get_downlevel_capabilities();
+    assert!(
+        downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
+        "Adapter does not support the minimum shader model required to run this example: {:?}",
+        required_downlevel_capabilities.shader_model
+    );
+    assert!(
+        downlevel_capabilities
+            .flags
+            .contains(required_downlevel_capabilities.flags),
+        "Adapter does not support the downlevel capabilities required to run this example: {:?}",
+        required_downlevel_capabilities.flags - downlevel_capabilities.flags
+    );
+
+    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the surface.
+    let needed_limits = settings
+        .required_limits
+        .clone()
+        .using_resolution(adapter.limits());
+
+    let trace_dir = std::env::var("WGPU_TRACE");
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: (optional_features & adapter_features) | required_features,
+                required_limits: needed_limits,
+            },
+            trace_dir.ok().as_ref().map(std::path::Path::new),
+        )
+        .await
+        .expect("Unable to find a suitable GPU adapter!")
 }
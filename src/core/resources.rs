@@ -1,8 +1,10 @@
-use std::marker::PhantomData;
+use std::{collections::HashMap, ffi::OsStr, marker::PhantomData, path::Path, sync::Arc};
 
-use assets_manager::{source::FileSystem, AssetCache};
+use assets_manager::{source::FileSystem, Asset, AssetCache, BoxedError, ReloadId};
 use downcast_rs::{impl_downcast, Downcast};
-use slotmap::{new_key_type, SlotMap};
+use slotmap::{new_key_type, SecondaryMap, SlotMap};
+
+use super::assets::{AssetResource, WithExtension};
 
 new_key_type! {
     pub struct ResourceKey;
@@ -18,30 +20,99 @@ pub struct ResourceHandle<T: Resource + ?Sized> {
     phantom: PhantomData<T>,
 }
 
-impl<T: Resource> ResourceHandle<T> {
+impl<T: Resource + ?Sized> ResourceHandle<T> {
     const fn new(key: ResourceKey) -> Self {
         Self {
             key,
             phantom: PhantomData,
         }
     }
+    /// Handle to the resource at `key`, without checking it is a `T`.
+    pub const fn from_key(key: ResourceKey) -> Self {
+        Self::new(key)
+    }
+    pub const fn key(&self) -> ResourceKey {
+        self.key
+    }
+}
+impl<T: Resource + ?Sized> Clone for ResourceHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T: Resource + ?Sized> Copy for ResourceHandle<T> {}
+impl<T: Resource + ?Sized> PartialEq for ResourceHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
 }
+impl<T: Resource + ?Sized> Eq for ResourceHandle<T> {}
+
+// Where a resource loaded through the asset cache came from, to reload it when its file changes
+struct AssetSource {
+    id: String,
+    reload_id: ReloadId,
+    reload: fn(&mut ResourceManager, ResourceKey) -> Result<bool, BoxedError>,
+}
+
 pub struct ResourceManager {
-    pub external_assets: AssetCache<FileSystem>,
+    pub external_assets: Arc<AssetCache<FileSystem>>,
     resources: SlotMap<ResourceKey, Box<dyn Resource>>,
+    // Asset paths resources were loaded from, so handles can be saved in scenes
+    paths: SecondaryMap<ResourceKey, String>,
+    keys_by_path: HashMap<String, ResourceKey>,
+    sources: SecondaryMap<ResourceKey, AssetSource>,
+    versions: SecondaryMap<ResourceKey, u32>,
 }
 
 impl ResourceManager {
     pub fn new() -> std::io::Result<Self> {
+        Self::from_directory("assets")
+    }
+    pub fn from_directory(path: impl AsRef<Path>) -> std::io::Result<Self> {
         Ok(Self {
-            external_assets: AssetCache::new("assets")?,
+            external_assets: Arc::new(AssetCache::new(path)?),
             resources: SlotMap::default(),
+            paths: SecondaryMap::default(),
+            keys_by_path: HashMap::default(),
+            sources: SecondaryMap::default(),
+            versions: SecondaryMap::default(),
         })
     }
     pub fn save_resource<T: Resource>(&mut self, resource: T) -> ResourceHandle<T> {
         let id = self.resources.insert(Box::new(resource));
         ResourceHandle::new(id)
     }
+    /// Saves a resource identified by its asset path, replacing any resource previously saved at that path.
+    pub fn save_resource_at<T: Resource>(
+        &mut self,
+        path: impl Into<String>,
+        resource: T,
+    ) -> ResourceHandle<T> {
+        let path = path.into();
+        if let Some(old) = self.keys_by_path.remove(&path) {
+            self.resources.remove(old);
+            self.paths.remove(old);
+            self.sources.remove(old);
+            self.versions.remove(old);
+        }
+        let handle = self.save_resource(resource);
+        self.paths.insert(handle.key, path.clone());
+        self.keys_by_path.insert(path, handle.key);
+        handle
+    }
+    pub fn resource_path<T: Resource + ?Sized>(&self, handle: &ResourceHandle<T>) -> Option<&str> {
+        self.paths.get(handle.key).map(String::as_str)
+    }
+    pub fn key_at_path(&self, path: &str) -> Option<ResourceKey> {
+        self.keys_by_path.get(path).copied()
+    }
+    pub fn handle_at_path<T: Resource>(&self, path: &str) -> Option<ResourceHandle<T>> {
+        let key = self.key_at_path(path)?;
+        self.resources[key]
+            .is::<T>()
+            .then(|| ResourceHandle::new(key))
+    }
     pub fn load_resource<T: Resource>(&self, handle: &ResourceHandle<T>) -> Option<&T> {
         let resource = self.resources.get(handle.key)?;
         resource.downcast_ref()
@@ -51,12 +122,263 @@ impl ResourceManager {
         &mut self,
         handle: &mut ResourceHandle<T>,
     ) -> Option<&mut T> {
-        let resource = self.resources.get_mut(handle.key)?;
-        resource.downcast_mut()
+        let resource = self.resources.get_mut(handle.key)?.downcast_mut()?;
+        // Borrowing mutably counts as a change, copies of the resource are refreshed
+        if let Some(version) = self.versions.entry(handle.key) {
+            *version.or_default() += 1;
+        }
+        Some(resource)
+    }
+    /// Whether the resource at `key` is still saved, it isn't once removed.
+    pub fn contains_key(&self, key: ResourceKey) -> bool {
+        self.resources.contains_key(key)
     }
     #[allow(clippy::needless_pass_by_value)]
     pub fn remove_resource<T: Resource>(&mut self, handle: ResourceHandle<T>) -> Option<Box<T>> {
         let resource = self.resources.remove(handle.key)?;
+        if let Some(path) = self.paths.remove(handle.key) {
+            self.keys_by_path.remove(&path);
+        }
+        self.sources.remove(handle.key);
+        self.versions.remove(handle.key);
         resource.downcast().ok()
     }
+
+    /// Loads the asset at `path`, relative to the asset directory, through `external_assets`.
+    ///
+    /// Loading the same path again returns the same handle.
+    pub fn load<T: AssetResource>(&mut self, path: &str) -> Result<ResourceHandle<T>, BoxedError> {
+        if self.key_at_path(path).is_some() {
+            return self
+                .handle_at_path(path)
+                .ok_or_else(|| format!("{path} is already loaded as another type").into());
+        }
+        let (id, extension) = asset_id(path)?;
+        // The cache reads the first extension of an asset it finds, so each extension is loaded as its own asset
+        match T::Asset::EXTENSIONS.iter().position(|&e| e == extension) {
+            Some(0) => self.load_with_extension::<T, 0>(path, id),
+            Some(1) => self.load_with_extension::<T, 1>(path, id),
+            Some(2) => self.load_with_extension::<T, 2>(path, id),
+            _ => Err(format!(
+                "{path} doesn't have one of the extensions {:?}",
+                T::Asset::EXTENSIONS
+            )
+            .into()),
+        }
+    }
+
+    fn load_with_extension<T: AssetResource, const N: usize>(
+        &mut self,
+        path: &str,
+        id: String,
+    ) -> Result<ResourceHandle<T>, BoxedError> {
+        let cache = Arc::clone(&self.external_assets);
+        let asset = cache.load::<WithExtension<T::Asset, N>>(&id)?;
+        let reload_id = asset.last_reload_id();
+        let resource = T::from_asset(&asset.read().0, self)?;
+        let handle = self.save_resource_at(path, resource);
+        self.sources.insert(
+            handle.key,
+            AssetSource {
+                id,
+                reload_id,
+                reload: reload_asset::<T, N>,
+            },
+        );
+        Ok(handle)
+    }
+
+    /// Reloads resources whose asset files changed on disk and returns their keys.
+    ///
+    /// Handles stay valid, if reloading fails the previous version of the resource is kept.
+    pub fn hot_reload(&mut self) -> Vec<ResourceKey> {
+        self.external_assets.hot_reload();
+        // Reloading needs the manager mutably, so the keys can't be borrowed from it
+        let keys: Vec<_> = self.sources.keys().collect();
+        let mut reloaded = vec![];
+        for key in keys {
+            let reload = self.sources[key].reload;
+            match reload(self, key) {
+                Ok(true) => reloaded.push(key),
+                Ok(false) => {}
+                Err(error) => tracing::warn!("Failed to reload {}: {error}", self.paths[key]),
+            }
+        }
+        reloaded
+    }
+
+    /// Incremented every time the resource is reloaded or borrowed mutably, so copies of it (e.g. on the GPU) can be refreshed.
+    pub fn version<T: Resource + ?Sized>(&self, handle: &ResourceHandle<T>) -> u32 {
+        self.versions.get(handle.key).copied().unwrap_or_default()
+    }
+}
+
+fn reload_asset<T: AssetResource, const N: usize>(
+    resources: &mut ResourceManager,
+    key: ResourceKey,
+) -> Result<bool, BoxedError> {
+    let cache = Arc::clone(&resources.external_assets);
+    let source = &mut resources.sources[key];
+    let Some(asset) = cache.get_cached::<WithExtension<T::Asset, N>>(&source.id) else {
+        return Ok(false);
+    };
+    if !source.reload_id.update(asset.last_reload_id()) {
+        return Ok(false);
+    }
+    let resource = T::from_asset(&asset.read().0, resources)?;
+    resources.resources[key] = Box::new(resource);
+    let version = resources.versions.get(key).copied().unwrap_or_default();
+    resources.versions.insert(key, version + 1);
+    Ok(true)
+}
+
+/// Splits a path like `textures/bricks.png` into the id `textures.bricks` used by `AssetCache` and its extension.
+///
+/// Ids separate directories with dots, so paths with other dots can't be loaded.
+fn asset_id(path: &str) -> Result<(String, &str), BoxedError> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default();
+    let components = Path::new(path)
+        .with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    if components.iter().any(|c| c.contains('.')) {
+        return Err(format!("{path} has a dot outside of its extension").into());
+    }
+    Ok((components.join("."), extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::{asset_id, ResourceManager};
+    use crate::{
+        core::{image::Image, material::StandardMaterial},
+        renderer::mesh::Mesh,
+    };
+
+    fn asset_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("iris_assets_{name}_{}", std::process::id()));
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]))
+            .save(dir.join("textures/red.png"))
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn paths_become_asset_ids() {
+        assert_eq!(
+            asset_id("textures/bricks.png").unwrap(),
+            ("textures.bricks".into(), "png"),
+            "Nested path"
+        );
+        assert_eq!(
+            asset_id("cube.obj").unwrap(),
+            ("cube".into(), "obj"),
+            "Top level path"
+        );
+        assert!(
+            asset_id("v1.2/cube.obj").is_err(),
+            "Dot in a directory name"
+        );
+    }
+
+    #[test]
+    fn loads_are_deduplicated_by_path() {
+        let mut resources = ResourceManager::from_directory(asset_dir("dedup")).unwrap();
+        let first = resources.load::<Image>("textures/red.png").unwrap();
+        let second = resources.load::<Image>("textures/red.png").unwrap();
+        assert!(first == second, "Same path gives the same handle");
+        let image = resources.load_resource(&first).unwrap();
+        assert_eq!(image.image.width(), 2, "Image was decoded");
+        assert_eq!(
+            resources.resource_path(&first),
+            Some("textures/red.png"),
+            "Path is remembered"
+        );
+        assert!(
+            resources.load::<Mesh>("textures/red.png").is_err(),
+            "Path is already an image"
+        );
+    }
+
+    #[test]
+    fn loads_the_requested_extension() {
+        let dir = asset_dir("extension");
+        RgbImage::from_pixel(4, 4, Rgb([0, 255, 0]))
+            .save(dir.join("textures/red.jpg"))
+            .unwrap();
+        let mut resources = ResourceManager::from_directory(dir).unwrap();
+        let png = resources.load::<Image>("textures/red.png").unwrap();
+        let jpg = resources.load::<Image>("textures/red.jpg").unwrap();
+        assert!(png != jpg, "Different files");
+        assert_eq!(
+            resources.load_resource(&png).unwrap().image.width(),
+            2,
+            "PNG read"
+        );
+        assert_eq!(
+            resources.load_resource(&jpg).unwrap().image.width(),
+            4,
+            "JPEG read, not the PNG next to it"
+        );
+        assert!(
+            resources.load::<Image>("textures/red.bmp").is_err(),
+            "Unsupported extension"
+        );
+
+        resources.load_resource_mut(&mut jpg.clone()).unwrap();
+        resources.remove_resource(jpg);
+        assert!(
+            !resources.versions.contains_key(jpg.key()) && !resources.paths.contains_key(jpg.key()),
+            "Removed resource forgotten"
+        );
+    }
+
+    #[test]
+    fn loads_meshes_and_materials() {
+        let dir = asset_dir("material");
+        fs::write(
+            dir.join("triangle.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("red.ron"),
+            r#"(
+                diffuse_texture: "textures/red.png",
+                diffuse_color: (r: 1.0, g: 1.0, b: 1.0),
+                normal_map: "textures/red.png",
+                specular: 0.5,
+                ior: 1.5,
+                roughness: 0.5,
+                ambient: (r: 0.1, g: 0.1, b: 0.1),
+            )"#,
+        )
+        .unwrap();
+        let mut resources = ResourceManager::from_directory(dir).unwrap();
+
+        let mesh = resources.load::<Mesh>("triangle.obj").unwrap();
+        let mesh = resources.load_resource(&mesh).unwrap();
+        assert_eq!(mesh.indices.len(), 3, "One triangle");
+
+        let material = resources.load::<StandardMaterial>("red.ron").unwrap();
+        let texture = resources.load::<Image>("textures/red.png").unwrap();
+        let material = resources.load_resource(&material).unwrap();
+        assert!(
+            material.diffuse_texture == texture && material.normal_map == texture,
+            "Textures are shared with other loads of the same path"
+        );
+        assert!(
+            resources.load::<Image>("missing.png").is_err(),
+            "No such file"
+        );
+    }
 }
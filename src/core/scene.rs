@@ -0,0 +1,581 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::renderer::light::Light;
+
+use super::{
+    component::Component,
+    entity::{Entity, EntityHierarchy, EntityKey},
+    material_renderer::MaterialRenderer,
+    mesh_renderer::MeshRenderer,
+    resources::{ResourceHandle, ResourceManager},
+    transform::Transform,
+};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    /// Parse errors keep their line and column, serialization errors have none
+    Ron(ron::error::SpannedError),
+    Json(serde_json::Error),
+    UnsupportedFormat(String),
+    UnknownComponent(String),
+    InvalidComponent {
+        name: String,
+        error: String,
+    },
+    MissingResource(String),
+    /// Component referencing a resource that wasn't loaded from a path, so it can't be saved
+    UnsavedResource {
+        entity: usize,
+        component: String,
+    },
+    InvalidParent {
+        entity: usize,
+        parent: usize,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Io(ref e) => write!(f, "Scene IO error: {e}"),
+            Self::Ron(ref e) => write!(f, "Invalid RON scene: {e}"),
+            Self::Json(ref e) => write!(f, "Invalid JSON scene: {e}"),
+            Self::UnsupportedFormat(ref extension) => {
+                write!(
+                    f,
+                    "Unsupported scene format {extension:?}, use .ron or .json"
+                )
+            }
+            Self::UnknownComponent(ref name) => write!(f, "Component {name} is not registered"),
+            Self::InvalidComponent {
+                ref name,
+                ref error,
+            } => write!(f, "Invalid {name} component: {error}"),
+            Self::MissingResource(ref path) => write!(f, "No resource loaded from {path}"),
+            Self::UnsavedResource {
+                entity,
+                ref component,
+            } => write!(
+                f,
+                "{component} of entity {entity} references a resource without a path"
+            ),
+            Self::InvalidParent { entity, parent } => {
+                write!(f, "Entity {entity} has invalid parent {parent}")
+            }
+        }
+    }
+}
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<ron::Error> for SceneError {
+    fn from(value: ron::Error) -> Self {
+        Self::Ron(ron::error::SpannedError {
+            code: value,
+            position: ron::error::Position { line: 0, col: 0 },
+        })
+    }
+}
+impl From<ron::error::SpannedError> for SceneError {
+    fn from(value: ron::error::SpannedError) -> Self {
+        Self::Ron(value)
+    }
+}
+impl From<serde_json::Error> for SceneError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+/// A component that references resources, saved through a serializable stand-in.
+pub trait SceneComponent: Component + Sized {
+    type Data: Serialize + DeserializeOwned;
+    /// `None` if a resource it references has no path to be saved as
+    fn to_data(&self, resources: &ResourceManager) -> Option<Self::Data>;
+    fn from_data(data: Self::Data, resources: &mut ResourceManager) -> Result<Self, SceneError>;
+}
+
+impl SceneComponent for MaterialRenderer {
+    /// Asset path of the material
+    type Data = String;
+    fn to_data(&self, resources: &ResourceManager) -> Option<Self::Data> {
+        resources.resource_path(&self.material).map(Into::into)
+    }
+    fn from_data(data: Self::Data, resources: &mut ResourceManager) -> Result<Self, SceneError> {
+        let key = resources
+            .key_at_path(&data)
+            .ok_or(SceneError::MissingResource(data))?;
+        Ok(Self {
+            material: ResourceHandle::from_key(key),
+        })
+    }
+}
+
+impl SceneComponent for MeshRenderer {
+    /// Asset path of the mesh
+    type Data = String;
+    fn to_data(&self, resources: &ResourceManager) -> Option<Self::Data> {
+        resources.resource_path(&self.mesh).map(Into::into)
+    }
+    fn from_data(data: Self::Data, resources: &mut ResourceManager) -> Result<Self, SceneError> {
+        let mesh = resources
+            .handle_at_path(&data)
+            .ok_or(SceneError::MissingResource(data))?;
+        Ok(Self { mesh })
+    }
+}
+
+/// `None` if the entity doesn't have the component, `Some(Ok(None))` if it can't be saved, see [`SceneComponent::to_data`]
+type SerializeFn = fn(&Entity, &ResourceManager) -> Option<Result<Option<Value>, SceneError>>;
+type DeserializeFn = fn(Value, &mut Entity, &mut ResourceManager) -> Result<(), SceneError>;
+
+struct ComponentRegistration {
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+}
+
+/// Components that can be saved in a [`Scene`], keyed by the name they are saved under.
+pub struct ComponentRegistry {
+    registrations: BTreeMap<String, ComponentRegistration>,
+}
+
+impl Default for ComponentRegistry {
+    /// Registry with the engine components already registered.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register::<Transform>("Transform");
+        registry.register::<Light>("Light");
+        registry.register_scene_component::<MaterialRenderer>("MaterialRenderer");
+        registry.register_scene_component::<MeshRenderer>("MeshRenderer");
+        registry
+    }
+}
+
+impl ComponentRegistry {
+    pub const fn empty() -> Self {
+        Self {
+            registrations: BTreeMap::new(),
+        }
+    }
+    pub fn register<T: Component + Serialize + DeserializeOwned>(&mut self, name: &str) {
+        self.registrations.insert(
+            name.into(),
+            ComponentRegistration {
+                serialize: |entity, _| {
+                    let component = entity.get_component::<T>()?;
+                    Some(
+                        serde_json::to_value(component)
+                            .map(Some)
+                            .map_err(Into::into),
+                    )
+                },
+                deserialize: |value, entity, _| {
+                    entity.add_component(serde_json::from_value::<T>(value)?);
+                    Ok(())
+                },
+            },
+        );
+    }
+    pub fn register_scene_component<T: SceneComponent>(&mut self, name: &str) {
+        self.registrations.insert(
+            name.into(),
+            ComponentRegistration {
+                serialize: |entity, resources| {
+                    let data = entity.get_component::<T>()?.to_data(resources);
+                    Some(
+                        data.map(serde_json::to_value)
+                            .transpose()
+                            .map_err(Into::into),
+                    )
+                },
+                deserialize: |value, entity, resources| {
+                    let data = serde_json::from_value(value)?;
+                    entity.add_component(T::from_data(data, resources)?);
+                    Ok(())
+                },
+            },
+        );
+    }
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.registrations.contains_key(name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SceneEntity {
+    pub name: String,
+    /// Index of the parent in [`Scene::entities`], always before this entity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, Value>,
+}
+
+/// Serializable snapshot of an [`EntityHierarchy`].
+///
+/// Only registered components are saved, resources are saved as the asset path they were loaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scene {
+    pub entities: Vec<SceneEntity>,
+}
+
+impl Scene {
+    pub fn from_hierarchy(
+        hierarchy: &EntityHierarchy,
+        registry: &ComponentRegistry,
+        resources: &ResourceManager,
+    ) -> Result<Self, SceneError> {
+        let mut entities = Vec::with_capacity(hierarchy.entities.len());
+        // Depth first so parents are always saved before their children
+        let mut stack: Vec<(EntityKey, Option<usize>)> =
+            hierarchy.roots().map(|key| (key, None)).collect();
+        stack.reverse();
+        while let Some((key, parent)) = stack.pop() {
+            let entity = &hierarchy.entities[key];
+            let index = entities.len();
+            let mut components = BTreeMap::new();
+            for (name, registration) in &registry.registrations {
+                if let Some(value) = (registration.serialize)(entity, resources) {
+                    let value = value?.ok_or_else(|| SceneError::UnsavedResource {
+                        entity: index,
+                        component: name.clone(),
+                    })?;
+                    components.insert(name.clone(), value);
+                }
+            }
+            entities.push(SceneEntity {
+                name: entity.name.clone(),
+                parent,
+                components,
+            });
+            stack.extend(
+                entity
+                    .children()
+                    .iter()
+                    .rev()
+                    .map(|&child| (child, Some(index))),
+            );
+        }
+        Ok(Self { entities })
+    }
+
+    /// Adds the entities of the scene to `hierarchy`, returning their keys in scene order.
+    ///
+    /// Nothing is added if any entity fails to load.
+    pub fn spawn(
+        &self,
+        hierarchy: &mut EntityHierarchy,
+        registry: &ComponentRegistry,
+        resources: &mut ResourceManager,
+    ) -> Result<Vec<EntityKey>, SceneError> {
+        let mut entities = Vec::with_capacity(self.entities.len());
+        for (index, scene_entity) in self.entities.iter().enumerate() {
+            if let Some(parent) = scene_entity.parent.filter(|&parent| parent >= index) {
+                return Err(SceneError::InvalidParent {
+                    entity: index,
+                    parent,
+                });
+            }
+            let mut entity = Entity::new(scene_entity.name.clone());
+            for (name, value) in &scene_entity.components {
+                let registration = registry
+                    .registrations
+                    .get(name)
+                    .ok_or_else(|| SceneError::UnknownComponent(name.clone()))?;
+                (registration.deserialize)(value.clone(), &mut entity, resources).map_err(
+                    |error| match error {
+                        SceneError::Json(error) => SceneError::InvalidComponent {
+                            name: name.clone(),
+                            error: error.to_string(),
+                        },
+                        error => error,
+                    },
+                )?;
+            }
+            entities.push(entity);
+        }
+
+        let mut keys: Vec<EntityKey> = Vec::with_capacity(entities.len());
+        for (entity, scene_entity) in entities.into_iter().zip(&self.entities) {
+            let key = hierarchy.entities.insert(entity);
+            if let Some(parent) = scene_entity.parent {
+                hierarchy.set_parent(key, Some(keys[parent]));
+            }
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    pub fn to_ron(&self) -> Result<String, SceneError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+    pub fn from_ron(source: &str) -> Result<Self, SceneError> {
+        Ok(ron::from_str(source)?)
+    }
+    pub fn to_json(&self) -> Result<String, SceneError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    pub fn from_json(source: &str) -> Result<Self, SceneError> {
+        Ok(serde_json::from_str(source)?)
+    }
+
+    /// Saves the scene as RON or JSON depending on the extension of `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        let contents = match SceneFormat::from_path(path)? {
+            SceneFormat::Ron => self.to_ron()?,
+            SceneFormat::Json => self.to_json()?,
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+    /// Loads a RON or JSON scene depending on the extension of `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let format = SceneFormat::from_path(path)?;
+        let source = fs::read_to_string(path)?;
+        match format {
+            SceneFormat::Ron => Self::from_ron(&source),
+            SceneFormat::Json => Self::from_json(&source),
+        }
+    }
+}
+
+enum SceneFormat {
+    Ron,
+    Json,
+}
+
+impl SceneFormat {
+    fn from_path(path: &Path) -> Result<Self, SceneError> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "ron" => Ok(Self::Ron),
+            "json" => Ok(Self::Json),
+            _ => Err(SceneError::UnsupportedFormat(extension)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+    use serde::{Deserialize, Serialize};
+
+    use super::{ComponentRegistry, Scene, SceneEntity, SceneError};
+    use crate::{
+        core::{
+            component::Component,
+            entity::EntityHierarchy,
+            material::Material,
+            material_renderer::MaterialRenderer,
+            mesh_renderer::MeshRenderer,
+            resources::{ResourceHandle, ResourceManager},
+            transform::Transform,
+        },
+        renderer::{
+            color::Color,
+            light::{Light, PointLight},
+            mesh::Mesh,
+        },
+    };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+    impl Component for Health {}
+
+    struct TestMaterial;
+    impl Material for TestMaterial {}
+
+    fn resources() -> ResourceManager {
+        ResourceManager::from_directory(std::env::temp_dir()).unwrap()
+    }
+
+    fn hierarchy(resources: &mut ResourceManager) -> EntityHierarchy {
+        let material = resources.save_resource_at("materials/test.ron", TestMaterial);
+        let mut hierarchy = EntityHierarchy::new();
+        let root = hierarchy.add_entity("root".into());
+        hierarchy.entities[root].add_component(Transform::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Quat::from_rotation_y(0.5),
+            Vec3::splat(2.0),
+        ));
+        hierarchy.entities[root].add_component(Health(7));
+        let child = hierarchy.add_child_entity(root, "child".into());
+        hierarchy.entities[child].new_component::<Transform>();
+        hierarchy.entities[child].add_component(MaterialRenderer {
+            material: ResourceHandle::<dyn Material>::from_key(material.key()),
+        });
+        let light = hierarchy.add_entity("light".into());
+        hierarchy.entities[light].add_component(Light::from(PointLight::new(
+            Color::WHITE,
+            Vec3::Y,
+            10.0,
+            [0.0, 0.0, 1.0],
+        )));
+        hierarchy
+    }
+
+    fn registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::default();
+        registry.register::<Health>("Health");
+        registry
+    }
+
+    fn round_trip(to: fn(&Scene) -> String, from: fn(&str) -> Result<Scene, SceneError>) {
+        let mut resources = resources();
+        let registry = registry();
+        let scene =
+            Scene::from_hierarchy(&hierarchy(&mut resources), &registry, &resources).unwrap();
+        let loaded = from(&to(&scene)).unwrap();
+        assert_eq!(loaded, scene, "Scene should round trip");
+
+        let mut hierarchy = EntityHierarchy::new();
+        let keys = loaded
+            .spawn(&mut hierarchy, &registry, &mut resources)
+            .unwrap();
+        assert_eq!(keys.len(), 3, "All entities spawned");
+        let (root, child) = (keys[0], keys[1]);
+        assert_eq!(hierarchy.parent(child), Some(root), "Hierarchy restored");
+        let transform = hierarchy.entities[root]
+            .get_component::<Transform>()
+            .unwrap();
+        assert_eq!(
+            transform.position,
+            Vec3::new(1.0, 2.0, 3.0),
+            "Transform restored"
+        );
+        assert_eq!(
+            hierarchy.entities[root].get_component::<Health>(),
+            Some(&Health(7)),
+            "User component restored"
+        );
+        let renderer = hierarchy.entities[child]
+            .get_component::<MaterialRenderer>()
+            .unwrap();
+        assert_eq!(
+            resources.resource_path(&renderer.material),
+            Some("materials/test.ron"),
+            "Material restored from its path"
+        );
+        assert!(
+            hierarchy.entities[keys[2]].has_component::<Light>(),
+            "Light restored"
+        );
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        round_trip(|scene| scene.to_ron().unwrap(), Scene::from_ron);
+    }
+    #[test]
+    fn json_round_trip() {
+        round_trip(|scene| scene.to_json().unwrap(), Scene::from_json);
+    }
+
+    #[test]
+    fn unknown_components_are_errors() {
+        let mut resources = resources();
+        let scene =
+            Scene::from_hierarchy(&hierarchy(&mut resources), &registry(), &resources).unwrap();
+        let mut hierarchy = EntityHierarchy::new();
+        let result = scene.spawn(
+            &mut hierarchy,
+            &ComponentRegistry::default(),
+            &mut resources,
+        );
+        assert!(
+            matches!(result, Err(SceneError::UnknownComponent(ref name)) if name == "Health"),
+            "Health is not registered"
+        );
+        assert!(hierarchy.entities.is_empty(), "Nothing spawned");
+    }
+
+    #[test]
+    fn invalid_scenes_spawn_nothing() {
+        let mut scene = Scene::default();
+        scene.entities.push(SceneEntity {
+            name: "root".into(),
+            ..Default::default()
+        });
+        scene.entities.push(SceneEntity {
+            name: "child".into(),
+            parent: Some(2),
+            ..Default::default()
+        });
+        let mut hierarchy = EntityHierarchy::new();
+        let result = scene.spawn(&mut hierarchy, &registry(), &mut resources());
+        assert!(
+            matches!(
+                result,
+                Err(SceneError::InvalidParent {
+                    entity: 1,
+                    parent: 2
+                })
+            ),
+            "Parent after its child"
+        );
+        assert!(hierarchy.entities.is_empty(), "Root not spawned");
+    }
+
+    #[test]
+    fn ron_errors_keep_their_position() {
+        let error =
+            Scene::from_ron("(\n    entities: [\n        (name: 1),\n    ],\n)").unwrap_err();
+        assert!(
+            matches!(error, SceneError::Ron(ref error) if error.position.line == 3),
+            "{error:?}"
+        );
+        assert!(error.to_string().contains("3:"), "{error}");
+    }
+
+    #[test]
+    fn missing_resources_are_errors() {
+        let mut resources = resources();
+        let scene =
+            Scene::from_hierarchy(&hierarchy(&mut resources), &registry(), &resources).unwrap();
+        let result = scene.spawn(
+            &mut EntityHierarchy::new(),
+            &registry(),
+            &mut self::resources(),
+        );
+        assert!(
+            matches!(result, Err(SceneError::MissingResource(_))),
+            "Material was not loaded"
+        );
+    }
+
+    #[test]
+    fn resources_without_a_path_are_errors() {
+        let mut resources = resources();
+        let mut hierarchy = hierarchy(&mut resources);
+        let mesh = resources.save_resource(Mesh::new(vec![], vec![]));
+        let light = hierarchy
+            .roots()
+            .find(|&key| hierarchy.entities[key].name == "light")
+            .unwrap();
+        hierarchy.entities[light].add_component(MeshRenderer { mesh });
+        let result = Scene::from_hierarchy(&hierarchy, &registry(), &resources);
+        assert!(
+            matches!(
+                result,
+                Err(SceneError::UnsavedResource { entity: 2, ref component }) if component == "MeshRenderer"
+            ),
+            "{result:?}"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use glam::{IVec2, IVec3, IVec4, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+pub use iris_engine_derive::ShaderType;
+
+/// Alignment and size of a type in WGSL, see the memory layout section of the WGSL spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeLayout {
+    pub align: usize,
+    pub size: usize,
+    /// Structs have to start at a multiple of 16 bytes in uniform buffers
+    pub is_struct: bool,
+    /// Whether the type follows the rules of uniform buffers, where array strides are multiples of 16
+    /// and struct members of struct type take a multiple of 16 bytes
+    pub uniform: bool,
+}
+
+impl TypeLayout {
+    pub const fn new(align: usize, size: usize) -> Self {
+        Self {
+            align,
+            size,
+            is_struct: false,
+            uniform: true,
+        }
+    }
+
+    /// Bytes between the elements of an array of the type
+    pub const fn stride(self) -> usize {
+        round_up(self.size, self.align)
+    }
+
+    /// Layout of `array<T, count>`
+    pub const fn array(self, count: usize) -> Self {
+        Self {
+            align: self.align,
+            size: self.stride() * count,
+            is_struct: false,
+            uniform: self.uniform && self.stride().is_multiple_of(16),
+        }
+    }
+
+    /// Layout of a struct with `members` in declaration order
+    pub const fn structure(members: &[Self]) -> Self {
+        let mut align = 1;
+        let mut end = 0;
+        let mut uniform = true;
+        let mut i = 0;
+        while i < members.len() {
+            let member = members[i];
+            let offset = round_up(end, member.align);
+            if member.align > align {
+                align = member.align;
+            }
+            uniform &= member.uniform;
+            if member.is_struct {
+                uniform &= offset.is_multiple_of(16);
+            }
+            // The next member starts after the padding of the struct member to 16 bytes
+            if i > 0 && members[i - 1].is_struct {
+                uniform &= offset.is_multiple_of(16);
+            }
+            end = offset + member.size;
+            i += 1;
+        }
+        Self {
+            align,
+            size: round_up(end, align),
+            is_struct: true,
+            uniform,
+        }
+    }
+}
+
+pub const fn round_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+/// Offsets of struct `members` in declaration order, each aligned after the end of the previous one.
+pub const fn member_offsets<const N: usize>(members: [TypeLayout; N]) -> [usize; N] {
+    let mut offsets = [0; N];
+    let mut end = 0;
+    let mut i = 0;
+    while i < N {
+        offsets[i] = round_up(end, members[i].align);
+        end = offsets[i] + members[i].size;
+        i += 1;
+    }
+    offsets
+}
+
+/// A type with a WGSL memory layout, written to buffers with the padding shaders expect.
+///
+/// Derive it for structs, member offsets and padding are computed from the fields. On `#[repr(C)]`
+/// structs, which are also uploaded with `bytemuck`, the derive checks at compile time that every field is
+/// already where WGSL expects it.
+pub trait ShaderType {
+    const LAYOUT: TypeLayout;
+    /// Names and layouts of the members of a struct, in declaration order. Empty for other types.
+    const MEMBERS: &'static [(&'static str, TypeLayout)] = &[];
+
+    /// Writes the value at the start of `bytes`, which holds at least [`TypeLayout::size`] bytes.
+    /// Padding is left untouched.
+    fn write(&self, bytes: &mut [u8]);
+
+    /// The value as the bytes of a buffer, with zeroed padding
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::LAYOUT.size];
+        self.write(&mut bytes);
+        bytes
+    }
+}
+
+/// Bytes of `data` as an `array<T>`, element `i` starts at `i * stride`.
+pub fn array_bytes<T: ShaderType>(data: &[T]) -> Vec<u8> {
+    let stride = T::LAYOUT.stride();
+    let mut bytes = vec![0; stride * data.len()];
+    for (element, bytes) in data.iter().zip(bytes.chunks_exact_mut(stride)) {
+        element.write(bytes);
+    }
+    bytes
+}
+
+macro_rules! impl_pod {
+    ($($ty:ty => ($align:expr, $size:expr)),* $(,)?) => {
+        $(
+            impl ShaderType for $ty {
+                const LAYOUT: TypeLayout = TypeLayout::new($align, $size);
+
+                fn write(&self, bytes: &mut [u8]) {
+                    bytes[..$size].copy_from_slice(bytemuck::bytes_of(self));
+                }
+            }
+        )*
+    };
+}
+
+impl_pod!(
+    f32 => (4, 4),
+    u32 => (4, 4),
+    i32 => (4, 4),
+    Vec2 => (8, 8),
+    UVec2 => (8, 8),
+    IVec2 => (8, 8),
+    Vec3 => (16, 12),
+    UVec3 => (16, 12),
+    IVec3 => (16, 12),
+    Vec4 => (16, 16),
+    UVec4 => (16, 16),
+    IVec4 => (16, 16),
+    Mat4 => (16, 64),
+);
+
+/// Columns are `vec3f`, padded to 16 bytes
+impl ShaderType for Mat3 {
+    const LAYOUT: TypeLayout = TypeLayout::new(16, 48);
+
+    fn write(&self, bytes: &mut [u8]) {
+        for (column, bytes) in self.to_cols_array_2d().iter().zip(bytes.chunks_mut(16)) {
+            bytes[..12].copy_from_slice(bytemuck::cast_slice(column));
+        }
+    }
+}
+
+impl<T: ShaderType, const N: usize> ShaderType for [T; N] {
+    const LAYOUT: TypeLayout = T::LAYOUT.array(N);
+
+    fn write(&self, bytes: &mut [u8]) {
+        let stride = T::LAYOUT.stride();
+        for (element, bytes) in self.iter().zip(bytes.chunks_mut(stride)) {
+            element.write(bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat3, Vec3, Vec4};
+
+    use super::{array_bytes, member_offsets, ShaderType, TypeLayout};
+
+    #[derive(ShaderType)]
+    struct Inner {
+        direction: Vec3,
+        intensity: f32,
+        range: f32,
+    }
+
+    #[derive(ShaderType)]
+    struct Outer {
+        scale: f32,
+        inner: Inner,
+        planes: [Vec4; 2],
+        count: u32,
+    }
+
+    #[test]
+    fn layouts_follow_the_wgsl_rules() {
+        // vec3f is aligned to 16 bytes but the next scalar fills its last 4
+        assert_eq!(
+            member_offsets([Vec3::LAYOUT, f32::LAYOUT, f32::LAYOUT]),
+            [0, 12, 16]
+        );
+        assert_eq!(Inner::LAYOUT.align, 16);
+        assert_eq!(Inner::LAYOUT.size, 32, "Rounded up to the alignment");
+        assert_eq!(
+            member_offsets([f32::LAYOUT, Inner::LAYOUT, <[Vec4; 2]>::LAYOUT, u32::LAYOUT]),
+            [0, 16, 48, 80]
+        );
+        assert_eq!(Outer::LAYOUT.size, 96);
+        assert!(Outer::LAYOUT.uniform);
+        assert!(
+            !<[u32; 3]>::LAYOUT.uniform,
+            "Array strides of uniform buffers are multiples of 16"
+        );
+        let scalar = TypeLayout::structure(&[f32::LAYOUT]);
+        assert!(
+            !TypeLayout::structure(&[scalar, f32::LAYOUT]).uniform,
+            "A struct member of a uniform buffer struct takes a multiple of 16 bytes"
+        );
+    }
+
+    #[test]
+    fn padding_is_written_as_zeroes() {
+        let outer = Outer {
+            scale: 2.0,
+            inner: Inner {
+                direction: Vec3::new(1.0, 2.0, 3.0),
+                intensity: 4.0,
+                range: 5.0,
+            },
+            planes: [Vec4::ONE, Vec4::splat(6.0)],
+            count: 7,
+        };
+        let floats: Vec<f32> = bytemuck::cast_slice(&outer.to_bytes()).to_vec();
+        #[rustfmt::skip]
+        assert_eq!(floats, [
+            2.0, 0.0, 0.0, 0.0,
+            1.0, 2.0, 3.0, 4.0,
+            5.0, 0.0, 0.0, 0.0,
+            1.0, 1.0, 1.0, 1.0,
+            6.0, 6.0, 6.0, 6.0,
+            f32::from_bits(7), 0.0, 0.0, 0.0,
+        ]);
+
+        let columns: Vec<f32> = bytemuck::cast_slice(&Mat3::IDENTITY.to_bytes()).to_vec();
+        assert_eq!(
+            columns,
+            [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        );
+        let elements = array_bytes(&[Vec3::X, Vec3::Y]);
+        assert_eq!(elements.len(), 32, "Elements are a stride apart");
+        assert_eq!(&elements[16..20], bytemuck::bytes_of(&0.0_f32));
+        assert_eq!(&elements[20..24], bytemuck::bytes_of(&1.0_f32));
+    }
+}
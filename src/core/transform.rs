@@ -1,34 +1,117 @@
-use glam::{Affine3A, Quat, Vec3};
+use glam::{Affine3A, Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
 
 use crate::renderer::gui::{quat_edit, vec3_edit};
 
 use super::component::Component;
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "LocalTransform", into = "LocalTransform")]
 pub struct Transform {
     pub position: Vec3,
     pub rotation: Quat,
     pub scale: Vec3,
     global_transform: Affine3A,
+    // Local transform the global transform was last computed from, to detect edits of the public fields
+    last_local: Affine3A,
+    dirty: bool,
+}
+
+// Only the local part is saved, the global transform is recomputed from the hierarchy
+#[derive(Serialize, Deserialize)]
+struct LocalTransform {
+    position: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+}
+impl From<LocalTransform> for Transform {
+    fn from(value: LocalTransform) -> Self {
+        Self::new(value.position, value.rotation, value.scale)
+    }
+}
+impl From<Transform> for LocalTransform {
+    fn from(value: Transform) -> Self {
+        Self {
+            position: value.position,
+            rotation: value.rotation,
+            scale: value.scale,
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
 }
 
 impl Transform {
+    pub const IDENTITY: Self = Self {
+        position: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+        global_transform: Affine3A::IDENTITY,
+        last_local: Affine3A::IDENTITY,
+        dirty: true,
+    };
+
     pub fn new(position: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        let local_transform = Affine3A::from_scale_rotation_translation(scale, rotation, position);
         Self {
             position,
             rotation,
             scale,
-            global_transform: Affine3A::from_scale_rotation_translation(scale, rotation, position),
+            global_transform: local_transform,
+            last_local: local_transform,
+            dirty: true,
         }
     }
 
+    pub fn from_affine(transform: Affine3A) -> Self {
+        let (scale, rotation, position) = transform.to_scale_rotation_translation();
+        Self::new(position, rotation, scale)
+    }
+
     pub fn update_global_transform(&mut self, parent: Self) {
-        self.global_transform = parent.global_transform * self.local_transform();
+        self.update_global_transform_from(parent.global_transform);
+    }
+
+    pub(crate) fn update_global_transform_from(&mut self, parent_global_transform: Affine3A) {
+        let local_transform = self.local_transform();
+        self.global_transform = parent_global_transform * local_transform;
+        self.last_local = local_transform;
+        self.dirty = false;
     }
 
     pub fn local_transform(&self) -> Affine3A {
         Affine3A::from_scale_rotation_translation(self.scale, self.rotation, self.position)
     }
+
+    /// World space transform, valid after the last `EntityHierarchy::update_transform_hierarchies`.
+    pub const fn global_transform(&self) -> Affine3A {
+        self.global_transform
+    }
+
+    pub fn world_matrix(&self) -> Mat4 {
+        Mat4::from(self.global_transform)
+    }
+
+    /// Whether the global transform needs to be recomputed.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty || self.local_transform() != self.last_local
+    }
+
+    pub const fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn set_local_transform(&mut self, transform: Affine3A) {
+        let (scale, rotation, position) = transform.to_scale_rotation_translation();
+        self.position = position;
+        self.rotation = rotation;
+        self.scale = scale;
+        self.dirty = true;
+    }
 }
 
 impl Component for Transform {
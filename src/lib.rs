@@ -1,22 +1,27 @@
-use bytemuck::{Pod, Zeroable};
+use crate::core::shader_type::ShaderType;
+
+// Lets the derive macros name `::iris_engine` paths inside the crate too
+extern crate self as iris_engine;
 
 pub mod collision;
 pub mod core;
 pub mod renderer;
 
+#[cfg(test)]
 pub(crate) mod tests;
 pub mod visibility;
 
+/// Converts to the type uploaded to the GPU, whose [`ShaderType`] layout matches the shaders.
 pub trait GpuSendable<T>
 where
-    T: Clone + Copy + Pod + Zeroable,
+    T: ShaderType,
 {
     fn to_gpu(&self) -> T;
 }
 
 impl<T> GpuSendable<T> for T
 where
-    T: Clone + Copy + Pod + Zeroable,
+    T: Clone + Copy + ShaderType,
 {
     fn to_gpu(&self) -> T {
         *self
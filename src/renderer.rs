@@ -1,16 +1,30 @@
 pub mod app;
+pub mod batch;
 pub mod bind_group;
 pub mod buffer;
 pub mod camera;
+pub mod cluster;
 pub mod color;
 pub mod compute;
+pub mod culling;
+pub mod deferred;
 pub mod egui_renderer;
+pub mod environment;
+pub mod gltf_import;
+pub mod golden;
 pub mod gui;
 pub mod light;
 pub mod material;
 pub mod mesh;
 pub mod model;
+pub mod obj_import;
+pub mod post_process;
+pub mod reflection;
+pub mod render_graph;
 pub mod render_pipeline;
 pub mod resources;
+pub mod scene_renderer;
+pub mod shader;
+pub mod shadow;
 pub mod texture;
 pub mod wgpu_renderer;
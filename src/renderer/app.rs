@@ -1,6 +1,7 @@
-use std::{result::Result, sync::Arc, time::Instant};
+use std::{path::Path, result::Result, sync::Arc, time::Instant};
 
 use egui_wgpu::ScreenDescriptor;
+use image::{ImageResult, RgbaImage};
 use winit::{
     error::EventLoopError,
     event::{Event, KeyEvent, WindowEvent},
@@ -9,7 +10,18 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-use super::{egui_renderer::EguiRenderer, wgpu_renderer::Renderer};
+use crate::core::{
+    render_assets::RenderAssets,
+    resources::{ResourceKey, ResourceManager},
+};
+
+use super::{
+    egui_renderer::EguiRenderer,
+    gui::shader_errors_window,
+    render_graph::RenderGraph,
+    shader::ShaderWatcher,
+    wgpu_renderer::{Renderer, RendererSettings},
+};
 
 pub trait App: Sized {
     const SRGB: bool = true;
@@ -29,6 +41,11 @@ pub trait App: Sized {
         }
     }
 
+    /// Checked against the adapter, see [`Renderer::settings`].
+    fn renderer_settings() -> RendererSettings {
+        RendererSettings::default()
+    }
+
     fn required_limits() -> wgpu::Limits {
         wgpu::Limits::downlevel_defaults() // These downlevel limits will allow the code to run on all possible hardware
     }
@@ -39,7 +56,20 @@ pub trait App: Sized {
     fn gui_register(&mut self, _egui_renderer: &mut EguiRenderer, _renderer: &mut Renderer) {}
     fn resize(&mut self, _renderer: &mut Renderer) {}
     fn input(&mut self, _event: WindowEvent, _renderer: &mut Renderer) {}
-    fn render(&mut self, _view: &wgpu::TextureView, _renderer: &mut Renderer) {}
+    /// A shader changed on disk while hot reloading, see [`RendererSettings::hot_reload`].
+    /// Pipelines built from it should be rebuilt, like with [`Batcher::reload_pipelines`](super::batch::Batcher::reload_pipelines).
+    fn shaders_changed(&mut self, _renderer: &Renderer) {}
+    /// The resources of the app and their GPU copies. Copies of removed resources are evicted before each frame.
+    /// While hot reloading, resources whose files change are also reloaded and their images and meshes uploaded again.
+    fn resources(&mut self) -> Option<(&mut ResourceManager, &mut RenderAssets)> {
+        None
+    }
+    /// Resources were reloaded from disk, see [`App::resources`].
+    fn resources_changed(&mut self, _keys: &[ResourceKey], _renderer: &Renderer) {}
+    /// Adds the passes of the frame to `graph`, whose [`RenderGraph::SURFACE`] is presented.
+    ///
+    /// The gui of the frame has already run, its pass is added after these.
+    fn render<'a>(&'a mut self, _graph: &mut RenderGraph<'a>, _renderer: &Renderer) {}
 }
 
 struct FrameCounter {
@@ -80,12 +110,18 @@ struct AppHandler<A: App> {
     frame_counter: FrameCounter,
     egui_renderer: EguiRenderer,
     scale_factor: f32,
+    shader_watcher: Option<ShaderWatcher>,
 }
 
 impl<A: App> AppHandler<A> {
     fn new(mut renderer: Renderer, window: Arc<Window>) -> Self {
         let egui_renderer =
             EguiRenderer::new(&renderer.device, renderer.config.format, None, 1, &window);
+        // Before the app builds its pipelines, so they already use the sources on disk
+        let shader_watcher = renderer
+            .settings
+            .hot_reload
+            .then(|| ShaderWatcher::new(ShaderWatcher::ENGINE_DIR));
         Self {
             app: A::init(&mut renderer),
             renderer,
@@ -93,6 +129,7 @@ impl<A: App> AppHandler<A> {
             frame_counter: FrameCounter::new(),
             egui_renderer,
             scale_factor: 1.0,
+            shader_watcher,
         }
     }
 }
@@ -156,6 +193,24 @@ impl<A: App> AppHandler<A> {
 
                     WindowEvent::RedrawRequested => {
                         self.frame_counter.update();
+                        if let Some(ref mut watcher) = self.shader_watcher {
+                            if watcher.poll() {
+                                self.app.shaders_changed(&self.renderer);
+                            }
+                        }
+                        if let Some((resources, assets)) = self.app.resources() {
+                            assets.evict(resources);
+                            if self.renderer.settings.hot_reload {
+                                let reloaded = assets.hot_reload(
+                                    resources,
+                                    &self.renderer.device,
+                                    &self.renderer.queue,
+                                );
+                                if !reloaded.is_empty() {
+                                    self.app.resources_changed(&reloaded, &self.renderer);
+                                }
+                            }
+                        }
 
                         let frame = self.renderer.acquire();
                         let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
@@ -165,11 +220,6 @@ impl<A: App> AppHandler<A> {
                         self.app
                             .gui_register(&mut self.egui_renderer, &mut self.renderer);
 
-                        self.app.render(&view, &mut self.renderer);
-
-                        let mut encoder = self.renderer.device.create_command_encoder(
-                            &wgpu::CommandEncoderDescriptor { label: None },
-                        );
                         let screen_descriptor = ScreenDescriptor {
                             size_in_pixels: [
                                 self.renderer.config.width,
@@ -177,17 +227,24 @@ impl<A: App> AppHandler<A> {
                             ],
                             pixels_per_point: self.window.scale_factor() as f32 * self.scale_factor,
                         };
-
-                        self.egui_renderer.draw(
+                        self.egui_renderer.prepare(
                             &self.renderer.device,
                             &self.renderer.queue,
-                            &mut encoder,
                             &self.window,
-                            &view,
-                            &screen_descriptor,
-                            |ctx| self.app.gui(ctx, &self.renderer),
+                            screen_descriptor,
+                            |ctx| {
+                                self.app.gui(ctx, &self.renderer);
+                                if let Some(ref watcher) = self.shader_watcher {
+                                    shader_errors_window(ctx, &watcher.errors());
+                                }
+                            },
                         );
-                        self.renderer.queue.submit(Some(encoder.finish()));
+
+                        let mut graph = self.renderer.graph();
+                        self.app.render(&mut graph, &self.renderer);
+                        self.egui_renderer
+                            .add_to_graph(&mut graph, RenderGraph::SURFACE);
+                        self.renderer.execute(graph, &view);
                         frame.present();
 
                         self.window.request_redraw();
@@ -221,3 +278,52 @@ async fn start<A: App>() -> Result<(), EventLoopError> {
 pub fn run<A: App>() -> Result<(), EventLoopError> {
     pollster::block_on(start::<A>())
 }
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeadlessSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Number of frames rendered before the output is read back.
+    pub frames: u32,
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for HeadlessSettings {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            frames: 1,
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+/// Drives an [`App`] for `settings.frames` frames without a window and returns the last frame.
+///
+/// Returns `None` if no adapter is available. The gui is not drawn in headless mode.
+pub fn render_headless<A: App>(settings: HeadlessSettings) -> Option<RgbaImage> {
+    // Several headless runs can share a process (e.g. tests), so the subscriber may already be set
+    tracing_subscriber::fmt().try_init().ok();
+    let mut renderer = pollster::block_on(Renderer::headless::<A>(
+        settings.width,
+        settings.height,
+        A::SRGB,
+        settings.force_fallback_adapter,
+    ))?;
+    let mut app = A::init(&mut renderer);
+    for _ in 0..settings.frames {
+        let view = renderer.offscreen_view()?;
+        let mut graph = renderer.graph();
+        app.render(&mut graph, &renderer);
+        renderer.execute(graph, &view);
+    }
+    renderer.render_to_image()
+}
+
+/// Like [`render_headless`], but writes the last frame to `path`.
+pub fn run_headless<A: App>(settings: HeadlessSettings, path: impl AsRef<Path>) -> ImageResult<()> {
+    let image =
+        render_headless::<A>(settings).expect("No suitable GPU adapters found on the system!");
+    image.save(path)
+}
@@ -0,0 +1,795 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Range,
+    rc::{Rc, Weak},
+};
+
+use glam::Vec3;
+
+use crate::visibility::frustum::Frustum;
+
+use super::{
+    buffer::{grow, IndexBuffer, VertexBuffer},
+    culling::{CullInstance, DrawIndexedArgs, GpuCulling},
+    material::{Material, MaterialPipelineBuilder, SharedMaterial, MATERIAL_GROUP},
+    mesh::{Mesh, Vertex},
+    model::{Instance, Model},
+    render_graph::{RenderGraph, ResourceHandle},
+    render_pipeline::RenderPipelineBuilder,
+    shadow::ShadowCaster,
+    wgpu_renderer::ShadingPath,
+};
+
+/// Instances the buffers hold before they first grow
+const INITIAL_CAPACITY: usize = 64;
+
+fn material_key(material: &SharedMaterial) -> *const () {
+    Rc::as_ptr(material).cast()
+}
+
+fn instance_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        size: (size_of::<Instance>() * INITIAL_CAPACITY) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Where a mesh is in the [`MeshPool`], kept until the mesh is dropped.
+#[derive(Debug)]
+struct GpuMesh {
+    mesh: Weak<Mesh>,
+    indices: Range<u32>,
+    base_vertex: i32,
+}
+
+/// Vertices and indices of every mesh in one pair of buffers, so draws of different meshes can be merged.
+#[derive(Debug)]
+struct MeshPool {
+    meshes: HashMap<*const Mesh, GpuMesh>,
+    vertex_buffer: VertexBuffer<Vertex>,
+    index_buffer: IndexBuffer,
+}
+
+impl MeshPool {
+    fn new(device: &wgpu::Device) -> Self {
+        Self {
+            meshes: HashMap::new(),
+            vertex_buffer: VertexBuffer::new(vec![], device),
+            index_buffer: IndexBuffer::new(vec![], device),
+        }
+    }
+
+    /// Adds the missing `meshes` and forgets the dropped ones, uploading the pool again when either happens.
+    fn update(&mut self, meshes: &[&Rc<Mesh>], device: &wgpu::Device) {
+        let missing = meshes
+            .iter()
+            .any(|&mesh| !self.meshes.contains_key(&Rc::as_ptr(mesh)));
+        let dropped = self
+            .meshes
+            .values()
+            .any(|mesh| mesh.mesh.strong_count() == 0);
+        if !missing && !dropped {
+            return;
+        }
+        let mut live: Vec<_> = self
+            .meshes
+            .values()
+            .filter_map(|mesh| mesh.mesh.upgrade())
+            .collect();
+        live.extend(meshes.iter().map(|&mesh| mesh.clone()));
+        self.meshes.clear();
+        let (mut vertices, mut indices) = (vec![], vec![]);
+        for mesh in live {
+            if self.meshes.contains_key(&Rc::as_ptr(&mesh)) {
+                continue;
+            }
+            let base_vertex =
+                i32::try_from(vertices.len()).expect("Mesh pool exceeds i32::MAX vertices");
+            let first_index = indices.len() as u32;
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+            self.meshes.insert(
+                Rc::as_ptr(&mesh),
+                GpuMesh {
+                    mesh: Rc::downgrade(&mesh),
+                    indices: first_index..indices.len() as u32,
+                    base_vertex,
+                },
+            );
+        }
+        self.vertex_buffer = VertexBuffer::new(vertices, device);
+        self.index_buffer = IndexBuffer::new(indices, device);
+    }
+}
+
+#[derive(Debug)]
+struct MaterialPipeline {
+    material: Weak<dyn for<'a> Material<'a>>,
+    pipeline: wgpu::RenderPipeline,
+    /// Writes the G-buffer of the deferred path
+    gbuffer: bool,
+    /// Hash of the WGSL the pipeline was built from
+    source: u64,
+    /// Set by [`Batcher::reload_pipelines`], the next [`Batcher::prepare`] rebuilds the pipeline if its source changed
+    stale: bool,
+}
+
+impl MaterialPipeline {
+    /// Pipeline writing the G-buffer if `deferred` and the material has an entry for it, shading forward otherwise.
+    fn new(
+        material: &SharedMaterial,
+        deferred: bool,
+        pipeline: impl Fn(RenderPipelineBuilder) -> wgpu::RenderPipeline,
+    ) -> Self {
+        let gbuffer = deferred
+            .then(|| MaterialPipelineBuilder::gbuffer(material.as_ref()))
+            .flatten();
+        Self {
+            material: Rc::downgrade(material),
+            gbuffer: gbuffer.is_some(),
+            pipeline: pipeline(
+                gbuffer.unwrap_or_else(|| MaterialPipelineBuilder::new(material.as_ref())),
+            ),
+            source: source_hash(material.as_ref()),
+            stale: false,
+        }
+    }
+
+    /// Rebuilds the pipeline if the material shader changed, keeping the current one when the new one fails to build.
+    fn reload(
+        &mut self,
+        material: &SharedMaterial,
+        deferred: bool,
+        pipeline: impl Fn(RenderPipelineBuilder) -> wgpu::RenderPipeline,
+        device: &wgpu::Device,
+    ) {
+        self.stale = false;
+        if source_hash(material.as_ref()) == self.source {
+            return;
+        }
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let reloaded = Self::new(material, deferred, pipeline);
+        match pollster::block_on(device.pop_error_scope()) {
+            None => *self = reloaded,
+            Some(error) => tracing::error!("Keeping the last pipeline of {material:?}: {error}"),
+        }
+    }
+}
+
+fn source_hash(material: &dyn for<'a> Material<'a>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let wgpu::ShaderSource::Wgsl(ref source) = material.shader().source {
+        source.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[derive(Debug)]
+struct Batch {
+    material: SharedMaterial,
+    indices: Range<u32>,
+    base_vertex: i32,
+    instances: Range<u32>,
+    /// Instances that passed CPU culling
+    visible: Range<u32>,
+}
+
+#[derive(Debug)]
+enum Culling {
+    /// Visible instances are uploaded by [`Batcher::prepare`]
+    Cpu {
+        visible: wgpu::Buffer,
+    },
+    Gpu(Box<GpuCulling>),
+}
+
+/// Models sharing a material and a mesh
+type Group<'m> = (&'m SharedMaterial, &'m Rc<Mesh>, Vec<&'m Model>);
+
+/// Groups `models` in the order they are drawn: opaque ones by material, then transparent ones one by one
+/// from the farthest to the nearest to `camera_position`.
+fn group_models<'m>(
+    models: impl IntoIterator<Item = &'m Model>,
+    camera_position: Vec3,
+) -> Vec<Group<'m>> {
+    let mut groups: Vec<Group<'_>> = vec![];
+    let mut group_indices = HashMap::new();
+    let mut material_order = HashMap::new();
+    for (model_index, model) in models.into_iter().enumerate() {
+        let material = model.shared_material();
+        // Transparent models can't share a draw, they are sorted one by one
+        let transparent = material
+            .alpha_mode()
+            .is_transparent()
+            .then_some(model_index);
+        let key = (
+            material_key(material),
+            Rc::as_ptr(model.mesh()),
+            transparent,
+        );
+        let index = *group_indices.entry(key).or_insert_with(|| {
+            groups.push((material, model.mesh(), vec![]));
+            groups.len() - 1
+        });
+        groups[index].2.push(model);
+        let order = material_order.len();
+        material_order
+            .entry(material_key(material))
+            .or_insert(order);
+    }
+    // Opaque batches of a material are next to each other, to be drawn together,
+    // transparent batches follow from back to front
+    let sort_key = |&(material, _, ref models): &Group<'_>| {
+        if material.alpha_mode().is_transparent() {
+            let distance = models[0]
+                .bounding_box()
+                .center()
+                .distance_squared(camera_position);
+            (true, 0, -distance)
+        } else {
+            (false, material_order[&material_key(material)], 0.0)
+        }
+    };
+    groups.sort_by(|a, b| {
+        let (a, b) = (sort_key(a), sort_key(b));
+        (a.0, a.1).cmp(&(b.0, b.1)).then(a.2.total_cmp(&b.2))
+    });
+    groups
+}
+
+/// Draws [`Model`]s sharing a mesh and a material with one instanced call.
+///
+/// Models with a transparent [`AlphaMode`](super::material::AlphaMode) are drawn one by one after the opaque ones,
+/// from the farthest to the nearest to the camera, so they blend over what is behind them.
+///
+/// [`Self::prepare`] groups the models and uploads their transforms every frame,
+/// [`Self::draw`] then records the draws in a pass that has the scene and shadow bind groups set.
+/// Mesh buffers and material pipelines are cached for as long as the mesh or material is alive.
+///
+/// In the deferred path, materials with a G-buffer entry are drawn by [`Self::draw_gbuffer`] instead.
+///
+/// Models outside the view frustum are culled by a compute pass writing indirect draws when the adapter supports it,
+/// see [`GpuCulling::supported`], and on the CPU otherwise.
+#[derive(Debug)]
+pub struct Batcher {
+    meshes: MeshPool,
+    pipelines: HashMap<*const (), MaterialPipeline>,
+    batches: Vec<Batch>,
+    /// Every instance, shadows are not culled
+    instances: wgpu::Buffer,
+    culling: Culling,
+    shading: ShadingPath,
+}
+
+impl Batcher {
+    pub fn new(device: &wgpu::Device, gpu_culling: bool, shading: ShadingPath) -> Self {
+        let culling = if gpu_culling {
+            Culling::Gpu(Box::new(GpuCulling::new(device)))
+        } else {
+            Culling::Cpu {
+                visible: instance_buffer(device),
+            }
+        };
+        Self {
+            meshes: MeshPool::new(device),
+            pipelines: HashMap::new(),
+            batches: vec![],
+            instances: instance_buffer(device),
+            culling,
+            shading,
+        }
+    }
+
+    /// Groups `models` by mesh and material, and writes their instances into the instance buffers.
+    ///
+    /// Models outside of `frustum` are culled, transparent ones are sorted by their distance to `camera_position`.
+    /// `pipeline` finishes the pipeline of materials seen for the first time,
+    /// it should add the bind groups following the material and build with [`Vertex`] and [`Instance`] buffers.
+    /// G-buffer pipelines come with their targets, the format they are built with is ignored.
+    #[allow(single_use_lifetimes)]
+    pub fn prepare<'m>(
+        &mut self,
+        models: impl IntoIterator<Item = &'m Model>,
+        frustum: Option<&Frustum>,
+        camera_position: Vec3,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline: impl Fn(RenderPipelineBuilder) -> wgpu::RenderPipeline,
+    ) {
+        let groups = group_models(models, camera_position);
+        let meshes: Vec<_> = groups.iter().map(|&(_, mesh, _)| mesh).collect();
+        self.meshes.update(&meshes, device);
+
+        let gpu_culling = matches!(self.culling, Culling::Gpu(_));
+        let deferred = self.shading == ShadingPath::Deferred;
+        let multi_draw = matches!(self.culling, Culling::Gpu(ref culling) if culling.multi_draw());
+        let (mut instances, mut visible, mut cull_instances, mut draws) =
+            (vec![], vec![], vec![], vec![]);
+        let batches = groups
+            .into_iter()
+            .enumerate()
+            .map(|(index, (material, mesh, models))| {
+                let start = instances.len() as u32;
+                let visible_start = visible.len() as u32;
+                for model in models {
+                    let instance = model.instance();
+                    instances.push(instance);
+                    let bounding_box = model.bounding_box();
+                    if gpu_culling {
+                        let sphere = bounding_box
+                            .center()
+                            .extend(bounding_box.size().length() * 0.5);
+                        cull_instances.push(CullInstance::new(
+                            instance,
+                            sphere,
+                            index as u32,
+                            start,
+                        ));
+                    } else if frustum
+                        .is_none_or(|frustum| frustum.intersect_oriented_bounding_box(bounding_box))
+                    {
+                        visible.push(instance);
+                    }
+                }
+                let gpu_mesh = &self.meshes.meshes[&Rc::as_ptr(mesh)];
+                draws.push(DrawIndexedArgs {
+                    index_count: gpu_mesh.indices.len() as u32,
+                    instance_count: 0,
+                    first_index: gpu_mesh.indices.start,
+                    base_vertex: gpu_mesh.base_vertex,
+                    first_instance: if multi_draw { start } else { 0 },
+                });
+                match self.pipelines.entry(material_key(material)) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(MaterialPipeline::new(material, deferred, &pipeline));
+                    }
+                    Entry::Occupied(mut entry) if entry.get().stale => {
+                        entry
+                            .get_mut()
+                            .reload(material, deferred, &pipeline, device);
+                    }
+                    Entry::Occupied(_) => {}
+                }
+                Batch {
+                    material: material.clone(),
+                    indices: gpu_mesh.indices.clone(),
+                    base_vertex: gpu_mesh.base_vertex,
+                    instances: start..instances.len() as u32,
+                    visible: visible_start..visible.len() as u32,
+                }
+            })
+            .collect();
+        // Replacing the batches drops the last frame's references before pruning the pipelines
+        self.batches = batches;
+        self.pipelines
+            .retain(|_, pipeline| pipeline.material.strong_count() > 0);
+
+        grow(
+            &mut self.instances,
+            size_of_val(&instances[..]) as u64,
+            device,
+        );
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(&instances));
+        }
+        match self.culling {
+            Culling::Cpu {
+                visible: ref mut buffer,
+            } => {
+                grow(buffer, size_of_val(&visible[..]) as u64, device);
+                if !visible.is_empty() {
+                    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&visible));
+                }
+            }
+            Culling::Gpu(ref mut culling) => {
+                culling.update(frustum, &cull_instances, &draws, device, queue);
+            }
+        }
+    }
+
+    /// Forgets the cached pipelines, for when a material changed its shader or bind group layout.
+    pub fn invalidate_pipelines(&mut self) {
+        self.pipelines.clear();
+    }
+
+    /// Rebuilds the pipelines whose shader source changed on the next [`Self::prepare`], like after a
+    /// [`ShaderWatcher::poll`](super::shader::ShaderWatcher::poll) saw an edit.
+    /// A pipeline that fails to build is reported and the previous one stays in use.
+    pub fn reload_pipelines(&mut self) {
+        for pipeline in self.pipelines.values_mut() {
+            pipeline.stale = true;
+        }
+    }
+
+    /// Number of instanced draws [`Self::draw`] records
+    pub const fn draw_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    /// Number of models in the last [`Self::prepare`]
+    pub fn instance_count(&self) -> u32 {
+        self.batches.last().map_or(0, |batch| batch.instances.end)
+    }
+
+    /// Adds the culling pass when culling on the GPU, returns the instances [`Self::draw`] reads.
+    pub fn add_to_graph<'a>(&'a self, graph: &mut RenderGraph<'a>) -> ResourceHandle {
+        match self.culling {
+            Culling::Cpu { ref visible } => graph.import_buffer(visible),
+            Culling::Gpu(ref culling) => {
+                let visible = graph.import_buffer(culling.visible());
+                graph
+                    .add_pass("Cull Instances")
+                    .write(visible)
+                    .run(move |ctx| culling.cull(ctx.encoder));
+                visible
+            }
+        }
+    }
+
+    /// Draws the visible instances of the batches shaded forward with their material pipeline,
+    /// which are all of them in the forward path.
+    pub fn draw<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>) {
+        self.draw_batches(rpass, Some(false));
+    }
+
+    /// Draws the visible instances of the batches writing the G-buffer, in a pass from [`GBuffer::begin_pass`](super::deferred::GBuffer::begin_pass).
+    pub fn draw_gbuffer<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>) {
+        self.draw_batches(rpass, Some(true));
+    }
+
+    /// Draws the visible instances of every batch with the pipeline and bind groups already set, like a wireframe overlay.
+    pub fn draw_geometry<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>) {
+        self.draw_batches(rpass, None);
+    }
+
+    /// Binds the material of the batches whose pipeline does or doesn't write the G-buffer, and skips the others.
+    /// Without `gbuffer`, every batch is drawn with what is bound.
+    fn draw_batches<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>, gbuffer: Option<bool>) {
+        if self.batches.is_empty() {
+            return;
+        }
+        rpass.set_vertex_buffer(0, self.meshes.vertex_buffer.buffer.slice(..));
+        rpass.set_index_buffer(
+            self.meshes.index_buffer.buffer.slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+        let mut next = 0;
+        for run in self
+            .batches
+            .chunk_by(|a, b| Rc::ptr_eq(&a.material, &b.material))
+        {
+            let first = next;
+            next += run.len();
+            if let Some(gbuffer) = gbuffer {
+                let material = &run[0].material;
+                let pipeline = &self.pipelines[&material_key(material)];
+                if pipeline.gbuffer != gbuffer {
+                    continue;
+                }
+                rpass.set_pipeline(&pipeline.pipeline);
+                rpass.set_bind_group(MATERIAL_GROUP, &material.bind_group().bind_group, &[]);
+            }
+            match self.culling {
+                Culling::Cpu { ref visible } => {
+                    rpass.set_vertex_buffer(1, visible.slice(..));
+                    for batch in run.iter().filter(|batch| !batch.visible.is_empty()) {
+                        rpass.draw_indexed(
+                            batch.indices.clone(),
+                            batch.base_vertex,
+                            batch.visible.clone(),
+                        );
+                    }
+                }
+                Culling::Gpu(ref culling) if culling.multi_draw() => {
+                    rpass.set_vertex_buffer(1, culling.visible().slice(..));
+                    rpass.multi_draw_indexed_indirect(
+                        culling.draws(),
+                        (first * size_of::<DrawIndexedArgs>()) as u64,
+                        run.len() as u32,
+                    );
+                }
+                Culling::Gpu(ref culling) => {
+                    for (index, batch) in run.iter().enumerate() {
+                        // Draws can't start past the first instance, bind the instances from there instead
+                        let offset =
+                            u64::from(batch.instances.start) * size_of::<Instance>() as u64;
+                        rpass.set_vertex_buffer(1, culling.visible().slice(offset..));
+                        rpass.draw_indexed_indirect(
+                            culling.draws(),
+                            ((first + index) * size_of::<DrawIndexedArgs>()) as u64,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// One caster per batch with all its instances, for [`ShadowMaps::render`](super::shadow::ShadowMaps::render).
+    pub fn shadow_casters(&self) -> Vec<ShadowCaster<'_>> {
+        self.batches
+            .iter()
+            .map(|batch| ShadowCaster {
+                vertex_buffer: &self.meshes.vertex_buffer,
+                index_buffer: &self.meshes.index_buffer,
+                indices: batch.indices.clone(),
+                base_vertex: batch.base_vertex,
+                instance_buffer: &self.instances,
+                instances: batch.instances.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use glam::{Affine3A, Vec3};
+
+    use super::{Batcher, Culling};
+    use crate::{
+        collision::shapes::{Cuboid, Sphere},
+        renderer::{
+            bind_group::{BindGroup, BindGroupBuilder},
+            buffer::Buffer,
+            camera::OrbitCamera,
+            culling::{DrawIndexedArgs, GpuCulling},
+            material::{AlphaMode, SharedMaterial, UnlitMaterialBuilder},
+            mesh::{Meshable, Vertex},
+            model::{Instance, Model},
+            render_pipeline::RenderPipelineBuilder,
+            wgpu_renderer::ShadingPath,
+        },
+        tests::adapter_device,
+    };
+
+    /// Camera bind group the unlit shader reads at group 1
+    fn scene(device: &wgpu::Device) -> (Buffer, BindGroup) {
+        let camera = Buffer::new(device, 256, wgpu::BufferUsages::UNIFORM);
+        let scene = BindGroupBuilder::new()
+            .uniform(&camera.buffer)
+            .build(device);
+        (camera, scene)
+    }
+
+    fn pipeline(
+        builder: RenderPipelineBuilder,
+        scene: &BindGroup,
+        device: &wgpu::Device,
+    ) -> wgpu::RenderPipeline {
+        builder
+            .bind_group(&scene)
+            .build_with_instancing::<Vertex, Instance>(device, wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    /// Cubes along x, from -50 to 50, seen from the camera at z = 5
+    fn row_of_cubes(material: &SharedMaterial) -> Vec<Model> {
+        let cube = Rc::new(Cuboid::new(Vec3::ONE).mesh());
+        (-50..=50)
+            .map(|x| {
+                let transform = Affine3A::from_translation(Vec3::X * x as f32);
+                Model::with_shared_material(transform, cube.clone(), material.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn models_sharing_mesh_and_material_share_a_draw() {
+        let Some((_, device, queue)) = adapter_device(GpuCulling::MULTI_DRAW_FEATURES) else {
+            return;
+        };
+        let (_camera, scene) = scene(&device);
+        let build = |builder: RenderPipelineBuilder| pipeline(builder, &scene, &device);
+
+        let cube = Rc::new(Cuboid::new(Vec3::ONE).mesh());
+        let sphere = Rc::new(Sphere::new(Vec3::ZERO, 1.0).mesh());
+        let red: SharedMaterial = Rc::new(UnlitMaterialBuilder::new().build(&device, &queue));
+        let blue: SharedMaterial = Rc::new(UnlitMaterialBuilder::new().build(&device, &queue));
+        let at = |x: f32| Affine3A::from_translation(Vec3::X * x);
+        let mut models: Vec<_> = (0..3)
+            .map(|i| Model::with_shared_material(at(i as f32), cube.clone(), red.clone()))
+            .collect();
+        models.push(Model::with_shared_material(at(3.0), cube.clone(), blue));
+        models.push(Model::with_shared_material(
+            at(4.0),
+            sphere.clone(),
+            red.clone(),
+        ));
+        models.push(Model::with_shared_material(at(5.0), cube.clone(), red));
+
+        let mut batcher = Batcher::new(&device, false, ShadingPath::Forward);
+        batcher.prepare(&models, None, Vec3::ZERO, &device, &queue, build);
+        assert_eq!(batcher.draw_count(), 3, "One draw per mesh and material");
+        assert_eq!(batcher.instance_count(), 6, "Every model is an instance");
+        assert_eq!(
+            batcher.batches[0].instances,
+            0..4,
+            "Models of a batch are contiguous in the instance buffer"
+        );
+        assert!(
+            Rc::ptr_eq(&batcher.batches[0].material, &batcher.batches[1].material),
+            "Batches of a material are next to each other"
+        );
+        assert_eq!(batcher.pipelines.len(), 2, "One pipeline per material");
+        assert_eq!(batcher.meshes.meshes.len(), 2, "Meshes are pooled once");
+        assert_eq!(
+            batcher.meshes.index_buffer.indices.len(),
+            cube.indices.len() + sphere.indices.len(),
+            "Meshes share the index buffer"
+        );
+
+        models.truncate(3);
+        drop(sphere);
+        batcher.prepare(&models, None, Vec3::ZERO, &device, &queue, build);
+        assert_eq!(batcher.draw_count(), 1, "Dropped models are not drawn");
+        assert_eq!(
+            batcher.pipelines.len(),
+            1,
+            "Dropped materials are forgotten"
+        );
+        assert_eq!(
+            batcher.meshes.meshes.len(),
+            1,
+            "Dropped meshes are forgotten"
+        );
+    }
+
+    #[test]
+    fn transparent_models_are_drawn_last_from_back_to_front() {
+        let Some((_, device, queue)) = adapter_device(GpuCulling::MULTI_DRAW_FEATURES) else {
+            return;
+        };
+        let (_camera, scene) = scene(&device);
+        let build = |builder: RenderPipelineBuilder| pipeline(builder, &scene, &device);
+
+        let glass: SharedMaterial = Rc::new(
+            UnlitMaterialBuilder::new()
+                .alpha_mode(AlphaMode::Blend)
+                .build(&device, &queue),
+        );
+        let opaque: SharedMaterial = Rc::new(UnlitMaterialBuilder::new().build(&device, &queue));
+        let cube = Rc::new(Cuboid::new(Vec3::ONE).mesh());
+        // Transparent models get their own mesh, to tell their draws apart
+        let panes: Vec<_> = (0..3)
+            .map(|_| Rc::new(Cuboid::new(Vec3::ONE).mesh()))
+            .collect();
+        let at = |z: f32| Affine3A::from_translation(Vec3::Z * z);
+        let models = [
+            Model::with_shared_material(at(1.0), panes[0].clone(), glass.clone()),
+            Model::with_shared_material(at(0.0), cube.clone(), opaque.clone()),
+            Model::with_shared_material(at(-5.0), panes[1].clone(), glass.clone()),
+            Model::with_shared_material(at(3.0), panes[2].clone(), glass),
+            Model::with_shared_material(at(2.0), cube, opaque),
+        ];
+
+        let mut batcher = Batcher::new(&device, false, ShadingPath::Forward);
+        batcher.prepare(&models, None, Vec3::Z * 10.0, &device, &queue, build);
+        assert_eq!(
+            batcher.draw_count(),
+            4,
+            "Opaque models share a draw, transparent ones don't"
+        );
+        assert!(
+            !batcher.batches[0].material.alpha_mode().is_transparent(),
+            "Opaque models are drawn first"
+        );
+        let order: Vec<_> = batcher.batches[1..]
+            .iter()
+            .map(|batch| {
+                panes
+                    .iter()
+                    .position(|pane| {
+                        batcher.meshes.meshes[&Rc::as_ptr(pane)].indices == batch.indices
+                    })
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(order, [1, 0, 2], "Farthest transparent model first");
+    }
+
+    #[test]
+    fn instance_buffer_grows_on_demand() {
+        let Some((_, device, queue)) = adapter_device(GpuCulling::MULTI_DRAW_FEATURES) else {
+            return;
+        };
+        let (_camera, scene) = scene(&device);
+        let material: SharedMaterial = Rc::new(UnlitMaterialBuilder::new().build(&device, &queue));
+        let models = row_of_cubes(&material);
+
+        let mut batcher = Batcher::new(&device, false, ShadingPath::Forward);
+        batcher.prepare(&models, None, Vec3::ZERO, &device, &queue, |builder| {
+            pipeline(builder, &scene, &device)
+        });
+        assert_eq!(batcher.draw_count(), 1, "Every model shares the draw");
+        assert!(
+            batcher.instances.size() >= (models.len() * size_of::<Instance>()) as u64,
+            "Grown buffer fits every instance"
+        );
+    }
+
+    #[test]
+    fn cpu_culling_skips_models_outside_the_frustum() {
+        let Some((_, device, queue)) = adapter_device(GpuCulling::MULTI_DRAW_FEATURES) else {
+            return;
+        };
+        let (_camera, scene) = scene(&device);
+        let material: SharedMaterial = Rc::new(UnlitMaterialBuilder::new().build(&device, &queue));
+        let models = row_of_cubes(&material);
+        let frustum = OrbitCamera::new(5.0, 1.0).frustum();
+
+        let mut batcher = Batcher::new(&device, false, ShadingPath::Forward);
+        batcher.prepare(
+            &models,
+            Some(&frustum),
+            Vec3::ZERO,
+            &device,
+            &queue,
+            |builder| pipeline(builder, &scene, &device),
+        );
+        let visible = batcher.batches[0].visible.len();
+        assert!(
+            visible > 0 && visible < models.len(),
+            "Only cubes in front of the camera are drawn, got {visible}"
+        );
+        let casters = batcher.shadow_casters();
+        assert_eq!(
+            casters[0].instances.len(),
+            models.len(),
+            "Shadows are cast by every model"
+        );
+    }
+
+    #[test]
+    fn gpu_culling_matches_cpu_culling() {
+        let Some((adapter, device, queue)) = adapter_device(GpuCulling::MULTI_DRAW_FEATURES) else {
+            return;
+        };
+        if !GpuCulling::supported(&adapter) {
+            tracing::warn!("No compute or indirect support, skipping");
+            return;
+        }
+        let (_camera, scene) = scene(&device);
+        let material: SharedMaterial = Rc::new(UnlitMaterialBuilder::new().build(&device, &queue));
+        let models = row_of_cubes(&material);
+        let frustum = OrbitCamera::new(5.0, 1.0).frustum();
+        let build = |builder: RenderPipelineBuilder| pipeline(builder, &scene, &device);
+
+        let mut cpu = Batcher::new(&device, false, ShadingPath::Forward);
+        cpu.prepare(&models, Some(&frustum), Vec3::ZERO, &device, &queue, build);
+        let mut gpu = Batcher::new(&device, true, ShadingPath::Forward);
+        gpu.prepare(&models, Some(&frustum), Vec3::ZERO, &device, &queue, build);
+        let Culling::Gpu(ref culling) = gpu.culling else {
+            unreachable!("Batcher culls on the GPU");
+        };
+
+        let source = culling.draws();
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: size_of::<DrawIndexedArgs>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        culling.cull(&mut encoder);
+        encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, buffer.size());
+        queue.submit([encoder.finish()]);
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let draw: DrawIndexedArgs = *bytemuck::from_bytes(&slice.get_mapped_range());
+
+        assert_eq!(
+            draw.instance_count as usize,
+            cpu.batches[0].visible.len(),
+            "Spheres and boxes cull the same cubes"
+        );
+        assert_eq!(
+            draw.index_count as usize,
+            cpu.meshes.index_buffer.indices.len(),
+            "Draws the whole cube"
+        );
+    }
+}
@@ -4,110 +4,201 @@ use super::texture::Texture;
 pub struct BindGroup {
     pub layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
+    /// What `layout` was created from, wgpu layouts can't be read back
+    pub entries: Vec<wgpu::BindGroupLayoutEntry>,
 }
 
-#[derive(Debug)]
-pub struct BindGroupBuilder<'a> {
-    counter: u32,
-    layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
-    bind_group_entries: Vec<wgpu::BindGroupEntry<'a>>,
+/// Entries of a bind group layout in binding order, without the resources [`BindGroupBuilder`] binds.
+/// Describes a layout without a device, like when checking it against a shader.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BindGroupLayoutEntries {
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
 }
 
-impl<'a> BindGroupBuilder<'a> {
+impl BindGroupLayoutEntries {
     pub fn new() -> Self {
-        Self {
-            counter: 0,
-            layout_entries: vec![],
-            bind_group_entries: vec![],
-        }
+        Self::default()
     }
-    pub fn uniform(mut self, uniform_buffer: &'a wgpu::Buffer) -> Self {
-        self.layout_entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.counter,
-            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        });
-        self.bind_group_entries.push(wgpu::BindGroupEntry {
-            binding: self.counter,
-            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                buffer: uniform_buffer,
-                offset: 0,
-                size: None,
-            }),
-        });
-        self.counter += 1;
-
-        self
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
     }
-
-    pub fn storage_buffer(mut self, storage_buffer: &'a wgpu::Buffer) -> Self {
-        self.layout_entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.counter,
+    fn next_binding(&self) -> u32 {
+        self.entries.len() as u32
+    }
+    fn buffer(mut self, ty: wgpu::BufferBindingType) -> Self {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding: self.next_binding(),
             visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
             ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                ty,
                 has_dynamic_offset: false,
                 min_binding_size: None,
             },
             count: None,
         });
-        self.bind_group_entries.push(wgpu::BindGroupEntry {
-            binding: self.counter,
-            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                buffer: storage_buffer,
-                offset: 0,
-                size: None,
-            }),
-        });
-        self.counter += 1;
-
         self
     }
-
-    pub fn texture(mut self, texture: &'a Texture) -> Self {
-        self.layout_entries.extend([
+    /// A texture followed by its sampler, both read by the fragment shader
+    fn sampled(
+        mut self,
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+        sampler: wgpu::SamplerBindingType,
+    ) -> Self {
+        let binding = self.next_binding();
+        self.entries.extend([
             wgpu::BindGroupLayoutEntry {
-                binding: self.counter,
+                binding,
                 visibility: wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type,
+                    view_dimension,
                     multisampled: false,
                 },
                 count: None,
             },
             wgpu::BindGroupLayoutEntry {
-                binding: self.counter + 1,
+                binding: binding + 1,
                 visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                ty: wgpu::BindingType::Sampler(sampler),
                 count: None,
             },
         ]);
+        self
+    }
+    pub fn uniform(self) -> Self {
+        self.buffer(wgpu::BufferBindingType::Uniform)
+    }
+    pub fn storage_buffer(self) -> Self {
+        self.buffer(wgpu::BufferBindingType::Storage { read_only: true })
+    }
+    pub fn texture(self) -> Self {
+        self.sampled(
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::TextureViewDimension::D2,
+            wgpu::SamplerBindingType::Filtering,
+        )
+    }
+    /// A cubemap texture and its sampler.
+    pub fn cube_texture(self) -> Self {
+        self.sampled(
+            wgpu::TextureSampleType::Float { filterable: true },
+            wgpu::TextureViewDimension::Cube,
+            wgpu::SamplerBindingType::Filtering,
+        )
+    }
+    /// A depth texture array and its comparison sampler.
+    pub fn depth_texture_array(self) -> Self {
+        self.sampled(
+            wgpu::TextureSampleType::Depth,
+            wgpu::TextureViewDimension::D2Array,
+            wgpu::SamplerBindingType::Comparison,
+        )
+    }
+    /// A depth cubemap array and its comparison sampler.
+    pub fn depth_texture_cube_array(self) -> Self {
+        self.sampled(
+            wgpu::TextureSampleType::Depth,
+            wgpu::TextureViewDimension::CubeArray,
+            wgpu::SamplerBindingType::Comparison,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct BindGroupBuilder<'a> {
+    layout: BindGroupLayoutEntries,
+    bind_group_entries: Vec<wgpu::BindGroupEntry<'a>>,
+}
 
+impl<'a> BindGroupBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            layout: BindGroupLayoutEntries::new(),
+            bind_group_entries: vec![],
+        }
+    }
+    fn buffer(mut self, buffer: &'a wgpu::Buffer) -> Self {
+        self.bind_group_entries.push(wgpu::BindGroupEntry {
+            binding: self.bind_group_entries.len() as u32,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: 0,
+                size: None,
+            }),
+        });
+        self
+    }
+    fn sampled(mut self, texture: &'a Texture) -> Self {
+        let binding = self.bind_group_entries.len() as u32;
         self.bind_group_entries.extend([
             wgpu::BindGroupEntry {
-                binding: self.counter,
+                binding,
                 resource: wgpu::BindingResource::TextureView(&texture.view),
             },
             wgpu::BindGroupEntry {
-                binding: self.counter + 1,
+                binding: binding + 1,
                 resource: wgpu::BindingResource::Sampler(&texture.sampler),
             },
         ]);
-        self.counter += 2;
-
         self
     }
+    pub fn uniform(self, uniform_buffer: &'a wgpu::Buffer) -> Self {
+        Self {
+            layout: self.layout.uniform(),
+            ..self
+        }
+        .buffer(uniform_buffer)
+    }
+
+    pub fn storage_buffer(self, storage_buffer: &'a wgpu::Buffer) -> Self {
+        Self {
+            layout: self.layout.storage_buffer(),
+            ..self
+        }
+        .buffer(storage_buffer)
+    }
+
+    pub fn texture(self, texture: &'a Texture) -> Self {
+        Self {
+            layout: self.layout.texture(),
+            ..self
+        }
+        .sampled(texture)
+    }
+
+    /// A cubemap texture and its sampler.
+    pub fn cube_texture(self, texture: &'a Texture) -> Self {
+        Self {
+            layout: self.layout.cube_texture(),
+            ..self
+        }
+        .sampled(texture)
+    }
+
+    /// A depth texture array and its comparison sampler.
+    pub fn depth_texture_array(self, texture: &'a Texture) -> Self {
+        Self {
+            layout: self.layout.depth_texture_array(),
+            ..self
+        }
+        .sampled(texture)
+    }
+
+    /// A depth cubemap array and its comparison sampler.
+    pub fn depth_texture_cube_array(self, texture: &'a Texture) -> Self {
+        Self {
+            layout: self.layout.depth_texture_cube_array(),
+            ..self
+        }
+        .sampled(texture)
+    }
 
     pub fn build(self, device: &wgpu::Device) -> BindGroup {
+        let entries = self.layout.entries;
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &self.layout_entries,
+            entries: &entries,
         });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Bind Group Layout"),
@@ -118,6 +209,7 @@ impl<'a> BindGroupBuilder<'a> {
         BindGroup {
             layout: bind_group_layout,
             bind_group,
+            entries,
         }
     }
 }
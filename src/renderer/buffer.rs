@@ -1,9 +1,12 @@
-use std::{fmt::Debug, mem};
+use std::fmt::Debug;
 
 use bytemuck::{Pod, Zeroable};
 use wgpu::util::DeviceExt;
 
-use crate::GpuSendable;
+use crate::{
+    core::shader_type::{array_bytes, ShaderType},
+    GpuSendable,
+};
 
 use super::resources::VertexAttributeLayout;
 
@@ -53,12 +56,18 @@ pub struct UniformBuffer<T> {
 impl<T> UniformBuffer<T> {
     pub fn new<U>(data: T, device: &wgpu::Device) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
+        const {
+            assert!(
+                U::LAYOUT.uniform,
+                "Type breaks the layout rules of uniform buffers"
+            );
+        };
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&[data.to_gpu()]),
+            contents: &data.to_gpu().to_bytes(),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
         });
         Self { data, buffer }
@@ -66,10 +75,27 @@ impl<T> UniformBuffer<T> {
 
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data.to_gpu()]));
+        queue.write_buffer(&self.buffer, 0, &self.data.to_gpu().to_bytes());
+    }
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    /// Writes only `field`, a field of [`Self::data`], like the one parameter a gui edit changed.
+    ///
+    /// Offsets in `data` are the WGSL ones when `T` is `#[repr(C)]` and derives [`ShaderType`], which checks it.
+    pub fn update_field<F: Pod>(&self, field: &F, queue: &wgpu::Queue) {
+        let data = bytemuck::bytes_of(&self.data).as_ptr_range();
+        let field = bytemuck::bytes_of(field);
+        let range = field.as_ptr_range();
+        assert!(
+            data.start <= range.start && range.end <= data.end,
+            "Updated field is not part of the buffer data"
+        );
+        let offset = range.start.addr() - data.start.addr();
+        queue.write_buffer(&self.buffer, offset as u64, field);
     }
 }
 #[derive(Debug)]
@@ -81,13 +107,19 @@ pub struct UniformBufferArray<T> {
 impl<T> UniformBufferArray<T> {
     pub fn new<U>(data: &[T], device: &wgpu::Device) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U> + Clone + Copy,
     {
+        const {
+            assert!(
+                U::LAYOUT.array(1).uniform,
+                "Type breaks the layout rules of uniform buffer arrays"
+            );
+        };
         let gpu_data: Vec<U> = data.iter().map(GpuSendable::to_gpu).collect();
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&gpu_data),
+            contents: &array_bytes(&gpu_data),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
         });
         Self {
@@ -97,24 +129,24 @@ impl<T> UniformBufferArray<T> {
     }
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         let gpu_data: Vec<U> = self.data.iter().map(GpuSendable::to_gpu).collect();
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&gpu_data));
+        queue.write_buffer(&self.buffer, 0, &array_bytes(&gpu_data));
     }
     pub fn update_at<U>(&self, index: usize, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         assert!(index < self.data.len(), "Updated buffer at index > length");
 
-        let gpu_data = self.data[index].to_gpu();
+        let offset = index * U::LAYOUT.stride();
         queue.write_buffer(
             &self.buffer,
-            index as u64,
-            bytemuck::cast_slice(&[gpu_data]),
+            offset as u64,
+            &self.data[index].to_gpu().to_bytes(),
         );
     }
 }
@@ -128,12 +160,12 @@ pub struct StorageBuffer<T> {
 impl<T> StorageBuffer<T> {
     pub fn new<U>(data: T, device: &wgpu::Device) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&[data.to_gpu()]),
+            contents: &data.to_gpu().to_bytes(),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
         });
         Self { data, buffer }
@@ -141,10 +173,10 @@ impl<T> StorageBuffer<T> {
 
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data.to_gpu()]));
+        queue.write_buffer(&self.buffer, 0, &self.data.to_gpu().to_bytes());
     }
 }
 
@@ -155,19 +187,20 @@ pub struct StorageBufferArray<T> {
 }
 
 impl<T> StorageBufferArray<T> {
+    /// Makes room for `size` elements
     pub fn new<U>(data: &[T], device: &wgpu::Device, queue: &wgpu::Queue, size: u64) -> Self
     where
-        U: Clone + Copy + Pod + Zeroable + Debug,
+        U: ShaderType,
         T: GpuSendable<U> + Clone + Copy,
     {
         let gpu_data: Vec<U> = data.iter().map(GpuSendable::to_gpu).collect();
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: mem::size_of::<T>() as u64 * size,
+            size: U::LAYOUT.stride() as u64 * size,
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
-        queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&gpu_data));
+        queue.write_buffer(&buffer, 0, &array_bytes(&gpu_data));
         Self {
             data: data.to_vec(),
             buffer,
@@ -175,31 +208,69 @@ impl<T> StorageBufferArray<T> {
     }
     pub fn update<U>(&self, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         if self.data.is_empty() {
-            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[U::zeroed()]));
+            queue.write_buffer(&self.buffer, 0, &vec![0; U::LAYOUT.stride()]);
         } else {
             let gpu_data: Vec<U> = self.data.iter().map(GpuSendable::to_gpu).collect();
-            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&gpu_data));
+            queue.write_buffer(&self.buffer, 0, &array_bytes(&gpu_data));
         }
     }
+    /// Like [`Self::update`], but reallocates the buffer when `data` no longer fits in it.
+    ///
+    /// Returns true when the buffer was replaced, bind groups using it have to be rebuilt.
+    pub fn update_or_grow<U>(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool
+    where
+        U: ShaderType,
+        T: GpuSendable<U>,
+    {
+        let needed = (U::LAYOUT.stride() * self.data.len()) as u64;
+        let grown = needed > self.buffer.size();
+        if grown {
+            let capacity = self.data.len().next_power_of_two();
+            self.buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: (U::LAYOUT.stride() * capacity) as u64,
+                usage: self.buffer.usage(),
+                mapped_at_creation: false,
+            });
+        }
+        self.update(queue);
+        grown
+    }
     pub fn update_at<U>(&self, index: usize, queue: &wgpu::Queue)
     where
-        U: Clone + Copy + Pod + Zeroable,
+        U: ShaderType,
         T: GpuSendable<U>,
     {
         assert!(index < self.data.len(), "Updated buffer at index > length");
 
-        let gpu_data = self.data[index].to_gpu();
+        let offset = index * U::LAYOUT.stride();
         queue.write_buffer(
             &self.buffer,
-            index as u64,
-            bytemuck::cast_slice(&[gpu_data]),
+            offset as u64,
+            &self.data[index].to_gpu().to_bytes(),
         );
     }
 }
+/// Replaces `buffer` with a bigger one when `size` bytes don't fit, returns true if it did.
+///
+/// The contents are not copied, bind groups using the buffer have to be rebuilt.
+pub(crate) fn grow(buffer: &mut wgpu::Buffer, size: u64, device: &wgpu::Device) -> bool {
+    let grown = size > buffer.size();
+    if grown {
+        *buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: size.next_power_of_two(),
+            usage: buffer.usage(),
+            mapped_at_creation: false,
+        });
+    }
+    grown
+}
+
 #[derive(Debug)]
 pub struct Buffer {
     pub buffer: wgpu::Buffer,
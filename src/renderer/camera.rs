@@ -1,13 +1,17 @@
 use std::f32::consts::FRAC_PI_2;
 
-use bytemuck::{Pod, Zeroable};
 use glam::{Mat4, Quat, Vec3, Vec4};
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
 };
 
-use crate::{collision::shapes::Plane, visibility::frustum::Frustum, GpuSendable};
+use crate::{
+    collision::shapes::Plane,
+    core::{component::Component, shader_type::ShaderType},
+    visibility::frustum::{Frustum, FrustumBuilder},
+    GpuSendable,
+};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct OrbitCamera {
@@ -18,25 +22,22 @@ pub struct OrbitCamera {
     drag: bool,
     last_mouse_pos: PhysicalPosition<f64>,
 }
-#[repr(C)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[derive(Debug, Clone, Copy, ShaderType)]
 pub struct GpuCamera {
-    projection: Mat4,
+    proj: Mat4,
     view: Mat4,
     inv_view: Mat4,
     position: Vec3,
-    _pad: f32,
 }
 
 impl GpuSendable<GpuCamera> for OrbitCamera {
     fn to_gpu(&self) -> GpuCamera {
         let view = self.view();
         GpuCamera {
-            projection: self.camera.matrix_rh(),
+            proj: self.camera.matrix_rh(),
             view,
             inv_view: view.inverse().transpose(),
             position: self.position(),
-            _pad: 0.0,
         }
     }
 }
@@ -60,6 +61,9 @@ impl OrbitCamera {
     pub fn view(&self) -> Mat4 {
         Mat4::look_at_rh(self.position(), Vec3::ZERO, Vec3::Y)
     }
+    pub const fn perspective(&self) -> &PerspectiveCamera {
+        &self.camera
+    }
     pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
         self.camera.aspect_ratio = aspect_ratio;
     }
@@ -106,9 +110,9 @@ impl OrbitCamera {
         false
     }
 
+    /// World space frustum of the camera.
     pub fn frustum(&self) -> Frustum {
-        // TODO: align frustum with view
-        self.camera.frustum(false, false)
+        FrustumBuilder::new(self.camera.matrix_rh() * self.view()).build()
     }
 }
 #[derive(Clone, Copy, Debug)]
@@ -119,6 +123,8 @@ pub struct PerspectiveCamera {
     pub far: f32,
 }
 
+impl Component for PerspectiveCamera {}
+
 impl Default for PerspectiveCamera {
     fn default() -> Self {
         Self {
@@ -238,6 +244,8 @@ pub struct OrthographicCamera {
     pub far: f32,
 }
 
+impl Component for OrthographicCamera {}
+
 impl OrthographicCamera {
     pub const fn new(size: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
         Self {
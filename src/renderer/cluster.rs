@@ -0,0 +1,322 @@
+use glam::{Mat4, UVec3, Vec2};
+
+use crate::core::shader_type::ShaderType;
+
+use super::{
+    bind_group::BindGroupBuilder,
+    buffer::{Buffer, StorageBufferArray, UniformBuffer},
+    camera::PerspectiveCamera,
+    compute::ComputePipelineBuilder,
+    light::Light,
+    render_graph::{RenderGraph, ResourceHandle},
+    shader::{Shader, ShaderDefs},
+};
+
+/// Number of clusters the view frustum is split into along x, y and depth
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+/// Lights past this many in a single cluster don't light it
+pub const MAX_CLUSTER_LIGHTS: u32 = 64;
+
+const WORKGROUP_SIZE: u32 = 64;
+const CLUSTER_SHADER: Shader =
+    Shader::new("shaders/cluster.wgsl", include_str!("shaders/cluster.wgsl"));
+
+const fn cluster_count() -> u32 {
+    CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2]
+}
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+struct GpuClusters {
+    view: Mat4,
+    grid: UVec3,
+    max_cluster_lights: u32,
+    screen_size: Vec2,
+    projection_scale: Vec2,
+    near: f32,
+    far: f32,
+    light_count: u32,
+}
+
+/// Lights of the scene, binned into view space clusters by a compute pass.
+///
+/// Screen tiles are split into depth slices spaced logarithmically between the camera planes,
+/// the lit and pbr shaders only walk the lights of the cluster each fragment falls in.
+#[derive(Debug)]
+pub struct LightClusters {
+    /// Grows when lights are added, see [`LightClusters::update_lights`]
+    pub lights: StorageBufferArray<Light>,
+    clusters: UniformBuffer<GpuClusters>,
+    cluster_lights: Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl LightClusters {
+    pub fn new(lights: &[Light], device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let lights = StorageBufferArray::new(lights, device, queue, lights.len().max(1) as u64);
+        let clusters = UniformBuffer::new(GpuClusters::default(), device);
+        let cluster_lights = Buffer::new(
+            device,
+            u64::from(4 * cluster_count() * (MAX_CLUSTER_LIGHTS + 1)),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let storage = |read_only| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let entries: Vec<_> = [
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            storage(true),
+            storage(false),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, ty)| wgpu::BindGroupLayoutEntry {
+            binding: binding as u32,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty,
+            count: None,
+        })
+        .collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Clusters Layout"),
+            entries: &entries,
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &clusters.buffer,
+            &lights.buffer,
+            &cluster_lights.buffer,
+        );
+        let pipeline = ComputePipelineBuilder::new(CLUSTER_SHADER.variant(&ShaderDefs::new()))
+            .add_bind_group(&layout)
+            .build(device, "assign_lights");
+        Self {
+            lights,
+            clusters,
+            cluster_lights,
+            layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        clusters: &wgpu::Buffer,
+        lights: &wgpu::Buffer,
+        cluster_lights: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        let entries: Vec<_> = [clusters, lights, cluster_lights]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Clusters"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    /// Uploads [`Self::lights`], growing the buffer if they don't fit anymore.
+    ///
+    /// Returns true when the buffer was replaced, bind groups made with [`Self::bind`] have to be rebuilt.
+    pub fn update_lights(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let grown = self.lights.update_or_grow(device, queue);
+        if grown {
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.layout,
+                &self.clusters.buffer,
+                &self.lights.buffer,
+                &self.cluster_lights.buffer,
+            );
+        }
+        grown
+    }
+
+    /// Fits the clusters to the frustum of `camera` seen from `view`, on a `width` x `height` target.
+    pub fn update(
+        &mut self,
+        view: Mat4,
+        camera: &PerspectiveCamera,
+        width: u32,
+        height: u32,
+        queue: &wgpu::Queue,
+    ) {
+        let projection = camera.matrix_rh();
+        self.clusters.data = GpuClusters {
+            view,
+            grid: UVec3::from_array(CLUSTER_GRID),
+            max_cluster_lights: MAX_CLUSTER_LIGHTS,
+            screen_size: Vec2::new(width as f32, height as f32),
+            projection_scale: Vec2::new(projection.x_axis.x, projection.y_axis.y),
+            near: camera.near,
+            far: camera.far,
+            light_count: self.lights.data.len() as u32,
+        };
+        self.clusters.update(queue);
+    }
+
+    /// Records the compute pass that fills the light list of every cluster.
+    pub fn assign(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Assign Lights To Clusters"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups(cluster_count().div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// Adds the [`Self::assign`] pass, returns the light lists for the passes shading with them.
+    pub fn add_to_graph<'a>(&'a self, graph: &mut RenderGraph<'a>) -> ResourceHandle {
+        let cluster_lights = graph.import_buffer(&self.cluster_lights.buffer);
+        graph
+            .add_pass("Assign Lights To Clusters")
+            .write(cluster_lights)
+            .run(move |ctx| self.assign(ctx.encoder));
+        cluster_lights
+    }
+
+    /// Adds the lights, cluster parameters and cluster light lists to `builder`.
+    ///
+    /// The lit and pbr shaders expect them right after the camera at [`SCENE_GROUP`](super::material::SCENE_GROUP).
+    pub fn bind<'a>(&'a self, builder: BindGroupBuilder<'a>) -> BindGroupBuilder<'a> {
+        builder
+            .storage_buffer(&self.lights.buffer)
+            .uniform(&self.clusters.buffer)
+            .storage_buffer(&self.cluster_lights.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{cluster_count, GpuClusters, LightClusters, CLUSTER_SHADER, MAX_CLUSTER_LIGHTS};
+    use crate::{
+        renderer::{
+            camera::OrbitCamera,
+            color::Color,
+            light::{DirectionalLight, GpuLight, Light, PointLight},
+            reflection::ShaderReflection,
+            shader::ShaderDefs,
+        },
+        tests::device,
+    };
+
+    /// Runs the clustering pass and returns the light indices of every cluster.
+    fn assign(
+        clusters: &mut LightClusters,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<Vec<u32>> {
+        let camera = OrbitCamera::new(2.0, 1.0);
+        clusters.update(camera.view(), camera.perspective(), 64, 64, queue);
+        let source = &clusters.cluster_lights.buffer;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: source.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        clusters.assign(&mut encoder);
+        encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, source.size());
+        queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        data.chunks(MAX_CLUSTER_LIGHTS as usize + 1)
+            .map(|list| list[1..=list[0] as usize].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn lights_only_reach_clusters_in_their_range() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let lights = [
+            DirectionalLight::default().into(),
+            // At the orbit target, in front of the camera
+            PointLight::new(Color::WHITE, Vec3::ZERO, 0.5, [0.0, 0.0, 1.0]).into(),
+            // Behind the camera
+            PointLight::new(Color::WHITE, Vec3::Z * 10.0, 1.0, [0.0, 0.0, 1.0]).into(),
+        ];
+        let mut clusters = LightClusters::new(&lights, &device, &queue);
+        let lists = assign(&mut clusters, &device, &queue);
+        assert_eq!(lists.len(), cluster_count() as usize);
+        assert!(
+            lists.iter().all(|list| list.first() == Some(&0)),
+            "Directional lights reach every cluster"
+        );
+        let lit = lists.iter().filter(|list| list.contains(&1)).count();
+        assert!(
+            lit > 0 && lit < lists.len(),
+            "The point light reaches some clusters, got {lit}"
+        );
+        assert!(
+            !lists.iter().any(|list| list.contains(&2)),
+            "Lights behind the camera reach no cluster"
+        );
+    }
+
+    #[test]
+    fn light_buffer_grows_on_demand() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut clusters =
+            LightClusters::new(&[DirectionalLight::default().into()], &device, &queue);
+        clusters
+            .lights
+            .data
+            .extend(std::iter::repeat(Light::from(PointLight::default())).take(299));
+        assert!(
+            clusters.update_lights(&device, &queue),
+            "Buffer is replaced when lights outgrow it"
+        );
+        assert!(
+            clusters.lights.buffer.size() >= (300 * size_of::<GpuLight>()) as u64,
+            "Grown buffer fits every light"
+        );
+        assert!(
+            !clusters.update_lights(&device, &queue),
+            "Buffer is kept while lights fit"
+        );
+        let lists = assign(&mut clusters, &device, &queue);
+        assert!(
+            lists
+                .iter()
+                .any(|list| list.len() == MAX_CLUSTER_LIGHTS as usize),
+            "Crowded clusters are capped"
+        );
+    }
+
+    #[test]
+    fn gpu_types_match_their_shader_structs() {
+        let descriptor = CLUSTER_SHADER.variant(&ShaderDefs::new());
+        let wgpu::ShaderSource::Wgsl(source) = descriptor.source else {
+            unreachable!("The cluster shader is WGSL");
+        };
+        let reflection = ShaderReflection::new(descriptor.label.unwrap(), &source).unwrap();
+        reflection.check_struct::<GpuClusters>("Clusters").unwrap();
+        reflection.check_struct::<GpuLight>("Light").unwrap();
+    }
+}
@@ -1,19 +1,45 @@
 use std::ops::{Add, AddAssign, Mul, MulAssign};
 
 use bytemuck::{Pod, Zeroable};
-use glam::Vec3;
+use glam::{Vec3, Vec4};
+use serde::{Deserialize, Serialize};
 
+use crate::core::shader_type::{ShaderType, TypeLayout};
+
+/// Linear RGB color with straight (not premultiplied) alpha.
 #[allow(clippy::module_name_repetitions)]
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, Serialize, Deserialize)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
     pub b: f32,
+    /// Opaque when missing from serialized colors
+    #[serde(default = "Color::opaque")]
+    pub a: f32,
+}
+/// A `vec4f` in shaders
+impl ShaderType for Color {
+    const LAYOUT: TypeLayout = Vec4::LAYOUT;
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[..16].copy_from_slice(bytemuck::bytes_of(self));
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Self::BLACK
+    }
 }
 impl From<wgpu::Color> for Color {
     fn from(value: wgpu::Color) -> Self {
-        Self::new(value.r as f32, value.g as f32, value.b as f32)
+        Self::rgba(
+            value.r as f32,
+            value.g as f32,
+            value.b as f32,
+            value.a as f32,
+        )
     }
 }
 impl From<Color> for wgpu::Color {
@@ -22,7 +48,7 @@ impl From<Color> for wgpu::Color {
             r: value.r.into(),
             g: value.g.into(),
             b: value.b.into(),
-            a: 1.0,
+            a: value.a.into(),
         }
     }
 }
@@ -36,6 +62,16 @@ impl From<Color> for [f32; 3] {
         [value.r, value.g, value.b]
     }
 }
+impl From<[f32; 4]> for Color {
+    fn from(value: [f32; 4]) -> Self {
+        Self::rgba(value[0], value[1], value[2], value[3])
+    }
+}
+impl From<Color> for [f32; 4] {
+    fn from(value: Color) -> Self {
+        [value.r, value.g, value.b, value.a]
+    }
+}
 
 impl From<Vec3> for Color {
     fn from(value: Vec3) -> Self {
@@ -47,6 +83,16 @@ impl From<Color> for Vec3 {
         Self::new(value.r, value.g, value.b)
     }
 }
+impl From<Vec4> for Color {
+    fn from(value: Vec4) -> Self {
+        Self::rgba(value.x, value.y, value.z, value.w)
+    }
+}
+impl From<Color> for Vec4 {
+    fn from(value: Color) -> Self {
+        Self::new(value.r, value.g, value.b, value.a)
+    }
+}
 
 impl Color {
     pub const BLACK: Self = Self::new(0.0, 0.0, 0.0);
@@ -54,12 +100,28 @@ impl Color {
     pub const RED: Self = Self::new(1.0, 0.0, 0.0);
     pub const GREEN: Self = Self::new(0.0, 1.0, 0.0);
     pub const BLUE: Self = Self::new(0.0, 0.0, 1.0);
+    pub const TRANSPARENT: Self = Self::rgba(0.0, 0.0, 0.0, 0.0);
 
+    /// Opaque color
     pub const fn new(r: f32, g: f32, b: f32) -> Self {
-        Self { r, g, b }
+        Self::rgba(r, g, b, 1.0)
+    }
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+    pub const fn with_alpha(self, a: f32) -> Self {
+        Self { a, ..self }
+    }
+    /// Color channels multiplied by alpha, for [`AlphaMode::Premultiplied`](super::material::AlphaMode::Premultiplied)
+    pub fn premultiplied(self) -> Self {
+        Self::rgba(self.r * self.a, self.g * self.a, self.b * self.a, self.a)
+    }
+    const fn opaque() -> f32 {
+        1.0
     }
 }
 
+/// Modulates both the color and alpha channels
 impl Mul for Color {
     type Output = Self;
 
@@ -68,6 +130,7 @@ impl Mul for Color {
             r: self.r * rhs.r,
             g: self.g * rhs.g,
             b: self.b * rhs.b,
+            a: self.a * rhs.a,
         }
     }
 }
@@ -81,6 +144,7 @@ impl AddAssign for Color {
         *self = *self + rhs;
     }
 }
+/// Adds the color channels, keeping the alpha of `self`
 impl Add for Color {
     type Output = Self;
 
@@ -89,10 +153,12 @@ impl Add for Color {
             r: self.r + rhs.r,
             g: self.g + rhs.g,
             b: self.b + rhs.b,
+            a: self.a,
         }
     }
 }
 
+/// Scales the color channels, like the intensity of a light
 impl Mul<f32> for Color {
     type Output = Self;
 
@@ -101,6 +167,7 @@ impl Mul<f32> for Color {
             r: rhs * self.r,
             g: rhs * self.g,
             b: rhs * self.b,
+            a: self.a,
         }
     }
 }
@@ -17,7 +17,7 @@ pub fn generate_mipmaps(
             base_mip_level: level,
             mip_level_count: Some(1),
             base_array_layer: layer,
-            array_layer_count: Some(texture.depth_or_array_layers()),
+            array_layer_count: Some(1),
         }));
         if level > 0 {
             let previous_size = mip_sizes[level as usize - 1];
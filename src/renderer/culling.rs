@@ -0,0 +1,277 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+use wgpu::include_wgsl;
+
+use crate::{core::shader_type::ShaderType, visibility::frustum::Frustum};
+
+use super::{
+    buffer::{grow, UniformBuffer},
+    compute::ComputePipelineBuilder,
+    model::Instance,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+struct GpuFrustum {
+    planes: [Vec4; 6],
+    plane_count: u32,
+    instance_count: u32,
+}
+
+/// An instance to cull, with the batch whose draw it is counted in.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, ShaderType)]
+pub struct CullInstance {
+    pub instance: Instance,
+    /// World space center in xyz, radius in w
+    pub sphere: Vec4,
+    /// Index of the draw in the indirect buffer
+    pub batch: u32,
+    /// Where the visible instances of the batch start in [`GpuCulling::visible`]
+    pub first: u32,
+    #[padding]
+    _padding: [u32; 2],
+}
+
+impl CullInstance {
+    pub const fn new(instance: Instance, sphere: Vec4, batch: u32, first: u32) -> Self {
+        Self {
+            instance,
+            sphere,
+            batch,
+            first,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Arguments of `draw_indexed_indirect`, laid out like `wgpu::util::DrawIndexedIndirectArgs`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, PartialEq, Eq, ShaderType)]
+pub struct DrawIndexedArgs {
+    pub index_count: u32,
+    /// Counted by the culling pass, upload zero
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+/// Frustum culling of instances in a compute pass.
+///
+/// Visible instances are packed per batch into [`Self::visible`], and counted in the indirect draws of [`Self::draws`].
+#[derive(Debug)]
+pub struct GpuCulling {
+    frustum: UniformBuffer<GpuFrustum>,
+    instances: wgpu::Buffer,
+    draws: wgpu::Buffer,
+    visible: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    multi_draw: bool,
+}
+
+impl GpuCulling {
+    /// Whether `adapter` can run the culling pass and draw its results.
+    pub fn supported(adapter: &wgpu::Adapter) -> bool {
+        adapter.get_downlevel_capabilities().flags.contains(
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION,
+        )
+    }
+
+    /// Features [`Self::multi_draw`] needs, requested by the renderer when the adapter has them.
+    pub const MULTI_DRAW_FEATURES: wgpu::Features =
+        wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let frustum = UniformBuffer::new(GpuFrustum::default(), device);
+        let storage = |size: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: size as u64,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let instances = storage(size_of::<CullInstance>(), wgpu::BufferUsages::COPY_DST);
+        let draws = storage(
+            size_of::<DrawIndexedArgs>(),
+            // COPY_SRC lets the counts be read back
+            wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        );
+        let visible = storage(size_of::<Instance>(), wgpu::BufferUsages::VERTEX);
+
+        let storage = |read_only| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let entries: Vec<_> = [
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            storage(true),
+            storage(false),
+            storage(false),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, ty)| wgpu::BindGroupLayoutEntry {
+            binding: binding as u32,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty,
+            count: None,
+        })
+        .collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Culling Layout"),
+            entries: &entries,
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            [&frustum.buffer, &instances, &draws, &visible],
+        );
+        let pipeline = ComputePipelineBuilder::new(include_wgsl!("shaders/cull.wgsl"))
+            .add_bind_group(&layout)
+            .build(device, "cull");
+        Self {
+            frustum,
+            instances,
+            draws,
+            visible,
+            layout,
+            bind_group,
+            pipeline,
+            multi_draw: device.features().contains(Self::MULTI_DRAW_FEATURES),
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffers: [&wgpu::Buffer; 4],
+    ) -> wgpu::BindGroup {
+        let entries: Vec<_> = buffers
+            .into_iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Culling"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    /// Whether all draws of a material can be recorded with one `multi_draw_indexed_indirect`.
+    ///
+    /// Otherwise every draw has `first_instance` zero, and [`Self::visible`] has to be bound at the batch start.
+    pub const fn multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    /// Uploads the instances to cull against `frustum` and the draws counting them, growing the buffers as needed.
+    pub fn update(
+        &mut self,
+        frustum: Option<&Frustum>,
+        instances: &[CullInstance],
+        draws: &[DrawIndexedArgs],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let mut planes = [Vec4::ZERO; 6];
+        let plane_count = frustum.map_or(0, |frustum| {
+            let frustum_planes = frustum.planes();
+            for (plane, frustum_plane) in planes.iter_mut().zip(&frustum_planes) {
+                *plane = frustum_plane.homogeneous();
+            }
+            frustum_planes.len() as u32
+        });
+        self.frustum.data = GpuFrustum {
+            planes,
+            plane_count,
+            instance_count: instances.len() as u32,
+        };
+        self.frustum.update(queue);
+
+        let instances_size = size_of_val(instances) as u64;
+        let mut grown = grow(&mut self.instances, instances_size, device);
+        grown |= grow(&mut self.draws, size_of_val(draws) as u64, device);
+        grown |= grow(
+            &mut self.visible,
+            (size_of::<Instance>() * instances.len()) as u64,
+            device,
+        );
+        if grown {
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.layout,
+                [
+                    &self.frustum.buffer,
+                    &self.instances,
+                    &self.draws,
+                    &self.visible,
+                ],
+            );
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(instances));
+            queue.write_buffer(&self.draws, 0, bytemuck::cast_slice(draws));
+        }
+    }
+
+    /// Records the compute pass that culls the instances of the last [`Self::update`].
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        let instance_count = self.frustum.data.instance_count;
+        if instance_count == 0 {
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Instances"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups(instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// Indirect draw arguments, one [`DrawIndexedArgs`] per batch
+    pub const fn draws(&self) -> &wgpu::Buffer {
+        &self.draws
+    }
+
+    /// Visible instances, the vertex buffer of the indirect draws
+    pub const fn visible(&self) -> &wgpu::Buffer {
+        &self.visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CullInstance, DrawIndexedArgs, GpuFrustum};
+    use crate::renderer::{model::Instance, reflection::ShaderReflection};
+
+    #[test]
+    fn gpu_types_match_their_shader_structs() {
+        let reflection =
+            ShaderReflection::new("shaders/cull.wgsl", include_str!("shaders/cull.wgsl")).unwrap();
+        reflection.check_struct::<GpuFrustum>("Frustum").unwrap();
+        reflection.check_struct::<Instance>("Instance").unwrap();
+        reflection
+            .check_struct::<CullInstance>("CullInstance")
+            .unwrap();
+        reflection
+            .check_struct::<DrawIndexedArgs>("DrawArgs")
+            .unwrap();
+    }
+}
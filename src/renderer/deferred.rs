@@ -0,0 +1,356 @@
+use super::{
+    post_process::{fullscreen_shader, HDR_FORMAT},
+    render_graph::{PassContext, RenderGraph, ResourceHandle, TextureDesc},
+    render_pipeline::RenderPipelineBuilder,
+    shader::{ShaderComposer, ShaderDefs},
+    texture::Texture,
+};
+
+/// Formats of the albedo, normal and material targets of the [`GBuffer`], in the order of the shader outputs
+pub const GBUFFER_FORMATS: [wgpu::TextureFormat; 3] = [
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+/// Surface attributes of the deferred path, written by the [`Material::gbuffer_entry`](super::material::Material::gbuffer_entry)
+/// of each material and shaded by [`DeferredLighting`].
+#[derive(Debug, Clone, Copy)]
+pub struct GBuffer {
+    pub albedo: ResourceHandle,
+    /// World space normal, after normal mapping
+    pub normal: ResourceHandle,
+    /// Roughness, specular and index of refraction
+    pub material: ResourceHandle,
+    /// Positions are reconstructed from it
+    pub depth: ResourceHandle,
+}
+
+impl GBuffer {
+    /// Declares the targets for this frame, they are never multisampled.
+    pub fn create(graph: &mut RenderGraph) -> Self {
+        let [albedo, normal, material] = GBUFFER_FORMATS;
+        Self {
+            albedo: graph.create_texture(TextureDesc::new("G-Buffer Albedo", albedo)),
+            normal: graph.create_texture(TextureDesc::new("G-Buffer Normal", normal)),
+            material: graph.create_texture(TextureDesc::new("G-Buffer Material", material)),
+            depth: graph.create_texture(TextureDesc::new("G-Buffer Depth", Texture::DEPTH_FORMAT)),
+        }
+    }
+
+    /// Starts a pass clearing the targets, for pipelines made with
+    /// [`MaterialPipelineBuilder::gbuffer`](super::material::MaterialPipelineBuilder::gbuffer).
+    pub fn begin_pass<'p>(&self, ctx: &'p mut PassContext<'_>) -> wgpu::RenderPass<'p> {
+        let attachment = |handle| {
+            Some(wgpu::RenderPassColorAttachment {
+                view: ctx.view(handle),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        };
+        let color_attachments = [
+            attachment(self.albedo),
+            attachment(self.normal),
+            attachment(self.material),
+        ];
+        let depth = ctx.view(self.depth);
+        ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+}
+
+/// Full-screen pass shading the [`GBuffer`] with the lights and environment of the scene.
+///
+/// It shares its lighting with [`PbrMaterial`](super::material::PbrMaterial), so it takes the scene and shadow
+/// bind groups of the forward pipelines, at [`SCENE_GROUP`](super::material::SCENE_GROUP) and
+/// [`SHADOW_GROUP`](super::material::SHADOW_GROUP). The G-buffer is bound at group 0.
+#[derive(Debug)]
+pub struct DeferredLighting {
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl DeferredLighting {
+    pub fn new(
+        device: &wgpu::Device,
+        scene_layout: &wgpu::BindGroupLayout,
+        shadow_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        // Depth included, the targets are only loaded from
+        let entries: Vec<_> = (0..4)
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            })
+            .collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-Buffer Layout"),
+            entries: &entries,
+        });
+        let lighting = ShaderComposer::new()
+            .compose(
+                "shaders/deferred.wgsl",
+                include_str!("shaders/deferred.wgsl"),
+                &ShaderDefs::new(),
+            )
+            .unwrap_or_else(|e| panic!("{e}"));
+        let pipeline = RenderPipelineBuilder::new(fullscreen_shader(&lighting))
+            .add_bind_group(&layout)
+            .add_bind_group(scene_layout)
+            .add_bind_group(shadow_layout)
+            .blend(wgpu::BlendState::REPLACE)
+            .build_fullscreen(device, HDR_FORMAT);
+        Self { layout, pipeline }
+    }
+
+    /// Binds the targets of `gbuffer` for [`Self::draw`], in a pass that reads them.
+    pub fn bind_group(&self, ctx: &PassContext<'_>, gbuffer: &GBuffer) -> wgpu::BindGroup {
+        let entries: Vec<_> = [
+            gbuffer.albedo,
+            gbuffer.normal,
+            gbuffer.material,
+            gbuffer.depth,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, handle)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: wgpu::BindingResource::TextureView(ctx.view(handle)),
+        })
+        .collect();
+        ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("G-Buffer"),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+
+    /// Shades every covered pixel of the G-buffer into an [`HDR_FORMAT`] target, the background is left as it is.
+    ///
+    /// The scene and shadow bind groups must be set.
+    pub fn draw<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>, gbuffer: &'p wgpu::BindGroup) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, gbuffer, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use glam::{Affine3A, Vec3};
+    use image::RgbaImage;
+
+    use super::{DeferredLighting, GBuffer};
+    use crate::{
+        collision::shapes::{Cuboid, Sphere},
+        renderer::{
+            batch::Batcher,
+            bind_group::BindGroupBuilder,
+            buffer::UniformBuffer,
+            camera::OrbitCamera,
+            cluster::LightClusters,
+            color::Color,
+            environment::Environment,
+            golden::compare_images,
+            light::DirectionalLight,
+            material::{PbrMaterialBuilder, UnlitMaterialBuilder, SCENE_GROUP, SHADOW_GROUP},
+            mesh::{Meshable, Vertex},
+            model::{Instance, Model},
+            post_process::{
+                BloomSettings, PostProcess, PostProcessSettings, Tonemapping, HDR_FORMAT,
+            },
+            render_graph::{RenderGraph, TexturePool},
+            render_pipeline::{RenderPassBuilder, RenderPipelineBuilder},
+            resources::get_texture_data,
+            shadow::ShadowMaps,
+            texture::Texture,
+            wgpu_renderer::ShadingPath,
+        },
+        tests::device,
+    };
+
+    const SIZE: u32 = 64;
+
+    /// A pbr sphere next to an unlit cube, lit by a directional light and tonemapped linearly.
+    fn render(shading: ShadingPath, device: &wgpu::Device, queue: &wgpu::Queue) -> RgbaImage {
+        let camera = UniformBuffer::new(OrbitCamera::new(3.0, 1.0), device);
+        let light = DirectionalLight {
+            direction: Vec3::NEG_ONE,
+            ..Default::default()
+        };
+        let mut light_clusters = LightClusters::new(&[light.into()], device, queue);
+        let environment = Environment::from_color(Color::new(0.1, 0.1, 0.1), device, queue);
+        let scene = environment
+            .bind(light_clusters.bind(BindGroupBuilder::new().uniform(&camera.buffer)))
+            .build(device);
+        let mut shadow_maps = ShadowMaps::new(
+            device,
+            256,
+            ShadowMaps::DEFAULT_LAYERS,
+            ShadowMaps::DEFAULT_CUBES,
+            16,
+        );
+        let sphere = Model::new(
+            Affine3A::from_translation(Vec3::X * -0.6),
+            Rc::new(Sphere::new(Vec3::ZERO, 0.5).mesh()),
+            PbrMaterialBuilder::new()
+                .diffuse_color(Color::new(0.8, 0.4, 0.2))
+                .roughness(0.5)
+                .build(device, queue),
+        );
+        let cube = Model::new(
+            Affine3A::from_translation(Vec3::X * 0.6),
+            Rc::new(Cuboid::new(Vec3::splat(0.5)).mesh()),
+            UnlitMaterialBuilder::new()
+                .diffuse_color(Color::new(0.2, 0.4, 0.8))
+                .build(device, queue),
+        );
+
+        light_clusters.update(
+            camera.data.view(),
+            camera.data.perspective(),
+            SIZE,
+            SIZE,
+            queue,
+        );
+        shadow_maps.update(
+            &light_clusters.lights.data,
+            camera.data.view(),
+            camera.data.perspective(),
+            queue,
+        );
+        let mut batcher = Batcher::new(device, false, shading);
+        batcher.prepare(
+            [&sphere, &cube],
+            None,
+            camera.data.position(),
+            device,
+            queue,
+            |builder: RenderPipelineBuilder| {
+                builder
+                    .bind_group(&scene)
+                    .bind_group(&shadow_maps.bind_group)
+                    .depth(Texture::DEPTH_FORMAT)
+                    .build_with_instancing::<Vertex, Instance>(device, HDR_FORMAT)
+            },
+        );
+        let lighting = DeferredLighting::new(device, &scene.layout, &shadow_maps.bind_group.layout);
+        let mut post_process = PostProcess::new(device, wgpu::TextureFormat::Rgba8Unorm);
+        post_process.settings = PostProcessSettings {
+            tonemapping: Tonemapping::None,
+            bloom: BloomSettings {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut graph = RenderGraph::new(SIZE, SIZE, 1);
+        let cluster_lights = light_clusters.add_to_graph(&mut graph);
+        let instances = batcher.add_to_graph(&mut graph);
+        let shadows = shadow_maps.add_to_graph(&mut graph, batcher.shadow_casters());
+        let hdr = PostProcess::create_target(&mut graph);
+        let (batcher, scene, shadow_maps) = (&batcher, &scene, &shadow_maps);
+        let depth = if shading == ShadingPath::Deferred {
+            let gbuffer = GBuffer::create(&mut graph);
+            graph
+                .add_pass("G-Buffer")
+                .read(instances)
+                .write(gbuffer.albedo)
+                .write(gbuffer.normal)
+                .write(gbuffer.material)
+                .write(gbuffer.depth)
+                .run(move |ctx| {
+                    let mut rpass = gbuffer.begin_pass(ctx);
+                    rpass.set_bind_group(SCENE_GROUP, &scene.bind_group, &[]);
+                    rpass.set_bind_group(SHADOW_GROUP, &shadow_maps.bind_group.bind_group, &[]);
+                    batcher.draw_gbuffer(&mut rpass);
+                });
+            let lighting = &lighting;
+            graph
+                .add_pass("Lighting")
+                .read(cluster_lights)
+                .read(shadows)
+                .read(gbuffer.albedo)
+                .read(gbuffer.normal)
+                .read(gbuffer.material)
+                .read(gbuffer.depth)
+                .write(hdr)
+                .run(move |ctx| {
+                    let bind_group = lighting.bind_group(ctx, &gbuffer);
+                    let mut rpass = RenderPassBuilder::new().build(ctx.encoder, ctx.view(hdr));
+                    rpass.set_bind_group(SCENE_GROUP, &scene.bind_group, &[]);
+                    rpass.set_bind_group(SHADOW_GROUP, &shadow_maps.bind_group.bind_group, &[]);
+                    lighting.draw(&mut rpass, &bind_group);
+                });
+            gbuffer.depth
+        } else {
+            graph.create_depth_texture()
+        };
+        graph
+            .add_pass("Forward")
+            .read(cluster_lights)
+            .read(instances)
+            .read(shadows)
+            .read_write(hdr)
+            .read_write(depth)
+            .run(move |ctx| {
+                let mut builder = RenderPassBuilder::new().depth(ctx.view(depth));
+                if shading == ShadingPath::Deferred {
+                    builder = builder.load();
+                }
+                let mut rpass = builder.build(ctx.encoder, ctx.view(hdr));
+                rpass.set_bind_group(SCENE_GROUP, &scene.bind_group, &[]);
+                rpass.set_bind_group(SHADOW_GROUP, &shadow_maps.bind_group.bind_group, &[]);
+                batcher.draw(&mut rpass);
+            });
+        post_process.add_to_graph(&mut graph, hdr, RenderGraph::SURFACE);
+
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let output = Texture::render_target(device, SIZE, SIZE, format, format);
+        graph.execute(device, queue, &mut TexturePool::default(), &output.view);
+        let (pixels, ..) = get_texture_data(&output.texture, device, queue, 0);
+        RgbaImage::from_raw(SIZE, SIZE, pixels.samples).expect("Readback has the target size")
+    }
+
+    #[test]
+    fn deferred_lighting_matches_forward() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let forward = render(ShadingPath::Forward, &device, &queue);
+        let deferred = render(ShadingPath::Deferred, &device, &queue);
+        let lit = deferred.pixels().filter(|pixel| pixel.0[0] > 16).count();
+        assert!(lit > 100, "The sphere is lit, {lit} pixels");
+        let comparison = compare_images(&forward, &deferred, 8).expect("Same size");
+        assert!(
+            comparison.mismatched_ratio() < 0.01,
+            "G-buffer precision barely changes the image, {} pixels differ by up to {}",
+            comparison.mismatched_pixels,
+            comparison.max_channel_difference
+        );
+    }
+}
@@ -1,9 +1,20 @@
-use super::texture::Texture;
+use super::{
+    render_graph::{RenderGraph, ResourceHandle},
+    texture::Texture,
+};
+
+/// Ui of the current frame, waiting to be drawn.
+struct EguiFrame {
+    tris: Vec<egui::ClippedPrimitive>,
+    free: Vec<egui::TextureId>,
+    screen_descriptor: egui_wgpu::ScreenDescriptor,
+}
 
 #[allow(missing_debug_implementations)]
 pub struct EguiRenderer {
     state: egui_winit::State,
     renderer: egui_wgpu::Renderer,
+    frame: Option<EguiFrame>,
 }
 
 impl EguiRenderer {
@@ -44,6 +55,7 @@ impl EguiRenderer {
         Self {
             state: egui_state,
             renderer: egui_renderer,
+            frame: None,
         }
     }
 
@@ -58,15 +70,13 @@ impl EguiRenderer {
         self.context().set_pixels_per_point(v);
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub(crate) fn draw(
+    /// Runs the ui and uploads its textures, [`EguiRenderer::add_to_graph`] then draws it.
+    pub(crate) fn prepare(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        encoder: &mut wgpu::CommandEncoder,
         window: &winit::window::Window,
-        window_surface_view: &wgpu::TextureView,
-        screen_descriptor: &egui_wgpu::ScreenDescriptor,
+        screen_descriptor: egui_wgpu::ScreenDescriptor,
         run_ui: impl FnOnce(&egui::Context),
     ) {
         self.set_pixels_per_point(screen_descriptor.pixels_per_point);
@@ -86,26 +96,50 @@ impl EguiRenderer {
             self.renderer
                 .update_texture(device, queue, id, &image_delta);
         }
-        self.renderer
-            .update_buffers(device, queue, encoder, &tris, screen_descriptor);
-        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: window_surface_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            label: Some("egui main render pass"),
-            occlusion_query_set: None,
+        self.frame = Some(EguiFrame {
+            tris,
+            free: full_output.textures_delta.free,
+            screen_descriptor,
+        });
+    }
+
+    /// Adds the pass drawing the ui prepared last over `target`.
+    pub(crate) fn add_to_graph<'a>(
+        &'a mut self,
+        graph: &mut RenderGraph<'a>,
+        target: ResourceHandle,
+    ) {
+        let Some(frame) = self.frame.take() else {
+            return;
+        };
+        let renderer = &mut self.renderer;
+        graph.add_pass("egui").read_write(target).run(move |ctx| {
+            renderer.update_buffers(
+                ctx.device,
+                ctx.queue,
+                ctx.encoder,
+                &frame.tris,
+                &frame.screen_descriptor,
+            );
+            let mut rpass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: ctx.view(target),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                label: Some("egui main render pass"),
+                occlusion_query_set: None,
+            });
+            renderer.render(&mut rpass, &frame.tris, &frame.screen_descriptor);
+            drop(rpass);
+            for x in &frame.free {
+                renderer.free_texture(x);
+            }
         });
-        self.renderer.render(&mut rpass, &tris, screen_descriptor);
-        drop(rpass);
-        for x in &full_output.textures_delta.free {
-            self.renderer.free_texture(x);
-        }
     }
 }
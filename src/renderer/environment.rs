@@ -0,0 +1,677 @@
+use std::{path::Path, rc::Rc};
+
+use image::{DynamicImage, ImageError, Rgba, Rgba32FImage};
+use wgpu::{include_wgsl, util::DeviceExt};
+
+use crate::core::shader_type::ShaderType;
+
+use super::{
+    bind_group::{BindGroup, BindGroupBuilder},
+    buffer::UniformBuffer,
+    color::Color,
+    compute::ComputePipelineBuilder,
+    resources::get_max_mip_level_count,
+    shader::{Shader, ShaderDefs},
+    texture::Texture,
+};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const WORKGROUP_SIZE: u32 = 8;
+const SKYBOX_SHADER: Shader =
+    Shader::new("shaders/skybox.wgsl", include_str!("shaders/skybox.wgsl"));
+
+/// Image based lighting textures filtered from a skybox cubemap.
+///
+/// The pbr shader samples the irradiance map for diffuse ambient light and the prefiltered map
+/// together with the BRDF lookup table for specular reflections.
+#[derive(Debug)]
+pub struct Environment {
+    pub skybox: Texture,
+    /// Cosine weighted average of the skybox around each direction
+    pub irradiance: Texture,
+    /// Skybox blurred by the GGX lobe, see [`Environment::prefiltered_roughness`]
+    pub prefiltered: Texture,
+    /// Scale and bias to the Fresnel reflectance at normal incidence, by `NdotV` and roughness
+    pub brdf_lut: Texture,
+}
+
+impl Environment {
+    pub const IRRADIANCE_SIZE: u32 = 32;
+    pub const PREFILTERED_SIZE: u32 = 128;
+    pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+    pub const BRDF_LUT_SIZE: u32 = 256;
+
+    /// Filters a cubemap, like the ones created by [`Texture::cubemap`].
+    pub fn from_cubemap(skybox: Texture, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        // The skybox view may only expose the first mip, filtering reads all of them
+        let source = cube_view(&skybox.texture);
+        Self::filter(skybox, &source, device, queue)
+    }
+
+    /// Projects an equirectangular panorama onto a cubemap and filters it, HDR images keep their range.
+    pub fn from_equirectangular(
+        image: &DynamicImage,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let image = image.to_rgba32f();
+        let equirectangular = device
+            .create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some("Equirectangular"),
+                    size: wgpu::Extent3d {
+                        width: image.width(),
+                        height: image.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                wgpu::util::TextureDataOrder::LayerMajor,
+                bytemuck::cast_slice(image.as_raw()),
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // A quarter of the panorama width keeps about the same texel density
+        let face_size = (image.width() / 4).max(1);
+        let texture = cube_texture(
+            device,
+            "Skybox",
+            face_size,
+            get_max_mip_level_count(face_size, face_size),
+        );
+        let layout = compute_layout(
+            device,
+            &[
+                (
+                    0,
+                    wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                ),
+                OUTPUT_BINDING,
+                FACE_BINDING,
+            ],
+        );
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Equirectangular To Cubemap"),
+        });
+        dispatch_faces(
+            device,
+            &mut encoder,
+            &compute_pipeline(device, &layout, "equirectangular_to_cubemap"),
+            &layout,
+            &texture,
+            &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&equirectangular),
+            }],
+        );
+        queue.submit([encoder.finish()]);
+
+        let skybox = Texture {
+            view: Rc::new(cube_view(&texture)),
+            sampler: Rc::new(cube_sampler(device)),
+            texture: Rc::new(texture),
+            egui_id: None,
+        };
+        let source = cube_view(&skybox.texture);
+        Self::filter(skybox, &source, device, queue)
+    }
+
+    /// Loads an equirectangular panorama, `.hdr` files included.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, ImageError> {
+        let image = image::open(path)?;
+        Ok(Self::from_equirectangular(&image, device, queue))
+    }
+
+    /// Environment of a single color, which lights every direction equally.
+    pub fn from_color(color: Color, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let image = Rgba32FImage::from_pixel(1, 1, Rgba([color.r, color.g, color.b, 1.0]));
+        Self::from_equirectangular(&image.into(), device, queue)
+    }
+
+    /// Roughness the prefiltered map is convolved with at `mip`, from 0 at the first to 1 at the last.
+    pub fn prefiltered_roughness(mip: u32) -> f32 {
+        mip_roughness(mip, Self::PREFILTERED_MIP_LEVELS)
+    }
+
+    /// Adds the skybox, irradiance, prefiltered and BRDF textures to `builder`.
+    ///
+    /// The pbr and skybox shaders expect them right after the [`LightClusters`](super::cluster::LightClusters) at [`SCENE_GROUP`](super::material::SCENE_GROUP).
+    pub fn bind<'a>(&'a self, builder: BindGroupBuilder<'a>) -> BindGroupBuilder<'a> {
+        builder
+            .cube_texture(&self.skybox)
+            .cube_texture(&self.irradiance)
+            .cube_texture(&self.prefiltered)
+            .texture(&self.brdf_lut)
+    }
+
+    fn filter(
+        skybox: Texture,
+        source: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let irradiance = cube_texture(device, "Irradiance", Self::IRRADIANCE_SIZE, 1);
+        let prefiltered = cube_texture(
+            device,
+            "Prefiltered Environment",
+            Self::PREFILTERED_SIZE,
+            Self::PREFILTERED_MIP_LEVELS,
+        );
+        let sampler = cube_sampler(device);
+        let layout = compute_layout(
+            device,
+            &[
+                OUTPUT_BINDING,
+                (
+                    2,
+                    wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                ),
+                (
+                    3,
+                    wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                ),
+                FACE_BINDING,
+            ],
+        );
+        let environment = [
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ];
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Filter Environment"),
+        });
+        dispatch_faces(
+            device,
+            &mut encoder,
+            &compute_pipeline(device, &layout, "irradiance"),
+            &layout,
+            &irradiance,
+            &environment,
+        );
+        dispatch_faces(
+            device,
+            &mut encoder,
+            &compute_pipeline(device, &layout, "prefilter"),
+            &layout,
+            &prefiltered,
+            &environment,
+        );
+        let brdf_lut = integrate_brdf(device, &mut encoder);
+        queue.submit([encoder.finish()]);
+
+        Self {
+            skybox,
+            irradiance: Texture {
+                view: Rc::new(cube_view(&irradiance)),
+                sampler: Rc::new(cube_sampler(device)),
+                texture: Rc::new(irradiance),
+                egui_id: None,
+            },
+            prefiltered: Texture {
+                view: Rc::new(cube_view(&prefiltered)),
+                sampler: Rc::new(cube_sampler(device)),
+                texture: Rc::new(prefiltered),
+                egui_id: None,
+            },
+            brdf_lut,
+        }
+    }
+}
+
+/// Draws the skybox of an [`Environment`] behind the scene.
+#[derive(Debug)]
+pub struct Skybox {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Skybox {
+    /// `scene_layout` is the layout of the group with the camera, lights and [`Environment`] textures.
+    /// `sample_count` is the MSAA sample count of the pass it is drawn in.
+    pub fn new(
+        device: &wgpu::Device,
+        scene_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(SKYBOX_SHADER.variant(&ShaderDefs::new()));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skybox Pipeline Layout"),
+            bind_group_layouts: &[scene_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Skybox Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(color_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Drawn on the far plane, only where nothing else was
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+        Self { pipeline }
+    }
+
+    /// Should be drawn after the opaque geometry, `scene` is bound at group 0.
+    pub fn render<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, scene: &'a BindGroup) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &scene.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+/// Uniform of environment.wgsl, selects the face and mip written by a dispatch.
+#[derive(Clone, Copy, Debug, ShaderType)]
+struct Face {
+    index: u32,
+    mip: u32,
+    roughness: f32,
+}
+
+const OUTPUT_BINDING: (u32, wgpu::BindingType) = (
+    1,
+    wgpu::BindingType::StorageTexture {
+        access: wgpu::StorageTextureAccess::WriteOnly,
+        format: FORMAT,
+        view_dimension: wgpu::TextureViewDimension::D2,
+    },
+);
+const FACE_BINDING: (u32, wgpu::BindingType) = (
+    4,
+    wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    },
+);
+
+fn mip_roughness(mip: u32, mip_level_count: u32) -> f32 {
+    mip as f32 / mip_level_count.saturating_sub(1).max(1) as f32
+}
+
+fn cube_texture(device: &wgpu::Device, label: &str, size: u32, mips: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: mips,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Every mip of a cubemap, for sampling.
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Cubemap View"),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn cube_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Cubemap"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// Entry points of environment.wgsl use different subsets of its bindings.
+fn compute_layout(
+    device: &wgpu::Device,
+    entries: &[(u32, wgpu::BindingType)],
+) -> wgpu::BindGroupLayout {
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|&(binding, ty)| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty,
+            count: None,
+        })
+        .collect();
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Environment Layout"),
+        entries: &entries,
+    })
+}
+
+fn compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    ComputePipelineBuilder::new(include_wgsl!("shaders/environment.wgsl"))
+        .add_bind_group(layout)
+        .build(device, entry_point)
+}
+
+/// Runs `pipeline` once for every face and mip of the cubemap `texture`.
+///
+/// Faces are written through single layer views, since OpenGL can't view a cubemap as an array.
+fn dispatch_faces(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::ComputePipeline,
+    layout: &wgpu::BindGroupLayout,
+    texture: &wgpu::Texture,
+    shared_entries: &[wgpu::BindGroupEntry],
+) {
+    let mip_level_count = texture.mip_level_count();
+    let faces: Vec<_> = (0..mip_level_count)
+        .flat_map(|mip| (0..6).map(move |index| (mip, index)))
+        .map(|(mip, index)| {
+            let face = Face {
+                index,
+                mip,
+                roughness: mip_roughness(mip, mip_level_count),
+            };
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Cubemap Face View"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                base_array_layer: index,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            (UniformBuffer::new(face, device), view)
+        })
+        .collect();
+    let bind_groups: Vec<_> = faces
+        .iter()
+        .map(|(face, view)| {
+            let mut entries = shared_entries.to_vec();
+            entries.extend([
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: face.buffer.as_entire_binding(),
+                },
+            ]);
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Cubemap Face"),
+                layout,
+                entries: &entries,
+            })
+        })
+        .collect();
+
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Cubemap Faces"),
+        timestamp_writes: None,
+    });
+    pass.set_pipeline(pipeline);
+    for ((face, _), bind_group) in faces.iter().zip(&bind_groups) {
+        pass.set_bind_group(0, bind_group, &[]);
+        dispatch(&mut pass, (texture.width() >> face.data.mip).max(1));
+    }
+}
+
+/// Computes the lookup table of [`Environment::brdf_lut`].
+fn integrate_brdf(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("BRDF Lookup Table"),
+        size: wgpu::Extent3d {
+            width: Environment::BRDF_LUT_SIZE,
+            height: Environment::BRDF_LUT_SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let layout = compute_layout(device, &[OUTPUT_BINDING]);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("BRDF Lookup Table"),
+        layout: &layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+
+    let pipeline = compute_pipeline(device, &layout, "integrate_brdf");
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("BRDF Lookup Table"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        dispatch(&mut pass, Environment::BRDF_LUT_SIZE);
+    }
+
+    Texture {
+        view: Rc::new(view),
+        sampler: Rc::new(device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("BRDF Lookup Table"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })),
+        texture: Rc::new(texture),
+        egui_id: None,
+    }
+}
+
+fn dispatch(pass: &mut wgpu::ComputePass, size: u32) {
+    let workgroups = size.div_ceil(WORKGROUP_SIZE);
+    pass.dispatch_workgroups(workgroups, workgroups, 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use super::{Environment, Face, Skybox};
+    use crate::{
+        renderer::{
+            bind_group::BindGroupBuilder, buffer::UniformBuffer, camera::OrbitCamera,
+            cluster::LightClusters, color::Color, light::PointLight, reflection::ShaderReflection,
+            render_pipeline::RenderPassBuilder, resources::get_texture_data, texture::Texture,
+        },
+        tests::device,
+    };
+
+    /// Draws `cubemap` as a skybox and returns the center pixel.
+    fn render_cubemap(cubemap: &Texture, device: &wgpu::Device, queue: &wgpu::Queue) -> [u8; 4] {
+        let camera = UniformBuffer::new(OrbitCamera::new(2.0, 1.0), device);
+        let lights = LightClusters::new(&[PointLight::default().into()], device, queue);
+        let scene = lights
+            .bind(BindGroupBuilder::new().uniform(&camera.buffer))
+            .cube_texture(cubemap)
+            .build(device);
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let target = Texture::render_target(device, 4, 4, format, format);
+        let skybox = Skybox::new(device, &scene.layout, format, None, 1);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut rpass = RenderPassBuilder::new().build(&mut encoder, &target.view);
+            skybox.render(&mut rpass, &scene);
+        }
+        queue.submit([encoder.finish()]);
+        let (pixels, ..) = get_texture_data(&target.texture, device, queue, 0);
+        let center = 4 * (2 * 4 + 2);
+        std::array::from_fn(|i| pixels.samples[center + i])
+    }
+
+    fn f16_to_f32(bits: u16) -> f32 {
+        let exponent = i32::from((bits >> 10) & 0x1f);
+        let mantissa = f32::from(bits & 0x3ff) / 1024.0;
+        let value = if exponent == 0 {
+            mantissa * 2_f32.powi(-14)
+        } else {
+            (1.0 + mantissa) * 2_f32.powi(exponent - 15)
+        };
+        if bits & 0x8000 == 0 {
+            value
+        } else {
+            -value
+        }
+    }
+
+    #[test]
+    fn prefiltered_mips_span_every_roughness() {
+        assert_relative_eq!(Environment::prefiltered_roughness(0), 0.0);
+        assert_relative_eq!(
+            Environment::prefiltered_roughness(Environment::PREFILTERED_MIP_LEVELS - 1),
+            1.0
+        );
+    }
+
+    #[test]
+    fn uniform_environment_lights_with_its_color() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let color = Color::new(0.25, 0.5, 0.75);
+        let environment = Environment::from_color(color, &device, &queue);
+        let expected = [64, 128, 191, 255];
+        for cubemap in [
+            &environment.skybox,
+            &environment.irradiance,
+            &environment.prefiltered,
+        ] {
+            let pixel = render_cubemap(cubemap, &device, &queue);
+            assert!(
+                pixel.iter().zip(expected).all(|(&a, b)| a.abs_diff(b) <= 2),
+                "Every direction has the environment color, got {pixel:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn brdf_lut_is_a_fraction_of_the_reflectance() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let environment = Environment::from_color(Color::WHITE, &device, &queue);
+        let size = Environment::BRDF_LUT_SIZE;
+        // Rgba16Float rows of the lookup table are 256 byte aligned already
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: u64::from(8 * size * size),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            environment.brdf_lut.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(8 * size),
+                    rows_per_image: None,
+                },
+            },
+            environment.brdf_lut.texture.size(),
+        );
+        queue.submit([encoder.finish()]);
+        buffer.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+        let data = buffer.slice(..).get_mapped_range();
+        let texel = |x: u32, y: u32| -> [f32; 2] {
+            let offset = (8 * (y * size + x)) as usize;
+            std::array::from_fn(|i| {
+                f16_to_f32(u16::from_le_bytes([
+                    data[offset + 2 * i],
+                    data[offset + 2 * i + 1],
+                ]))
+            })
+        };
+        for (x, y) in [
+            (0, 0),
+            (size / 2, size / 2),
+            (size - 1, 0),
+            (size - 1, size - 1),
+        ] {
+            let [scale, bias] = texel(x, y);
+            assert!(
+                scale >= 0.0 && bias >= 0.0 && scale + bias <= 1.01,
+                "Scale {scale} and bias {bias} at {x}, {y} reflect at most all the light"
+            );
+        }
+        let [smooth_scale, _] = texel(size - 1, 0);
+        assert!(
+            smooth_scale > 0.9,
+            "Smooth surfaces seen head on reflect almost all of f0, got {smooth_scale}"
+        );
+    }
+
+    #[test]
+    fn face_matches_the_shader_struct() {
+        ShaderReflection::new(
+            "shaders/environment.wgsl",
+            include_str!("shaders/environment.wgsl"),
+        )
+        .unwrap()
+        .check_struct::<Face>("Face")
+        .unwrap();
+    }
+}
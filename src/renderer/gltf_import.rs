@@ -0,0 +1,762 @@
+use std::{path::Path, rc::Rc};
+
+use glam::{Affine3A, Quat, Vec2, Vec3, Vec4};
+use gltf::{camera::Projection, image::Format, khr_lights_punctual::Kind};
+use image::{DynamicImage, ImageBuffer};
+
+use crate::core::{
+    entity::{EntityHierarchy, EntityKey},
+    image::Image,
+    material::StandardMaterial,
+    material_renderer::MaterialRenderer,
+    mesh_renderer::MeshRenderer,
+    resources::{ResourceHandle, ResourceManager},
+    transform::Transform,
+};
+
+use super::{
+    camera::{OrthographicCamera, PerspectiveCamera},
+    color::Color,
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+    material::{AlphaMode, PbrMaterial, PbrMaterialBuilder, SharedMaterial},
+    mesh::{Mesh, Vertex},
+    model::Model,
+    texture::Texture,
+};
+
+#[derive(Debug, Clone)]
+pub struct GltfPrimitive {
+    pub mesh: Rc<Mesh>,
+    /// Index into [`GltfScene::materials`], `None` uses the default material.
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfMaterial {
+    pub name: Option<String>,
+    pub diffuse_color: Color,
+    /// Index into [`GltfScene::images`]
+    pub diffuse_texture: Option<usize>,
+    /// Index into [`GltfScene::images`]
+    pub normal_texture: Option<usize>,
+    pub roughness: f32,
+    pub specular: f32,
+    pub ior: f32,
+    pub alpha_mode: AlphaMode,
+}
+
+impl Default for GltfMaterial {
+    fn default() -> Self {
+        Self {
+            name: None,
+            diffuse_color: Color::WHITE,
+            diffuse_texture: None,
+            normal_texture: None,
+            roughness: 1.0,
+            specular: 0.5,
+            // glTF default when KHR_materials_ior is not used
+            ior: 1.5,
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+}
+
+impl GltfMaterial {
+    /// `textures` are the [`GltfScene::images`] on the GPU, shared by the materials using them.
+    pub fn to_pbr(
+        &self,
+        textures: &[Option<Texture>],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> PbrMaterial {
+        let mut builder = PbrMaterialBuilder::new()
+            .diffuse_color(self.diffuse_color)
+            .roughness(self.roughness)
+            .specular(self.specular)
+            .index_of_refraction(self.ior)
+            .alpha_mode(self.alpha_mode);
+        if let Some(texture) = texture(textures, self.diffuse_texture) {
+            builder = builder.diffuse_texture(texture.clone());
+        }
+        if let Some(texture) = texture(textures, self.normal_texture) {
+            builder = builder.normal_texture(texture.clone());
+        }
+        builder.build(device, queue)
+    }
+
+    /// `textures` are the [`GltfScene::images`] saved as resources, `defaults` the diffuse and normal textures
+    /// of materials without them.
+    pub fn to_standard(
+        &self,
+        textures: &[Option<ResourceHandle<Image>>],
+        defaults: [ResourceHandle<Image>; 2],
+    ) -> StandardMaterial {
+        let [default_diffuse, default_normal] = defaults;
+        StandardMaterial {
+            diffuse_texture: *texture(textures, self.diffuse_texture).unwrap_or(&default_diffuse),
+            diffuse_color: self.diffuse_color,
+            normal_map: *texture(textures, self.normal_texture).unwrap_or(&default_normal),
+            specular: self.specular,
+            ior: self.ior,
+            roughness: self.roughness,
+            ambient: PbrMaterial::DEFAULT_AMBIENT_COLOR,
+        }
+    }
+}
+
+fn texture<T>(textures: &[Option<T>], index: Option<usize>) -> Option<&T> {
+    textures.get(index?)?.as_ref()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GltfCamera {
+    Perspective(PerspectiveCamera),
+    Orthographic(OrthographicCamera),
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfNode {
+    pub name: String,
+    pub transform: Transform,
+    pub children: Vec<usize>,
+    /// Index into [`GltfScene::meshes`]
+    pub mesh: Option<usize>,
+    pub camera: Option<GltfCamera>,
+    /// Light in node space, pointing down -Z
+    pub light: Option<Light>,
+}
+
+/// Contents of a glTF 2.0 file (.gltf or .glb), decoded but not yet uploaded to the GPU.
+#[derive(Debug, Clone, Default)]
+pub struct GltfScene {
+    /// Every glTF mesh is a list of primitives, each with its own material.
+    pub meshes: Vec<Vec<GltfPrimitive>>,
+    pub materials: Vec<GltfMaterial>,
+    /// Textures of the materials, `None` for images in unsupported formats
+    pub images: Vec<Option<DynamicImage>>,
+    pub nodes: Vec<GltfNode>,
+    /// Root nodes of the default scene
+    pub roots: Vec<usize>,
+}
+
+impl GltfScene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, gltf::Error> {
+        let (document, buffers, images) = gltf::import(path)?;
+        let images: Vec<_> = images.into_iter().map(convert_image).collect();
+
+        let materials = document
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                let [r, g, b, a] = pbr.base_color_factor();
+                GltfMaterial {
+                    name: material.name().map(Into::into),
+                    diffuse_color: Color::rgba(r, g, b, a),
+                    diffuse_texture: pbr
+                        .base_color_texture()
+                        .map(|info| info.texture().source().index()),
+                    normal_texture: material
+                        .normal_texture()
+                        .map(|info| info.texture().source().index()),
+                    roughness: pbr.roughness_factor(),
+                    specular: material
+                        .specular()
+                        .map_or(0.5, |specular| 0.5 * specular.specular_factor()),
+                    ior: material.ior().unwrap_or(1.5),
+                    alpha_mode: convert_alpha_mode(&material),
+                }
+            })
+            .collect();
+
+        let meshes = document
+            .meshes()
+            .map(|mesh| {
+                mesh.primitives()
+                    .filter_map(|primitive| {
+                        if primitive.mode() != gltf::mesh::Mode::Triangles {
+                            tracing::warn!(
+                                "Skipping {:?} primitive of mesh {:?}, only triangles are supported",
+                                primitive.mode(),
+                                mesh.name()
+                            );
+                            return None;
+                        }
+                        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                        let positions: Vec<Vec3> =
+                            reader.read_positions()?.map(Vec3::from).collect();
+                        let normals = reader
+                            .read_normals()
+                            .map(|normals| normals.map(Vec3::from).collect());
+                        let uvs = reader
+                            .read_tex_coords(0)
+                            .map(|uvs| uvs.into_f32().map(Vec2::from).collect());
+                        let tangents = reader
+                            .read_tangents()
+                            .map(|tangents| tangents.map(Vec4::from).collect());
+                        let indices = reader.read_indices().map_or_else(
+                            || (0..positions.len() as u32).collect(),
+                            |indices| indices.into_u32().collect(),
+                        );
+                        Some(GltfPrimitive {
+                            mesh: Rc::new(build_mesh(
+                                &positions, normals, uvs, tangents, indices,
+                            )),
+                            material: primitive.material().index(),
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let nodes = document
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                GltfNode {
+                    name: node
+                        .name()
+                        .map_or_else(|| format!("Node {}", node.index()), Into::into),
+                    transform: Transform::new(
+                        translation.into(),
+                        Quat::from_array(rotation),
+                        scale.into(),
+                    ),
+                    children: node.children().map(|child| child.index()).collect(),
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    camera: node.camera().map(|camera| convert_camera(&camera)),
+                    light: node.light().map(|light| convert_light(&light)),
+                }
+            })
+            .collect();
+
+        let roots = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .map(|scene| scene.nodes().map(|node| node.index()).collect())
+            .unwrap_or_default();
+
+        Ok(Self {
+            meshes,
+            materials,
+            images,
+            nodes,
+            roots,
+        })
+    }
+
+    /// World transform of every node, in node order.
+    pub fn world_transforms(&self) -> Vec<Affine3A> {
+        let mut transforms = vec![Affine3A::IDENTITY; self.nodes.len()];
+        let mut stack: Vec<_> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Affine3A::IDENTITY))
+            .collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            transforms[index] = parent * node.transform.local_transform();
+            stack.extend(
+                node.children
+                    .iter()
+                    .map(|&child| (child, transforms[index])),
+            );
+        }
+        transforms
+    }
+
+    /// One [`Model`] per primitive, with the world transform of its node.
+    ///
+    /// Primitives with the same material share it, so nodes reusing a mesh are drawn as instances.
+    /// Every image is uploaded once, however many materials use it.
+    pub fn to_models(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Model> {
+        let transforms = self.world_transforms();
+        let textures: Vec<_> = self
+            .images
+            .iter()
+            .map(|image| {
+                image
+                    .as_ref()
+                    .map(|image| Texture::new(image.clone(), device, queue))
+            })
+            .collect();
+        // The last slot is for primitives without a material
+        let mut materials: Vec<Option<SharedMaterial>> = vec![None; self.materials.len() + 1];
+        let mut models = vec![];
+        for (node, transform) in self.nodes.iter().zip(transforms) {
+            let Some(mesh) = node.mesh else {
+                continue;
+            };
+            for primitive in &self.meshes[mesh] {
+                let index = primitive
+                    .material
+                    .filter(|&index| index < self.materials.len())
+                    .unwrap_or(self.materials.len());
+                let material = materials[index].get_or_insert_with(|| {
+                    let material = self.materials.get(index).cloned().unwrap_or_default();
+                    Rc::new(material.to_pbr(&textures, device, queue))
+                });
+                models.push(Model::with_shared_material(
+                    transform,
+                    primitive.mesh.clone(),
+                    material.clone(),
+                ));
+            }
+        }
+        models
+    }
+
+    /// Adds the node hierarchy to `hierarchy`, returning the keys of the root entities.
+    ///
+    /// Meshes, materials and images are saved once in `resources`. A mesh with several primitives gets a child
+    /// entity per primitive.
+    pub fn spawn(
+        &self,
+        hierarchy: &mut EntityHierarchy,
+        resources: &mut ResourceManager,
+    ) -> Vec<EntityKey> {
+        let textures: Vec<_> = self
+            .images
+            .iter()
+            .map(|image| {
+                image
+                    .as_ref()
+                    .map(|image| resources.save_resource(Image::new(image.clone())))
+            })
+            .collect();
+        let defaults = [
+            PbrMaterial::default_diffuse_texture(),
+            PbrMaterial::default_normal_texture(),
+        ]
+        .map(|image| resources.save_resource(Image::new(image)));
+        let materials: Vec<_> = self
+            .materials
+            .iter()
+            .map(|material| resources.save_resource(material.to_standard(&textures, defaults)))
+            .collect();
+        let default_material =
+            resources.save_resource(GltfMaterial::default().to_standard(&textures, defaults));
+        let meshes: Vec<Vec<_>> = self
+            .meshes
+            .iter()
+            .map(|primitives| {
+                primitives
+                    .iter()
+                    .map(|primitive| {
+                        let mesh = resources.save_resource(primitive.mesh.as_ref().clone());
+                        let material = primitive
+                            .material
+                            .and_then(|index| materials.get(index).copied())
+                            .unwrap_or(default_material);
+                        (mesh, material)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let transforms = self.world_transforms();
+        let mut roots = vec![];
+        let mut stack: Vec<_> = self.roots.iter().rev().map(|&root| (root, None)).collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            let key = if let Some(parent) = parent {
+                hierarchy.add_child_entity(parent, node.name.clone())
+            } else {
+                let key = hierarchy.add_entity(node.name.clone());
+                roots.push(key);
+                key
+            };
+            let entity = &mut hierarchy.entities[key];
+            entity.add_component(node.transform);
+            if let Some(light) = node.light {
+                entity.add_component(light_to_world(light, transforms[index]));
+            }
+            match node.camera {
+                Some(GltfCamera::Perspective(camera)) => entity.add_component(camera),
+                Some(GltfCamera::Orthographic(camera)) => entity.add_component(camera),
+                None => {}
+            }
+            if let Some(primitives) = node.mesh.map(|mesh| &meshes[mesh]) {
+                if let [(mesh, material)] = primitives.as_slice() {
+                    entity.add_component(MeshRenderer { mesh: *mesh });
+                    entity.add_component(MaterialRenderer {
+                        material: (*material).into(),
+                    });
+                } else {
+                    for (i, &(mesh, material)) in primitives.iter().enumerate() {
+                        let child =
+                            hierarchy.add_child_entity(key, format!("{} primitive {i}", node.name));
+                        let entity = &mut hierarchy.entities[child];
+                        entity.new_component::<Transform>();
+                        entity.add_component(MeshRenderer { mesh });
+                        entity.add_component(MaterialRenderer {
+                            material: material.into(),
+                        });
+                    }
+                }
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, Some(key))));
+        }
+        roots
+    }
+}
+
+/// Lights store world space positions and directions, so they are baked from the node transform.
+fn light_to_world(light: Light, transform: Affine3A) -> Light {
+    let position = transform.translation.into();
+    let direction = transform.transform_vector3(Vec3::NEG_Z).normalize_or_zero();
+    match light {
+        Light::DirectionalLight(light) => DirectionalLight { direction, ..light }.into(),
+        Light::PointLight(light) => PointLight { position, ..light }.into(),
+        Light::SpotLight(light) => SpotLight {
+            position,
+            direction,
+            ..light
+        }
+        .into(),
+    }
+}
+
+fn build_mesh(
+    positions: &[Vec3],
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<Vec2>>,
+    tangents: Option<Vec<Vec4>>,
+    indices: Vec<u32>,
+) -> Mesh {
+    let has_normals = normals.is_some();
+    let has_uvs = uvs.is_some();
+    let normals = normals.unwrap_or_else(|| vec![Vec3::ZERO; positions.len()]);
+    let uvs = uvs.unwrap_or_else(|| vec![Vec2::ZERO; positions.len()]);
+    let vertices = positions
+        .iter()
+        .zip(normals)
+        .zip(uvs)
+        .map(|((&position, normal), uv)| Vertex {
+            position,
+            normal,
+            uv,
+            tangent: Vec3::X,
+            bitangent: Vec3::Y,
+        })
+        .collect();
+    let mut mesh = Mesh { vertices, indices };
+    if !has_normals {
+        mesh.recalculate_normals();
+    }
+    match tangents {
+        Some(tangents) => {
+            for (vertex, tangent) in mesh.vertices.iter_mut().zip(tangents) {
+                vertex.tangent = tangent.truncate();
+                // The w component stores the handedness of the tangent frame
+                vertex.bitangent = vertex.normal.cross(vertex.tangent) * tangent.w;
+            }
+        }
+        None if has_uvs => mesh.recalculate_tangents(),
+        // Without uvs any frame orthogonal to the normal will do
+        None => {
+            for vertex in &mut mesh.vertices {
+                if vertex.normal != Vec3::ZERO {
+                    (vertex.tangent, vertex.bitangent) = vertex.normal.any_orthonormal_pair();
+                }
+            }
+        }
+    }
+    mesh
+}
+
+fn convert_camera(camera: &gltf::Camera) -> GltfCamera {
+    match camera.projection() {
+        Projection::Perspective(perspective) => {
+            let default = PerspectiveCamera::default();
+            GltfCamera::Perspective(PerspectiveCamera::new(
+                perspective.yfov(),
+                perspective.aspect_ratio().unwrap_or(default.aspect_ratio),
+                perspective.znear(),
+                // Infinite projections are not supported
+                perspective.zfar().unwrap_or(default.far),
+            ))
+        }
+        Projection::Orthographic(orthographic) => {
+            GltfCamera::Orthographic(OrthographicCamera::new(
+                2.0 * orthographic.xmag(),
+                orthographic.ymag() / orthographic.xmag(),
+                orthographic.znear(),
+                orthographic.zfar(),
+            ))
+        }
+    }
+}
+
+fn convert_light(light: &gltf::khr_lights_punctual::Light) -> Light {
+    let color = Color::from(light.color()) * light.intensity();
+    match light.kind() {
+        Kind::Directional => DirectionalLight::new(color, Vec3::NEG_Z).into(),
+        Kind::Point => PointLight {
+            color: color.into(),
+            range: light.range().unwrap_or_else(|| PointLight::default().range),
+            ..PointLight::default()
+        }
+        .into(),
+        Kind::Spot {
+            outer_cone_angle, ..
+        } => SpotLight::new(
+            color,
+            Vec3::ZERO,
+            Vec3::NEG_Z,
+            light.range().unwrap_or_else(|| SpotLight::default().range),
+            outer_cone_angle,
+        )
+        .into(),
+    }
+}
+
+fn convert_alpha_mode(material: &gltf::Material) -> AlphaMode {
+    match material.alpha_mode() {
+        gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+        gltf::material::AlphaMode::Mask => {
+            AlphaMode::Mask(material.alpha_cutoff().unwrap_or(AlphaMode::DEFAULT_CUTOFF))
+        }
+        gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+    }
+}
+
+fn convert_image(data: gltf::image::Data) -> Option<DynamicImage> {
+    let (width, height) = (data.width, data.height);
+    let pixels = data.pixels;
+    let u16s = || -> Vec<u16> {
+        pixels
+            .chunks_exact(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect()
+    };
+    let f32s = || -> Vec<f32> {
+        pixels
+            .chunks_exact(4)
+            .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    };
+    let image = match data.format {
+        Format::R8 => {
+            ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageLuma8)
+        }
+        Format::R8G8 => {
+            ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageLumaA8)
+        }
+        Format::R8G8B8 => {
+            ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageRgb8)
+        }
+        Format::R8G8B8A8 => {
+            ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageRgba8)
+        }
+        Format::R16 => ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageLuma16),
+        Format::R16G16 => {
+            ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageLumaA16)
+        }
+        Format::R16G16B16 => {
+            ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageRgb16)
+        }
+        Format::R16G16B16A16 => {
+            ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageRgba16)
+        }
+        Format::R32G32B32FLOAT => {
+            ImageBuffer::from_raw(width, height, f32s()).map(DynamicImage::ImageRgb32F)
+        }
+        Format::R32G32B32A32FLOAT => {
+            ImageBuffer::from_raw(width, height, f32s()).map(DynamicImage::ImageRgba32F)
+        }
+    };
+    // Textures are uploaded as 8 bit rgba
+    image.map(|image| DynamicImage::ImageRgba8(image.into_rgba8()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use approx::assert_relative_eq;
+    use glam::{Affine3A, Vec3};
+    use image::{DynamicImage, RgbaImage};
+
+    use super::{GltfCamera, GltfMaterial, GltfNode, GltfPrimitive, GltfScene};
+    use crate::{
+        core::{
+            entity::EntityHierarchy,
+            material::StandardMaterial,
+            material_renderer::MaterialRenderer,
+            mesh_renderer::MeshRenderer,
+            resources::{ResourceHandle, ResourceManager},
+            transform::Transform,
+        },
+        renderer::{light::Light, material::AlphaMode},
+    };
+
+    const GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "extensionsUsed": ["KHR_lights_punctual", "KHR_materials_ior"],
+        "extensions": {
+            "KHR_lights_punctual": {
+                "lights": [{ "type": "spot", "color": [1, 0, 0], "intensity": 2, "range": 5,
+                             "spot": { "outerConeAngle": 0.5 } }]
+            }
+        },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 2] }],
+        "nodes": [
+            { "name": "root", "translation": [1, 0, 0], "children": [1] },
+            { "name": "triangle", "translation": [0, 2, 0], "mesh": 0 },
+            { "name": "light", "translation": [0, 0, 3],
+              "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+            { "name": "unused", "camera": 0 }
+        ],
+        "cameras": [{ "type": "perspective",
+                      "perspective": { "yfov": 1.0, "znear": 0.1, "zfar": 50 } }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [0.5, 0.25, 1, 0.75],
+                                                  "roughnessFactor": 0.3 },
+                        "alphaMode": "MASK", "alphaCutoff": 0.25,
+                        "extensions": { "KHR_materials_ior": { "ior": 1.33 } } }],
+        "buffers": [{ "uri": "triangle.bin", "byteLength": 36 }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0] }]
+    }"#;
+
+    /// Every test writes its own copy, since tests run in parallel
+    fn load(test: &str) -> GltfScene {
+        let directory = std::env::temp_dir().join(format!("iris_gltf_{test}"));
+        fs::create_dir_all(&directory).unwrap();
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        fs::write(
+            directory.join("triangle.bin"),
+            bytemuck::cast_slice::<f32, u8>(&positions),
+        )
+        .unwrap();
+        let path = directory.join("triangle.gltf");
+        fs::write(&path, GLTF).unwrap();
+        GltfScene::load(path).unwrap()
+    }
+
+    #[test]
+    fn imports_meshes_and_materials() {
+        let scene = load("imports_meshes_and_materials");
+        let mesh = &scene.meshes[0][0].mesh;
+        assert_eq!(mesh.indices, [0, 1, 2], "Non indexed primitive");
+        for vertex in &mesh.vertices {
+            assert_relative_eq!(vertex.normal, Vec3::Z);
+            assert_relative_eq!(vertex.tangent.dot(vertex.normal), 0.0);
+        }
+        let material = &scene.materials[0];
+        assert_relative_eq!(material.diffuse_color.r, 0.5);
+        assert_relative_eq!(material.roughness, 0.3);
+        assert_relative_eq!(material.ior, 1.33);
+        assert_relative_eq!(material.diffuse_color.a, 0.75);
+        assert_eq!(
+            material.alpha_mode,
+            AlphaMode::Mask(0.25),
+            "Alpha mode imported"
+        );
+        assert!(
+            matches!(scene.nodes[3].camera, Some(GltfCamera::Perspective(camera)) if camera.far == 50.0),
+            "Camera imported"
+        );
+    }
+
+    #[test]
+    fn node_transforms_are_accumulated() {
+        let scene = load("node_transforms_are_accumulated");
+        assert_eq!(scene.roots, [0, 2], "Roots of the default scene");
+        let transforms = scene.world_transforms();
+        assert_relative_eq!(
+            transforms[1],
+            Affine3A::from_translation(Vec3::new(1.0, 2.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn spawn_builds_hierarchy() {
+        let scene = load("spawn_builds_hierarchy");
+        let mut resources = ResourceManager::from_directory(std::env::temp_dir()).unwrap();
+        let mut hierarchy = EntityHierarchy::new();
+        let roots = scene.spawn(&mut hierarchy, &mut resources);
+        assert_eq!(roots.len(), 2, "Two root entities");
+        hierarchy.update_transform_hierarchies();
+
+        let triangle = hierarchy.children(roots[0])[0];
+        let entity = &hierarchy.entities[triangle];
+        assert!(entity.has_component::<MeshRenderer>(), "Mesh attached");
+        assert!(
+            entity.has_component::<MaterialRenderer>(),
+            "Material attached"
+        );
+        assert_relative_eq!(
+            hierarchy.entities[triangle]
+                .get_component::<Transform>()
+                .unwrap()
+                .global_transform()
+                .translation,
+            Vec3::new(1.0, 2.0, 0.0).into()
+        );
+
+        let Some(&Light::SpotLight(light)) = hierarchy.entities[roots[1]].get_component::<Light>()
+        else {
+            panic!("Spot light should be imported");
+        };
+        assert_relative_eq!(light.position, Vec3::new(0.0, 0.0, 3.0));
+        assert_relative_eq!(light.direction, Vec3::NEG_Z);
+        assert_relative_eq!(light.color, Vec3::new(2.0, 0.0, 0.0));
+        assert_relative_eq!(light.range, 5.0);
+        assert_relative_eq!(light.outer_cutoff, 0.5);
+    }
+
+    #[test]
+    fn spawn_saves_shared_images_once() {
+        let mut scene = load("spawn_saves_shared_images_once");
+        scene.images = vec![Some(DynamicImage::ImageRgba8(RgbaImage::new(2, 2)))];
+        let material = GltfMaterial {
+            diffuse_texture: Some(0),
+            ..GltfMaterial::default()
+        };
+        scene.materials = vec![material.clone(), material];
+        let mesh = scene.meshes[0][0].mesh.clone();
+        scene.meshes = vec![(0..2)
+            .map(|material| GltfPrimitive {
+                mesh: mesh.clone(),
+                material: Some(material),
+            })
+            .collect()];
+        scene.nodes = vec![GltfNode {
+            name: "triangles".into(),
+            transform: Transform::default(),
+            children: vec![],
+            mesh: Some(0),
+            camera: None,
+            light: None,
+        }];
+        scene.roots = vec![0];
+
+        let mut resources = ResourceManager::from_directory(std::env::temp_dir()).unwrap();
+        let mut hierarchy = EntityHierarchy::new();
+        let roots = scene.spawn(&mut hierarchy, &mut resources);
+        let materials: Vec<_> = hierarchy
+            .children(roots[0])
+            .iter()
+            .map(|&child| {
+                let handle = hierarchy.entities[child]
+                    .get_component::<MaterialRenderer>()
+                    .unwrap()
+                    .material;
+                let handle = ResourceHandle::<StandardMaterial>::from_key(handle.key());
+                resources.load_resource(&handle).unwrap()
+            })
+            .collect();
+        assert_eq!(materials.len(), 2, "One entity per primitive");
+        assert_eq!(
+            materials[0].diffuse_texture, materials[1].diffuse_texture,
+            "Image saved once"
+        );
+        assert_eq!(
+            materials[0].normal_map, materials[1].normal_map,
+            "Default normal map saved once"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use image::{Rgba, RgbaImage};
+use palette::{color_difference::Ciede2000, IntoColor, Lab, Srgb};
+
+use super::app::{render_headless, App, HeadlessSettings};
+
+/// Set to `1` to overwrite reference images with the current output instead of comparing.
+pub const UPDATE_GOLDEN_ENV: &str = "IRIS_UPDATE_GOLDEN";
+
+#[derive(Debug, Clone, Copy)]
+pub struct GoldenSettings {
+    pub headless: HeadlessSettings,
+    /// Largest per-channel difference for a pixel to still count as matching.
+    pub pixel_tolerance: u8,
+    /// Fraction of pixels allowed to exceed `pixel_tolerance`.
+    pub max_mismatched_ratio: f32,
+    /// Largest allowed mean CIEDE2000 difference over the whole image.
+    /// Around 1.0 is the threshold of a just noticeable difference.
+    pub max_mean_delta_e: f32,
+}
+
+impl Default for GoldenSettings {
+    fn default() -> Self {
+        Self {
+            headless: HeadlessSettings {
+                width: 256,
+                height: 256,
+                frames: 3,
+                // Software adapters give the most reproducible output across machines
+                force_fallback_adapter: true,
+            },
+            pixel_tolerance: 8,
+            max_mismatched_ratio: 0.005,
+            max_mean_delta_e: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageComparison {
+    pub mismatched_pixels: usize,
+    pub total_pixels: usize,
+    pub max_channel_difference: u8,
+    pub mean_delta_e: f32,
+    /// Mismatched pixels in red, scaled by their difference, over a dimmed copy of the actual image.
+    pub diff: RgbaImage,
+}
+
+impl ImageComparison {
+    pub fn mismatched_ratio(&self) -> f32 {
+        if self.total_pixels == 0 {
+            0.0
+        } else {
+            self.mismatched_pixels as f32 / self.total_pixels as f32
+        }
+    }
+    pub fn passes(&self, settings: &GoldenSettings) -> bool {
+        self.mismatched_ratio() <= settings.max_mismatched_ratio
+            && self.mean_delta_e <= settings.max_mean_delta_e
+    }
+}
+
+/// Compares two images of the same size, returning `None` if the sizes differ.
+pub fn compare_images(
+    reference: &RgbaImage,
+    actual: &RgbaImage,
+    pixel_tolerance: u8,
+) -> Option<ImageComparison> {
+    if reference.dimensions() != actual.dimensions() {
+        return None;
+    }
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut mismatched_pixels = 0;
+    let mut max_channel_difference = 0;
+    let mut total_delta_e = 0.0;
+    for ((expected, found), out) in reference
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let channel_difference = expected
+            .0
+            .iter()
+            .zip(found.0)
+            .map(|(e, f)| e.abs_diff(f))
+            .max()
+            .unwrap_or_default();
+        max_channel_difference = max_channel_difference.max(channel_difference);
+        total_delta_e += delta_e(*expected, *found);
+
+        *out = if channel_difference > pixel_tolerance {
+            mismatched_pixels += 1;
+            Rgba([channel_difference.saturating_mul(2).max(128), 0, 0, 255])
+        } else {
+            let luma = (u16::from(found.0[0]) + u16::from(found.0[1]) + u16::from(found.0[2])) / 12;
+            Rgba([luma as u8, luma as u8, luma as u8, 255])
+        };
+    }
+    let total_pixels = (actual.width() * actual.height()) as usize;
+    let mean_delta_e = if total_pixels == 0 {
+        0.0
+    } else {
+        total_delta_e / total_pixels as f32
+    };
+    Some(ImageComparison {
+        mismatched_pixels,
+        total_pixels,
+        max_channel_difference,
+        mean_delta_e,
+        diff,
+    })
+}
+
+fn delta_e(a: Rgba<u8>, b: Rgba<u8>) -> f32 {
+    let to_lab = |p: Rgba<u8>| -> Lab {
+        Srgb::new(p.0[0], p.0[1], p.0[2])
+            .into_format::<f32>()
+            .into_color()
+    };
+    to_lab(a).difference(to_lab(b))
+}
+
+#[derive(Debug)]
+pub enum GoldenOutcome {
+    Passed(ImageComparison),
+    Failed {
+        comparison: Option<ImageComparison>,
+        actual: PathBuf,
+        diff: Option<PathBuf>,
+    },
+    MissingReference,
+    ReferenceUpdated,
+    NoAdapter,
+}
+
+/// Renders `A` headlessly and compares the result with the PNG at `reference`.
+///
+/// On failure the actual frame and the diff image are written to `target/golden`.
+pub fn check_golden<A: App>(
+    reference: impl AsRef<Path>,
+    settings: &GoldenSettings,
+) -> GoldenOutcome {
+    let reference = reference.as_ref();
+    let Some(actual) = render_headless::<A>(settings.headless) else {
+        return GoldenOutcome::NoAdapter;
+    };
+    if env::var(UPDATE_GOLDEN_ENV).is_ok_and(|v| v == "1") {
+        actual
+            .save(reference)
+            .expect("Unable to save reference image");
+        return GoldenOutcome::ReferenceUpdated;
+    }
+    let Ok(expected) = image::open(reference) else {
+        return GoldenOutcome::MissingReference;
+    };
+    let comparison = compare_images(&expected.into_rgba8(), &actual, settings.pixel_tolerance);
+    if let Some(comparison) = comparison.as_ref().filter(|c| c.passes(settings)) {
+        return GoldenOutcome::Passed(comparison.clone());
+    }
+
+    let output_dir = output_dir();
+    std::fs::create_dir_all(&output_dir).expect("Unable to create golden output directory");
+    let stem = reference
+        .file_stem()
+        .map_or_else(|| "golden".into(), |s| s.to_string_lossy().into_owned());
+    let name = reference.parent().and_then(Path::file_name).map_or_else(
+        || stem.clone(),
+        |dir| format!("{}_{stem}", dir.to_string_lossy()),
+    );
+    let actual_path = output_dir.join(format!("{name}.actual.png"));
+    actual.save(&actual_path).expect("Unable to save image");
+    let diff_path = comparison.as_ref().map(|comparison| {
+        let path = output_dir.join(format!("{name}.diff.png"));
+        comparison.diff.save(&path).expect("Unable to save image");
+        path
+    });
+    GoldenOutcome::Failed {
+        comparison,
+        actual: actual_path,
+        diff: diff_path,
+    }
+}
+
+/// Panics unless the output of `A` matches the reference image. Skips if there is no adapter.
+pub fn assert_golden<A: App>(reference: impl AsRef<Path>, settings: &GoldenSettings) {
+    let reference = reference.as_ref();
+    match check_golden::<A>(reference, settings) {
+        GoldenOutcome::Passed(_) => {}
+        GoldenOutcome::ReferenceUpdated => {
+            tracing::warn!("Updated reference image {}", reference.display());
+        }
+        GoldenOutcome::NoAdapter => {
+            tracing::warn!("No adapter available, skipping {}", reference.display());
+        }
+        GoldenOutcome::MissingReference => panic!(
+            "Missing reference image {}, run with {UPDATE_GOLDEN_ENV}=1 to create it",
+            reference.display()
+        ),
+        GoldenOutcome::Failed {
+            comparison: Some(comparison),
+            actual,
+            diff,
+        } => panic!(
+            "{} does not match: {}/{} pixels over tolerance (max difference {}), mean delta E {:.3}\nactual: {}\ndiff: {}",
+            reference.display(),
+            comparison.mismatched_pixels,
+            comparison.total_pixels,
+            comparison.max_channel_difference,
+            comparison.mean_delta_e,
+            actual.display(),
+            diff.unwrap_or_default().display(),
+        ),
+        GoldenOutcome::Failed {
+            comparison: None,
+            actual,
+            ..
+        } => panic!(
+            "{} has a different size than the rendered frame\nactual: {}",
+            reference.display(),
+            actual.display()
+        ),
+    }
+}
+
+fn output_dir() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map_or_else(|| PathBuf::from("target"), PathBuf::from)
+        .join("golden")
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::{compare_images, GoldenSettings};
+
+    #[test]
+    fn identical_images_match() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+        let comparison = compare_images(&image, &image, 0).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 0, "No pixel should differ");
+        assert!(comparison.mean_delta_e.abs() < 1e-4, "Delta E should be 0");
+        assert!(comparison.passes(&GoldenSettings::default()), "Should pass");
+    }
+    #[test]
+    fn different_pixel_is_reported() {
+        let reference = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        let mut actual = reference.clone();
+        actual.put_pixel(1, 2, Rgba([255, 255, 255, 255]));
+        let comparison = compare_images(&reference, &actual, 8).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 1, "One pixel should differ");
+        assert_eq!(
+            comparison.max_channel_difference, 255,
+            "Difference is maximal"
+        );
+        assert_eq!(comparison.diff.get_pixel(1, 2).0[1], 0, "Diff pixel is red");
+        assert!(
+            !comparison.passes(&GoldenSettings::default()),
+            "Should fail"
+        );
+    }
+    #[test]
+    fn small_differences_are_tolerated() {
+        let reference = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+        let actual = RgbaImage::from_pixel(4, 4, Rgba([103, 100, 98, 255]));
+        let comparison = compare_images(&reference, &actual, 8).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 0, "Within tolerance");
+    }
+    #[test]
+    fn different_sizes_do_not_compare() {
+        let reference = RgbaImage::new(4, 4);
+        let actual = RgbaImage::new(4, 5);
+        assert!(
+            compare_images(&reference, &actual, 8).is_none(),
+            "Sizes differ"
+        );
+    }
+}
@@ -4,10 +4,35 @@ use egui::{Button, DragValue, Response, Slider, TextureId, Ui, Vec2};
 use glam::{Affine3A, EulerRot, Quat, Vec3};
 use std::f32::consts::PI;
 use std::ops::RangeInclusive;
+use std::rc::Rc;
 
 use super::color::Color;
 use super::light::{DirectionalLight, Light, PointLight, SpotLight};
-use super::material::{LitMaterialBuilder, Material, PbrMaterialBuilder, UnlitMaterialBuilder};
+use super::material::{
+    AlphaMode, LitMaterialBuilder, PbrMaterialBuilder, SharedMaterial, UnlitMaterialBuilder,
+};
+
+/// Lists the shaders that failed to reload, until they are fixed.
+pub fn shader_errors_window(ctx: &egui::Context, errors: &[String]) {
+    if errors.is_empty() {
+        return;
+    }
+    egui::Window::new("Shader Errors")
+        .anchor(egui::Align2::LEFT_BOTTOM, Vec2::new(8.0, -8.0))
+        .resizable(false)
+        .vscroll(true)
+        .show(ctx, |ui| {
+            ui.label("The last working version of these shaders is in use.");
+            for error in errors {
+                ui.separator();
+                ui.label(
+                    egui::RichText::new(error)
+                        .monospace()
+                        .color(ui.visuals().error_fg_color),
+                );
+            }
+        });
+}
 
 pub fn transform_edit(ui: &mut Ui, transform: &mut Affine3A) -> bool {
     let mut changed = false;
@@ -63,27 +88,57 @@ pub fn texture_edit(ui: &mut Ui, texture_id: TextureId, label: &str) -> bool {
 
 pub fn change_material(
     ui: &mut Ui,
-    material: &mut Box<dyn for<'a> Material<'a>>,
+    material: &mut SharedMaterial,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
 ) -> bool {
     let mut changed = false;
     ui.menu_button("Change Material", |ui| {
         if ui.button("Unlit").clicked() {
-            *material = Box::new(UnlitMaterialBuilder::new().build(device, queue));
+            *material = Rc::new(UnlitMaterialBuilder::new().build(device, queue));
             changed = true;
         }
         if ui.button("Lit (Blinn-Phong").clicked() {
-            *material = Box::new(LitMaterialBuilder::new().build(device, queue));
+            *material = Rc::new(LitMaterialBuilder::new().build(device, queue));
             changed = true;
         }
         if ui.button("Pbr").clicked() {
-            *material = Box::new(PbrMaterialBuilder::new().build(device, queue));
+            *material = Rc::new(PbrMaterialBuilder::new().build(device, queue));
             changed = true;
         }
     });
     changed
 }
+/// Picks the [`AlphaMode`] and the cutoff of [`AlphaMode::Mask`].
+pub fn alpha_mode_edit(ui: &mut Ui, alpha_mode: &mut AlphaMode) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Alpha Mode");
+        egui::ComboBox::from_id_source("Alpha Mode")
+            .selected_text(format!("{alpha_mode:?}"))
+            .show_ui(ui, |ui| {
+                for mode in [
+                    AlphaMode::Opaque,
+                    AlphaMode::Mask(AlphaMode::DEFAULT_CUTOFF),
+                    AlphaMode::Blend,
+                    AlphaMode::Additive,
+                    AlphaMode::Premultiplied,
+                ] {
+                    let selected =
+                        std::mem::discriminant(alpha_mode) == std::mem::discriminant(&mode);
+                    if ui.selectable_label(selected, format!("{mode:?}")).clicked() && !selected {
+                        *alpha_mode = mode;
+                        changed = true;
+                    }
+                }
+            });
+    });
+    if let AlphaMode::Mask(ref mut cutoff) = *alpha_mode {
+        changed |= float_edit(ui, cutoff, "Alpha Cutoff", 0.0..=1.0);
+    }
+    changed
+}
+
 pub fn lights_gui(ui: &mut Ui, lights: &mut Vec<Light>) -> bool {
     let mut changed = false;
     let mut indices = vec![];
@@ -136,6 +191,7 @@ pub fn drag_angle_clamp(ui: &mut Ui, radians: &mut f32, range: RangeInclusive<f3
     response
 }
 
+/// Edits the color channels, keeping the alpha.
 pub fn color_edit<I: Into<Color> + From<Color> + Clone + Copy>(
     ui: &mut Ui,
     into_color: &mut I,
@@ -147,7 +203,7 @@ pub fn color_edit<I: Into<Color> + From<Color> + Clone + Copy>(
         ui.label(label);
         let response = ui.color_edit_button_rgb(&mut rgb);
         if response.changed() {
-            color = rgb.into();
+            color = Color::from(rgb).with_alpha(color.a);
             *into_color = color.into();
         }
         response
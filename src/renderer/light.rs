@@ -1,24 +1,40 @@
-use bytemuck::{Pod, Zeroable};
+use std::f32::consts::{FRAC_PI_2, PI};
 
 use egui::Ui;
 use glam::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
 
-use crate::GpuSendable;
+use crate::{
+    core::{component::Component, shader_type::ShaderType},
+    GpuSendable,
+};
 
 use super::{
+    camera::PerspectiveCamera,
     color::Color,
     gui::{array3_edit, color_edit, direction_edit, drag_angle_clamp, float_edit, vec3_edit},
+    shadow::ShadowSettings,
 };
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+/// Near plane of the shadow projections of point and spot lights
+const SHADOW_NEAR: f32 = 0.05;
+
+fn shadow_up(direction: Vec3) -> Vec3 {
+    if direction.normalize().y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    }
+}
+
+#[derive(Clone, Copy, Debug, ShaderType)]
 pub struct GpuLight {
     position: Vec4,
     color_range: Vec4,
     custom_data: Vec4,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Light {
     DirectionalLight(DirectionalLight),
     PointLight(PointLight),
@@ -34,6 +50,13 @@ impl GpuSendable<GpuLight> for Light {
         }
     }
 }
+impl Component for Light {
+    fn gui(&mut self, ui: &mut Ui) {
+        // Resolves to the inherent method
+        Self::gui(self, ui);
+    }
+}
+
 impl From<DirectionalLight> for Light {
     fn from(value: DirectionalLight) -> Self {
         Self::DirectionalLight(value)
@@ -51,6 +74,7 @@ impl From<SpotLight> for Light {
 }
 
 impl Light {
+    #[allow(clippy::same_name_method)]
     pub fn gui(&mut self, ui: &mut Ui) -> bool {
         let mut changed = false;
         match *self {
@@ -58,6 +82,7 @@ impl Light {
                 ui.label("Directional Light");
                 changed |= color_edit(ui, &mut light.color, "Color");
                 changed |= direction_edit(ui, &mut light.direction, "Direction");
+                changed |= light.shadow.gui(ui);
             }
             Self::PointLight(ref mut light) => {
                 ui.label("Point Light");
@@ -65,6 +90,7 @@ impl Light {
                 changed |= vec3_edit(ui, &mut light.position, "Position");
                 changed |= float_edit(ui, &mut light.range, "Range", 1.0..=100.0);
                 changed |= array3_edit(ui, &mut light.attenuation, "Attenuation function");
+                changed |= light.shadow.gui(ui);
             }
             Self::SpotLight(ref mut light) => {
                 ui.label("Spot Light");
@@ -77,16 +103,19 @@ impl Light {
                     light.outer_cutoff = f32::max(0.0, light.outer_cutoff);
                     ui.label("Cutoff");
                 });
+                changed |= light.shadow.gui(ui);
             }
         };
         changed
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct DirectionalLight {
     pub direction: Vec3,
     pub color: Vec3,
+    #[serde(default)]
+    pub shadow: ShadowSettings,
 }
 
 impl Default for DirectionalLight {
@@ -94,6 +123,7 @@ impl Default for DirectionalLight {
         Self {
             direction: Vec3::NEG_ONE,
             color: Color::WHITE.into(),
+            shadow: ShadowSettings::default(),
         }
     }
 }
@@ -103,8 +133,50 @@ impl DirectionalLight {
         Self {
             color: color.into(),
             direction: direction.normalize(),
+            shadow: ShadowSettings::default(),
         }
     }
+
+    /// Orthographic view projection covering the view space slice `near..far` of `camera`.
+    pub fn cascade_view_projection(
+        &self,
+        view: Mat4,
+        camera: &PerspectiveCamera,
+        near: f32,
+        far: f32,
+    ) -> Mat4 {
+        let inverse_view = view.inverse();
+        let tan_half_fov = (camera.fov_y * 0.5).tan();
+        let corners = [near, far].into_iter().flat_map(|depth| {
+            let half_height = depth * tan_half_fov;
+            let half_width = half_height * camera.aspect_ratio;
+            [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+                inverse_view.transform_point3(Vec3::new(x * half_width, y * half_height, -depth))
+            })
+        });
+        let corners: Vec<Vec3> = corners.collect();
+        let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+        // A bounding sphere keeps the projection size stable while the camera rotates
+        let radius = corners
+            .iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+        let direction = self.direction.normalize();
+        // Casters behind the camera slice still need to be in the map
+        let extension = camera.far;
+        let eye = center - direction * (radius + extension);
+        let view = Mat4::look_to_rh(eye, direction, shadow_up(direction));
+        let projection = Mat4::orthographic_rh(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            0.0,
+            2.0f32.mul_add(radius, extension),
+        );
+        projection * view
+    }
 }
 
 impl GpuSendable<GpuLight> for DirectionalLight {
@@ -116,12 +188,14 @@ impl GpuSendable<GpuLight> for DirectionalLight {
         }
     }
 }
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct PointLight {
     pub position: Vec3,
     pub color: Vec3,
     pub range: f32,
     pub attenuation: [f32; 3],
+    #[serde(default)]
+    pub shadow: ShadowSettings,
 }
 
 impl Default for PointLight {
@@ -131,6 +205,7 @@ impl Default for PointLight {
             position: Vec3::ZERO,
             range: 100.0,
             attenuation: [0.0, 0.0, 1.0],
+            shadow: ShadowSettings::default(),
         }
     }
 }
@@ -142,8 +217,26 @@ impl PointLight {
             color: color.into(),
             range,
             attenuation,
+            shadow: ShadowSettings::default(),
         }
     }
+
+    /// View projections of the cube faces, in +X, -X, +Y, -Y, +Z, -Z order.
+    ///
+    /// Faces are oriented like the faces of a cube texture, which are left handed, so a direction
+    /// samples the texel rendered along it.
+    pub fn shadow_view_projections(&self) -> [Mat4; 6] {
+        let projection = Mat4::perspective_lh(FRAC_PI_2, 1.0, SHADOW_NEAR, self.range);
+        [
+            (Vec3::X, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Y),
+            (Vec3::Y, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::Z),
+            (Vec3::Z, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::Y),
+        ]
+        .map(|(direction, up)| projection * Mat4::look_to_lh(self.position, direction, up))
+    }
 }
 
 impl GpuSendable<GpuLight> for PointLight {
@@ -162,13 +255,15 @@ impl GpuSendable<GpuLight> for PointLight {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct SpotLight {
     pub position: Vec3,
     pub direction: Vec3,
     pub color: Vec3,
     pub range: f32,
     pub outer_cutoff: f32,
+    #[serde(default)]
+    pub shadow: ShadowSettings,
 }
 
 impl Default for SpotLight {
@@ -179,6 +274,7 @@ impl Default for SpotLight {
             color: Color::WHITE.into(),
             range: 100.0,
             outer_cutoff: f32::to_radians(30.0),
+            shadow: ShadowSettings::default(),
         }
     }
 }
@@ -197,36 +293,29 @@ impl SpotLight {
             color: color.into(),
             range,
             outer_cutoff,
+            shadow: ShadowSettings::default(),
         }
     }
 
-    pub fn project_texture_matrix(&self, width: usize, height: usize) -> Mat4 {
-        let z_axis = self.direction;
-        // Assume texture uses up as T direction
-        let y_axis = Vec3::Z;
-        let x_axis = y_axis.cross(z_axis);
-        let w_axis = Vec4::new(
-            -x_axis.dot(self.position),
-            -y_axis.dot(self.position),
-            -z_axis.dot(self.position),
-            1.0,
-        );
-        let from_world_to_light = Mat4::from_cols(
-            x_axis.extend(0.0),
-            y_axis.extend(0.0),
-            z_axis.extend(0.0),
-            w_axis,
-        );
+    /// View projection of the cone, `aspect_ratio` times as wide as it is high
+    fn view_projection(&self, aspect_ratio: f32) -> Mat4 {
+        let direction = self.direction.normalize();
+        // The cone spans twice the cutoff angle
+        let fov = (2.0 * self.outer_cutoff).min(PI - 0.01);
+        let projection = Mat4::perspective_rh(fov, aspect_ratio, SHADOW_NEAR, self.range);
+        projection * Mat4::look_to_rh(self.position, direction, shadow_up(direction))
+    }
 
-        let f = 1.0 / f32::tan(0.5 * self.outer_cutoff);
-        let aspect_ratio = width as f32 / height as f32;
-        let projection = Mat4::from_cols(
-            Vec4::X * f * 0.5,
-            Vec4::Y * f * 0.5 * aspect_ratio.recip(),
-            Vec4::new(0.5, 0.5, 0.0, 1.0),
-            Vec4::ZERO,
-        );
-        from_world_to_light * projection
+    pub fn shadow_view_projection(&self) -> Mat4 {
+        self.view_projection(1.0)
+    }
+
+    /// Maps world positions to the uvs of a `width` by `height` texture projected by the light, after the
+    /// division by w. Uses the projection of the shadow map, with v pointing down the texture.
+    pub fn project_texture_matrix(&self, width: usize, height: usize) -> Mat4 {
+        let clip_to_uv = Mat4::from_translation(Vec3::new(0.5, 0.5, 0.0))
+            * Mat4::from_scale(Vec3::new(0.5, -0.5, 1.0));
+        clip_to_uv * self.view_projection(width as f32 / height as f32)
     }
 }
 
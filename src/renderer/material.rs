@@ -1,31 +1,144 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, rc::Rc};
 
+use bytemuck::{Pod, Zeroable};
 use egui::Ui;
+use glam::Vec3;
 use image::{DynamicImage, ImageBuffer};
-use wgpu::{include_wgsl, ShaderModuleDescriptor};
+use serde::{Deserialize, Serialize};
+use wgpu::ShaderModuleDescriptor;
+
+use crate::core::shader_type::ShaderType;
 
 use super::{
-    bind_group::{BindGroup, BindGroupBuilder},
+    bind_group::{BindGroup, BindGroupBuilder, BindGroupLayoutEntries},
     buffer::UniformBuffer,
     color::Color,
+    deferred::GBUFFER_FORMATS,
     egui_renderer::EguiRenderer,
-    gui::{color_edit, float_edit, texture_edit},
+    gui::{alpha_mode_edit, color_edit, float_edit, texture_edit},
     render_pipeline::RenderPipelineBuilder,
+    shader::{Shader, ShaderDefs},
     texture::Texture,
 };
 
+/// How the alpha of a material combines it with what is behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum AlphaMode {
+    /// Alpha is ignored
+    #[default]
+    Opaque,
+    /// Fragments with an alpha below the cutoff are discarded, the others are opaque
+    Mask(f32),
+    /// Blends the color over the target by its alpha
+    Blend,
+    /// Adds the color weighted by its alpha to the target, like fire or glows
+    Additive,
+    /// Blends a color already multiplied by its alpha over the target
+    Premultiplied,
+}
+
+impl AlphaMode {
+    /// Cutoff of [`Self::Mask`] in glTF when the file leaves it out
+    pub const DEFAULT_CUTOFF: f32 = 0.5;
+
+    /// Transparent materials don't write depth and are drawn after the opaque ones, from back to front.
+    pub const fn is_transparent(self) -> bool {
+        matches!(self, Self::Blend | Self::Additive | Self::Premultiplied)
+    }
+
+    pub const fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            Self::Opaque | Self::Mask(_) => wgpu::BlendState::REPLACE,
+            Self::Blend => wgpu::BlendState::ALPHA_BLENDING,
+            Self::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            Self::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        }
+    }
+
+    /// The `alpha_cutoff` of the material parameters, fragments with a lower alpha are discarded.
+    pub const fn cutoff(self) -> f32 {
+        match self {
+            Self::Mask(cutoff) => cutoff,
+            _ => 0.0,
+        }
+    }
+
+    /// Only the `ALPHA_MASK` variant of the material shaders reads the cutoff and discards.
+    pub fn shader_defs(self) -> ShaderDefs {
+        let defs = ShaderDefs::new();
+        if matches!(self, Self::Mask(_)) {
+            defs.with("ALPHA_MASK")
+        } else {
+            defs
+        }
+    }
+}
+
+const UNLIT_SHADER: Shader = Shader::new("shaders/unlit.wgsl", include_str!("shaders/unlit.wgsl"));
+const LIT_SHADER: Shader = Shader::new("shaders/lit.wgsl", include_str!("shaders/lit.wgsl"));
+const PBR_SHADER: Shader = Shader::new("shaders/pbr.wgsl", include_str!("shaders/pbr.wgsl"));
+
 pub trait Material<'a>: Debug {
     fn shader(&self) -> ShaderModuleDescriptor<'a>;
+    /// Defines [`Self::shader`] is specialized with, a [`Shader`] composes one variant per set.
+    fn shader_defs(&self) -> ShaderDefs {
+        self.alpha_mode().shader_defs()
+    }
     fn bind_group(&self) -> &BindGroup;
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Opaque
+    }
+    /// Fragment entry point of [`Self::shader`] writing the [`GBuffer`](super::deferred::GBuffer) of the deferred path.
+    /// Materials without one are shaded forward.
+    fn gbuffer_entry(&self) -> Option<&'a str> {
+        None
+    }
     fn gui(&mut self, _ui: &mut Ui, _queue: &wgpu::Queue, _device: &wgpu::Device) -> bool {
         false
     }
     fn gui_register(&mut self, _egui_renderer: &mut EguiRenderer, _device: &wgpu::Device) {}
 }
+
+/// A material several [`Model`](super::model::Model)s can be drawn with
+pub type SharedMaterial = Rc<dyn for<'a> Material<'a>>;
+
+/// Uniforms of an [`UnlitMaterial`], laid out like `UnlitParameters` in unlit.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub struct UnlitParameters {
+    pub diffuse_color: Color,
+    /// [`AlphaMode::cutoff`] of the material
+    pub alpha_cutoff: f32,
+    #[padding]
+    _padding: [f32; 3],
+}
+
+impl UnlitParameters {
+    pub const fn new(diffuse_color: Color, alpha_cutoff: f32) -> Self {
+        Self {
+            diffuse_color,
+            alpha_cutoff,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UnlitMaterial {
     pub diffuse_texture: Texture,
-    pub diffuse_color: UniformBuffer<Color>,
+    pub parameters: UniformBuffer<UnlitParameters>,
+    pub alpha_mode: AlphaMode,
     pub bind_group: BindGroup,
 }
 
@@ -35,27 +148,41 @@ impl UnlitMaterial {
         ImageBuffer::from_pixel(1, 1, image::Rgba([255_u8, 255_u8, 255_u8, 255_u8])).into()
     }
     pub fn from_pbr(value: PbrMaterial, device: &wgpu::Device) -> Self {
+        let parameters = UnlitParameters::new(
+            value.parameters.data.diffuse_color,
+            value.parameters.data.alpha_cutoff,
+        );
         let mut s = Self {
             diffuse_texture: value.diffuse_texture,
-            diffuse_color: value.diffuse_color,
+            parameters: UniformBuffer::new(parameters, device),
+            alpha_mode: value.alpha_mode,
             bind_group: value.bind_group,
         };
         s.rebuild_bind_group(device);
         s
     }
     pub fn from_lit(value: LitMaterial, device: &wgpu::Device) -> Self {
+        let parameters = UnlitParameters::new(
+            value.parameters.data.diffuse_color,
+            value.parameters.data.alpha_cutoff,
+        );
         let mut s = Self {
             bind_group: value.bind_group,
             diffuse_texture: value.diffuse_texture,
-            diffuse_color: value.diffuse_color,
+            parameters: UniformBuffer::new(parameters, device),
+            alpha_mode: value.alpha_mode,
         };
         s.rebuild_bind_group(device);
         s
     }
+    /// Layout of [`Material::bind_group`], the textures and uniforms in binding order
+    pub fn layout() -> BindGroupLayoutEntries {
+        BindGroupLayoutEntries::new().texture().uniform()
+    }
     fn rebuild_bind_group(&mut self, device: &wgpu::Device) {
         self.bind_group = BindGroupBuilder::new()
             .texture(&self.diffuse_texture)
-            .uniform(&self.diffuse_color.buffer)
+            .uniform(&self.parameters.buffer)
             .build(device);
     }
 }
@@ -66,11 +193,23 @@ impl<'a> Material<'a> for UnlitMaterial {
     }
 
     fn shader(&self) -> ShaderModuleDescriptor<'a> {
-        include_wgsl!("shaders/unlit.wgsl")
+        UNLIT_SHADER.variant(&self.shader_defs())
+    }
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
     }
     fn gui(&mut self, ui: &mut Ui, queue: &wgpu::Queue, device: &wgpu::Device) -> bool {
-        if color_edit(ui, &mut self.diffuse_color.data, "Diffuse Color") {
-            self.diffuse_color.update(queue);
+        let parameters = &mut self.parameters;
+        if color_edit(ui, &mut parameters.data.diffuse_color, "Diffuse Color") {
+            parameters.update_field(&parameters.data.diffuse_color, queue);
+        }
+        if float_edit(ui, &mut parameters.data.diffuse_color.a, "Alpha", 0.0..=1.0) {
+            parameters.update_field(&parameters.data.diffuse_color.a, queue);
+        }
+        if alpha_mode_edit(ui, &mut self.alpha_mode) {
+            parameters.data.alpha_cutoff = self.alpha_mode.cutoff();
+            parameters.update_field(&parameters.data.alpha_cutoff, queue);
+            return true;
         }
         if let Some(id) = self.diffuse_texture.egui_id {
             if texture_edit(ui, id, "Diffuse Texture") {
@@ -94,6 +233,7 @@ impl<'a> Material<'a> for UnlitMaterial {
 pub struct UnlitMaterialBuilder {
     diffuse_texture: Option<Texture>,
     diffuse_color: Option<Color>,
+    alpha_mode: Option<AlphaMode>,
 }
 
 impl UnlitMaterialBuilder {
@@ -112,10 +252,20 @@ impl UnlitMaterialBuilder {
             ..self
         }
     }
+    pub fn alpha_mode(self, alpha_mode: AlphaMode) -> Self {
+        Self {
+            alpha_mode: Some(alpha_mode),
+            ..self
+        }
+    }
     pub fn build(self, device: &wgpu::Device, queue: &wgpu::Queue) -> UnlitMaterial {
-        let diffuse_color = UniformBuffer::new(
-            self.diffuse_color
-                .unwrap_or(UnlitMaterial::DEFAULT_DIFFUSE_COLOR),
+        let alpha_mode = self.alpha_mode.unwrap_or_default();
+        let parameters = UniformBuffer::new(
+            UnlitParameters::new(
+                self.diffuse_color
+                    .unwrap_or(UnlitMaterial::DEFAULT_DIFFUSE_COLOR),
+                alpha_mode.cutoff(),
+            ),
             device,
         );
         let diffuse_texture = self.diffuse_texture.unwrap_or_else(|| {
@@ -123,24 +273,37 @@ impl UnlitMaterialBuilder {
         });
         let bind_group = BindGroupBuilder::new()
             .texture(&diffuse_texture)
-            .uniform(&diffuse_color.buffer)
+            .uniform(&parameters.buffer)
             .build(device);
         UnlitMaterial {
             diffuse_texture,
-            diffuse_color,
+            parameters,
+            alpha_mode,
             bind_group,
         }
     }
 }
 
+/// Uniforms of a [`LitMaterial`], laid out like `LitParameters` in lit.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub struct LitParameters {
+    pub diffuse_color: Color,
+    pub specular_color: Vec3,
+    pub specular_exponent: f32,
+    pub ambient: Vec3,
+    /// [`AlphaMode::cutoff`] of the material
+    pub alpha_cutoff: f32,
+}
+
 #[derive(Debug)]
 pub struct LitMaterial {
     pub diffuse_texture: Texture,
-    pub diffuse_color: UniformBuffer<Color>,
     pub normal_map: Texture,
-    pub specular_color: UniformBuffer<Color>,
-    pub specular_exponent: UniformBuffer<f32>,
-    pub ambient: UniformBuffer<Color>,
+    /// Whether `normal_map` was given, the shader skips sampling the flat default otherwise
+    pub normal_mapped: bool,
+    pub parameters: UniformBuffer<LitParameters>,
+    pub alpha_mode: AlphaMode,
     pub bind_group: BindGroup,
 }
 
@@ -153,43 +316,56 @@ impl LitMaterial {
     pub fn default_normal_texture() -> DynamicImage {
         ImageBuffer::from_pixel(1, 1, image::Rgba::<u8>([0, 0, 255, 255])).into()
     }
-    const DEFAULT_SPECULAR_EXPONENT: f32 = 100.0;
-    const DEFAULT_AMBIENT_COLOR: Color = Color::new(0.01, 0.01, 0.01);
+    pub(crate) const DEFAULT_SPECULAR_EXPONENT: f32 = 100.0;
+    pub(crate) const DEFAULT_AMBIENT_COLOR: Color = Color::new(0.01, 0.01, 0.01);
 
     pub fn from_pbr(value: PbrMaterial, device: &wgpu::Device) -> Self {
+        let parameters = LitParameters {
+            diffuse_color: value.parameters.data.diffuse_color,
+            specular_color: Self::DEFAULT_SPECULAR_COLOR.into(),
+            specular_exponent: Self::DEFAULT_SPECULAR_EXPONENT,
+            ambient: value.ambient.into(),
+            alpha_cutoff: value.parameters.data.alpha_cutoff,
+        };
         let mut s = Self {
             bind_group: value.bind_group,
             diffuse_texture: value.diffuse_texture,
-            diffuse_color: value.diffuse_color,
             normal_map: value.normal_map,
-            specular_color: UniformBuffer::new(Self::DEFAULT_SPECULAR_COLOR, device),
-            specular_exponent: UniformBuffer::new(Self::DEFAULT_SPECULAR_EXPONENT, device),
-            ambient: value.ambient,
+            normal_mapped: value.normal_mapped,
+            parameters: UniformBuffer::new(parameters, device),
+            alpha_mode: value.alpha_mode,
         };
         s.rebuild_bind_group(device);
         s
     }
     pub fn from_unlit(value: UnlitMaterial, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let parameters = LitParameters {
+            diffuse_color: value.parameters.data.diffuse_color,
+            specular_color: Self::DEFAULT_SPECULAR_COLOR.into(),
+            specular_exponent: Self::DEFAULT_SPECULAR_EXPONENT,
+            ambient: Self::DEFAULT_AMBIENT_COLOR.into(),
+            alpha_cutoff: value.parameters.data.alpha_cutoff,
+        };
         let mut s = Self {
             bind_group: value.bind_group,
             diffuse_texture: value.diffuse_texture,
-            diffuse_color: value.diffuse_color,
             normal_map: Texture::new(Self::default_normal_texture(), device, queue),
-            specular_color: UniformBuffer::new(Self::DEFAULT_SPECULAR_COLOR, device),
-            specular_exponent: UniformBuffer::new(Self::DEFAULT_SPECULAR_EXPONENT, device),
-            ambient: UniformBuffer::new(Self::DEFAULT_AMBIENT_COLOR, device),
+            normal_mapped: false,
+            parameters: UniformBuffer::new(parameters, device),
+            alpha_mode: value.alpha_mode,
         };
         s.rebuild_bind_group(device);
         s
     }
+    /// Layout of [`Material::bind_group`], the textures and uniforms in binding order
+    pub fn layout() -> BindGroupLayoutEntries {
+        BindGroupLayoutEntries::new().texture().uniform().texture()
+    }
     fn rebuild_bind_group(&mut self, device: &wgpu::Device) {
         self.bind_group = BindGroupBuilder::new()
             .texture(&self.diffuse_texture)
-            .uniform(&self.diffuse_color.buffer)
+            .uniform(&self.parameters.buffer)
             .texture(&self.normal_map)
-            .uniform(&self.specular_color.buffer)
-            .uniform(&self.specular_exponent.buffer)
-            .uniform(&self.ambient.buffer)
             .build(device);
     }
 }
@@ -199,11 +375,31 @@ impl<'a> Material<'a> for LitMaterial {
         &self.bind_group
     }
     fn shader(&self) -> ShaderModuleDescriptor<'a> {
-        include_wgsl!("shaders/lit.wgsl")
+        LIT_SHADER.variant(&self.shader_defs())
+    }
+    fn shader_defs(&self) -> ShaderDefs {
+        let defs = self.alpha_mode.shader_defs();
+        if self.normal_mapped {
+            defs.with("NORMAL_MAP")
+        } else {
+            defs
+        }
+    }
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
     }
     fn gui(&mut self, ui: &mut Ui, queue: &wgpu::Queue, device: &wgpu::Device) -> bool {
-        if color_edit(ui, &mut self.diffuse_color.data, "Diffuse Color") {
-            self.diffuse_color.update(queue);
+        let parameters = &mut self.parameters;
+        if color_edit(ui, &mut parameters.data.diffuse_color, "Diffuse Color") {
+            parameters.update_field(&parameters.data.diffuse_color, queue);
+        }
+        if float_edit(ui, &mut parameters.data.diffuse_color.a, "Alpha", 0.0..=1.0) {
+            parameters.update_field(&parameters.data.diffuse_color.a, queue);
+        }
+        if alpha_mode_edit(ui, &mut self.alpha_mode) {
+            parameters.data.alpha_cutoff = self.alpha_mode.cutoff();
+            parameters.update_field(&parameters.data.alpha_cutoff, queue);
+            return true;
         }
         if let Some(id) = self.diffuse_texture.egui_id {
             if texture_edit(ui, id, "Diffuse Texture") {
@@ -221,25 +417,27 @@ impl<'a> Material<'a> for LitMaterial {
                 if let Some(path) = rfd::FileDialog::new().pick_file() {
                     if let Ok(texture) = Texture::from_path(path, device, queue) {
                         self.normal_map = texture;
+                        self.normal_mapped = true;
                         self.rebuild_bind_group(device);
                         return true;
                     }
                 }
             }
         }
-        if color_edit(ui, &mut self.specular_color.data, "Specular Color") {
-            self.specular_color.update(queue);
+        let parameters = &mut self.parameters;
+        if color_edit(ui, &mut parameters.data.specular_color, "Specular Color") {
+            parameters.update_field(&parameters.data.specular_color, queue);
         }
         if float_edit(
             ui,
-            &mut self.specular_exponent.data,
+            &mut parameters.data.specular_exponent,
             "Specular Exponent",
             0.0..=1000.0,
         ) {
-            self.specular_exponent.update(queue);
+            parameters.update_field(&parameters.data.specular_exponent, queue);
         }
-        if color_edit(ui, &mut self.ambient.data, "Ambient Color") {
-            self.ambient.update(queue);
+        if color_edit(ui, &mut parameters.data.ambient, "Ambient Color") {
+            parameters.update_field(&parameters.data.ambient, queue);
         }
         false
     }
@@ -257,6 +455,7 @@ pub struct LitMaterialBuilder {
     specular_color: Option<Color>,
     specular_exponent: Option<f32>,
     ambient: Option<Color>,
+    alpha_mode: Option<AlphaMode>,
 }
 
 impl LitMaterialBuilder {
@@ -299,62 +498,79 @@ impl LitMaterialBuilder {
             ..self
         }
     }
+    pub fn alpha_mode(self, alpha_mode: AlphaMode) -> Self {
+        Self {
+            alpha_mode: Some(alpha_mode),
+            ..self
+        }
+    }
     pub fn build(self, device: &wgpu::Device, queue: &wgpu::Queue) -> LitMaterial {
-        let diffuse_color = UniformBuffer::new(
-            self.diffuse_color
-                .unwrap_or(LitMaterial::DEFAULT_DIFFUSE_COLOR),
+        let alpha_mode = self.alpha_mode.unwrap_or_default();
+        let parameters = UniformBuffer::new(
+            LitParameters {
+                diffuse_color: self
+                    .diffuse_color
+                    .unwrap_or(LitMaterial::DEFAULT_DIFFUSE_COLOR),
+                specular_color: self
+                    .specular_color
+                    .unwrap_or(LitMaterial::DEFAULT_SPECULAR_COLOR)
+                    .into(),
+                specular_exponent: self
+                    .specular_exponent
+                    .unwrap_or(LitMaterial::DEFAULT_SPECULAR_EXPONENT),
+                ambient: self
+                    .ambient
+                    .unwrap_or(LitMaterial::DEFAULT_AMBIENT_COLOR)
+                    .into(),
+                alpha_cutoff: alpha_mode.cutoff(),
+            },
             device,
         );
         let diffuse_texture = self
             .diffuse_texture
             .unwrap_or_else(|| Texture::new(LitMaterial::default_diffuse_texture(), device, queue));
-        let specular_color = UniformBuffer::new(
-            self.specular_color
-                .unwrap_or(LitMaterial::DEFAULT_SPECULAR_COLOR),
-            device,
-        );
-
-        let specular_exponent = UniformBuffer::new(
-            self.specular_exponent
-                .unwrap_or(LitMaterial::DEFAULT_SPECULAR_EXPONENT),
-            device,
-        );
+        let normal_mapped = self.normal_map.is_some();
         let normal_map = self
             .normal_map
             .unwrap_or_else(|| Texture::new(LitMaterial::default_normal_texture(), device, queue));
-        let ambient = UniformBuffer::new(
-            self.ambient.unwrap_or(LitMaterial::DEFAULT_AMBIENT_COLOR),
-            device,
-        );
         let bind_group = BindGroupBuilder::new()
             .texture(&diffuse_texture)
-            .uniform(&diffuse_color.buffer)
+            .uniform(&parameters.buffer)
             .texture(&normal_map)
-            .uniform(&specular_color.buffer)
-            .uniform(&specular_exponent.buffer)
-            .uniform(&ambient.buffer)
             .build(device);
         LitMaterial {
             diffuse_texture,
-            diffuse_color,
             normal_map,
-            specular_color,
-            specular_exponent,
-            ambient,
+            normal_mapped,
+            parameters,
+            alpha_mode,
             bind_group,
         }
     }
 }
 
+/// Uniforms of a [`PbrMaterial`], laid out like `PbrParameters` in pbr.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, ShaderType)]
+pub struct PbrParameters {
+    pub diffuse_color: Color,
+    pub specular: f32,
+    pub ior: f32,
+    pub roughness: f32,
+    /// [`AlphaMode::cutoff`] of the material
+    pub alpha_cutoff: f32,
+}
+
 #[derive(Debug)]
 pub struct PbrMaterial {
     pub diffuse_texture: Texture,
-    pub diffuse_color: UniformBuffer<Color>,
     pub normal_map: Texture,
-    pub specular: UniformBuffer<f32>,
-    pub ior: UniformBuffer<f32>,
-    pub roughness: UniformBuffer<f32>,
-    pub ambient: UniformBuffer<Color>,
+    /// Whether `normal_map` was given, the shader skips sampling the flat default otherwise
+    pub normal_mapped: bool,
+    pub parameters: UniformBuffer<PbrParameters>,
+    /// Kept for conversions to [`LitMaterial`], the pbr shader is lit by the [`Environment`](super::environment::Environment) instead
+    pub ambient: Color,
+    pub alpha_mode: AlphaMode,
     pub bind_group: BindGroup,
 }
 
@@ -367,47 +583,59 @@ impl PbrMaterial {
         ImageBuffer::from_pixel(1, 1, image::Rgba::<u8>([0, 0, 255, 255])).into()
     }
     const DEFAULT_SPECULAR: f32 = 0.5;
-    const DEFAULT_IOR: f32 = 1.4;
+    pub(crate) const DEFAULT_IOR: f32 = 1.4;
     const DEFAULT_ROUGHNESS: f32 = 1.0;
-    const DEFAULT_AMBIENT_COLOR: Color = Color::new(0.01, 0.01, 0.01);
+    pub(crate) const DEFAULT_AMBIENT_COLOR: Color = Color::new(0.01, 0.01, 0.01);
 
     pub fn from_lit(value: LitMaterial, device: &wgpu::Device) -> Self {
+        let parameters = PbrParameters {
+            diffuse_color: value.parameters.data.diffuse_color,
+            specular: Self::DEFAULT_SPECULAR,
+            ior: Self::DEFAULT_IOR,
+            roughness: Self::DEFAULT_ROUGHNESS,
+            alpha_cutoff: value.parameters.data.alpha_cutoff,
+        };
         let mut s = Self {
             bind_group: value.bind_group,
             diffuse_texture: value.diffuse_texture,
-            diffuse_color: value.diffuse_color,
             normal_map: value.normal_map,
-            ambient: value.ambient,
-            specular: UniformBuffer::new(Self::DEFAULT_SPECULAR, device),
-            ior: UniformBuffer::new(Self::DEFAULT_IOR, device),
-            roughness: UniformBuffer::new(Self::DEFAULT_ROUGHNESS, device),
+            normal_mapped: value.normal_mapped,
+            parameters: UniformBuffer::new(parameters, device),
+            ambient: value.parameters.data.ambient.into(),
+            alpha_mode: value.alpha_mode,
         };
         s.rebuild_bind_group(device);
         s
     }
     pub fn from_unlit(value: UnlitMaterial, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let parameters = PbrParameters {
+            diffuse_color: value.parameters.data.diffuse_color,
+            specular: Self::DEFAULT_SPECULAR,
+            ior: Self::DEFAULT_IOR,
+            roughness: Self::DEFAULT_ROUGHNESS,
+            alpha_cutoff: value.parameters.data.alpha_cutoff,
+        };
         let mut s = Self {
             bind_group: value.bind_group,
             diffuse_texture: value.diffuse_texture,
-            diffuse_color: value.diffuse_color,
             normal_map: Texture::new(Self::default_normal_texture(), device, queue),
-            ambient: UniformBuffer::new(Self::DEFAULT_AMBIENT_COLOR, device),
-            specular: UniformBuffer::new(Self::DEFAULT_SPECULAR, device),
-            ior: UniformBuffer::new(Self::DEFAULT_IOR, device),
-            roughness: UniformBuffer::new(Self::DEFAULT_ROUGHNESS, device),
+            normal_mapped: false,
+            parameters: UniformBuffer::new(parameters, device),
+            ambient: Self::DEFAULT_AMBIENT_COLOR,
+            alpha_mode: value.alpha_mode,
         };
         s.rebuild_bind_group(device);
         s
     }
+    /// Layout of [`Material::bind_group`], the textures and uniforms in binding order
+    pub fn layout() -> BindGroupLayoutEntries {
+        BindGroupLayoutEntries::new().texture().uniform().texture()
+    }
     fn rebuild_bind_group(&mut self, device: &wgpu::Device) {
         self.bind_group = BindGroupBuilder::new()
             .texture(&self.diffuse_texture)
-            .uniform(&self.diffuse_color.buffer)
+            .uniform(&self.parameters.buffer)
             .texture(&self.normal_map)
-            .uniform(&self.specular.buffer)
-            .uniform(&self.ior.buffer)
-            .uniform(&self.roughness.buffer)
-            .uniform(&self.ambient.buffer)
             .build(device);
     }
 }
@@ -418,11 +646,34 @@ impl<'a> Material<'a> for PbrMaterial {
     }
 
     fn shader(&self) -> ShaderModuleDescriptor<'a> {
-        include_wgsl!("shaders/pbr.wgsl")
+        PBR_SHADER.variant(&self.shader_defs())
+    }
+    fn shader_defs(&self) -> ShaderDefs {
+        let defs = self.alpha_mode.shader_defs();
+        if self.normal_mapped {
+            defs.with("NORMAL_MAP")
+        } else {
+            defs
+        }
+    }
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+    fn gbuffer_entry(&self) -> Option<&'a str> {
+        Some("fs_gbuffer")
     }
     fn gui(&mut self, ui: &mut Ui, queue: &wgpu::Queue, device: &wgpu::Device) -> bool {
-        if color_edit(ui, &mut self.diffuse_color.data, "Diffuse Color") {
-            self.diffuse_color.update(queue);
+        let parameters = &mut self.parameters;
+        if color_edit(ui, &mut parameters.data.diffuse_color, "Diffuse Color") {
+            parameters.update_field(&parameters.data.diffuse_color, queue);
+        }
+        if float_edit(ui, &mut parameters.data.diffuse_color.a, "Alpha", 0.0..=1.0) {
+            parameters.update_field(&parameters.data.diffuse_color.a, queue);
+        }
+        if alpha_mode_edit(ui, &mut self.alpha_mode) {
+            parameters.data.alpha_cutoff = self.alpha_mode.cutoff();
+            parameters.update_field(&parameters.data.alpha_cutoff, queue);
+            return true;
         }
         if let Some(id) = self.diffuse_texture.egui_id {
             if texture_edit(ui, id, "Diffuse Texture") {
@@ -440,23 +691,32 @@ impl<'a> Material<'a> for PbrMaterial {
                 if let Some(path) = rfd::FileDialog::new().pick_file() {
                     if let Ok(texture) = Texture::from_path(path, device, queue) {
                         self.normal_map = texture;
+                        self.normal_mapped = true;
                         self.rebuild_bind_group(device);
                         return true;
                     }
                 }
             }
         }
-        if float_edit(ui, &mut self.specular.data, "Specular Intensity", 0.0..=1.0) {
-            self.specular.update(queue);
-        }
-        if float_edit(ui, &mut self.ior.data, "Index of Refraction", 0.5..=3.0) {
-            self.ior.update(queue);
+        let parameters = &mut self.parameters;
+        if float_edit(
+            ui,
+            &mut parameters.data.specular,
+            "Specular Intensity",
+            0.0..=1.0,
+        ) {
+            parameters.update_field(&parameters.data.specular, queue);
         }
-        if float_edit(ui, &mut self.roughness.data, "Roughness", 0.0..=1.0) {
-            self.roughness.update(queue);
+        if float_edit(
+            ui,
+            &mut parameters.data.ior,
+            "Index of Refraction",
+            0.5..=3.0,
+        ) {
+            parameters.update_field(&parameters.data.ior, queue);
         }
-        if color_edit(ui, &mut self.ambient.data, "Ambient Color") {
-            self.ambient.update(queue);
+        if float_edit(ui, &mut parameters.data.roughness, "Roughness", 0.0..=1.0) {
+            parameters.update_field(&parameters.data.roughness, queue);
         }
         false
     }
@@ -475,6 +735,7 @@ pub struct PbrMaterialBuilder {
     ior: Option<f32>,
     roughness: Option<f32>,
     ambient: Option<Color>,
+    alpha_mode: Option<AlphaMode>,
 }
 
 impl PbrMaterialBuilder {
@@ -523,62 +784,171 @@ impl PbrMaterialBuilder {
             ..self
         }
     }
+    pub fn alpha_mode(self, alpha_mode: AlphaMode) -> Self {
+        Self {
+            alpha_mode: Some(alpha_mode),
+            ..self
+        }
+    }
     pub fn build(self, device: &wgpu::Device, queue: &wgpu::Queue) -> PbrMaterial {
-        let diffuse_color = UniformBuffer::new(
-            self.diffuse_color
-                .unwrap_or(PbrMaterial::DEFAULT_DIFFUSE_COLOR),
+        let alpha_mode = self.alpha_mode.unwrap_or_default();
+        let parameters = UniformBuffer::new(
+            PbrParameters {
+                diffuse_color: self
+                    .diffuse_color
+                    .unwrap_or(PbrMaterial::DEFAULT_DIFFUSE_COLOR),
+                specular: self.specular.unwrap_or(PbrMaterial::DEFAULT_SPECULAR),
+                ior: self.ior.unwrap_or(PbrMaterial::DEFAULT_IOR),
+                roughness: self.roughness.unwrap_or(PbrMaterial::DEFAULT_ROUGHNESS),
+                alpha_cutoff: alpha_mode.cutoff(),
+            },
             device,
         );
         let diffuse_texture = self
             .diffuse_texture
             .unwrap_or_else(|| Texture::new(PbrMaterial::default_diffuse_texture(), device, queue));
-        let ior = UniformBuffer::new(self.ior.unwrap_or(PbrMaterial::DEFAULT_IOR), device);
-        let specular = UniformBuffer::new(
-            self.specular.unwrap_or(PbrMaterial::DEFAULT_SPECULAR),
-            device,
-        );
-        let roughness = UniformBuffer::new(
-            self.roughness.unwrap_or(PbrMaterial::DEFAULT_ROUGHNESS),
-            device,
-        );
+        let normal_mapped = self.normal_map.is_some();
         let normal_map = self
             .normal_map
             .unwrap_or_else(|| Texture::new(PbrMaterial::default_normal_texture(), device, queue));
-        let ambient = UniformBuffer::new(
-            self.ambient.unwrap_or(PbrMaterial::DEFAULT_AMBIENT_COLOR),
-            device,
-        );
 
         let bind_group = BindGroupBuilder::new()
             .texture(&diffuse_texture)
-            .uniform(&diffuse_color.buffer)
+            .uniform(&parameters.buffer)
             .texture(&normal_map)
-            .uniform(&specular.buffer)
-            .uniform(&ior.buffer)
-            .uniform(&roughness.buffer)
-            .uniform(&ambient.buffer)
             .build(device);
         PbrMaterial {
             diffuse_texture,
-            diffuse_color,
             normal_map,
-            specular,
-            ior,
-            roughness,
-            ambient,
+            normal_mapped,
+            parameters,
+            ambient: self.ambient.unwrap_or(PbrMaterial::DEFAULT_AMBIENT_COLOR),
+            alpha_mode,
             bind_group,
         }
     }
 }
 
+/// Bind group indices of the pipelines made with [`MaterialPipelineBuilder`], the material comes first
+///
+/// Transforms are not bound, they come from an [`Instance`](super::model::Instance) vertex buffer.
+pub const MATERIAL_GROUP: u32 = 0;
+/// Camera, lights and environment
+pub const SCENE_GROUP: u32 = 1;
+pub const SHADOW_GROUP: u32 = 2;
+
 #[derive(Debug, Clone, Copy)]
 pub struct MaterialPipelineBuilder;
 
 impl MaterialPipelineBuilder {
     #[allow(clippy::new_ret_no_self)]
+    /// Blends by the [`Material::alpha_mode`], transparent materials don't write depth.
     pub fn new<'a>(material: &'a dyn Material<'a>) -> RenderPipelineBuilder<'a> {
         let shader = material.shader();
         let bind_group = material.bind_group();
-        RenderPipelineBuilder::new(shader).add_bind_group(&bind_group.layout)
+        let alpha_mode = material.alpha_mode();
+        RenderPipelineBuilder::new(shader)
+            .bind_group(bind_group)
+            .blend(alpha_mode.blend_state())
+            .depth_write(!alpha_mode.is_transparent())
+    }
+    /// Pipeline writing the [`GBuffer`](super::deferred::GBuffer), if the material has a [`Material::gbuffer_entry`].
+    /// Transparent materials are always shaded forward.
+    pub fn gbuffer<'a>(material: &'a dyn Material<'a>) -> Option<RenderPipelineBuilder<'a>> {
+        if material.alpha_mode().is_transparent() {
+            return None;
+        }
+        let entry = material.gbuffer_entry()?;
+        Some(
+            Self::new(material)
+                .fragment_entry(entry)
+                .color_targets(&GBUFFER_FORMATS)
+                .blend(wgpu::BlendState::REPLACE),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LitMaterial, LitParameters, PbrMaterial, PbrParameters, UnlitMaterial, UnlitParameters,
+        LIT_SHADER, MATERIAL_GROUP, PBR_SHADER, UNLIT_SHADER,
+    };
+    use crate::renderer::{
+        bind_group::BindGroupLayoutEntries,
+        camera::GpuCamera,
+        mesh::Vertex,
+        model::Instance,
+        reflection::ShaderReflection,
+        resources::VertexAttributeLayout,
+        shader::{Shader, ShaderDefs},
+        shadow::GpuShadow,
+    };
+
+    fn check(shader: &Shader, layout: &BindGroupLayoutEntries, entry_points: &[&str]) {
+        let defines = [
+            &[][..],
+            &["ALPHA_MASK"],
+            &["NORMAL_MAP"],
+            &["ALPHA_MASK", "NORMAL_MAP"],
+        ];
+        for names in defines {
+            let defs = names
+                .iter()
+                .fold(ShaderDefs::new(), |defs, &name| defs.with(name));
+            let descriptor = shader.variant(&defs);
+            let wgpu::ShaderSource::Wgsl(source) = descriptor.source else {
+                unreachable!("Material shaders are WGSL");
+            };
+            let reflection = ShaderReflection::new(descriptor.label.unwrap(), &source).unwrap();
+            // Scene and shadow groups are checked when the pipelines are built
+            let mut groups = [None; 3];
+            groups[MATERIAL_GROUP as usize] = Some(layout.entries());
+            if let Err(error) = reflection.check(
+                entry_points,
+                &groups,
+                &[Vertex::layout(), Instance::layout()],
+            ) {
+                panic!("{names:?}: {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn material_layouts_match_their_shaders() {
+        check(
+            &UNLIT_SHADER,
+            &UnlitMaterial::layout(),
+            &["vs_main", "fs_main"],
+        );
+        check(&LIT_SHADER, &LitMaterial::layout(), &["vs_main", "fs_main"]);
+        check(
+            &PBR_SHADER,
+            &PbrMaterial::layout(),
+            &["vs_main", "fs_main", "fs_gbuffer"],
+        );
+    }
+
+    fn reflect(shader: &Shader) -> ShaderReflection {
+        let descriptor = shader.variant(&ShaderDefs::new());
+        let wgpu::ShaderSource::Wgsl(source) = descriptor.source else {
+            unreachable!("Material shaders are WGSL");
+        };
+        ShaderReflection::new(descriptor.label.unwrap(), &source).unwrap()
+    }
+
+    #[test]
+    fn gpu_types_match_their_shader_structs() {
+        reflect(&UNLIT_SHADER)
+            .check_struct::<UnlitParameters>("UnlitParameters")
+            .unwrap();
+        let lit = reflect(&LIT_SHADER);
+        lit.check_struct::<LitParameters>("LitParameters").unwrap();
+        // Scene structs, imported by the material shaders
+        lit.check_struct::<GpuCamera>("Camera").unwrap();
+        lit.check_struct::<GpuShadow>("Shadow").unwrap();
+        reflect(&PBR_SHADER)
+            .check_struct::<PbrParameters>("PbrParameters")
+            .unwrap();
     }
 }
@@ -79,7 +79,7 @@ pub fn compute_tangent_frame(face: [Vertex; 3], expected_normal: Vec3) -> (Vec3,
 }
 
 impl Mesh {
-    pub fn from_obj(path: impl AsRef<Path> + Debug) -> Self {
+    pub fn from_obj(path: impl AsRef<Path> + Debug) -> Result<Self, tobj::LoadError> {
         load_geometry(path)
     }
     pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
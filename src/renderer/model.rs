@@ -4,21 +4,22 @@ use bytemuck::{Pod, Zeroable};
 use egui::Ui;
 use glam::{Affine3A, Mat4, Vec4};
 
-use crate::visibility::bounding_volume::{aabb::Aabb, obb::Obb};
+use crate::{
+    core::shader_type::ShaderType,
+    visibility::bounding_volume::{aabb::Aabb, obb::Obb},
+};
 
 use super::{
-    bind_group::{BindGroup, BindGroupBuilder},
-    buffer::UniformBuffer,
     egui_renderer::EguiRenderer,
     gui::{change_material, transform_edit},
-    material::{Material, MaterialPipelineBuilder},
+    material::{Material, MaterialPipelineBuilder, SharedMaterial},
     mesh::Mesh,
     render_pipeline::RenderPipelineBuilder,
     resources::VertexAttributeLayout,
 };
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable, PartialEq)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, PartialEq, ShaderType)]
 pub struct Instance {
     pub x_axis: Vec4,
     pub y_axis: Vec4,
@@ -71,13 +72,12 @@ impl InstancedModel {
     }
 }
 
+/// A mesh drawn with a material, [`Batcher`](super::batch::Batcher) draws models sharing both in one call.
 #[derive(Debug)]
 pub struct Model {
     transform: Affine3A,
     mesh: Rc<Mesh>,
-    material: Box<dyn for<'a> Material<'a>>,
-    transform_uniform: UniformBuffer<Mat4>,
-    transform_bind_group: BindGroup,
+    material: SharedMaterial,
     bounding_box: Aabb,
 }
 
@@ -86,33 +86,40 @@ impl Model {
         transform: Affine3A,
         mesh: Rc<Mesh>,
         material: M,
-        device: &wgpu::Device,
     ) -> Self {
-        let transform_uniform = UniformBuffer::new(Mat4::from(transform), device);
-        let transform_bind_group = BindGroupBuilder::new()
-            .uniform(&transform_uniform.buffer)
-            .build(device);
+        Self::with_shared_material(transform, mesh, Rc::new(material))
+    }
+
+    /// Like [`Self::new`], with a material other models may use as well.
+    pub fn with_shared_material(
+        transform: Affine3A,
+        mesh: Rc<Mesh>,
+        material: SharedMaterial,
+    ) -> Self {
         let bounding_box = mesh.calculate_bounding_box();
         Self {
             transform,
             mesh,
-            material: Box::new(material),
-            transform_uniform,
-            transform_bind_group,
+            material,
             bounding_box,
         }
     }
 
     pub fn gui_register(&mut self, egui_renderer: &mut EguiRenderer, device: &wgpu::Device) {
-        self.material.gui_register(egui_renderer, device);
+        if let Some(material) = Rc::get_mut(&mut self.material) {
+            material.gui_register(egui_renderer, device);
+        }
     }
     pub fn gui(&mut self, ui: &mut Ui, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
         let mut changed = false;
-        if transform_edit(ui, &mut self.transform) {
-            self.set_transform(self.transform, queue);
-        }
+        transform_edit(ui, &mut self.transform);
         ui.collapsing("Material", |ui| {
-            changed |= self.material.gui(ui, queue, device);
+            match Rc::get_mut(&mut self.material) {
+                Some(material) => changed |= material.gui(ui, queue, device),
+                None => {
+                    ui.label("Shared with other models");
+                }
+            }
             changed |= change_material(ui, &mut self.material, device, queue);
         });
         changed
@@ -120,13 +127,10 @@ impl Model {
 
     pub fn pipeline(&self) -> RenderPipelineBuilder {
         MaterialPipelineBuilder::new(self.material.as_ref())
-            .add_bind_group(&self.transform_bind_group.layout)
     }
 
-    pub fn set_transform(&mut self, transform: Affine3A, queue: &wgpu::Queue) {
+    pub const fn set_transform(&mut self, transform: Affine3A) {
         self.transform = transform;
-        self.transform_uniform.data = transform.into();
-        self.transform_uniform.update(queue);
     }
 
     pub fn set_mesh(&mut self, mesh: Rc<Mesh>) {
@@ -135,11 +139,7 @@ impl Model {
     }
 
     pub fn set_material<M: for<'a> Material<'a> + 'static>(&mut self, material: M) {
-        self.material = Box::new(material);
-    }
-
-    pub const fn transform_bind_group(&self) -> &BindGroup {
-        &self.transform_bind_group
+        self.material = Rc::new(material);
     }
 
     pub const fn mesh(&self) -> &Rc<Mesh> {
@@ -150,10 +150,19 @@ impl Model {
         self.transform
     }
 
+    /// Per instance data of the model, read by the material shaders.
+    pub fn instance(&self) -> Instance {
+        Instance::new(self.transform.into())
+    }
+
     pub fn material(&self) -> &dyn Material {
         self.material.as_ref()
     }
 
+    pub const fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
     pub fn bounding_box(&self) -> Obb {
         self.transform * self.bounding_box
     }
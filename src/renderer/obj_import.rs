@@ -0,0 +1,432 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use glam::Affine3A;
+use image::{DynamicImage, ImageError};
+
+use super::{
+    color::Color,
+    material::{AlphaMode, LitMaterial, LitMaterialBuilder, PbrMaterial, PbrMaterialBuilder},
+    mesh::Mesh,
+    model::Model,
+    resources::{mesh_from_obj_mesh, OBJ_LOAD_OPTIONS},
+    texture::Texture,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Load(tobj::LoadError),
+    Texture { path: PathBuf, error: ImageError },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Load(ref e) => write!(f, "Failed to load OBJ file: {e}"),
+            Self::Texture {
+                ref path,
+                ref error,
+            } => write!(f, "Failed to load texture {}: {error}", path.display()),
+        }
+    }
+}
+impl std::error::Error for ObjError {}
+
+impl From<tobj::LoadError> for ObjError {
+    fn from(value: tobj::LoadError) -> Self {
+        Self::Load(value)
+    }
+}
+
+/// Material parameters read from an MTL file.
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    /// `Kd`, with the `d` dissolve as alpha
+    pub diffuse_color: Color,
+    /// `Ks`
+    pub specular_color: Color,
+    /// `Ns`
+    pub specular_exponent: f32,
+    /// `Ka`
+    pub ambient: Color,
+    /// `Ni`
+    pub ior: f32,
+    /// `map_Kd`
+    pub diffuse_texture: Option<DynamicImage>,
+    /// `map_Bump`
+    pub normal_texture: Option<DynamicImage>,
+}
+
+impl ObjMaterial {
+    /// Reads `material`, with texture paths relative to `directory`.
+    pub fn new(material: &tobj::Material, directory: &Path) -> Result<Self, ObjError> {
+        let texture = |name: &Option<String>| -> Result<Option<DynamicImage>, ObjError> {
+            let Some(path) = name.as_deref().and_then(texture_file_name) else {
+                return Ok(None);
+            };
+            let path = directory.join(path);
+            image::open(&path)
+                .map(Some)
+                .map_err(|error| ObjError::Texture { path, error })
+        };
+        let defaults = Self::default();
+        Ok(Self {
+            name: material.name.clone(),
+            diffuse_color: material
+                .diffuse
+                .map_or(defaults.diffuse_color, Color::from)
+                .with_alpha(material.dissolve.unwrap_or(1.0)),
+            specular_color: material
+                .specular
+                .map_or(defaults.specular_color, Into::into),
+            specular_exponent: material.shininess.unwrap_or(defaults.specular_exponent),
+            ambient: material.ambient.map_or(defaults.ambient, Into::into),
+            ior: material.optical_density.unwrap_or(defaults.ior),
+            diffuse_texture: texture(&material.diffuse_texture)?,
+            normal_texture: texture(&material.normal_texture)?,
+        })
+    }
+
+    /// Roughness matching the Blinn-Phong specular exponent.
+    pub fn roughness(&self) -> f32 {
+        (2.0 / (self.specular_exponent + 2.0)).sqrt()
+    }
+
+    /// Dissolved materials are blended
+    pub fn alpha_mode(&self) -> AlphaMode {
+        if self.diffuse_color.a < 1.0 {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        }
+    }
+
+    pub fn to_lit(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> LitMaterial {
+        let mut builder = LitMaterialBuilder::new()
+            .diffuse_color(self.diffuse_color)
+            .specular_color(self.specular_color)
+            .specular_exponent(self.specular_exponent)
+            .ambient(self.ambient)
+            .alpha_mode(self.alpha_mode());
+        if let Some(ref image) = self.diffuse_texture {
+            builder = builder.diffuse_texture(Texture::new(image.clone(), device, queue));
+        }
+        if let Some(ref image) = self.normal_texture {
+            builder = builder.normal_texture(Texture::new(image.clone(), device, queue));
+        }
+        builder.build(device, queue)
+    }
+
+    pub fn to_pbr(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> PbrMaterial {
+        let Color { r, g, b, .. } = self.specular_color;
+        let mut builder = PbrMaterialBuilder::new()
+            .diffuse_color(self.diffuse_color)
+            .specular((r + g + b) / 3.0)
+            .roughness(self.roughness())
+            .index_of_refraction(self.ior)
+            .ambient(self.ambient)
+            .alpha_mode(self.alpha_mode());
+        if let Some(ref image) = self.diffuse_texture {
+            builder = builder.diffuse_texture(Texture::new(image.clone(), device, queue));
+        }
+        if let Some(ref image) = self.normal_texture {
+            builder = builder.normal_texture(Texture::new(image.clone(), device, queue));
+        }
+        builder.build(device, queue)
+    }
+}
+
+impl Default for ObjMaterial {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            diffuse_color: Color::WHITE,
+            specular_color: Color::WHITE,
+            specular_exponent: LitMaterial::DEFAULT_SPECULAR_EXPONENT,
+            ambient: LitMaterial::DEFAULT_AMBIENT_COLOR,
+            ior: PbrMaterial::DEFAULT_IOR,
+            diffuse_texture: None,
+            normal_texture: None,
+        }
+    }
+}
+
+/// Texture statements can start with options, like `map_Bump -bm 0.3 normal.png`.
+/// The file name is the rest of the statement, so it can contain spaces.
+fn texture_file_name(statement: &str) -> Option<&str> {
+    let mut rest = statement.trim();
+    while let Some(option) = rest.strip_prefix('-') {
+        let (option, mut arguments) = split_token(option);
+        // `-o`, `-s` and `-t` take one to three numbers
+        let (required, max) = match option {
+            "o" | "s" | "t" => (1, 3),
+            "mm" => (2, 2),
+            _ => (1, 1),
+        };
+        for i in 0..max {
+            let (argument, next) = split_token(arguments);
+            if i >= required && argument.parse::<f32>().is_err() {
+                break;
+            }
+            arguments = next;
+        }
+        rest = arguments;
+    }
+    (!rest.is_empty()).then_some(rest)
+}
+
+/// First whitespace separated token of `text` and what follows it
+fn split_token(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    text.find(char::is_whitespace)
+        .map_or((text, ""), |end| (&text[..end], text[end..].trim_start()))
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjSubMesh {
+    /// Name of the object or group
+    pub name: String,
+    pub mesh: Rc<Mesh>,
+    /// Index into [`ObjScene::materials`], `None` uses the default material.
+    pub material: Option<usize>,
+}
+
+/// Contents of an OBJ file and its MTL materials.
+#[derive(Debug, Clone, Default)]
+pub struct ObjScene {
+    /// One sub-mesh per object and material
+    pub meshes: Vec<ObjSubMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+impl ObjScene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ObjError> {
+        Self::load_with(path.as_ref(), true)
+    }
+
+    /// Like [`Self::load`], but the `map_` textures of the materials aren't read, e.g. when they are missing.
+    pub fn load_without_textures(path: impl AsRef<Path>) -> Result<Self, ObjError> {
+        Self::load_with(path.as_ref(), false)
+    }
+
+    fn load_with(path: &Path, textures: bool) -> Result<Self, ObjError> {
+        let (models, materials) = tobj::load_obj(path, &OBJ_LOAD_OPTIONS)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let materials = materials
+            .unwrap_or_else(|error| {
+                tracing::warn!("No materials loaded for {}: {error}", path.display());
+                vec![]
+            })
+            .into_iter()
+            .map(|mut material| {
+                if !textures {
+                    material.diffuse_texture = None;
+                    material.normal_texture = None;
+                }
+                ObjMaterial::new(&material, directory)
+            })
+            .collect::<Result<_, _>>()?;
+        let meshes = models
+            .into_iter()
+            .filter(|model| !model.mesh.indices.is_empty())
+            .map(|model| ObjSubMesh {
+                mesh: Rc::new(mesh_from_obj_mesh(&model.mesh)),
+                material: model.mesh.material_id,
+                name: model.name,
+            })
+            .collect();
+        Ok(Self { meshes, materials })
+    }
+
+    fn material(&self, sub_mesh: &ObjSubMesh) -> ObjMaterial {
+        sub_mesh
+            .material
+            .and_then(|index| self.materials.get(index))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// One [`Model`] with a [`LitMaterial`] per sub-mesh.
+    pub fn to_lit_models(
+        &self,
+        transform: Affine3A,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<Model> {
+        self.meshes
+            .iter()
+            .map(|sub_mesh| {
+                let material = self.material(sub_mesh).to_lit(device, queue);
+                Model::new(transform, sub_mesh.mesh.clone(), material)
+            })
+            .collect()
+    }
+
+    /// One [`Model`] with a [`PbrMaterial`] per sub-mesh.
+    pub fn to_pbr_models(
+        &self,
+        transform: Affine3A,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<Model> {
+        self.meshes
+            .iter()
+            .map(|sub_mesh| {
+                let material = self.material(sub_mesh).to_pbr(device, queue);
+                Model::new(transform, sub_mesh.mesh.clone(), material)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use approx::assert_relative_eq;
+
+    use super::{texture_file_name, ObjError, ObjScene};
+    use crate::renderer::{
+        material::AlphaMode,
+        resources::{mesh_from_obj_models, OBJ_LOAD_OPTIONS},
+    };
+
+    const OBJ: &str = "mtllib materials.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0
+vt 0 0
+vt 1 0
+vt 0 1
+vt 1 1
+o first
+usemtl red
+f 1/1 2/2 3/3
+o second
+usemtl blue
+f 2/2 4/4 3/3
+usemtl red
+f 1/1 2/2 4/4
+";
+
+    const MTL: &str = "newmtl red
+Kd 1 0 0
+Ks 0.5 0.5 0.5
+Ns 50
+Ni 1.5
+
+newmtl blue
+Kd 0 0 1
+d 0.5
+map_Kd -s 1 1 1 blue.png
+";
+
+    fn write(test: &str, mtl: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("iris_obj_{test}"));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("materials.mtl"), mtl).unwrap();
+        image::RgbaImage::new(2, 2)
+            .save(directory.join("blue.png"))
+            .unwrap();
+        let path = directory.join("model.obj");
+        fs::write(&path, OBJ).unwrap();
+        path
+    }
+
+    #[test]
+    fn sub_mesh_per_object_and_material() {
+        let scene = ObjScene::load(write("sub_meshes", MTL)).unwrap();
+        let names: Vec<_> = scene.meshes.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["first", "second", "second"], "Split by material");
+        let materials: Vec<_> = scene
+            .meshes
+            .iter()
+            .map(|m| scene.materials[m.material.unwrap()].name.as_str())
+            .collect();
+        assert_eq!(materials, ["red", "blue", "red"], "Materials assigned");
+
+        let red = &scene.materials[scene.meshes[0].material.unwrap()];
+        assert_relative_eq!(red.diffuse_color.r, 1.0);
+        assert_relative_eq!(red.specular_color.g, 0.5);
+        assert_relative_eq!(red.specular_exponent, 50.0);
+        assert_relative_eq!(red.ior, 1.5);
+        assert!(red.diffuse_texture.is_none(), "Red has no texture");
+        assert_eq!(red.alpha_mode(), AlphaMode::Opaque, "Red is not dissolved");
+        let blue = &scene.materials[scene.meshes[1].material.unwrap()];
+        assert_eq!(
+            blue.diffuse_texture
+                .as_ref()
+                .map(image::DynamicImage::width),
+            Some(2),
+            "Texture loaded relative to the OBJ file, skipping options"
+        );
+        assert_relative_eq!(blue.diffuse_color.a, 0.5);
+        assert_eq!(
+            blue.alpha_mode(),
+            AlphaMode::Blend,
+            "Dissolved blue is blended"
+        );
+    }
+
+    #[test]
+    fn merged_indices_are_offset() {
+        let path = write("merged", MTL);
+        let (models, _) = tobj::load_obj(path, &OBJ_LOAD_OPTIONS).unwrap();
+        let mesh = mesh_from_obj_models(&models);
+        let max_index = mesh.indices.iter().copied().max().unwrap();
+        assert_eq!(
+            max_index as usize,
+            mesh.vertices.len() - 1,
+            "Every model indexes its own vertices"
+        );
+        let first = models[0].mesh.positions.len() / 3;
+        assert!(
+            mesh.indices[3..].iter().all(|&i| i as usize >= first),
+            "Second model is offset past the first"
+        );
+    }
+
+    #[test]
+    fn missing_textures_are_errors() {
+        let mtl = MTL.replace("blue.png", "missing.png");
+        let path = write("missing_texture", &mtl);
+        let result = ObjScene::load(&path);
+        assert!(
+            matches!(result, Err(ObjError::Texture { ref path, .. }) if path.ends_with("missing.png")),
+            "Missing texture reported"
+        );
+        let scene = ObjScene::load_without_textures(&path).unwrap();
+        assert!(
+            scene
+                .materials
+                .iter()
+                .all(|material| material.diffuse_texture.is_none()),
+            "Textures skipped"
+        );
+        assert!(
+            matches!(ObjScene::load("does/not/exist.obj"), Err(ObjError::Load(_))),
+            "Missing OBJ reported"
+        );
+    }
+
+    #[test]
+    fn texture_options_are_skipped() {
+        assert_eq!(texture_file_name("boat.png"), Some("boat.png"));
+        assert_eq!(
+            texture_file_name("-bm 0.3 my normals.png"),
+            Some("my normals.png")
+        );
+        assert_eq!(
+            texture_file_name("-s 2 2 -mm 0 1 -clamp on wood texture.jpg"),
+            Some("wood texture.jpg"),
+            "Options with several arguments"
+        );
+        assert_eq!(texture_file_name("-o 0.5 wall.png"), Some("wall.png"));
+        assert_eq!(texture_file_name("-bm 0.3"), None, "Options only");
+    }
+}
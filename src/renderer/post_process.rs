@@ -0,0 +1,802 @@
+use std::fmt::Debug;
+
+use egui::{ComboBox, Ui};
+
+use crate::{core::shader_type::ShaderType, GpuSendable};
+
+use super::{
+    bind_group::BindGroup,
+    buffer::UniformBuffer,
+    compute::ComputePipelineBuilder,
+    gui::float_edit,
+    render_graph::{RenderGraph, ResourceHandle, TextureDesc, TextureSize},
+    render_pipeline::RenderPipelineBuilder,
+    texture::Texture,
+};
+
+/// Format of the target scenes are rendered into before post-processing
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Most mips of the bloom chain, the first one is half the size of the target
+pub const BLOOM_MIP_LEVELS: u32 = 6;
+
+const WORKGROUP_SIZE: u32 = 8;
+/// Custom compute passes write to the targets as storage textures
+const TARGET: TextureDesc = TextureDesc::new("HDR Target", HDR_FORMAT).usage(
+    wgpu::TextureUsages::RENDER_ATTACHMENT
+        .union(wgpu::TextureUsages::TEXTURE_BINDING)
+        .union(wgpu::TextureUsages::STORAGE_BINDING),
+);
+/// Vertex shader prepended to the fragment shaders of full-screen passes
+const FULLSCREEN_VERTEX: &str = include_str!("shaders/fullscreen.wgsl");
+
+const TEXTURE: wgpu::BindingType = wgpu::BindingType::Texture {
+    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+    view_dimension: wgpu::TextureViewDimension::D2,
+    multisampled: false,
+};
+const SAMPLER: wgpu::BindingType = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+const UNIFORM: wgpu::BindingType = wgpu::BindingType::Buffer {
+    ty: wgpu::BufferBindingType::Uniform,
+    has_dynamic_offset: false,
+    min_binding_size: None,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tonemapping {
+    /// Clips everything above 1
+    None,
+    Reinhard,
+    #[default]
+    Aces,
+    AgX,
+}
+
+impl Tonemapping {
+    pub const ALL: [Self; 4] = [Self::None, Self::Reinhard, Self::Aces, Self::AgX];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Reinhard => "Reinhard",
+            Self::Aces => "ACES",
+            Self::AgX => "AgX",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Brightness a color needs to bloom, the part above it spreads
+    pub threshold: f32,
+    /// Scale of the blurred light added back to the image
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            intensity: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PostProcessSettings {
+    /// In stops, the HDR color is scaled by `2^exposure` before tonemapping
+    pub exposure: f32,
+    pub tonemapping: Tonemapping,
+    pub bloom: BloomSettings,
+}
+
+impl PostProcessSettings {
+    pub fn gui(&mut self, ui: &mut Ui) -> bool {
+        let before = *self;
+        ui.collapsing("Post Processing", |ui| {
+            float_edit(ui, &mut self.exposure, "Exposure", -8.0..=8.0);
+            ComboBox::from_label("Tonemapping")
+                .selected_text(self.tonemapping.name())
+                .show_ui(ui, |ui| {
+                    for tonemapping in Tonemapping::ALL {
+                        ui.selectable_value(&mut self.tonemapping, tonemapping, tonemapping.name());
+                    }
+                });
+            ui.checkbox(&mut self.bloom.enabled, "Bloom");
+            if self.bloom.enabled {
+                float_edit(ui, &mut self.bloom.threshold, "Threshold", 0.0..=4.0);
+                float_edit(ui, &mut self.bloom.intensity, "Intensity", 0.0..=1.0);
+            }
+        });
+        *self != before
+    }
+}
+
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct GpuPostProcessSettings {
+    exposure: f32,
+    tonemapping: u32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+}
+
+impl GpuSendable<GpuPostProcessSettings> for PostProcessSettings {
+    fn to_gpu(&self) -> GpuPostProcessSettings {
+        GpuPostProcessSettings {
+            exposure: self.exposure,
+            tonemapping: self.tonemapping as u32,
+            bloom_threshold: self.bloom.threshold,
+            bloom_intensity: if self.bloom.enabled {
+                self.bloom.intensity
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// A custom step of the [`PostProcess`] chain, run before bloom and tonemapping.
+pub trait PostProcessPass: Debug {
+    /// Records the pass, which reads `input` and writes every texel of `output`.
+    ///
+    /// Both are [`HDR_FORMAT`] textures with the size of the target.
+    fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &Texture,
+        output: &Texture,
+    );
+}
+
+/// Post-process pass drawing a triangle over the screen with a custom fragment shader.
+///
+/// The WGSL source is appended to `shaders/fullscreen.wgsl`, so its `fs_main` takes a `FullscreenOutput`.
+/// The input texture and its sampler are at group 0, an optional custom bind group at group 1.
+#[derive(Debug)]
+pub struct FullscreenPass {
+    layout: wgpu::BindGroupLayout,
+    bind_group: Option<BindGroup>,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl FullscreenPass {
+    pub fn new(device: &wgpu::Device, fragment: &str, bind_group: Option<BindGroup>) -> Self {
+        let layout = create_layout(device, wgpu::ShaderStages::FRAGMENT, &[TEXTURE, SAMPLER]);
+        let mut builder =
+            RenderPipelineBuilder::new(fullscreen_shader(fragment)).add_bind_group(&layout);
+        if let Some(ref bind_group) = bind_group {
+            builder = builder.add_bind_group(&bind_group.layout);
+        }
+        let pipeline = builder
+            .blend(wgpu::BlendState::REPLACE)
+            .build_fullscreen(device, HDR_FORMAT);
+        Self {
+            layout,
+            bind_group,
+            pipeline,
+        }
+    }
+}
+
+impl PostProcessPass for FullscreenPass {
+    fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &Texture,
+        output: &Texture,
+    ) {
+        let input = create_bind_group(
+            device,
+            &self.layout,
+            &[
+                wgpu::BindingResource::TextureView(&input.view),
+                wgpu::BindingResource::Sampler(&input.sampler),
+            ],
+        );
+        let mut bind_groups = vec![&input];
+        bind_groups.extend(self.bind_group.as_ref().map(|b| &b.bind_group));
+        draw_fullscreen(
+            encoder,
+            &self.pipeline,
+            &bind_groups,
+            &output.view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        );
+    }
+}
+
+/// Post-process pass running a compute shader over every texel of the output.
+///
+/// The entry point has a workgroup size of 8x8, reads the input texture at `@group(0) @binding(0)`
+/// and writes the `texture_storage_2d<rgba16float,write>` at `@binding(1)`.
+/// An optional custom bind group goes at group 1.
+#[derive(Debug)]
+pub struct ComputePass {
+    layout: wgpu::BindGroupLayout,
+    bind_group: Option<BindGroup>,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl ComputePass {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: wgpu::ShaderModuleDescriptor,
+        entry_point: &str,
+        bind_group: Option<BindGroup>,
+    ) -> Self {
+        let layout = create_layout(
+            device,
+            wgpu::ShaderStages::COMPUTE,
+            &[
+                TEXTURE,
+                wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: HDR_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+            ],
+        );
+        let mut builder = ComputePipelineBuilder::new(shader).add_bind_group(&layout);
+        if let Some(ref bind_group) = bind_group {
+            builder = builder.add_bind_group(&bind_group.layout);
+        }
+        let pipeline = builder.build(device, entry_point);
+        Self {
+            layout,
+            bind_group,
+            pipeline,
+        }
+    }
+}
+
+impl PostProcessPass for ComputePass {
+    fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &Texture,
+        output: &Texture,
+    ) {
+        let bind_group = create_bind_group(
+            device,
+            &self.layout,
+            &[
+                wgpu::BindingResource::TextureView(&input.view),
+                wgpu::BindingResource::TextureView(&output.view),
+            ],
+        );
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Post Process Compute Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        if let Some(ref custom) = self.bind_group {
+            pass.set_bind_group(1, &custom.bind_group, &[]);
+        }
+        let size = output.texture.size();
+        pass.dispatch_workgroups(
+            size.width.div_ceil(WORKGROUP_SIZE),
+            size.height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
+
+/// Scenes render into a target from [`PostProcess::create_target`],
+/// the passes added by [`PostProcess::add_to_graph`] then write the final image to the output.
+///
+/// The chain runs the custom passes in the order they were added, then bloom, exposure and tonemapping.
+#[derive(Debug)]
+pub struct PostProcess {
+    pub settings: PostProcessSettings,
+    uniform: UniformBuffer<PostProcessSettings>,
+    passes: Vec<Box<dyn PostProcessPass>>,
+    sampler: wgpu::Sampler,
+    input_layout: wgpu::BindGroupLayout,
+    settings_bind_group: wgpu::BindGroup,
+    prefilter: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    tonemap_layout: wgpu::BindGroupLayout,
+    tonemap: wgpu::RenderPipeline,
+}
+
+impl PostProcess {
+    /// `output_format` is the format of the output passed to [`PostProcess::add_to_graph`], usually the swapchain.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let settings = PostProcessSettings::default();
+        let uniform = UniformBuffer::new(settings, device);
+        let sampler = linear_sampler(device);
+        let input_layout = create_layout(device, wgpu::ShaderStages::FRAGMENT, &[TEXTURE, SAMPLER]);
+        let settings_layout = create_layout(device, wgpu::ShaderStages::FRAGMENT, &[UNIFORM]);
+        let settings_bind_group = create_bind_group(
+            device,
+            &settings_layout,
+            &[uniform.buffer.as_entire_binding()],
+        );
+
+        let bloom = |entry_point, blend| {
+            RenderPipelineBuilder::new(fullscreen_shader(include_str!("shaders/bloom.wgsl")))
+                .add_bind_group(&input_layout)
+                .add_bind_group(&settings_layout)
+                .fragment_entry(entry_point)
+                .blend(blend)
+                .build_fullscreen(device, HDR_FORMAT)
+        };
+        let prefilter = bloom("fs_prefilter", wgpu::BlendState::REPLACE);
+        let downsample = bloom("fs_downsample", wgpu::BlendState::REPLACE);
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let upsample = bloom(
+            "fs_upsample",
+            wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            },
+        );
+
+        let tonemap_layout = create_layout(
+            device,
+            wgpu::ShaderStages::FRAGMENT,
+            &[TEXTURE, SAMPLER, TEXTURE, SAMPLER, UNIFORM],
+        );
+        let tonemap =
+            RenderPipelineBuilder::new(fullscreen_shader(include_str!("shaders/tonemap.wgsl")))
+                .add_bind_group(&tonemap_layout)
+                .blend(wgpu::BlendState::REPLACE)
+                .build_fullscreen(device, output_format);
+
+        Self {
+            settings,
+            uniform,
+            passes: vec![],
+            sampler,
+            input_layout,
+            settings_bind_group,
+            prefilter,
+            downsample,
+            upsample,
+            tonemap_layout,
+            tonemap,
+        }
+    }
+
+    /// Declares a surface sized [`HDR_FORMAT`] texture for the scene to render into.
+    pub fn create_target(graph: &mut RenderGraph) -> ResourceHandle {
+        graph.create_texture(TARGET)
+    }
+
+    pub fn add_pass(&mut self, pass: impl PostProcessPass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Adds the whole chain, from the HDR `input` to `output`.
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        input: ResourceHandle,
+        output: ResourceHandle,
+    ) {
+        let mut input = input;
+        if !self.passes.is_empty() {
+            // Custom passes read one target and write the other
+            let mut scratch = graph.create_texture(TARGET);
+            for pass in &self.passes {
+                let output = scratch;
+                graph
+                    .add_pass("Post Process Pass")
+                    .read(input)
+                    .write(output)
+                    .run(move |ctx| {
+                        pass.run(
+                            ctx.device,
+                            ctx.encoder,
+                            ctx.texture(input),
+                            ctx.texture(output),
+                        );
+                    });
+                scratch = input;
+                input = output;
+            }
+        }
+
+        let bloom = graph.create_texture(if self.settings.bloom.enabled {
+            let (width, height) = graph.surface_size();
+            let (width, height) = ((width / 2).max(1), (height / 2).max(1));
+            TextureDesc::new("Bloom", HDR_FORMAT)
+                .size(TextureSize::Fixed { width, height })
+                .mip_level_count(BLOOM_MIP_LEVELS.min(width.min(height).ilog2() + 1))
+        } else {
+            // Only bound, bloom intensity is 0
+            TextureDesc::new("Bloom", HDR_FORMAT).size(TextureSize::Fixed {
+                width: 1,
+                height: 1,
+            })
+        });
+        if self.settings.bloom.enabled {
+            graph
+                .add_pass("Bloom")
+                .read(input)
+                .write(bloom)
+                .run(move |ctx| {
+                    self.bloom(
+                        ctx.device,
+                        ctx.encoder,
+                        ctx.view(input),
+                        &ctx.texture(bloom).texture,
+                    );
+                });
+        }
+
+        graph
+            .add_pass("Tonemap")
+            .read(input)
+            .read(bloom)
+            .write(output)
+            .run(move |ctx| {
+                // Queue writes land before the commands of the graph, bloom sees the settings too
+                ctx.queue
+                    .write_buffer(&self.uniform.buffer, 0, &self.settings.to_gpu().to_bytes());
+                let bloom = mip_view(&ctx.texture(bloom).texture, 0);
+                let bind_group = create_bind_group(
+                    ctx.device,
+                    &self.tonemap_layout,
+                    &[
+                        wgpu::BindingResource::TextureView(ctx.view(input)),
+                        wgpu::BindingResource::Sampler(&self.sampler),
+                        wgpu::BindingResource::TextureView(&bloom),
+                        wgpu::BindingResource::Sampler(&self.sampler),
+                        self.uniform.buffer.as_entire_binding(),
+                    ],
+                );
+                draw_fullscreen(
+                    ctx.encoder,
+                    &self.tonemap,
+                    &[&bind_group],
+                    ctx.view(output),
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                );
+            });
+    }
+
+    /// Downsamples the bright parts of `input` along the mip chain, then adds each mip back to the larger one.
+    fn bloom(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        bloom: &wgpu::Texture,
+    ) {
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        let views: Vec<_> = (0..bloom.mip_level_count())
+            .map(|mip| mip_view(bloom, mip))
+            .collect();
+        let bind_groups: Vec<_> = views
+            .iter()
+            .map(|view| {
+                create_bind_group(
+                    device,
+                    &self.input_layout,
+                    &[
+                        wgpu::BindingResource::TextureView(view),
+                        wgpu::BindingResource::Sampler(&self.sampler),
+                    ],
+                )
+            })
+            .collect();
+        let input = create_bind_group(
+            device,
+            &self.input_layout,
+            &[
+                wgpu::BindingResource::TextureView(input),
+                wgpu::BindingResource::Sampler(&self.sampler),
+            ],
+        );
+        draw_fullscreen(
+            encoder,
+            &self.prefilter,
+            &[&input, &self.settings_bind_group],
+            &views[0],
+            clear,
+        );
+        for mip in 1..views.len() {
+            draw_fullscreen(
+                encoder,
+                &self.downsample,
+                &[&bind_groups[mip - 1], &self.settings_bind_group],
+                &views[mip],
+                clear,
+            );
+        }
+        for mip in (1..views.len()).rev() {
+            draw_fullscreen(
+                encoder,
+                &self.upsample,
+                &[&bind_groups[mip], &self.settings_bind_group],
+                &views[mip - 1],
+                wgpu::LoadOp::Load,
+            );
+        }
+    }
+}
+
+pub(crate) fn fullscreen_shader(fragment: &str) -> wgpu::ShaderModuleDescriptor<'static> {
+    wgpu::ShaderModuleDescriptor {
+        label: Some("Fullscreen Shader"),
+        source: wgpu::ShaderSource::Wgsl(format!("{FULLSCREEN_VERTEX}\n{fragment}").into()),
+    }
+}
+
+fn linear_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Post Process"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+fn mip_view(texture: &wgpu::Texture, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Bloom Mip"),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
+
+/// Layout with one binding of each type, numbered in order.
+fn create_layout(
+    device: &wgpu::Device,
+    visibility: wgpu::ShaderStages,
+    entries: &[wgpu::BindingType],
+) -> wgpu::BindGroupLayout {
+    let entries: Vec<_> = entries
+        .iter()
+        .enumerate()
+        .map(|(binding, &ty)| wgpu::BindGroupLayoutEntry {
+            binding: binding as u32,
+            visibility,
+            ty,
+            count: None,
+        })
+        .collect();
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Post Process Layout"),
+        entries: &entries,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    resources: &[wgpu::BindingResource],
+) -> wgpu::BindGroup {
+    let entries: Vec<_> = resources
+        .iter()
+        .enumerate()
+        .map(|(binding, resource)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: resource.clone(),
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Post Process"),
+        layout,
+        entries: &entries,
+    })
+}
+
+fn draw_fullscreen(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Post Process"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        pass.set_bind_group(index as u32, bind_group, &[]);
+    }
+    pass.draw(0..3, 0..1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fullscreen_shader, BloomSettings, ComputePass, FullscreenPass, GpuPostProcessSettings,
+        PostProcess, PostProcessSettings, Tonemapping,
+    };
+    use crate::{
+        renderer::{
+            reflection::ShaderReflection,
+            render_graph::{RenderGraph, TexturePool},
+            render_pipeline::RenderPassBuilder,
+            resources::get_texture_data,
+            texture::Texture,
+        },
+        tests::device,
+    };
+
+    const SIZE: u32 = 16;
+
+    fn post_process(device: &wgpu::Device, settings: PostProcessSettings) -> PostProcess {
+        let mut post_process = PostProcess::new(device, wgpu::TextureFormat::Rgba8Unorm);
+        post_process.settings = settings;
+        post_process
+    }
+
+    /// Fills the HDR target with `value` and returns the red channel of the output.
+    fn run(
+        post_process: &mut PostProcess,
+        value: f64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> u8 {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let output = Texture::render_target(device, SIZE, SIZE, format, format);
+        let mut graph = RenderGraph::new(SIZE, SIZE, 1);
+        let hdr = PostProcess::create_target(&mut graph);
+        graph.add_pass("Clear").write(hdr).run(|ctx| {
+            RenderPassBuilder::new()
+                .clear_color(wgpu::Color {
+                    r: value,
+                    g: value,
+                    b: value,
+                    a: 1.0,
+                })
+                .build(ctx.encoder, ctx.view(hdr));
+        });
+        post_process.add_to_graph(&mut graph, hdr, RenderGraph::SURFACE);
+        graph.execute(device, queue, &mut TexturePool::default(), &output.view);
+        let (pixels, ..) = get_texture_data(&output.texture, device, queue, 0);
+        pixels.samples[4 * (SIZE as usize / 2 * SIZE as usize + SIZE as usize / 2)]
+    }
+
+    fn linear() -> PostProcessSettings {
+        PostProcessSettings {
+            tonemapping: Tonemapping::None,
+            bloom: BloomSettings {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exposure_scales_in_stops() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut post_process = post_process(
+            &device,
+            PostProcessSettings {
+                exposure: 1.0,
+                ..linear()
+            },
+        );
+        let red = run(&mut post_process, 0.25, &device, &queue);
+        assert!(red.abs_diff(128) <= 2, "One stop doubles 0.25, got {red}");
+    }
+
+    #[test]
+    fn tonemapping_keeps_highlights_below_white() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut post_process = post_process(&device, linear());
+        assert_eq!(run(&mut post_process, 4.0, &device, &queue), 255);
+        for tonemapping in [Tonemapping::Reinhard, Tonemapping::Aces, Tonemapping::AgX] {
+            post_process.settings.tonemapping = tonemapping;
+            let red = run(&mut post_process, 4.0, &device, &queue);
+            assert!(
+                (128..250).contains(&red),
+                "{} maps 4.0 to a bright color below white, got {red}",
+                tonemapping.name()
+            );
+        }
+        post_process.settings.tonemapping = Tonemapping::Reinhard;
+        let red = run(&mut post_process, 1.0, &device, &queue);
+        assert!(red.abs_diff(128) <= 2, "Reinhard halves 1.0, got {red}");
+    }
+
+    #[test]
+    fn custom_passes_run_before_tonemapping() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut post_process = post_process(&device, linear());
+        post_process.add_pass(FullscreenPass::new(
+            &device,
+            "
+            @group(0) @binding(0) var input: texture_2d<f32>;
+            @group(0) @binding(1) var s_input: sampler;
+
+            @fragment
+            fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+                return textureSample(input, s_input, in.uv) * 0.5;
+            }
+            ",
+            None,
+        ));
+        post_process.add_pass(ComputePass::new(
+            &device,
+            wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(
+                    "
+                    @group(0) @binding(0) var input: texture_2d<f32>;
+                    @group(0) @binding(1) var output: texture_storage_2d<rgba16float,write>;
+
+                    @compute @workgroup_size(8, 8, 1)
+                    fn halve(@builtin(global_invocation_id) id: vec3u) {
+                        textureStore(output, id.xy, textureLoad(input, id.xy, 0) * 0.5);
+                    }
+                    "
+                    .into(),
+                ),
+            },
+            "halve",
+            None,
+        ));
+        let red = run(&mut post_process, 1.0, &device, &queue);
+        assert!(red.abs_diff(64) <= 2, "Both passes halve 1.0, got {red}");
+    }
+
+    #[test]
+    fn bloom_only_spreads_light_above_threshold() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut post_process = post_process(
+            &device,
+            PostProcessSettings {
+                bloom: BloomSettings {
+                    enabled: true,
+                    threshold: 1.0,
+                    intensity: 1.0,
+                },
+                ..linear()
+            },
+        );
+        let red = run(&mut post_process, 0.25, &device, &queue);
+        assert!(red.abs_diff(64) <= 2, "Dim colors don't bloom, got {red}");
+        post_process.settings.bloom.threshold = 0.0;
+        let red = run(&mut post_process, 0.25, &device, &queue);
+        assert!(red > 80, "Colors above the threshold bloom, got {red}");
+    }
+
+    #[test]
+    fn settings_match_the_shader_struct() {
+        let descriptor = fullscreen_shader(include_str!("shaders/tonemap.wgsl"));
+        let wgpu::ShaderSource::Wgsl(source) = descriptor.source else {
+            unreachable!("The tonemap shader is WGSL");
+        };
+        ShaderReflection::new("shaders/tonemap.wgsl", &source)
+            .unwrap()
+            .check_struct::<GpuPostProcessSettings>("Settings")
+            .unwrap();
+    }
+}
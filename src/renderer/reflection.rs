@@ -0,0 +1,938 @@
+use std::{collections::BTreeMap, fmt};
+
+use super::shader::{analyze, ShaderError};
+use crate::core::shader_type::{round_up, ShaderType};
+
+/// What a shader binds at a group and binding, or what a bind group layout entry provides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resource {
+    Uniform,
+    Storage {
+        read_only: bool,
+    },
+    Texture {
+        sample: Sample,
+        dimension: wgpu::TextureViewDimension,
+        multisampled: bool,
+    },
+    Sampler {
+        comparison: bool,
+    },
+    /// Binding arrays and the like, not checked
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sample {
+    Float,
+    Sint,
+    Uint,
+    Depth,
+    Storage,
+}
+
+impl Resource {
+    fn from_global(module: &naga::Module, global: &naga::GlobalVariable) -> Self {
+        match global.space {
+            naga::AddressSpace::Uniform => Self::Uniform,
+            naga::AddressSpace::Storage { access } => Self::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            naga::AddressSpace::Handle => match module.types[global.ty].inner {
+                naga::TypeInner::Image {
+                    dim,
+                    arrayed,
+                    class,
+                } => {
+                    let (sample, multisampled) = match class {
+                        naga::ImageClass::Sampled { kind, multi } => match kind {
+                            naga::ScalarKind::Float => (Sample::Float, multi),
+                            naga::ScalarKind::Sint => (Sample::Sint, multi),
+                            naga::ScalarKind::Uint => (Sample::Uint, multi),
+                            _ => return Self::Other,
+                        },
+                        naga::ImageClass::Depth { multi } => (Sample::Depth, multi),
+                        naga::ImageClass::Storage { .. } => (Sample::Storage, false),
+                    };
+                    Self::Texture {
+                        sample,
+                        dimension: view_dimension(dim, arrayed),
+                        multisampled,
+                    }
+                }
+                naga::TypeInner::Sampler { comparison } => Self::Sampler { comparison },
+                _ => Self::Other,
+            },
+            _ => Self::Other,
+        }
+    }
+
+    fn from_layout(ty: &wgpu::BindingType) -> Self {
+        match *ty {
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                ..
+            } => Self::Uniform,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                ..
+            } => Self::Storage { read_only },
+            wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+            } => Self::Texture {
+                sample: match sample_type {
+                    wgpu::TextureSampleType::Float { .. } => Sample::Float,
+                    wgpu::TextureSampleType::Sint => Sample::Sint,
+                    wgpu::TextureSampleType::Uint => Sample::Uint,
+                    wgpu::TextureSampleType::Depth => Sample::Depth,
+                },
+                dimension: view_dimension,
+                multisampled,
+            },
+            wgpu::BindingType::StorageTexture { view_dimension, .. } => Self::Texture {
+                sample: Sample::Storage,
+                dimension: view_dimension,
+                multisampled: false,
+            },
+            wgpu::BindingType::Sampler(sampler) => Self::Sampler {
+                comparison: sampler == wgpu::SamplerBindingType::Comparison,
+            },
+            wgpu::BindingType::AccelerationStructure => Self::Other,
+        }
+    }
+
+    /// A read-only storage buffer can be bound to a read-write entry, everything else must be the same.
+    fn accepts(self, layout: Self) -> bool {
+        match (self, layout) {
+            (Self::Other, _) => true,
+            (
+                Self::Storage { read_only },
+                Self::Storage {
+                    read_only: layout_read_only,
+                },
+            ) => read_only || !layout_read_only,
+            _ => self == layout,
+        }
+    }
+}
+
+impl fmt::Display for Resource {
+    /// Named like the WGSL types
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Uniform => write!(f, "uniform buffer"),
+            Self::Storage { read_only: true } => write!(f, "read-only storage buffer"),
+            Self::Storage { read_only: false } => write!(f, "read-write storage buffer"),
+            Self::Texture {
+                sample,
+                dimension,
+                multisampled,
+            } => {
+                let dimension = match dimension {
+                    wgpu::TextureViewDimension::D1 => "1d",
+                    wgpu::TextureViewDimension::D2 => "2d",
+                    wgpu::TextureViewDimension::D2Array => "2d_array",
+                    wgpu::TextureViewDimension::Cube => "cube",
+                    wgpu::TextureViewDimension::CubeArray => "cube_array",
+                    wgpu::TextureViewDimension::D3 => "3d",
+                };
+                let multisampled = if multisampled { "multisampled_" } else { "" };
+                match sample {
+                    Sample::Float => write!(f, "texture_{multisampled}{dimension}<f32>"),
+                    Sample::Sint => write!(f, "texture_{multisampled}{dimension}<i32>"),
+                    Sample::Uint => write!(f, "texture_{multisampled}{dimension}<u32>"),
+                    Sample::Depth => write!(f, "texture_depth_{multisampled}{dimension}"),
+                    Sample::Storage => write!(f, "texture_storage_{dimension}"),
+                }
+            }
+            Self::Sampler { comparison: false } => write!(f, "sampler"),
+            Self::Sampler { comparison: true } => write!(f, "sampler_comparison"),
+            Self::Other => write!(f, "unchecked resource"),
+        }
+    }
+}
+
+const fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+/// Scalar kind and component count a vertex attribute is read as by the shader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VertexType {
+    kind: naga::ScalarKind,
+    components: u32,
+}
+
+impl VertexType {
+    const fn from_type(inner: &naga::TypeInner) -> Option<Self> {
+        match *inner {
+            naga::TypeInner::Scalar(scalar) => Some(Self {
+                kind: scalar.kind,
+                components: 1,
+            }),
+            naga::TypeInner::Vector { size, scalar } => Some(Self {
+                kind: scalar.kind,
+                components: size as u32,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Normalized and half float formats are read as floats.
+    const fn from_format(format: wgpu::VertexFormat) -> Self {
+        use wgpu::VertexFormat as F;
+        let (kind, components) = match format {
+            F::Uint32 => (naga::ScalarKind::Uint, 1),
+            F::Uint8x2 | F::Uint16x2 | F::Uint32x2 => (naga::ScalarKind::Uint, 2),
+            F::Uint32x3 => (naga::ScalarKind::Uint, 3),
+            F::Uint8x4 | F::Uint16x4 | F::Uint32x4 => (naga::ScalarKind::Uint, 4),
+            F::Sint32 => (naga::ScalarKind::Sint, 1),
+            F::Sint8x2 | F::Sint16x2 | F::Sint32x2 => (naga::ScalarKind::Sint, 2),
+            F::Sint32x3 => (naga::ScalarKind::Sint, 3),
+            F::Sint8x4 | F::Sint16x4 | F::Sint32x4 => (naga::ScalarKind::Sint, 4),
+            F::Float32 | F::Float64 => (naga::ScalarKind::Float, 1),
+            F::Unorm8x2
+            | F::Snorm8x2
+            | F::Unorm16x2
+            | F::Snorm16x2
+            | F::Float16x2
+            | F::Float32x2
+            | F::Float64x2 => (naga::ScalarKind::Float, 2),
+            F::Float32x3 | F::Float64x3 => (naga::ScalarKind::Float, 3),
+            F::Unorm8x4
+            | F::Snorm8x4
+            | F::Unorm16x4
+            | F::Snorm16x4
+            | F::Float16x4
+            | F::Float32x4
+            | F::Float64x4 => (naga::ScalarKind::Float, 4),
+        };
+        Self { kind, components }
+    }
+}
+
+impl fmt::Display for VertexType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scalar = match self.kind {
+            naga::ScalarKind::Sint => "i32",
+            naga::ScalarKind::Uint => "u32",
+            naga::ScalarKind::Bool => "bool",
+            _ => "f32",
+        };
+        match self.components {
+            1 => write!(f, "{scalar}"),
+            n => write!(f, "vec{n}<{scalar}>"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    name: String,
+    resource: Resource,
+}
+
+#[derive(Debug, Clone)]
+struct VertexInput {
+    location: u32,
+    name: String,
+    ty: VertexType,
+}
+
+#[derive(Debug, Clone)]
+struct EntryPoint {
+    name: String,
+    stage: wgpu::ShaderStages,
+    /// Group and binding of the resources the entry point uses, directly or through the functions it calls
+    bindings: Vec<(u32, u32)>,
+    /// Vertex attributes read by a vertex entry point
+    inputs: Vec<VertexInput>,
+}
+
+/// Name, offset and size of a struct member, written like `name at offset (size bytes)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Member {
+    name: String,
+    offset: usize,
+    size: usize,
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` at {} ({} bytes)",
+            self.name, self.offset, self.size
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StructLayout {
+    members: Vec<Member>,
+    size: usize,
+}
+
+/// Bindings and vertex inputs of a WGSL shader, read with naga to check them against the Rust side of a pipeline.
+///
+/// Unlike wgpu validation, it doesn't need a device and lists every mismatch at once.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    file: String,
+    bindings: BTreeMap<(u32, u32), Binding>,
+    entry_points: Vec<EntryPoint>,
+    structs: BTreeMap<String, StructLayout>,
+}
+
+impl ShaderReflection {
+    /// Reflects composed WGSL, like a [`Shader`](super::shader::Shader) variant.
+    pub fn new(file: &str, source: &str) -> Result<Self, ShaderError> {
+        let (module, info) = analyze(file, source)?;
+        let bindings = module
+            .global_variables
+            .iter()
+            .filter_map(|(_, global)| {
+                let binding = global.binding.as_ref()?;
+                Some((
+                    (binding.group, binding.binding),
+                    Binding {
+                        name: global.name.clone().unwrap_or_default(),
+                        resource: Resource::from_global(&module, global),
+                    },
+                ))
+            })
+            .collect();
+        let entry_points = module
+            .entry_points
+            .iter()
+            .enumerate()
+            .map(|(index, entry_point)| {
+                let usage = info.get_entry_point(index);
+                let stage = match entry_point.stage {
+                    naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                    naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                    naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                };
+                let bindings = module
+                    .global_variables
+                    .iter()
+                    .filter(|&(handle, _)| !usage[handle].is_empty())
+                    .filter_map(|(_, global)| global.binding.as_ref())
+                    .map(|binding| (binding.group, binding.binding))
+                    .collect();
+                let inputs = if stage == wgpu::ShaderStages::VERTEX {
+                    vertex_inputs(&module, &entry_point.function)
+                } else {
+                    vec![]
+                };
+                EntryPoint {
+                    name: entry_point.name.clone(),
+                    stage,
+                    bindings,
+                    inputs,
+                }
+            })
+            .collect();
+        let structs = module
+            .types
+            .iter()
+            .filter_map(|(_, ty)| match ty.inner {
+                naga::TypeInner::Struct { ref members, span } => Some((
+                    ty.name.clone()?,
+                    StructLayout {
+                        members: members
+                            .iter()
+                            .map(|member| Member {
+                                name: member.name.clone().unwrap_or_default(),
+                                offset: member.offset as usize,
+                                size: module.types[member.ty].inner.size(module.to_ctx()) as usize,
+                            })
+                            .collect(),
+                        size: span as usize,
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+        Ok(Self {
+            file: file.to_owned(),
+            bindings,
+            entry_points,
+            structs,
+        })
+    }
+
+    /// Checks a pipeline made of `entry_points` binds everything they use with `groups`, in group order,
+    /// and gets their vertex inputs from `buffers`.
+    /// Groups whose entries aren't known are `None` and skipped.
+    ///
+    /// # Errors
+    /// Every binding and vertex input that doesn't match, with the group, binding or location and the types on both sides.
+    pub fn check(
+        &self,
+        entry_points: &[&str],
+        groups: &[Option<&[wgpu::BindGroupLayoutEntry]>],
+        buffers: &[wgpu::VertexBufferLayout],
+    ) -> Result<(), ReflectionError> {
+        let mut mismatches = vec![];
+        let mut used: BTreeMap<(u32, u32), wgpu::ShaderStages> = BTreeMap::new();
+        let mut inputs = vec![];
+        for &name in entry_points {
+            let Some(entry_point) = self.entry_points.iter().find(|e| e.name == name) else {
+                mismatches.push(Mismatch::UnknownEntryPoint {
+                    name: name.to_owned(),
+                });
+                continue;
+            };
+            for &binding in &entry_point.bindings {
+                *used.entry(binding).or_insert(wgpu::ShaderStages::NONE) |= entry_point.stage;
+            }
+            inputs.extend(&entry_point.inputs);
+        }
+
+        for (&(group, binding), &stages) in &used {
+            let declared = &self.bindings[&(group, binding)];
+            let name = declared.name.clone();
+            let shader = declared.resource.to_string();
+            let Some(&entries) = groups.get(group as usize) else {
+                mismatches.push(Mismatch::MissingGroup {
+                    group,
+                    binding,
+                    name,
+                    shader,
+                });
+                continue;
+            };
+            let Some(entries) = entries else {
+                continue;
+            };
+            let Some(entry) = entries.iter().find(|entry| entry.binding == binding) else {
+                mismatches.push(Mismatch::MissingBinding {
+                    group,
+                    binding,
+                    name,
+                    shader,
+                });
+                continue;
+            };
+            let layout = Resource::from_layout(&entry.ty);
+            if !declared.resource.accepts(layout) {
+                mismatches.push(Mismatch::BindingType {
+                    group,
+                    binding,
+                    name,
+                    shader,
+                    layout: layout.to_string(),
+                });
+            } else if !entry.visibility.contains(stages) {
+                mismatches.push(Mismatch::Visibility {
+                    group,
+                    binding,
+                    name,
+                    stages,
+                    visibility: entry.visibility,
+                });
+            }
+        }
+
+        for input in inputs {
+            let attribute = buffers
+                .iter()
+                .flat_map(|buffer| buffer.attributes)
+                .find(|attribute| attribute.shader_location == input.location);
+            match attribute {
+                None => mismatches.push(Mismatch::MissingVertexInput {
+                    location: input.location,
+                    name: input.name.clone(),
+                    shader: input.ty.to_string(),
+                }),
+                Some(attribute) if VertexType::from_format(attribute.format) != input.ty => {
+                    mismatches.push(Mismatch::VertexFormat {
+                        location: input.location,
+                        name: input.name.clone(),
+                        shader: input.ty.to_string(),
+                        format: attribute.format,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        self.result(mismatches)
+    }
+
+    /// Checks the WGSL struct `name` has the members of `T`, with the same names, offsets and sizes.
+    ///
+    /// # Errors
+    /// Every member that differs, and the struct sizes if they do.
+    pub fn check_struct<T: ShaderType>(&self, name: &str) -> Result<(), ReflectionError> {
+        let Some(shader) = self.structs.get(name) else {
+            return self.result(vec![Mismatch::MissingStruct {
+                name: name.to_owned(),
+            }]);
+        };
+        let mut end = 0;
+        let rust: Vec<Member> = T::MEMBERS
+            .iter()
+            .map(|&(name, layout)| {
+                let offset = round_up(end, layout.align);
+                end = offset + layout.size;
+                Member {
+                    name: name.to_owned(),
+                    offset,
+                    size: layout.size,
+                }
+            })
+            .collect();
+
+        let mut mismatches = vec![];
+        for index in 0..shader.members.len().max(rust.len()) {
+            let (shader, rust) = (shader.members.get(index), rust.get(index));
+            if shader != rust {
+                let describe = |member: Option<&Member>| {
+                    member.map_or_else(|| "nothing".to_owned(), ToString::to_string)
+                };
+                mismatches.push(Mismatch::StructMember {
+                    name: name.to_owned(),
+                    index,
+                    shader: describe(shader),
+                    rust: describe(rust),
+                });
+            }
+        }
+        if shader.size != T::LAYOUT.size {
+            mismatches.push(Mismatch::StructSize {
+                name: name.to_owned(),
+                shader: shader.size,
+                rust: T::LAYOUT.size,
+            });
+        }
+        self.result(mismatches)
+    }
+
+    fn result(&self, mismatches: Vec<Mismatch>) -> Result<(), ReflectionError> {
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(ReflectionError {
+                file: self.file.clone(),
+                mismatches,
+            })
+        }
+    }
+}
+
+/// Vertex attributes of the arguments of `function`, and of the members of its struct arguments.
+fn vertex_inputs(module: &naga::Module, function: &naga::Function) -> Vec<VertexInput> {
+    let input = |binding: Option<&naga::Binding>, name: &Option<String>, ty| {
+        let Some(&naga::Binding::Location { location, .. }) = binding else {
+            return None;
+        };
+        Some(VertexInput {
+            location,
+            name: name.clone().unwrap_or_default(),
+            ty: VertexType::from_type(&module.types[ty].inner)?,
+        })
+    };
+    function
+        .arguments
+        .iter()
+        .flat_map(|argument| match module.types[argument.ty].inner {
+            naga::TypeInner::Struct { ref members, .. } if argument.binding.is_none() => members
+                .iter()
+                .filter_map(|member| input(member.binding.as_ref(), &member.name, member.ty))
+                .collect(),
+            _ => input(argument.binding.as_ref(), &argument.name, argument.ty)
+                .into_iter()
+                .collect::<Vec<_>>(),
+        })
+        .collect()
+}
+
+fn stage_names(stages: wgpu::ShaderStages) -> String {
+    [
+        (wgpu::ShaderStages::VERTEX, "vertex"),
+        (wgpu::ShaderStages::FRAGMENT, "fragment"),
+        (wgpu::ShaderStages::COMPUTE, "compute"),
+    ]
+    .into_iter()
+    .filter(|&(stage, _)| stages.contains(stage))
+    .map(|(_, name)| name)
+    .collect::<Vec<_>>()
+    .join(" and ")
+}
+
+/// Something a shader uses that its pipeline doesn't provide, or provides differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    UnknownEntryPoint {
+        name: String,
+    },
+    /// The pipeline has fewer bind groups than the shader uses
+    MissingGroup {
+        group: u32,
+        binding: u32,
+        name: String,
+        shader: String,
+    },
+    MissingBinding {
+        group: u32,
+        binding: u32,
+        name: String,
+        shader: String,
+    },
+    BindingType {
+        group: u32,
+        binding: u32,
+        name: String,
+        shader: String,
+        layout: String,
+    },
+    /// The binding is hidden from a stage using it
+    Visibility {
+        group: u32,
+        binding: u32,
+        name: String,
+        stages: wgpu::ShaderStages,
+        visibility: wgpu::ShaderStages,
+    },
+    MissingVertexInput {
+        location: u32,
+        name: String,
+        shader: String,
+    },
+    VertexFormat {
+        location: u32,
+        name: String,
+        shader: String,
+        format: wgpu::VertexFormat,
+    },
+    MissingStruct {
+        name: String,
+    },
+    /// Member `index` of a struct differs in name, offset or size from the field of the Rust type
+    StructMember {
+        name: String,
+        index: usize,
+        shader: String,
+        rust: String,
+    },
+    StructSize {
+        name: String,
+        shader: usize,
+        rust: usize,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::UnknownEntryPoint { ref name } => write!(f, "no entry point `{name}`"),
+            Self::MissingGroup {
+                group,
+                binding,
+                ref name,
+                ref shader,
+            } => write!(
+                f,
+                "group {group} binding {binding} `{name}`: shader expects {shader}, the pipeline has no group {group}"
+            ),
+            Self::MissingBinding {
+                group,
+                binding,
+                ref name,
+                ref shader,
+            } => write!(
+                f,
+                "group {group} binding {binding} `{name}`: shader expects {shader}, the layout has no such binding"
+            ),
+            Self::BindingType {
+                group,
+                binding,
+                ref name,
+                ref shader,
+                ref layout,
+            } => write!(
+                f,
+                "group {group} binding {binding} `{name}`: shader expects {shader}, the layout has {layout}"
+            ),
+            Self::Visibility {
+                group,
+                binding,
+                ref name,
+                stages,
+                visibility,
+            } => write!(
+                f,
+                "group {group} binding {binding} `{name}`: used by the {} stage, the layout only shows it to {}",
+                stage_names(stages),
+                stage_names(visibility)
+            ),
+            Self::MissingVertexInput {
+                location,
+                ref name,
+                ref shader,
+            } => write!(
+                f,
+                "vertex input {location} `{name}`: shader expects {shader}, no vertex buffer has this location"
+            ),
+            Self::VertexFormat {
+                location,
+                ref name,
+                ref shader,
+                format,
+            } => write!(
+                f,
+                "vertex input {location} `{name}`: shader expects {shader}, the vertex buffer has {format:?}"
+            ),
+            Self::MissingStruct { ref name } => write!(f, "no struct `{name}`"),
+            Self::StructMember {
+                ref name,
+                index,
+                ref shader,
+                ref rust,
+            } => write!(
+                f,
+                "struct `{name}` member {index}: shader has {shader}, the Rust type has {rust}"
+            ),
+            Self::StructSize {
+                ref name,
+                shader,
+                rust,
+            } => write!(
+                f,
+                "struct `{name}`: shader size is {shader} bytes, the Rust type has {rust}"
+            ),
+        }
+    }
+}
+
+/// Every [`Mismatch`] between a shader and its pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflectionError {
+    pub file: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} doesn't match its pipeline:", self.file)?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {mismatch}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{Mismatch, ShaderReflection};
+    use crate::{
+        core::shader_type::ShaderType,
+        renderer::{
+            bind_group::BindGroupLayoutEntries, mesh::Vertex, resources::VertexAttributeLayout,
+        },
+    };
+
+    const SHADER: &str = "
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+@group(0) @binding(0) var texture: texture_2d<f32>;
+@group(0) @binding(1) var s_texture: sampler;
+@group(0) @binding(2) var<uniform> color: vec4f;
+@group(1) @binding(0) var<uniform> view_projection: mat4x4f;
+@group(1) @binding(1) var<storage, read_write> unused: array<u32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput, @location(5) offset: vec3<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view_projection * vec4f(in.position + offset, 1.0);
+    out.uv = in.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return textureSample(texture, s_texture, in.uv) * color;
+}
+";
+
+    fn offset_buffer() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![5 => Float32x3];
+        wgpu::VertexBufferLayout {
+            array_stride: 12,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+
+    #[test]
+    fn matching_pipeline_passes() {
+        let reflection = ShaderReflection::new("test.wgsl", SHADER).unwrap();
+        let material = BindGroupLayoutEntries::new().texture().uniform();
+        // The storage buffer at group 1 binding 1 is not used by the entry points
+        let scene = BindGroupLayoutEntries::new().uniform();
+        reflection
+            .check(
+                &["vs_main", "fs_main"],
+                &[Some(material.entries()), Some(scene.entries())],
+                &[Vertex::layout(), offset_buffer()],
+            )
+            .unwrap();
+        reflection
+            .check(
+                &["vs_main", "fs_main"],
+                &[None, None],
+                &[Vertex::layout(), offset_buffer()],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn mismatches_are_all_listed() {
+        let reflection = ShaderReflection::new("test.wgsl", SHADER).unwrap();
+        // Uniform and texture swapped
+        let material = BindGroupLayoutEntries::new().uniform().texture();
+        let vertices = wgpu::VertexBufferLayout {
+            array_stride: 24,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 2 => Float32x3],
+        };
+        let error = reflection
+            .check(
+                &["vs_main", "fs_main", "fs_missing"],
+                &[Some(material.entries())],
+                &[vertices],
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.mismatches,
+            [
+                Mismatch::UnknownEntryPoint {
+                    name: "fs_missing".to_owned()
+                },
+                Mismatch::BindingType {
+                    group: 0,
+                    binding: 0,
+                    name: "texture".to_owned(),
+                    shader: "texture_2d<f32>".to_owned(),
+                    layout: "uniform buffer".to_owned(),
+                },
+                Mismatch::BindingType {
+                    group: 0,
+                    binding: 1,
+                    name: "s_texture".to_owned(),
+                    shader: "sampler".to_owned(),
+                    layout: "texture_2d<f32>".to_owned(),
+                },
+                Mismatch::BindingType {
+                    group: 0,
+                    binding: 2,
+                    name: "color".to_owned(),
+                    shader: "uniform buffer".to_owned(),
+                    layout: "sampler".to_owned(),
+                },
+                Mismatch::MissingGroup {
+                    group: 1,
+                    binding: 0,
+                    name: "view_projection".to_owned(),
+                    shader: "uniform buffer".to_owned(),
+                },
+                Mismatch::VertexFormat {
+                    location: 2,
+                    name: "uv".to_owned(),
+                    shader: "vec2<f32>".to_owned(),
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                Mismatch::MissingVertexInput {
+                    location: 5,
+                    name: "offset".to_owned(),
+                    shader: "vec3<f32>".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            error.to_string().lines().nth(2),
+            Some("  group 0 binding 0 `texture`: shader expects texture_2d<f32>, the layout has uniform buffer")
+        );
+    }
+
+    #[test]
+    fn bindings_hidden_from_a_stage_are_reported() {
+        let reflection = ShaderReflection::new("test.wgsl", SHADER).unwrap();
+        let mut scene = BindGroupLayoutEntries::new().uniform().entries().to_vec();
+        scene[0].visibility = wgpu::ShaderStages::FRAGMENT;
+        let error = reflection
+            .check(
+                &["vs_main"],
+                &[None, Some(&scene)],
+                &[Vertex::layout(), offset_buffer()],
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "test.wgsl doesn't match its pipeline:\n  group 1 binding 0 `view_projection`: used by the vertex stage, the layout only shows it to fragment"
+        );
+    }
+
+    #[test]
+    fn struct_layouts_are_compared_member_by_member() {
+        #[derive(ShaderType)]
+        struct Light {
+            direction: Vec3,
+            intensity: f32,
+            color: Vec3,
+        }
+        #[derive(ShaderType)]
+        struct Misplaced {
+            direction: Vec3,
+            color: Vec3,
+            intensity: f32,
+        }
+
+        let reflection = ShaderReflection::new(
+            "test.wgsl",
+            "struct Light { direction: vec3f, intensity: f32, color: vec3f }",
+        )
+        .unwrap();
+        reflection.check_struct::<Light>("Light").unwrap();
+        let error = reflection.check_struct::<Misplaced>("Light").unwrap_err();
+        assert_eq!(
+            error.to_string().lines().skip(1).collect::<Vec<_>>(),
+            [
+                "  struct `Light` member 1: shader has `intensity` at 12 (4 bytes), the Rust type has `color` at 16 (12 bytes)",
+                "  struct `Light` member 2: shader has `color` at 16 (12 bytes), the Rust type has `intensity` at 28 (4 bytes)",
+            ]
+        );
+        assert_eq!(
+            reflection
+                .check_struct::<Light>("Missing")
+                .unwrap_err()
+                .mismatches,
+            [Mismatch::MissingStruct {
+                name: "Missing".to_owned()
+            }]
+        );
+    }
+}
@@ -0,0 +1,593 @@
+use std::{fmt::Debug, rc::Rc};
+
+use super::texture::Texture;
+
+/// Size of a transient texture, surface sized textures follow the surface when it is resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextureSize {
+    #[default]
+    Surface,
+    Fixed {
+        width: u32,
+        height: u32,
+    },
+}
+
+/// Description of a texture the graph allocates for the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub format: wgpu::TextureFormat,
+    pub size: TextureSize,
+    pub mip_level_count: u32,
+    pub sample_count: u32,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TextureDesc {
+    /// Surface sized texture that can be rendered to and sampled.
+    pub const fn new(label: &'static str, format: wgpu::TextureFormat) -> Self {
+        Self {
+            label,
+            format,
+            size: TextureSize::Surface,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                .union(wgpu::TextureUsages::TEXTURE_BINDING),
+        }
+    }
+    pub const fn size(self, size: TextureSize) -> Self {
+        Self { size, ..self }
+    }
+    pub const fn mip_level_count(self, mip_level_count: u32) -> Self {
+        Self {
+            mip_level_count,
+            ..self
+        }
+    }
+    pub const fn sample_count(self, sample_count: u32) -> Self {
+        Self {
+            sample_count,
+            ..self
+        }
+    }
+    pub const fn usage(self, usage: wgpu::TextureUsages) -> Self {
+        Self { usage, ..self }
+    }
+}
+
+/// Texture or buffer a pass of a [`RenderGraph`] reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceHandle(usize);
+
+#[derive(Debug, Clone, Copy)]
+enum Resource<'a> {
+    Surface,
+    Texture(&'a Texture),
+    Buffer(&'a wgpu::Buffer),
+    /// Index in [`RenderGraph::transients`]
+    Transient(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Resolved<'r> {
+    View(&'r wgpu::TextureView),
+    Texture(&'r Texture),
+    Buffer(&'r wgpu::Buffer),
+}
+
+/// What a pass gets to record its commands, with the resources of the graph.
+#[allow(missing_debug_implementations)]
+pub struct PassContext<'r> {
+    pub device: &'r wgpu::Device,
+    pub queue: &'r wgpu::Queue,
+    pub encoder: &'r mut wgpu::CommandEncoder,
+    resources: &'r [Resolved<'r>],
+}
+
+impl<'r> PassContext<'r> {
+    /// View of a texture, or of the surface.
+    pub fn view(&self, handle: ResourceHandle) -> &'r wgpu::TextureView {
+        match self.resources[handle.0] {
+            Resolved::View(view) => view,
+            Resolved::Texture(texture) => &texture.view,
+            Resolved::Buffer(_) => panic!("Resource {handle:?} is a buffer, not a texture"),
+        }
+    }
+    pub fn texture(&self, handle: ResourceHandle) -> &'r Texture {
+        match self.resources[handle.0] {
+            Resolved::Texture(texture) => texture,
+            Resolved::View(_) => panic!("The surface only has a view"),
+            Resolved::Buffer(_) => panic!("Resource {handle:?} is a buffer, not a texture"),
+        }
+    }
+    pub fn buffer(&self, handle: ResourceHandle) -> &'r wgpu::Buffer {
+        match self.resources[handle.0] {
+            Resolved::Buffer(buffer) => buffer,
+            Resolved::View(_) | Resolved::Texture(_) => {
+                panic!("Resource {handle:?} is a texture, not a buffer")
+            }
+        }
+    }
+}
+
+struct Pass<'a> {
+    name: &'static str,
+    reads: Vec<ResourceHandle>,
+    writes: Vec<ResourceHandle>,
+    run: Box<dyn FnOnce(&mut PassContext) + 'a>,
+}
+
+/// Passes of one frame, with the resources they read and write.
+///
+/// Passes run in dependency order, which follows the declaration order of the passes touching the same resource:
+/// a read sees the last write declared before it, and a write waits for the reads of the previous contents.
+/// A read declared before any write of a resource sees the contents left by all of its writers,
+/// so consumers can be declared before their producers.
+pub struct RenderGraph<'a> {
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    resources: Vec<Resource<'a>>,
+    transients: Vec<TextureDesc>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl Debug for RenderGraph<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderGraph")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("sample_count", &self.sample_count)
+            .field("resources", &self.resources)
+            .field("transients", &self.transients)
+            .field(
+                "passes",
+                &self.passes.iter().map(|pass| pass.name).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    /// The texture presented at the end of the frame
+    pub const SURFACE: ResourceHandle = ResourceHandle(0);
+
+    /// `sample_count` is the MSAA sample count of the renderer, see [`RenderGraph::create_msaa_texture`].
+    pub fn new(width: u32, height: u32, sample_count: u32) -> Self {
+        Self {
+            width,
+            height,
+            sample_count,
+            resources: vec![Resource::Surface],
+            transients: vec![],
+            passes: vec![],
+        }
+    }
+
+    pub const fn surface_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub const fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Declares a texture allocated from the [`TexturePool`] for this frame.
+    pub fn create_texture(&mut self, desc: TextureDesc) -> ResourceHandle {
+        self.transients.push(desc);
+        self.add_resource(Resource::Transient(self.transients.len() - 1))
+    }
+    /// Declares a [`Texture::DEPTH_FORMAT`] attachment with the sample count of the renderer.
+    pub fn create_depth_texture(&mut self) -> ResourceHandle {
+        self.create_texture(
+            TextureDesc::new("Depth", Texture::DEPTH_FORMAT)
+                .sample_count(self.sample_count)
+                .usage(wgpu::TextureUsages::RENDER_ATTACHMENT),
+        )
+    }
+    /// Declares a texture with the sample count of the renderer for passes to render into,
+    /// resolving it into a single sampled one. Returns `None` without MSAA.
+    ///
+    /// It is only used by the pass it is resolved in, so that pass needn't declare it.
+    pub fn create_msaa_texture(&mut self, desc: TextureDesc) -> Option<ResourceHandle> {
+        (self.sample_count > 1).then(|| {
+            self.create_texture(TextureDesc {
+                sample_count: self.sample_count,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ..desc
+            })
+        })
+    }
+    /// Makes a texture living outside of the graph usable by its passes.
+    pub fn import_texture(&mut self, texture: &'a Texture) -> ResourceHandle {
+        self.add_resource(Resource::Texture(texture))
+    }
+    /// Makes a buffer living outside of the graph usable by its passes.
+    pub fn import_buffer(&mut self, buffer: &'a wgpu::Buffer) -> ResourceHandle {
+        self.add_resource(Resource::Buffer(buffer))
+    }
+    fn add_resource(&mut self, resource: Resource<'a>) -> ResourceHandle {
+        self.resources.push(resource);
+        ResourceHandle(self.resources.len() - 1)
+    }
+
+    pub const fn add_pass<'g>(&'g mut self, name: &'static str) -> PassBuilder<'g, 'a> {
+        PassBuilder {
+            graph: self,
+            name,
+            reads: vec![],
+            writes: vec![],
+        }
+    }
+
+    /// Indices of the passes in the order they run.
+    ///
+    /// # Panics
+    /// If the dependencies of the passes form a cycle.
+    fn order(&self) -> Vec<usize> {
+        let mut dependencies = vec![vec![]; self.passes.len()];
+        for resource in 0..self.resources.len() {
+            let handle = ResourceHandle(resource);
+            let mut last_writer = None;
+            let mut readers = vec![];
+            let mut early_readers = vec![];
+            let mut writers = vec![];
+            for (index, pass) in self.passes.iter().enumerate() {
+                if pass.reads.contains(&handle) {
+                    match last_writer {
+                        Some(writer) => dependencies[index].push(writer),
+                        None => early_readers.push(index),
+                    }
+                    readers.push(index);
+                }
+                if pass.writes.contains(&handle) {
+                    // Early readers run after every writer, even when they write too
+                    dependencies[index]
+                        .extend(last_writer.filter(|writer| !early_readers.contains(writer)));
+                    dependencies[index].extend(
+                        std::mem::take(&mut readers)
+                            .into_iter()
+                            .filter(|reader| *reader != index && !early_readers.contains(reader)),
+                    );
+                    last_writer = Some(index);
+                    writers.push(index);
+                }
+            }
+            for reader in early_readers {
+                dependencies[reader].extend(writers.iter().filter(|writer| **writer != reader));
+            }
+        }
+
+        // Kahn's algorithm, ties go to the pass declared first
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len()).find(|&index| {
+                !done[index]
+                    && dependencies[index]
+                        .iter()
+                        .all(|&dependency| dependency == index || done[dependency])
+            });
+            let Some(next) = next else {
+                let stuck: Vec<_> = (0..self.passes.len())
+                    .filter(|&index| !done[index])
+                    .map(|index| self.passes[index].name)
+                    .collect();
+                panic!("Render graph has a dependency cycle between {stuck:?}");
+            };
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+
+    /// Allocates the transient textures and records every pass into one command buffer, which is then submitted.
+    pub fn execute(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &mut TexturePool,
+        surface: &wgpu::TextureView,
+    ) {
+        let order = self.order();
+        let textures = pool.allocate(device, &self.transients, self.width, self.height);
+        let resources: Vec<_> = self
+            .resources
+            .iter()
+            .map(|resource| match *resource {
+                Resource::Surface => Resolved::View(surface),
+                Resource::Texture(texture) => Resolved::Texture(texture),
+                Resource::Buffer(buffer) => Resolved::Buffer(buffer),
+                Resource::Transient(index) => Resolved::Texture(&pool.textures[textures[index]].1),
+            })
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Graph"),
+        });
+        let mut passes: Vec<_> = self.passes.into_iter().map(Some).collect();
+        for index in order {
+            let pass = passes[index].take().expect("Passes run once");
+            (pass.run)(&mut PassContext {
+                device,
+                queue,
+                encoder: &mut encoder,
+                resources: &resources,
+            });
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// Declares the resources of a pass, then adds it to the graph with [`PassBuilder::run`].
+#[allow(missing_debug_implementations)]
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    name: &'static str,
+    reads: Vec<ResourceHandle>,
+    writes: Vec<ResourceHandle>,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    pub fn read(mut self, handle: ResourceHandle) -> Self {
+        self.reads.push(handle);
+        self
+    }
+    pub fn write(mut self, handle: ResourceHandle) -> Self {
+        self.writes.push(handle);
+        self
+    }
+    /// Loads the previous contents of `handle` and writes over them.
+    pub fn read_write(self, handle: ResourceHandle) -> Self {
+        self.read(handle).write(handle)
+    }
+    pub fn run(self, run: impl FnOnce(&mut PassContext) + 'a) {
+        self.graph.passes.push(Pass {
+            name: self.name,
+            reads: self.reads,
+            writes: self.writes,
+            run: Box::new(run),
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TextureKey {
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+    sample_count: u32,
+    usage: wgpu::TextureUsages,
+}
+
+/// Textures of the previous frame, reused by the transient textures of the next one.
+///
+/// Textures no pass asked for in a frame are dropped, e.g. the old sizes after a resize.
+#[derive(Debug, Default)]
+pub struct TexturePool {
+    textures: Vec<(TextureKey, Texture)>,
+}
+
+impl TexturePool {
+    pub const fn len(&self) -> usize {
+        self.textures.len()
+    }
+    pub const fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Returns the index in the pool of the texture of every desc.
+    fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        descs: &[TextureDesc],
+        width: u32,
+        height: u32,
+    ) -> Vec<usize> {
+        let mut previous = std::mem::take(&mut self.textures);
+        descs
+            .iter()
+            .map(|desc| {
+                let (width, height) = match desc.size {
+                    TextureSize::Surface => (width, height),
+                    TextureSize::Fixed { width, height } => (width, height),
+                };
+                let key = TextureKey {
+                    format: desc.format,
+                    width,
+                    height,
+                    mip_level_count: desc.mip_level_count,
+                    sample_count: desc.sample_count,
+                    usage: desc.usage,
+                };
+                let texture = previous.iter().position(|(k, _)| *k == key).map_or_else(
+                    || create_texture(device, desc.label, key),
+                    |index| previous.swap_remove(index).1,
+                );
+                self.textures.push((key, texture));
+                self.textures.len() - 1
+            })
+            .collect()
+    }
+}
+
+fn create_texture(device: &wgpu::Device, label: &str, key: TextureKey) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: key.width.max(1),
+            height: key.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: key.mip_level_count,
+        sample_count: key.sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: key.format,
+        usage: key.usage,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    Texture {
+        texture: Rc::new(texture),
+        view: Rc::new(view),
+        sampler: Rc::new(sampler),
+        egui_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::{RenderGraph, TextureDesc, TexturePool};
+    use crate::{
+        renderer::{
+            render_pipeline::RenderPassBuilder, resources::get_texture_data, texture::Texture,
+        },
+        tests::device,
+    };
+
+    fn names(graph: &RenderGraph) -> Vec<&'static str> {
+        graph
+            .order()
+            .into_iter()
+            .map(|index| graph.passes[index].name)
+            .collect()
+    }
+
+    #[test]
+    fn passes_run_after_their_inputs() {
+        let mut graph = RenderGraph::new(1, 1, 1);
+        let hdr = graph.create_texture(TextureDesc::new("HDR", wgpu::TextureFormat::Rgba16Float));
+        let shadows = graph.create_texture(TextureDesc::new(
+            "Shadows",
+            wgpu::TextureFormat::Depth32Float,
+        ));
+        graph
+            .add_pass("egui")
+            .read_write(RenderGraph::SURFACE)
+            .run(|_| {});
+        graph
+            .add_pass("tonemap")
+            .read(hdr)
+            .write(RenderGraph::SURFACE)
+            .run(|_| {});
+        graph.add_pass("main").read(shadows).write(hdr).run(|_| {});
+        graph.add_pass("shadows").write(shadows).run(|_| {});
+        graph.add_pass("unrelated").run(|_| {});
+
+        let order = names(&graph);
+        let position = |name| order.iter().position(|n| *n == name).unwrap();
+        assert!(
+            position("shadows") < position("main"),
+            "Consumers can be declared before producers, got {order:?}"
+        );
+        assert!(
+            position("main") < position("tonemap"),
+            "Reads see the last write declared before, got {order:?}"
+        );
+        assert!(
+            position("tonemap") < position("egui"),
+            "Surface reads before any write see every write, got {order:?}"
+        );
+    }
+
+    #[test]
+    fn ping_pong_keeps_declaration_order() {
+        let mut graph = RenderGraph::new(1, 1, 1);
+        let a = graph.create_texture(TextureDesc::new("A", wgpu::TextureFormat::Rgba16Float));
+        let b = graph.create_texture(TextureDesc::new("B", wgpu::TextureFormat::Rgba16Float));
+        graph.add_pass("scene").write(a).run(|_| {});
+        graph.add_pass("first").read(a).write(b).run(|_| {});
+        graph.add_pass("second").read(b).write(a).run(|_| {});
+        graph.add_pass("third").read(a).write(b).run(|_| {});
+        assert_eq!(names(&graph), ["scene", "first", "second", "third"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "dependency cycle")]
+    fn cycles_panic() {
+        let mut graph = RenderGraph::new(1, 1, 1);
+        let a = graph.create_texture(TextureDesc::new("A", wgpu::TextureFormat::Rgba16Float));
+        let b = graph.create_texture(TextureDesc::new("B", wgpu::TextureFormat::Rgba16Float));
+        graph.add_pass("first").read(a).write(b).run(|_| {});
+        graph.add_pass("second").read(b).write(a).run(|_| {});
+        graph.order();
+    }
+
+    #[test]
+    fn transient_textures_follow_the_surface() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut pool = TexturePool::default();
+        let sizes = RefCell::new(vec![]);
+        let surface = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        for (width, height) in [(64, 32), (64, 32), (16, 16)] {
+            let mut graph = RenderGraph::new(width, height, 1);
+            let hdr =
+                graph.create_texture(TextureDesc::new("HDR", wgpu::TextureFormat::Rgba16Float));
+            graph.add_pass("main").write(hdr).run(|ctx| {
+                let size = ctx.texture(hdr).texture.size();
+                sizes.borrow_mut().push((size.width, size.height));
+            });
+            graph.execute(&device, &queue, &mut pool, &surface);
+            assert_eq!(pool.len(), 1, "Textures of old sizes are dropped");
+        }
+        assert_eq!(*sizes.borrow(), [(64, 32), (64, 32), (16, 16)]);
+    }
+
+    #[test]
+    fn msaa_textures_resolve_into_their_target() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let target = Texture::render_target(&device, 4, 4, format, format);
+        for sample_count in [1, 4] {
+            let mut graph = RenderGraph::new(4, 4, sample_count);
+            let msaa = graph.create_msaa_texture(TextureDesc::new("MSAA", format));
+            assert_eq!(msaa.is_some(), sample_count > 1);
+            let depth = graph.create_depth_texture();
+            graph
+                .add_pass("Clear")
+                .write(RenderGraph::SURFACE)
+                .write(depth)
+                .run(move |ctx| {
+                    let (surface, depth) = (ctx.view(RenderGraph::SURFACE), ctx.view(depth));
+                    RenderPassBuilder::new()
+                        .depth(depth)
+                        .msaa(msaa.map(|msaa| ctx.view(msaa)))
+                        .clear_color(wgpu::Color::RED)
+                        .build(ctx.encoder, surface);
+                });
+            graph.execute(&device, &queue, &mut TexturePool::default(), &target.view);
+            let (pixels, ..) = get_texture_data(&target.texture, &device, &queue, 0);
+            assert_eq!(pixels.samples[..4], [255, 0, 0, 255]);
+        }
+    }
+}
@@ -1,6 +1,11 @@
-use wgpu::include_wgsl;
+use super::{
+    bind_group::BindGroup,
+    reflection::ShaderReflection,
+    resources::VertexAttributeLayout,
+    shader::{Shader, ShaderDefs},
+};
 
-use super::resources::VertexAttributeLayout;
+const WIRE_SHADER: Shader = Shader::new("shaders/wire.wgsl", include_str!("shaders/wire.wgsl"));
 
 #[derive(Debug, Clone, Copy)]
 pub struct RenderPipelineWire;
@@ -8,7 +13,7 @@ pub struct RenderPipelineWire;
 impl<'a> RenderPipelineWire {
     #[allow(clippy::new_ret_no_self)]
     pub fn new() -> RenderPipelineBuilder<'a> {
-        let shader = include_wgsl!("shaders/wire.wgsl");
+        let shader = WIRE_SHADER.variant(&ShaderDefs::new());
         RenderPipelineBuilder::new(shader).polygon_mode(wgpu::PolygonMode::Line)
     }
 }
@@ -17,10 +22,16 @@ impl<'a> RenderPipelineWire {
 pub struct RenderPipelineBuilder<'a> {
     shader: wgpu::ShaderModuleDescriptor<'a>,
     depth_texture_format: Option<wgpu::TextureFormat>,
+    depth_write: Option<bool>,
     bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
+    /// Entries of each layout, for the ones added with [`Self::bind_group`]
+    bind_group_entries: Vec<Option<&'a [wgpu::BindGroupLayoutEntry]>>,
     fragment_entry: Option<&'a str>,
     polygon_mode: Option<wgpu::PolygonMode>,
     cull_mode: Option<wgpu::Face>,
+    blend: Option<wgpu::BlendState>,
+    sample_count: Option<u32>,
+    color_targets: Option<&'a [wgpu::TextureFormat]>,
 }
 
 impl<'a> RenderPipelineBuilder<'a> {
@@ -28,14 +39,26 @@ impl<'a> RenderPipelineBuilder<'a> {
         Self {
             shader,
             depth_texture_format: Option::default(),
+            depth_write: Option::default(),
             bind_group_layouts: Vec::default(),
+            bind_group_entries: Vec::default(),
             fragment_entry: Option::default(),
             polygon_mode: Option::default(),
             cull_mode: Option::default(),
+            blend: Option::default(),
+            sample_count: Option::default(),
+            color_targets: Option::default(),
         }
     }
     pub fn add_bind_group(mut self, bind_group_layout: &'a wgpu::BindGroupLayout) -> Self {
         self.bind_group_layouts.push(bind_group_layout);
+        self.bind_group_entries.push(None);
+        self
+    }
+    /// Adds the layout of `bind_group`, whose entries are checked against the shader in debug builds.
+    pub fn bind_group(mut self, bind_group: &'a BindGroup) -> Self {
+        self.bind_group_layouts.push(&bind_group.layout);
+        self.bind_group_entries.push(Some(&bind_group.entries));
         self
     }
 
@@ -45,6 +68,13 @@ impl<'a> RenderPipelineBuilder<'a> {
             ..self
         }
     }
+    /// Keeps the depth test but doesn't write depth when `false`, like transparent materials. Writes by default.
+    pub fn depth_write(self, depth_write: bool) -> Self {
+        Self {
+            depth_write: Some(depth_write),
+            ..self
+        }
+    }
     pub fn fragment_entry(self, fragment_entry: &'a str) -> Self {
         Self {
             fragment_entry: Some(fragment_entry),
@@ -60,6 +90,29 @@ impl<'a> RenderPipelineBuilder<'a> {
     pub fn cull_mode(self, cull_mode: Option<wgpu::Face>) -> Self {
         Self { cull_mode, ..self }
     }
+    /// Replaces the default alpha blending.
+    pub fn blend(self, blend: wgpu::BlendState) -> Self {
+        Self {
+            blend: Some(blend),
+            ..self
+        }
+    }
+
+    /// MSAA samples of the targets, usually [`RendererSettings::sample_count`](super::wgpu_renderer::RendererSettings).
+    pub fn sample_count(self, sample_count: u32) -> Self {
+        Self {
+            sample_count: Some(sample_count),
+            ..self
+        }
+    }
+
+    /// Renders into several targets, replacing the one of the format passed when building.
+    pub fn color_targets(self, formats: &'a [wgpu::TextureFormat]) -> Self {
+        Self {
+            color_targets: Some(formats),
+            ..self
+        }
+    }
 
     pub fn build<T>(
         self,
@@ -69,67 +122,7 @@ impl<'a> RenderPipelineBuilder<'a> {
     where
         T: Clone + Copy + bytemuck::Pod + bytemuck::Zeroable + VertexAttributeLayout,
     {
-        let module = device.create_shader_module(self.shader);
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &self.bind_group_layouts,
-            push_constant_ranges: &[],
-        });
-
-        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &module,
-                entry_point: "vs_main",
-                buffers: &[T::layout()],
-                // compilation_options: Default::default(),
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: self.cull_mode,
-                polygon_mode: self.polygon_mode.map_or_else(Default::default, |m| m),
-                ..Default::default()
-            },
-            depth_stencil: self.depth_texture_format.map(|depth_texture_format| {
-                wgpu::DepthStencilState {
-                    format: depth_texture_format,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &module,
-                entry_point: self.fragment_entry.map_or("fs_main", |f| f),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::Zero,
-                            dst_factor: wgpu::BlendFactor::One,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                    }),
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
-                // compilation_options: Default::default(),
-            }),
-            multiview: None,
-        })
+        self.build_with_buffers(device, surface_format, &[T::layout()])
     }
     pub fn build_with_instancing<T, I>(
         self,
@@ -140,7 +133,73 @@ impl<'a> RenderPipelineBuilder<'a> {
         T: Clone + Copy + bytemuck::Pod + bytemuck::Zeroable + VertexAttributeLayout,
         I: Clone + Copy + bytemuck::Pod + bytemuck::Zeroable + VertexAttributeLayout,
     {
+        self.build_with_buffers(device, surface_format, &[T::layout(), I::layout()])
+    }
+    /// Builds a pipeline without vertex buffers, whose `vs_main` generates its vertices, like a full-screen triangle.
+    pub fn build_fullscreen(
+        self,
+        device: &'a wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        self.build_with_buffers(device, surface_format, &[])
+    }
+
+    /// Logs the bindings and vertex inputs the shader expects but the pipeline doesn't provide,
+    /// before wgpu rejects the pipeline with a less readable error.
+    fn reflect(&self, fragment_entry: &str, buffers: &[wgpu::VertexBufferLayout]) {
+        let wgpu::ShaderSource::Wgsl(ref source) = self.shader.source else {
+            return;
+        };
+        // Invalid shaders are reported by wgpu when creating the module
+        let Ok(reflection) = ShaderReflection::new(self.shader.label.unwrap_or("shader"), source)
+        else {
+            return;
+        };
+        if let Err(error) = reflection.check(
+            &["vs_main", fragment_entry],
+            &self.bind_group_entries,
+            buffers,
+        ) {
+            tracing::error!("{error}");
+        }
+    }
+
+    fn build_with_buffers(
+        self,
+        device: &'a wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        buffers: &[wgpu::VertexBufferLayout],
+    ) -> wgpu::RenderPipeline {
+        let fragment_entry = self.fragment_entry.unwrap_or("fs_main");
+        if cfg!(debug_assertions) {
+            self.reflect(fragment_entry, buffers);
+        }
         let module = device.create_shader_module(self.shader);
+        let blend = self.blend.unwrap_or(wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        });
+        let targets: Vec<_> = self
+            .color_targets
+            .unwrap_or(&[surface_format])
+            .iter()
+            .map(|&format| {
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::all(),
+                })
+            })
+            .collect();
+        let depth_write_enabled = self.depth_write.unwrap_or(true);
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
             bind_group_layouts: &self.bind_group_layouts,
@@ -153,7 +212,7 @@ impl<'a> RenderPipelineBuilder<'a> {
             vertex: wgpu::VertexState {
                 module: &module,
                 entry_point: "vs_main",
-                buffers: &[T::layout(), I::layout()],
+                buffers,
                 // compilation_options: Default::default(),
             },
             primitive: wgpu::PrimitiveState {
@@ -167,36 +226,21 @@ impl<'a> RenderPipelineBuilder<'a> {
             depth_stencil: self.depth_texture_format.map(|depth_texture_format| {
                 wgpu::DepthStencilState {
                     format: depth_texture_format,
-                    depth_write_enabled: true,
+                    depth_write_enabled,
                     depth_compare: wgpu::CompareFunction::LessEqual,
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 }
             }),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: self.sample_count.unwrap_or(1),
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             fragment: Some(wgpu::FragmentState {
                 module: &module,
-                entry_point: self.fragment_entry.map_or("fs_main", |f| f),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::Zero,
-                            dst_factor: wgpu::BlendFactor::One,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                    }),
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
+                entry_point: fragment_entry,
+                targets: &targets,
                 // compilation_options: Default::default(),
             }),
             multiview: None,
@@ -208,6 +252,8 @@ impl<'a> RenderPipelineBuilder<'a> {
 pub struct RenderPassBuilder<'a> {
     clear_color: Option<wgpu::Color>,
     depth: Option<&'a wgpu::TextureView>,
+    msaa: Option<&'a wgpu::TextureView>,
+    load: bool,
 }
 
 impl<'a> RenderPassBuilder<'a> {
@@ -226,6 +272,14 @@ impl<'a> RenderPassBuilder<'a> {
             ..self
         }
     }
+    /// Draws over the previous contents of the targets instead of clearing them.
+    pub const fn load(self) -> Self {
+        Self { load: true, ..self }
+    }
+    /// Renders into the multisampled `msaa` when there is one, resolving it into the view passed to [`Self::build`].
+    pub const fn msaa(self, msaa: Option<&'a wgpu::TextureView>) -> Self {
+        Self { msaa, ..self }
+    }
     pub fn build(
         self,
         encoder: &'a mut wgpu::CommandEncoder,
@@ -234,10 +288,14 @@ impl<'a> RenderPassBuilder<'a> {
         encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
+                view: self.msaa.unwrap_or(view),
+                resolve_target: self.msaa.map(|_| view),
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(self.clear_color.unwrap_or(wgpu::Color::BLACK)),
+                    load: if self.load {
+                        wgpu::LoadOp::Load
+                    } else {
+                        wgpu::LoadOp::Clear(self.clear_color.unwrap_or(wgpu::Color::BLACK))
+                    },
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -245,7 +303,11 @@ impl<'a> RenderPassBuilder<'a> {
                 wgpu::RenderPassDepthStencilAttachment {
                     view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: if self.load {
+                            wgpu::LoadOp::Load
+                        } else {
+                            wgpu::LoadOp::Clear(1.0)
+                        },
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
@@ -12,7 +12,7 @@ use wgpu::Extent3d;
 
 use super::buffer::Buffer;
 use super::compute;
-use super::mesh::{compute_tangent_frame, Mesh, Vertex};
+use super::mesh::{Mesh, Vertex};
 
 pub fn get_max_mip_level_count(width: u32, height: u32) -> u32 {
     bit_width(u32::max(width, height))
@@ -125,9 +125,9 @@ pub fn get_texture_data(
     let channels = 4;
     let component_byte_size = 1;
     let bytes_per_row = width * channels * component_byte_size;
-    // Special case: WebGPU spec forbids texture-to-buffer copy with a
-    // bytesPerRow lower than 256 so we first copy to a temporary texture.
-    let padded_bytes_per_row = bytes_per_row.max(256);
+    // WebGPU requires texture-to-buffer copies to have a bytesPerRow that is a
+    // multiple of 256, so rows are padded and the padding is stripped after readback.
+    let padded_bytes_per_row = bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
     let pixel_buffer = Buffer::new(
         device,
         (padded_bytes_per_row * height).into(),
@@ -172,11 +172,16 @@ pub fn get_texture_data(
         .block_on()
         .expect("communication failed")
         .expect("buffer reading failed");
-    let pixels: &[u8] = &pixel_buffer.buffer.slice(..).get_mapped_range();
+    let padded_pixels: &[u8] = &pixel_buffer.buffer.slice(..).get_mapped_range();
+    let pixels = padded_pixels
+        .chunks_exact(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..bytes_per_row as usize])
+        .copied()
+        .collect();
 
     let layout = SampleLayout::row_major_packed(4, width, height);
     let buffer = FlatSamples {
-        samples: pixels.to_owned(),
+        samples: pixels,
         layout,
         color_hint: None,
     };
@@ -192,7 +197,7 @@ pub fn save_texture(
 ) {
     let (buffer, height, width) = get_texture_data(texture, device, queue, mip_level);
     let view = buffer.as_view::<Rgba<u8>>().unwrap();
-    thumbnail(&view, height, width)
+    thumbnail(&view, width, height)
         .save(path)
         .expect("Unable to save");
 }
@@ -272,79 +277,99 @@ pub fn write_mipmaps(queue: &wgpu::Queue, texture: &wgpu::Texture, image: Dynami
 pub trait VertexAttributeLayout {
     fn layout() -> wgpu::VertexBufferLayout<'static>;
 }
-pub fn load_geometry(path: impl AsRef<Path> + Debug) -> Mesh {
-    let (models, _) = tobj::load_obj(
-        path,
-        &tobj::LoadOptions {
-            single_index: true,
-            triangulate: true,
-            ignore_points: true,
-            ignore_lines: true,
-        },
-    )
-    .expect("Failed to OBJ load file");
+pub const OBJ_LOAD_OPTIONS: tobj::LoadOptions = tobj::LoadOptions {
+    single_index: true,
+    triangulate: true,
+    ignore_points: true,
+    ignore_lines: true,
+};
+
+pub fn load_geometry(path: impl AsRef<Path> + Debug) -> Result<Mesh, tobj::LoadError> {
+    let (models, _) = tobj::load_obj(path, &OBJ_LOAD_OPTIONS)?;
+    Ok(mesh_from_obj_models(&models))
+}
+
+/// Merges every model into a single mesh.
+pub fn mesh_from_obj_models(models: &[tobj::Model]) -> Mesh {
     let mut vertices = vec![];
     let mut indices: Vec<u32> = vec![];
-    for model in &models {
-        let mesh = &model.mesh;
-        indices.extend(&mesh.indices);
-        let mut positions = Vec::with_capacity(mesh.positions.len() / 3);
-        for p in mesh.positions.chunks_exact(3) {
-            positions.push(Vec3::new(p[0], p[1], p[2]));
+    for model in models {
+        let mesh = mesh_from_obj_mesh(&model.mesh);
+        // Indices of each model start from 0
+        let offset = vertices.len() as u32;
+        indices.extend(mesh.indices.iter().map(|i| i + offset));
+        vertices.extend(mesh.vertices);
+    }
+    Mesh { vertices, indices }
+}
+
+pub fn mesh_from_obj_mesh(mesh: &tobj::Mesh) -> Mesh {
+    let mut positions = Vec::with_capacity(mesh.positions.len() / 3);
+    for p in mesh.positions.chunks_exact(3) {
+        positions.push(Vec3::new(p[0], p[1], p[2]));
+    }
+
+    let normals = if mesh.normals.is_empty() {
+        vec![Vec3::ZERO; positions.len()]
+    } else {
+        let mut normals = Vec::with_capacity(positions.len());
+        for n in mesh.normals.chunks_exact(3) {
+            normals.push(Vec3::new(n[0], n[1], n[2]));
+        }
+        normals
+    };
+    let colors = if mesh.vertex_color.is_empty() {
+        vec![Vec3::ZERO; positions.len()]
+    } else {
+        let mut colors = Vec::with_capacity(positions.len());
+        for c in mesh.vertex_color.chunks_exact(3) {
+            colors.push(Vec3::new(c[0], c[1], c[2]));
         }
+        colors
+    };
 
-        let normals = if mesh.normals.is_empty() {
-            vec![Vec3::ZERO; positions.len()]
-        } else {
-            let mut normals = Vec::with_capacity(positions.len());
-            for n in mesh.normals.chunks_exact(3) {
-                normals.push(Vec3::new(n[0], n[1], n[2]));
-            }
-            normals
-        };
-        let colors = if mesh.vertex_color.is_empty() {
-            vec![Vec3::ZERO; positions.len()]
-        } else {
-            let mut colors = Vec::with_capacity(positions.len());
-            for c in mesh.vertex_color.chunks_exact(3) {
-                colors.push(Vec3::new(c[0], c[1], c[2]));
-            }
-            colors
-        };
+    let has_uvs = !mesh.texcoords.is_empty();
+    let uvs = if has_uvs {
+        let mut uvs = Vec::with_capacity(mesh.texcoords.len());
+        for uv in mesh.texcoords.chunks_exact(2) {
+            uvs.push(Vec2::new(uv[0], 1.0 - uv[1]));
+        }
+        uvs
+    } else {
+        vec![Vec2::ZERO; positions.len()]
+    };
 
-        let uvs = if mesh.texcoords.is_empty() {
-            vec![Vec2::ZERO; positions.len()]
-        } else {
-            let mut uvs = Vec::with_capacity(mesh.texcoords.len());
-            for uv in mesh.texcoords.chunks_exact(2) {
-                uvs.push(Vec2::new(uv[0], 1.0 - uv[1]));
-            }
-            uvs
-        };
+    let vertices = positions
+        .into_iter()
+        .zip(normals)
+        .zip(colors)
+        .zip(uvs)
+        .map(|(((p, n), _c), t)| Vertex {
+            position: p,
+            tangent: Vec3::Y,
+            bitangent: Vec3::Z,
+            normal: n,
+            // color: c,
+            uv: t,
+        })
+        .collect();
 
-        vertices.extend(positions.into_iter().zip(normals).zip(colors).zip(uvs).map(
-            |(((p, n), _c), t)| Vertex {
-                position: p,
-                tangent: Vec3::Y,
-                bitangent: Vec3::Z,
-                normal: n,
-                // color: c,
-                uv: t,
-            },
-        ));
+    let mut mesh = Mesh {
+        vertices,
+        indices: mesh.indices.clone(),
+    };
+    if mesh.vertices.iter().all(|v| v.normal == Vec3::ZERO) {
+        mesh.recalculate_normals();
     }
-
-    for i in indices.chunks_exact(3) {
-        let v1 = vertices[i[0] as usize];
-        let v2 = vertices[i[1] as usize];
-        let v3 = vertices[i[2] as usize];
-        for j in 0..3 {
-            let v = &mut vertices[i[j] as usize];
-            let (tangent, bitangent) = compute_tangent_frame([v1, v2, v3], v.normal);
-            v.tangent = tangent;
-            v.bitangent = bitangent;
+    if has_uvs {
+        mesh.recalculate_tangents();
+    } else {
+        // Without uvs any frame orthogonal to the normal will do
+        for vertex in &mut mesh.vertices {
+            if vertex.normal != Vec3::ZERO {
+                (vertex.tangent, vertex.bitangent) = vertex.normal.any_orthonormal_pair();
+            }
         }
     }
-
-    Mesh::new(vertices, indices)
+    mesh
 }
@@ -0,0 +1,267 @@
+use egui::Ui;
+use winit::event::WindowEvent;
+
+use super::{
+    batch::Batcher,
+    bind_group::{BindGroup, BindGroupBuilder},
+    buffer::UniformBuffer,
+    camera::OrbitCamera,
+    cluster::LightClusters,
+    color::Color,
+    culling::GpuCulling,
+    deferred::{DeferredLighting, GBuffer},
+    environment::Environment,
+    gui::{color_edit, lights_gui},
+    light::Light,
+    material::{SCENE_GROUP, SHADOW_GROUP},
+    mesh::Vertex,
+    model::{Instance, Model},
+    post_process::{PostProcess, HDR_FORMAT},
+    render_graph::{RenderGraph, TextureDesc},
+    render_pipeline::{RenderPassBuilder, RenderPipelineWire},
+    shadow::ShadowMaps,
+    texture::Texture,
+    wgpu_renderer::{Renderer, ShadingPath},
+};
+
+/// Most shadow casting lights of a [`SceneRenderer`]
+const MAX_SHADOWS: usize = 16;
+
+/// Draws models lit by clustered lights, shadows and an environment, seen from an orbit camera.
+///
+/// Adds the passes of a whole frame to a [`RenderGraph`]: light culling, shadows, the G-buffer and lighting
+/// passes of the deferred path, the forward pass with the optional wireframe, then post-processing to the surface.
+pub struct SceneRenderer {
+    pub camera: UniformBuffer<OrbitCamera>,
+    pub light_clusters: LightClusters,
+    pub environment: Environment,
+    pub shadow_maps: ShadowMaps,
+    pub batcher: Batcher,
+    pub post_process: PostProcess,
+    pub clear_color: Color,
+    /// Camera, lights and environment, bound at [`SCENE_GROUP`]
+    bind_group: BindGroup,
+    pipeline_wire: Option<wgpu::RenderPipeline>,
+    /// Only used by the deferred path
+    lighting: Option<DeferredLighting>,
+}
+
+impl SceneRenderer {
+    pub fn new(lights: &[Light], environment: Environment, r: &Renderer) -> Self {
+        let aspect_ratio = r.config.width as f32 / r.config.height as f32;
+        let camera = UniformBuffer::new(OrbitCamera::new(2.0, aspect_ratio), &r.device);
+        let light_clusters = LightClusters::new(lights, &r.device, &r.queue);
+        let bind_group = scene_bind_group(&camera, &light_clusters, &environment, &r.device);
+        let shadow_maps = ShadowMaps::new(
+            &r.device,
+            ShadowMaps::DEFAULT_RESOLUTION,
+            ShadowMaps::DEFAULT_LAYERS,
+            ShadowMaps::DEFAULT_CUBES,
+            MAX_SHADOWS,
+        );
+        let lighting = (r.settings.shading == ShadingPath::Deferred).then(|| {
+            DeferredLighting::new(
+                &r.device,
+                &bind_group.layout,
+                &shadow_maps.bind_group.layout,
+            )
+        });
+        let batcher = Batcher::new(
+            &r.device,
+            GpuCulling::supported(&r.adapter),
+            r.settings.shading,
+        );
+        let pipeline_wire = r
+            .device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+            .then(|| {
+                RenderPipelineWire::new()
+                    .bind_group(&bind_group)
+                    .polygon_mode(wgpu::PolygonMode::Line)
+                    .depth(Texture::DEPTH_FORMAT)
+                    .sample_count(r.settings.sample_count)
+                    .cull_mode(None)
+                    .build_with_instancing::<Vertex, Instance>(&r.device, HDR_FORMAT)
+            });
+        Self {
+            camera,
+            light_clusters,
+            environment,
+            shadow_maps,
+            batcher,
+            post_process: PostProcess::new(&r.device, r.config.view_formats[0]),
+            clear_color: Color::new(0.1, 0.2, 0.3),
+            bind_group,
+            pipeline_wire,
+            lighting,
+        }
+    }
+
+    /// Edits the lights, clear color and post-processing settings.
+    pub fn gui(&mut self, ui: &mut Ui, r: &Renderer) {
+        if lights_gui(ui, &mut self.light_clusters.lights.data)
+            && self.light_clusters.update_lights(&r.device, &r.queue)
+        {
+            // The light buffer grew
+            self.bind_group = scene_bind_group(
+                &self.camera,
+                &self.light_clusters,
+                &self.environment,
+                &r.device,
+            );
+        }
+        color_edit(ui, &mut self.clear_color, "Clear Color");
+        self.post_process.settings.gui(ui);
+    }
+
+    /// Moves the camera, see [`OrbitCamera::input`].
+    pub fn input(&mut self, event: &WindowEvent, r: &Renderer) {
+        if self.camera.data.input(event) {
+            self.camera.update(&r.queue);
+        }
+    }
+
+    pub fn resize(&mut self, r: &Renderer) {
+        let aspect_ratio = r.config.width as f32 / r.config.height as f32;
+        self.camera.data.set_aspect_ratio(aspect_ratio);
+        self.camera.update(&r.queue);
+    }
+
+    /// Prepares `models` and adds the passes drawing them to `graph`, presented on [`RenderGraph::SURFACE`].
+    pub fn add_to_graph<'a>(
+        &'a mut self,
+        graph: &mut RenderGraph<'a>,
+        models: impl IntoIterator<Item = &'a Model>,
+        r: &Renderer,
+    ) {
+        self.prepare(models, r);
+        let this = &*self;
+
+        let cluster_lights = this.light_clusters.add_to_graph(graph);
+        let instances = this.batcher.add_to_graph(graph);
+        let shadows = this
+            .shadow_maps
+            .add_to_graph(graph, this.batcher.shadow_casters());
+        let hdr = PostProcess::create_target(graph);
+        let depth = match this.lighting {
+            Some(ref lighting) => {
+                let gbuffer = GBuffer::create(graph);
+                graph
+                    .add_pass("G-Buffer")
+                    .read(instances)
+                    .write(gbuffer.albedo)
+                    .write(gbuffer.normal)
+                    .write(gbuffer.material)
+                    .write(gbuffer.depth)
+                    .run(move |ctx| {
+                        let mut rpass = gbuffer.begin_pass(ctx);
+                        this.set_bind_groups(&mut rpass);
+                        this.batcher.draw_gbuffer(&mut rpass);
+                    });
+                graph
+                    .add_pass("Lighting")
+                    .read(cluster_lights)
+                    .read(shadows)
+                    .read(gbuffer.albedo)
+                    .read(gbuffer.normal)
+                    .read(gbuffer.material)
+                    .read(gbuffer.depth)
+                    .write(hdr)
+                    .run(move |ctx| {
+                        let bind_group = lighting.bind_group(ctx, &gbuffer);
+                        let mut rpass = RenderPassBuilder::new()
+                            .clear_color(this.clear_color.into())
+                            .build(ctx.encoder, ctx.view(hdr));
+                        this.set_bind_groups(&mut rpass);
+                        lighting.draw(&mut rpass, &bind_group);
+                    });
+                gbuffer.depth
+            }
+            None => graph.create_depth_texture(),
+        };
+        let deferred = this.lighting.is_some();
+        let msaa = graph.create_msaa_texture(TextureDesc::new("MSAA", HDR_FORMAT));
+        let pass = graph
+            .add_pass("Main")
+            .read(cluster_lights)
+            .read(instances)
+            .read(shadows);
+        // Forward materials are drawn over the lit G-buffer
+        let pass = if deferred {
+            pass.read_write(hdr).read_write(depth)
+        } else {
+            pass.write(hdr).write(depth)
+        };
+        pass.run(move |ctx| {
+            let (hdr, depth) = (ctx.view(hdr), ctx.view(depth));
+            let mut builder = RenderPassBuilder::new()
+                .depth(depth)
+                .msaa(msaa.map(|msaa| ctx.view(msaa)))
+                .clear_color(this.clear_color.into());
+            if deferred {
+                builder = builder.load();
+            }
+            let mut rpass = builder.build(ctx.encoder, hdr);
+            this.set_bind_groups(&mut rpass);
+            this.batcher.draw(&mut rpass);
+            if let Some(ref pipe) = this.pipeline_wire {
+                rpass.set_pipeline(pipe);
+                rpass.set_bind_group(0, &this.bind_group.bind_group, &[]);
+                this.batcher.draw_geometry(&mut rpass);
+            }
+        });
+        this.post_process
+            .add_to_graph(graph, hdr, RenderGraph::SURFACE);
+    }
+
+    /// Updates the lights and shadows for the camera, and the batches of `models`.
+    #[allow(single_use_lifetimes)]
+    fn prepare<'m>(&mut self, models: impl IntoIterator<Item = &'m Model>, r: &Renderer) {
+        let camera = &self.camera.data;
+        self.light_clusters.update(
+            camera.view(),
+            camera.perspective(),
+            r.config.width,
+            r.config.height,
+            &r.queue,
+        );
+        self.shadow_maps.update(
+            &self.light_clusters.lights.data,
+            camera.view(),
+            camera.perspective(),
+            &r.queue,
+        );
+        self.batcher.prepare(
+            models,
+            Some(&camera.frustum()),
+            camera.position(),
+            &r.device,
+            &r.queue,
+            |builder| {
+                builder
+                    .bind_group(&self.bind_group)
+                    .bind_group(&self.shadow_maps.bind_group)
+                    .depth(Texture::DEPTH_FORMAT)
+                    .sample_count(r.settings.sample_count)
+                    .build_with_instancing::<Vertex, Instance>(&r.device, HDR_FORMAT)
+            },
+        );
+    }
+
+    fn set_bind_groups<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>) {
+        rpass.set_bind_group(SCENE_GROUP, &self.bind_group.bind_group, &[]);
+        rpass.set_bind_group(SHADOW_GROUP, &self.shadow_maps.bind_group.bind_group, &[]);
+    }
+}
+
+fn scene_bind_group(
+    camera: &UniformBuffer<OrbitCamera>,
+    light_clusters: &LightClusters,
+    environment: &Environment,
+    device: &wgpu::Device,
+) -> BindGroup {
+    environment
+        .bind(light_clusters.bind(BindGroupBuilder::new().uniform(&camera.buffer)))
+        .build(device)
+}
@@ -0,0 +1,665 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Shared WGSL modules of the engine shaders, by the name they are imported with.
+const ENGINE_MODULES: [(&str, &str); 8] = [
+    ("camera", include_str!("shaders/modules/camera.wgsl")),
+    ("mesh", include_str!("shaders/modules/mesh.wgsl")),
+    ("lights", include_str!("shaders/modules/lights.wgsl")),
+    ("clusters", include_str!("shaders/modules/clusters.wgsl")),
+    ("scene", include_str!("shaders/modules/scene.wgsl")),
+    ("shadows", include_str!("shaders/modules/shadows.wgsl")),
+    ("brdf", include_str!("shaders/modules/brdf.wgsl")),
+    (
+        "pbr_lighting",
+        include_str!("shaders/modules/pbr_lighting.wgsl"),
+    ),
+];
+
+/// Variants of the engine [`Shader`]s, watched by the [`ShaderWatcher`] the app creates
+static SHADERS: LazyLock<Arc<Mutex<ShaderCache>>> = LazyLock::new(Arc::default);
+
+/// Composed sources of [`Shader`] variants, and where they are read from while a [`ShaderWatcher`] is alive
+#[derive(Debug, Default)]
+struct ShaderCache {
+    /// By shader label and define set
+    variants: HashMap<(&'static str, ShaderDefs), Variant>,
+    hot_reload: Option<HotReload>,
+}
+
+impl ShaderCache {
+    fn variant(&mut self, shader: &Shader, defs: &ShaderDefs) -> String {
+        let generation = self.hot_reload.as_ref().map_or(0, |h| h.generation);
+        let key = (shader.label, defs.clone());
+        match self.variants.get(&key) {
+            Some(variant) if variant.generation == generation => variant.source.clone(),
+            cached => {
+                let previous = cached.map(|variant| variant.source.clone());
+                let source = match self.hot_reload.as_mut() {
+                    Some(hot_reload) => match hot_reload.load(shader.label, defs) {
+                        Ok(source) => {
+                            hot_reload.errors.remove(&key);
+                            source
+                        }
+                        Err(error) => {
+                            tracing::error!("{error}");
+                            hot_reload.errors.insert(key.clone(), error);
+                            previous.unwrap_or_else(|| shader.compose_embedded(defs))
+                        }
+                    },
+                    None => shader.compose_embedded(defs),
+                };
+                self.variants.insert(
+                    key,
+                    Variant {
+                        source: source.clone(),
+                        generation,
+                    },
+                );
+                source
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Variant {
+    source: String,
+    /// [`HotReload::generation`] it was composed in, 0 for the embedded sources
+    generation: u64,
+}
+
+#[derive(Debug)]
+struct HotReload {
+    dir: PathBuf,
+    /// Bumped by every change seen by [`ShaderWatcher::poll`]
+    generation: u64,
+    /// Variants whose source on disk fails, they keep their last good source meanwhile
+    errors: HashMap<(&'static str, ShaderDefs), ShaderError>,
+}
+
+impl HotReload {
+    /// Source of `label` under the watched directory, composed and validated.
+    /// Engine modules are read from `shaders/modules` when the directory has them.
+    fn load(&self, label: &str, defs: &ShaderDefs) -> Result<String, ShaderError> {
+        let source = read(&self.dir.join(label))?;
+        let mut composer = ShaderComposer::new();
+        for (name, _) in ENGINE_MODULES {
+            let path = self
+                .dir
+                .join("shaders/modules")
+                .join(name)
+                .with_extension("wgsl");
+            if let Ok(module) = fs::read_to_string(path) {
+                composer = composer.add_module(name, module);
+            }
+        }
+        let wgsl = composer.compose(label, &source, defs)?;
+        validate(label, &wgsl)?;
+        Ok(wgsl)
+    }
+}
+
+fn read(path: &Path) -> Result<String, ShaderError> {
+    fs::read_to_string(path).map_err(|error| ShaderError::Io {
+        file: path.display().to_string(),
+        message: error.to_string(),
+    })
+}
+
+/// Parses and validates composed WGSL with naga, like wgpu does when creating the shader module.
+pub fn validate(file: &str, source: &str) -> Result<naga::Module, ShaderError> {
+    analyze(file, source).map(|(module, _)| module)
+}
+
+/// [`validate`], also returning what the validator found out about each function, like the globals it uses.
+pub(crate) fn analyze(
+    file: &str,
+    source: &str,
+) -> Result<(naga::Module, naga::valid::ModuleInfo), ShaderError> {
+    let invalid = |message| ShaderError::Invalid {
+        file: file.to_owned(),
+        message,
+    };
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|error| invalid(error.emit_to_string_with_path(source, file)))?;
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| invalid(error.emit_to_string_with_path(source, file)))?;
+    Ok((module, info))
+}
+
+/// Names `#define`d for a shader variant, checked by its `#ifdef`s.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefs(BTreeSet<String>);
+
+impl ShaderDefs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with(mut self, name: impl Into<String>) -> Self {
+        self.define(name);
+        self
+    }
+    pub fn define(&mut self, name: impl Into<String>) {
+        self.0.insert(name.into());
+    }
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderError {
+    /// `#import` or `#include` of a module the composer doesn't know
+    UnknownModule {
+        file: String,
+        line: usize,
+        name: String,
+    },
+    UnknownDirective {
+        file: String,
+        line: usize,
+        directive: String,
+    },
+    /// `#else` or `#endif` outside of an `#ifdef`, or a second `#else`
+    UnmatchedDirective {
+        file: String,
+        line: usize,
+        directive: String,
+    },
+    /// `#ifdef` without its `#endif`
+    UnterminatedIfdef { file: String },
+    /// Module that `#include`s itself, directly or through other modules
+    IncludeCycle { name: String },
+    /// Source that can't be read from disk while hot reloading
+    Io { file: String, message: String },
+    /// Composed source rejected by naga, the message points at the faulty lines
+    Invalid { file: String, message: String },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::UnknownModule {
+                ref file,
+                line,
+                ref name,
+            } => write!(f, "{file}:{line}: unknown shader module `{name}`"),
+            Self::UnknownDirective {
+                ref file,
+                line,
+                ref directive,
+            } => write!(f, "{file}:{line}: unknown directive `#{directive}`"),
+            Self::UnmatchedDirective {
+                ref file,
+                line,
+                ref directive,
+            } => write!(
+                f,
+                "{file}:{line}: `#{directive}` without a matching `#ifdef`"
+            ),
+            Self::UnterminatedIfdef { ref file } => write!(f, "{file}: `#ifdef` without `#endif`"),
+            Self::IncludeCycle { ref name } => write!(f, "shader module `{name}` includes itself"),
+            Self::Io {
+                ref file,
+                ref message,
+            } => write!(f, "{file}: {message}"),
+            Self::Invalid { ref message, .. } => write!(f, "{message}"),
+        }
+    }
+}
+impl std::error::Error for ShaderError {}
+
+/// Resolves the directives of WGSL sources, given on their own line:
+/// - `#import name` pastes the module once per composed shader, later imports of it are skipped
+/// - `#include name` pastes the module every time
+/// - `#define NAME` defines `NAME` for the rest of the shader
+/// - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep or drop the lines between them
+#[derive(Debug, Clone)]
+pub struct ShaderComposer {
+    modules: HashMap<String, String>,
+}
+
+impl Default for ShaderComposer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShaderComposer {
+    /// Composer knowing the modules of `shaders/modules`, like `camera`, `mesh`, `lights` or `pbr_lighting`.
+    pub fn new() -> Self {
+        Self {
+            modules: ENGINE_MODULES
+                .iter()
+                .map(|&(name, source)| (name.to_owned(), source.to_owned()))
+                .collect(),
+        }
+    }
+    /// Adds a module, replacing the one with the same name.
+    pub fn add_module(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.modules.insert(name.into(), source.into());
+        self
+    }
+
+    /// WGSL of `source` specialized with `defs`, with its modules pasted in.
+    /// `file` names the source in errors.
+    pub fn compose(
+        &self,
+        file: &str,
+        source: &str,
+        defs: &ShaderDefs,
+    ) -> Result<String, ShaderError> {
+        let mut composition = Composition {
+            defs: defs.clone(),
+            imported: HashSet::new(),
+            including: Vec::new(),
+            output: String::with_capacity(source.len()),
+        };
+        self.expand(file, source, &mut composition)?;
+        Ok(composition.output)
+    }
+
+    fn expand<'s>(
+        &'s self,
+        file: &str,
+        source: &str,
+        composition: &mut Composition<'s>,
+    ) -> Result<(), ShaderError> {
+        let mut conditions: Vec<Condition> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let active = conditions.last().is_none_or(|c| c.active);
+            let Some(directive) = line.trim().strip_prefix('#') else {
+                if active {
+                    composition.output.push_str(line);
+                    composition.output.push('\n');
+                }
+                continue;
+            };
+            let (keyword, argument) = directive
+                .split_once(char::is_whitespace)
+                .map_or((directive, ""), |(keyword, argument)| {
+                    (keyword, argument.trim())
+                });
+            let unmatched = || ShaderError::UnmatchedDirective {
+                file: file.to_owned(),
+                line: index + 1,
+                directive: keyword.to_owned(),
+            };
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = composition.defs.is_defined(argument);
+                    conditions.push(Condition {
+                        active: active && defined == (keyword == "ifdef"),
+                        parent_active: active,
+                        in_else: false,
+                    });
+                }
+                "else" => {
+                    let condition = conditions.last_mut().ok_or_else(unmatched)?;
+                    if condition.in_else {
+                        return Err(unmatched());
+                    }
+                    condition.in_else = true;
+                    condition.active = condition.parent_active && !condition.active;
+                }
+                "endif" => {
+                    conditions.pop().ok_or_else(unmatched)?;
+                }
+                _ if !active => {}
+                "define" => composition.defs.define(argument),
+                "import" | "include" => {
+                    let (name, module) = self.modules.get_key_value(argument).ok_or_else(|| {
+                        ShaderError::UnknownModule {
+                            file: file.to_owned(),
+                            line: index + 1,
+                            name: argument.to_owned(),
+                        }
+                    })?;
+                    if keyword == "import" && !composition.imported.insert(name) {
+                        continue;
+                    }
+                    if composition.including.contains(&name.as_str()) {
+                        return Err(ShaderError::IncludeCycle { name: name.clone() });
+                    }
+                    composition.including.push(name);
+                    self.expand(name, module, composition)?;
+                    composition.including.pop();
+                }
+                _ => {
+                    return Err(ShaderError::UnknownDirective {
+                        file: file.to_owned(),
+                        line: index + 1,
+                        directive: keyword.to_owned(),
+                    })
+                }
+            }
+        }
+        if conditions.is_empty() {
+            Ok(())
+        } else {
+            Err(ShaderError::UnterminatedIfdef {
+                file: file.to_owned(),
+            })
+        }
+    }
+}
+
+/// State of one [`ShaderComposer::compose`] call, shared by the modules it pastes
+struct Composition<'s> {
+    defs: ShaderDefs,
+    imported: HashSet<&'s String>,
+    /// Modules being pasted, to catch include cycles
+    including: Vec<&'s str>,
+    output: String,
+}
+
+/// Branch of an `#ifdef` being read
+struct Condition {
+    active: bool,
+    parent_active: bool,
+    in_else: bool,
+}
+
+/// Engine WGSL shader, composed with the engine modules into one variant per set of [`ShaderDefs`].
+#[derive(Debug, Clone, Copy)]
+pub struct Shader {
+    label: &'static str,
+    source: &'static str,
+}
+
+impl Shader {
+    /// `label` identifies the shader in the variant cache, so it should be unique, like its path.
+    pub const fn new(label: &'static str, source: &'static str) -> Self {
+        Self { label, source }
+    }
+
+    /// Module descriptor of the variant for `defs`, composed once and cached after that.
+    ///
+    /// While a [`ShaderWatcher`] is alive, the variant is composed again from disk after each change,
+    /// and keeps its last good source when the new one fails.
+    ///
+    /// # Panics
+    /// If the embedded shader directives are malformed, engine shaders are checked by the tests.
+    pub fn variant(&self, defs: &ShaderDefs) -> wgpu::ShaderModuleDescriptor<'static> {
+        let source = SHADERS.lock().unwrap().variant(self, defs);
+        wgpu::ShaderModuleDescriptor {
+            label: Some(self.label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        }
+    }
+
+    fn compose_embedded(&self, defs: &ShaderDefs) -> String {
+        ShaderComposer::new()
+            .compose(self.label, self.source, defs)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+}
+
+/// Hot reloading of [`Shader`]s from the files under a directory.
+///
+/// While it is alive, shaders are read from there instead of the sources embedded at build time,
+/// and [`Self::poll`] tells when they change so the pipelines using them can be rebuilt without recompiling the crate.
+#[derive(Debug)]
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    /// Modification time and length of each `.wgsl` file
+    files: HashMap<PathBuf, (SystemTime, u64)>,
+    cache: Arc<Mutex<ShaderCache>>,
+    /// Minimum time between two scans of the directory
+    interval: Duration,
+    last_scan: Instant,
+}
+
+impl ShaderWatcher {
+    /// Directory the labels of the engine [`Shader`]s are relative to
+    pub const ENGINE_DIR: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/renderer");
+    /// Default minimum time between two scans, so polling every frame doesn't read the directory every frame
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Only one watcher should be alive at a time, the last one created decides where shaders are read from.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_cache(dir.into(), SHADERS.clone())
+    }
+
+    fn with_cache(dir: PathBuf, cache: Arc<Mutex<ShaderCache>>) -> Self {
+        cache.lock().unwrap().hot_reload = Some(HotReload {
+            dir: dir.clone(),
+            generation: 1,
+            errors: HashMap::new(),
+        });
+        let files = scan(&dir);
+        Self {
+            dir,
+            files,
+            cache,
+            interval: Self::POLL_INTERVAL,
+            last_scan: Instant::now(),
+        }
+    }
+
+    /// Whether a `.wgsl` file was added, removed or modified since the last poll.
+    /// When one was, variants are composed again from disk the next time they are asked for.
+    ///
+    /// The directory is scanned at most once per [`Self::POLL_INTERVAL`], polls in between return `false`.
+    pub fn poll(&mut self) -> bool {
+        if self.last_scan.elapsed() < self.interval {
+            return false;
+        }
+        self.last_scan = Instant::now();
+        let files = scan(&self.dir);
+        if files == self.files {
+            return false;
+        }
+        self.files = files;
+        if let Some(hot_reload) = self.cache.lock().unwrap().hot_reload.as_mut() {
+            hot_reload.generation += 1;
+            hot_reload.errors.clear();
+        }
+        true
+    }
+
+    /// Failures of the variants composed since the last change, sorted and without duplicates.
+    pub fn errors(&self) -> Vec<String> {
+        let errors: BTreeSet<_> = self
+            .cache
+            .lock()
+            .unwrap()
+            .hot_reload
+            .iter()
+            .flat_map(|hot_reload| hot_reload.errors.values())
+            .map(ToString::to_string)
+            .collect();
+        errors.into_iter().collect()
+    }
+}
+
+impl Drop for ShaderWatcher {
+    fn drop(&mut self) {
+        self.cache.lock().unwrap().hot_reload = None;
+    }
+}
+
+/// `.wgsl` files under `dir`, recursively
+fn scan(dir: &Path) -> HashMap<PathBuf, (SystemTime, u64)> {
+    let mut files = HashMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "wgsl")
+            {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.insert(path, (modified, metadata.len()));
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::{
+        validate, Shader, ShaderCache, ShaderComposer, ShaderDefs, ShaderError, ShaderWatcher,
+    };
+
+    fn composer() -> ShaderComposer {
+        ShaderComposer::new()
+            .add_module("a", "#import b\nfn a() {}")
+            .add_module("b", "fn b() {}")
+            .add_module("loop", "#include loop")
+    }
+
+    #[test]
+    fn imports_paste_modules_once() {
+        let source = "#import a\n#import b\nfn main() {}";
+        let composed = composer()
+            .compose("main", source, &ShaderDefs::new())
+            .unwrap();
+        assert_eq!(composed, "fn b() {}\nfn a() {}\nfn main() {}\n");
+    }
+
+    #[test]
+    fn ifdefs_keep_the_defined_branch() {
+        let source = "#ifdef X\nx\n#ifndef Y\nnot y\n#endif\n#else\nnot x\n#endif\n#define Y\n#ifdef Y\ny\n#endif";
+        let compose = |defs| composer().compose("main", source, &defs).unwrap();
+        assert_eq!(compose(ShaderDefs::new()), "not x\ny\n");
+        assert_eq!(compose(ShaderDefs::new().with("X")), "x\nnot y\ny\n");
+        assert_eq!(compose(ShaderDefs::new().with("X").with("Y")), "x\ny\n");
+    }
+
+    #[test]
+    fn malformed_directives_are_errors() {
+        let compose = |source| composer().compose("main", source, &ShaderDefs::new());
+        assert_eq!(
+            compose("\n#import c"),
+            Err(ShaderError::UnknownModule {
+                file: "main".to_owned(),
+                line: 2,
+                name: "c".to_owned()
+            })
+        );
+        assert!(
+            matches!(
+                compose("#endif"),
+                Err(ShaderError::UnmatchedDirective { .. })
+            ),
+            "endif without ifdef"
+        );
+        assert!(
+            matches!(
+                compose("#ifdef X"),
+                Err(ShaderError::UnterminatedIfdef { .. })
+            ),
+            "ifdef without endif"
+        );
+        assert!(
+            matches!(
+                compose("#pragma X"),
+                Err(ShaderError::UnknownDirective { .. })
+            ),
+            "unknown directive"
+        );
+        assert!(
+            matches!(
+                compose("#include loop"),
+                Err(ShaderError::IncludeCycle { .. })
+            ),
+            "include cycle"
+        );
+    }
+
+    #[test]
+    fn engine_shaders_are_valid_wgsl() {
+        let shaders = [
+            ("unlit", include_str!("shaders/unlit.wgsl")),
+            ("lit", include_str!("shaders/lit.wgsl")),
+            ("pbr", include_str!("shaders/pbr.wgsl")),
+            ("wire", include_str!("shaders/wire.wgsl")),
+            ("shadow", include_str!("shaders/shadow.wgsl")),
+            ("cluster", include_str!("shaders/cluster.wgsl")),
+            ("skybox", include_str!("shaders/skybox.wgsl")),
+            (
+                "deferred",
+                concat!(
+                    include_str!("shaders/fullscreen.wgsl"),
+                    include_str!("shaders/deferred.wgsl")
+                ),
+            ),
+        ];
+        let variants = [
+            ShaderDefs::new(),
+            ShaderDefs::new().with("ALPHA_MASK").with("NORMAL_MAP"),
+        ];
+        for (name, source) in shaders {
+            for defs in &variants {
+                let composed = ShaderComposer::new().compose(name, source, defs).unwrap();
+                if let Err(e) = validate(name, &composed) {
+                    panic!("{defs:?}\n{e}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn watched_shaders_keep_their_last_good_source() {
+        let dir = std::env::temp_dir().join(format!("iris_shader_watcher_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watched.wgsl");
+        let entry = |name: &str| format!("@compute @workgroup_size(1) fn {name}() {{}}\n");
+        fs::write(&path, entry("embedded")).unwrap();
+        let shader = Shader::new(
+            "watched.wgsl",
+            "@compute @workgroup_size(1) fn embedded() {}",
+        );
+        // Its own cache, the engine shaders of the other tests aren't read from `dir`
+        let cache = Arc::new(Mutex::new(ShaderCache::default()));
+        let source = || cache.lock().unwrap().variant(&shader, &ShaderDefs::new());
+
+        let mut watcher = ShaderWatcher::with_cache(dir.clone(), cache.clone());
+        watcher.interval = Duration::ZERO;
+        assert!(!watcher.poll(), "Nothing changed yet");
+        fs::write(&path, entry("edited")).unwrap();
+        assert!(watcher.poll(), "Edit seen");
+        assert_eq!(source(), entry("edited"));
+
+        fs::write(&path, "fn broken( {}").unwrap();
+        assert!(watcher.poll(), "Broken edit seen");
+        assert_eq!(source(), entry("edited"), "Last good source kept");
+        assert_eq!(watcher.errors().len(), 1, "{:?}", watcher.errors());
+
+        fs::write(&path, entry("fixed")).unwrap();
+        assert!(watcher.poll(), "Fix seen");
+        assert_eq!(source(), entry("fixed"));
+        assert!(watcher.errors().is_empty(), "{:?}", watcher.errors());
+
+        drop(watcher);
+        assert_eq!(source(), "@compute @workgroup_size(1) fn embedded() {}\n");
+        fs::remove_dir_all(&dir).ok();
+    }
+}
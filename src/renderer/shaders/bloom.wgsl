@@ -0,0 +1,48 @@
+// Bloom mip chain, with the downsample and upsample filters from Call of Duty: Advanced Warfare
+
+struct Settings {
+    exposure: f32,
+    tonemapping: u32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var s_source: sampler;
+
+@group(1) @binding(0) var<uniform> settings: Settings;
+
+fn sample(uv: vec2f, offset: vec2f) -> vec3f {
+    let texel = 1.0 / vec2f(textureDimensions(source));
+    return textureSample(source, s_source, uv + offset * texel).rgb;
+}
+
+// 13 taps in overlapping 2x2 boxes, which avoids the flickering of a plain box filter
+fn downsample(uv: vec2f) -> vec3f {
+    let corners = sample(uv, vec2f(-2.0, -2.0)) + sample(uv, vec2f(2.0, -2.0)) + sample(uv, vec2f(-2.0, 2.0)) + sample(uv, vec2f(2.0, 2.0));
+    let edges = sample(uv, vec2f(0.0, -2.0)) + sample(uv, vec2f(-2.0, 0.0)) + sample(uv, vec2f(2.0, 0.0)) + sample(uv, vec2f(0.0, 2.0));
+    let inner = sample(uv, vec2f(-1.0, -1.0)) + sample(uv, vec2f(1.0, -1.0)) + sample(uv, vec2f(-1.0, 1.0)) + sample(uv, vec2f(1.0, 1.0));
+    return sample(uv, vec2f(0.0)) * 0.125 + corners * 0.03125 + edges * 0.0625 + inner * 0.125;
+}
+
+// First downsample, keeps only the light above the threshold
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = downsample(in.uv);
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - settings.bloom_threshold, 0.0) / max(brightness, 0.0001);
+    return vec4f(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4f(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent filter, blended additively over the larger mip
+@fragment
+fn fs_upsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let corners = sample(in.uv, vec2f(-1.0, -1.0)) + sample(in.uv, vec2f(1.0, -1.0)) + sample(in.uv, vec2f(-1.0, 1.0)) + sample(in.uv, vec2f(1.0, 1.0));
+    let edges = sample(in.uv, vec2f(0.0, -1.0)) + sample(in.uv, vec2f(-1.0, 0.0)) + sample(in.uv, vec2f(1.0, 0.0)) + sample(in.uv, vec2f(0.0, 1.0));
+    return vec4f((sample(in.uv, vec2f(0.0)) * 4.0 + edges * 2.0 + corners) / 16.0, 1.0);
+}
@@ -0,0 +1,52 @@
+// Assigns lights to the view space clusters of `LightClusters`.
+// The list of each cluster starts with its light count, followed by up to max_cluster_lights indices.
+
+#import clusters
+#import lights
+
+@group(0) @binding(0) var<uniform> clusters: Clusters;
+@group(0) @binding(1) var<storage,read> lights: array<Light>;
+@group(0) @binding(2) var<storage,read_write> cluster_lights: array<u32>;
+
+// View space depth where a slice starts, slices are spaced logarithmically
+fn slice_depth(slice: u32) -> f32 {
+    return clusters.near * pow(clusters.far / clusters.near, f32(slice) / f32(clusters.grid.z));
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn assign_lights(@builtin(global_invocation_id) id: vec3u) {
+    let grid = clusters.grid;
+    let index = id.x;
+    if index >= grid.x * grid.y * grid.z {
+        return;
+    }
+    let cluster = vec3u(index % grid.x, (index / grid.x) % grid.y, index / (grid.x * grid.y));
+
+    // Tiles are counted from the top of the screen, ndc y points up
+    let ndc_min = vec2f(f32(cluster.x), f32(grid.y - cluster.y - 1u)) / vec2f(grid.xy) * 2.0 - 1.0;
+    let ndc_max = vec2f(f32(cluster.x + 1u), f32(grid.y - cluster.y)) / vec2f(grid.xy) * 2.0 - 1.0;
+    let near = slice_depth(cluster.z);
+    let far = slice_depth(cluster.z + 1u);
+    let aabb_min = vec3f(min(ndc_min * near, ndc_min * far) / clusters.projection_scale, -far);
+    let aabb_max = vec3f(max(ndc_max * near, ndc_max * far) / clusters.projection_scale, -near);
+
+    let first = index * (clusters.max_cluster_lights + 1u);
+    let light_count = min(clusters.light_count, arrayLength(&lights));
+    var count = 0u;
+    for (var i = 0u; i < light_count && count < clusters.max_cluster_lights; i++) {
+        let light = lights[i];
+        // Directional lights reach every cluster, the others are bounded by a sphere of their range
+        var visible = light.position.w == 0.0;
+        if !visible {
+            let center = (clusters.view * vec4f(light.position.xyz, 1.0)).xyz;
+            let offset = center - clamp(center, aabb_min, aabb_max);
+            let range = light.color_range.w;
+            visible = dot(offset, offset) <= range * range;
+        }
+        if visible {
+            cluster_lights[first + 1u + count] = i;
+            count++;
+        }
+    }
+    cluster_lights[first] = count;
+}
@@ -0,0 +1,52 @@
+struct Frustum {
+    planes: array<vec4f, 6>,
+    plane_count: u32,
+    instance_count: u32,
+}
+
+struct Instance {
+    x_axis: vec4f,
+    y_axis: vec4f,
+    z_axis: vec4f,
+    w_axis: vec4f,
+}
+
+struct CullInstance {
+    instance: Instance,
+    // World space center and radius
+    sphere: vec4f,
+    batch: u32,
+    first: u32,
+}
+
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<uniform> frustum: Frustum;
+@group(0) @binding(1) var<storage, read> instances: array<CullInstance>;
+@group(0) @binding(2) var<storage, read_write> draws: array<DrawArgs>;
+@group(0) @binding(3) var<storage, read_write> visible: array<Instance>;
+
+@compute @workgroup_size(64)
+fn cull(@builtin(global_invocation_id) id: vec3u) {
+    let index = id.x;
+    if index >= frustum.instance_count {
+        return;
+    }
+    let instance = instances[index];
+    let center = vec4f(instance.sphere.xyz, 1.0);
+    // Planes point inwards
+    for (var i = 0u; i < frustum.plane_count; i++) {
+        if dot(frustum.planes[i], center) < -instance.sphere.w {
+            return;
+        }
+    }
+    // Visible instances of a batch are packed at its start
+    let slot = atomicAdd(&draws[instance.batch].instance_count, 1u);
+    visible[instance.first + slot] = instance.instance;
+}
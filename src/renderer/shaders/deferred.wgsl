@@ -0,0 +1,43 @@
+// Lighting pass of the deferred path, shading the G-buffer written by fs_gbuffer in pbr.wgsl
+
+#import pbr_lighting
+
+@group(0) @binding(0) var albedo_texture: texture_2d<f32>;
+@group(0) @binding(1) var normal_texture: texture_2d<f32>;
+@group(0) @binding(2) var material_texture: texture_2d<f32>;
+// Bound as a float texture, GLSL can't load from depth textures
+@group(0) @binding(3) var depth_texture: texture_2d<f32>;
+
+// Material of the shaded pixel, read from the G-buffer
+var<private> specular: f32;
+var<private> ior: f32;
+var<private> roughness: f32;
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2i(in.clip_position.xy);
+    let depth = textureLoad(depth_texture, pixel, 0).r;
+    // Nothing was drawn here
+    if depth >= 1.0 {
+        discard;
+    }
+    let material = textureLoad(material_texture, pixel, 0);
+    roughness = material.r;
+    specular = material.g;
+    ior = material.b;
+
+    let N = normalize(textureLoad(normal_texture, pixel, 0).xyz);
+    let P = world_position(in.uv, depth);
+    let V = normalize(camera.position - P);
+    let diffuse = textureLoad(albedo_texture, pixel, 0).rgb;
+    return vec4<f32>(shade(P, N, V, diffuse, in.clip_position.xy, N), 1.0);
+}
+
+// Unprojects a depth with the perspective projection of the camera
+fn world_position(uv: vec2f, depth: f32) -> vec3f {
+    let ndc = uv * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+    let z = -camera.proj[3][2] / (depth + camera.proj[2][2]);
+    let view_position = vec3f(ndc * -z / vec2f(camera.proj[0][0], camera.proj[1][1]), z);
+    let view_rotation = mat3x3f(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz);
+    return camera.position + transpose(view_rotation) * view_position;
+}
@@ -0,0 +1,177 @@
+// Compute passes that build the image based lighting textures of an `Environment`.
+// Every entry point only uses some of the bindings, each pipeline layout lists just those.
+
+const PI: f32 = 3.14159265358979323846;
+// Angle between irradiance samples, in radians
+const IRRADIANCE_SAMPLE_DELTA: f32 = 0.05;
+const PREFILTER_SAMPLES: u32 = 128u;
+const BRDF_SAMPLES: u32 = 256u;
+
+struct Face {
+    // Cube face written, in +X, -X, +Y, -Y, +Z, -Z order
+    index: u32,
+    mip: u32,
+    // Roughness the prefiltered map is convolved with at this mip
+    roughness: f32,
+}
+
+// Each dispatch writes one face of one mip, as a 2D texture
+@group(0) @binding(0) var equirectangular: texture_2d<f32>;
+@group(0) @binding(1) var output: texture_storage_2d<rgba16float,write>;
+@group(0) @binding(2) var environment: texture_cube<f32>;
+@group(0) @binding(3) var s_environment: sampler;
+@group(0) @binding(4) var<uniform> face: Face;
+
+// Direction through `texel` of the face being written
+fn cube_direction(texel: vec2f, size: vec2u) -> vec3f {
+    let st = texel / vec2f(size) * 2.0 - 1.0;
+    switch face.index {
+        case 0u: { return normalize(vec3f(1.0, -st.y, -st.x)); }
+        case 1u: { return normalize(vec3f(-1.0, -st.y, st.x)); }
+        case 2u: { return normalize(vec3f(st.x, 1.0, st.y)); }
+        case 3u: { return normalize(vec3f(st.x, -1.0, -st.y)); }
+        case 4u: { return normalize(vec3f(st.x, -st.y, 1.0)); }
+        default: { return normalize(vec3f(-st.x, -st.y, -1.0)); }
+    }
+}
+
+fn tangent_to_world(N: vec3f) -> mat3x3f {
+    let up = select(vec3f(0.0, 1.0, 0.0), vec3f(1.0, 0.0, 0.0), abs(N.y) > 0.999);
+    let T = normalize(cross(up, N));
+    let B = cross(N, T);
+    return mat3x3f(T, B, N);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2f {
+    return vec2f(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Half vector in tangent space, distributed like the GGX lobe of roughness a
+fn importance_sample_ggx(xi: vec2f, a: f32) -> vec3f {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3f(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn distribution_ggx(NdotH: f32, a: f32) -> f32 {
+    let a2 = a * a;
+    let d = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Same visibility term as pbr.wgsl
+fn visibility_smith_ggx_correlated(NdotV: f32, NdotL: f32, a: f32) -> f32 {
+    let a2 = a * a;
+    let GGXV = NdotL * sqrt((NdotV - a2 * NdotV) * NdotV + a2);
+    let GGXL = NdotV * sqrt((NdotL - a2 * NdotL) * NdotL + a2);
+    return 0.5 / (GGXV + GGXL);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn equirectangular_to_cubemap(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+    // Lower mips average several directions per texel
+    let samples = min(1u << face.mip, 4u);
+    let source_size = textureDimensions(equirectangular);
+    var color = vec4f(0.0);
+    for (var x = 0u; x < samples; x++) {
+        for (var y = 0u; y < samples; y++) {
+            let texel = vec2f(id.xy) + (vec2f(f32(x), f32(y)) + 0.5) / f32(samples);
+            let d = cube_direction(texel, size);
+            let uv = vec2f(atan2(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+            color += textureLoad(equirectangular, min(vec2u(uv * vec2f(source_size)), source_size - 1u), 0);
+        }
+    }
+    textureStore(output, id.xy, color / f32(samples * samples));
+}
+
+// Cosine weighted average of the environment over the hemisphere around each direction,
+// which is the irradiance divided by PI
+@compute @workgroup_size(8, 8, 1)
+fn irradiance(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+    let basis = tangent_to_world(cube_direction(vec2f(id.xy) + 0.5, size));
+    // Read from the mip level where texels are about as far apart as the samples
+    let source_size = f32(textureDimensions(environment).x);
+    let lod = max(log2(IRRADIANCE_SAMPLE_DELTA * source_size / (0.5 * PI)), 0.0);
+    var irradiance = vec3f(0.0);
+    var weight = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += IRRADIANCE_SAMPLE_DELTA) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += IRRADIANCE_SAMPLE_DELTA) {
+            let direction = vec3f(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            // cos for Lambert, sin for the smaller solid angle near the pole
+            let w = cos(theta) * sin(theta);
+            irradiance += textureSampleLevel(environment, s_environment, basis * direction, lod).rgb * w;
+            weight += w;
+        }
+    }
+    textureStore(output, id.xy, vec4f(irradiance / weight, 1.0));
+}
+
+// Environment convolved with the GGX lobe of the roughness of the mip, assuming N = V = R
+@compute @workgroup_size(8, 8, 1)
+fn prefilter(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+    let N = cube_direction(vec2f(id.xy) + 0.5, size);
+    let basis = tangent_to_world(N);
+    let source_size = f32(textureDimensions(environment).x);
+    let texel_solid_angle = 4.0 * PI / (6.0 * source_size * source_size);
+    var color = vec3f(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < PREFILTER_SAMPLES; i++) {
+        let H = basis * importance_sample_ggx(hammersley(i, PREFILTER_SAMPLES), face.roughness);
+        let L = normalize(2.0 * dot(N, H) * H - N);
+        let NdotL = dot(N, L);
+        if NdotL > 0.0 {
+            // Unlikely samples cover a bigger solid angle, read them from a blurrier mip
+            let pdf = distribution_ggx(saturate(dot(N, H)), face.roughness) / 4.0;
+            let sample_solid_angle = 1.0 / (f32(PREFILTER_SAMPLES) * pdf + 0.0001);
+            var lod = 0.0;
+            if face.roughness > 0.0 {
+                lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+            }
+            color += textureSampleLevel(environment, s_environment, L, lod).rgb * NdotL;
+            weight += NdotL;
+        }
+    }
+    textureStore(output, id.xy, vec4f(color / weight, 1.0));
+}
+
+// Scale and bias to f0 of the split sum approximation, by NdotV and roughness
+@compute @workgroup_size(8, 8, 1)
+fn integrate_brdf(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+    let uv = (vec2f(id.xy) + 0.5) / vec2f(size);
+    let NdotV = uv.x;
+    let a = uv.y;
+    let V = vec3f(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < BRDF_SAMPLES; i++) {
+        let H = importance_sample_ggx(hammersley(i, BRDF_SAMPLES), a);
+        let L = normalize(2.0 * dot(V, H) * H - V);
+        let NdotL = saturate(L.z);
+        let NdotH = saturate(H.z);
+        let VdotH = saturate(dot(V, H));
+        if NdotL > 0.0 {
+            let G_vis = 4.0 * visibility_smith_ggx_correlated(NdotV, NdotL, a) * NdotL * VdotH / NdotH;
+            let Fc = pow(1.0 - VdotH, 5.0);
+            scale += (1.0 - Fc) * G_vis;
+            bias += Fc * G_vis;
+        }
+    }
+    textureStore(output, id.xy, vec4f(vec2f(scale, bias) / f32(BRDF_SAMPLES), 0.0, 1.0));
+}
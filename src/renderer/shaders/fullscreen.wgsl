@@ -0,0 +1,16 @@
+// Triangle covering the screen, prepended to the fragment shaders of the post-process chain
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // From (0, 0) at the top left to (1, 1) at the bottom right of the screen
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    var result: FullscreenOutput;
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    result.clip_position = vec4f(uv * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0), 0.0, 1.0);
+    result.uv = uv;
+    return result;
+}
@@ -0,0 +1,83 @@
+// BRDF of the pbr material.
+// Includers declare the `specular`, `ior` and `roughness` of the shaded surface.
+
+const PI :f32= 3.14159265358979323846;
+
+fn brdf(V: vec3f, L: vec3f, N: vec3f, diffuse: vec3f) -> vec3f {
+    let H = normalize(L + V);
+    let NdotH = saturate(dot(N, H));
+    let LdotH = saturate(dot(L, H));
+    let NdotV = saturate(dot(N, V));
+    let NdotL = saturate(dot(N, L));
+
+    let diffuse_bdrf = diffuse * diffuse_burley(NdotV, NdotL, LdotH, roughness);
+    let f0 = calculate_f0(ior);
+
+    let D = microfacet_distribution_ggx(NdotH);
+    let F = fresnel(LdotH, vec3f(f0));
+    let G = clamp(visibility_smith_ggx_correlated(NdotV, NdotL, roughness), 0.0, 1.0);
+
+    let specular_bdrf = D * F * G;
+    return (1.0 - specular) * diffuse_bdrf + specular * specular_bdrf;
+}
+
+fn calculate_f0(ior: f32) -> f32 {
+    let num = (ior - 1.0);
+    let den = (ior + 1.0);
+    return num * num / (den * den);
+}
+
+fn diffuse_lambertian() -> f32 {
+    return 1.0 / PI;
+}
+
+fn diffuse_burley(NdotV: f32, NdotL: f32, LdotH: f32, roughness: f32) -> f32 {
+    let f90 = 0.5 + 2.0 * roughness * LdotH * LdotH;
+    let light_scatter = fresnel_shlick(NdotL, 1.0, f90);
+    let view_scatter = fresnel_shlick(NdotV, 1.0, f90);
+    return light_scatter * view_scatter * (1.0 / PI);
+}
+fn fresnel(LdotH: f32, f0: vec3f) -> vec3f {
+    let f90 = saturate(dot(f0, vec3<f32>(50.0 * 0.33)));
+    return fresnel_shlick_vec(LdotH, f0, f90);
+}
+
+fn fresnel_shlick_vec(LdotH: f32, f0: vec3f, f90: f32) -> vec3f {
+    return f0 + (f90 - f0) * pow(1.0 - LdotH, 5.0);
+}
+fn fresnel_shlick(LdotH: f32, f0: f32, f90: f32) -> f32 {
+    return f0 + (f90 - f0) * pow(1.0 - LdotH, 5.0);
+}
+
+
+// T is direction aligned to where roughness is mx
+fn anisotropic_microfacet_distribution(H: vec3f, N: vec3f, T: vec3f) -> f32 {
+    let m = vec2f(10.0, 1.0); // Roughness vector   
+    let NdotH = saturate(dot(N, H));
+    let NdotH2 = NdotH * NdotH;
+    let NdotH4 = NdotH2 * NdotH2;
+    let first_factor = 1.0 / (4.0 * m.x * m.y * NdotH4);
+    // P is H projected onto plane with normal N
+    let P = normalize(H - NdotH * N);
+    let TdotP = saturate(dot(T, P));
+    let TdotP2 = TdotP * TdotP;
+    let second_factor = TdotP2 / (m.x * m.x) + (1.0 - TdotP2) / (m.y * m.y);
+    let third_factor = (NdotH2 - 1.0) / NdotH2;
+    return first_factor * exp(second_factor * third_factor);
+}
+
+
+fn microfacet_distribution_ggx(NdotH: f32) -> f32 {
+    let a = NdotH * roughness;
+    let k = roughness / (1.0 - NdotH * NdotH + a * a);
+    return k * k * (1.0 / PI);
+}
+
+
+fn visibility_smith_ggx_correlated(NdotV: f32, NdotL: f32, a: f32) -> f32 {
+    let a2 = roughness * roughness;
+    let GGXV = NdotL * sqrt((NdotV - a2 * NdotV) * NdotV + a2);
+    let GGXL = NdotV * sqrt((NdotL - a2 * NdotL) * NdotL + a2);
+    // It can divide by zero if NdotL and NdotV is 0
+    return 0.5 / (GGXV + GGXL);
+}
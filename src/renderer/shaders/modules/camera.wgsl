@@ -0,0 +1,8 @@
+// Layout of `GpuCamera`
+
+struct Camera {
+    proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    inv_view: mat4x4<f32>,
+    position: vec3f,
+}
@@ -0,0 +1,13 @@
+// Layout of the `LightClusters` uniform
+
+struct Clusters {
+    view: mat4x4f,
+    grid: vec3u,
+    max_cluster_lights: u32,
+    screen_size: vec2f,
+    // Diagonal of the projection, maps view space to clip space
+    projection_scale: vec2f,
+    near: f32,
+    far: f32,
+    light_count: u32,
+}
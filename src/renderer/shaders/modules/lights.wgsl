@@ -0,0 +1,61 @@
+// Layout of `GpuLight` and the intensity of each kind of light
+
+struct Light {
+    // In directional lights, w==0 and in point and spot lights w==1
+    position: vec4f,
+    // In point and spot lights, w is the range
+    color_range: vec4f,
+    // In point lights, these are 3 attenuation constants
+    // In spot lights, this is direction and cutoff
+    custom_data: vec4f,
+}
+
+fn directional_light(light_direction: vec3f, light: Light) -> vec3f {
+    let intensity = light.color_range.rgb;
+    return intensity;
+}
+fn point_light(light_direction: vec3f, light: Light) -> vec3f {
+    let L = normalize(light_direction);
+    let light_color = light.color_range.rgb;
+
+    let range = light.color_range.w;
+    let distance = length(light_direction);
+    let attenuation_consts = light.custom_data.xyz;
+
+    let attenuation = attenuation_consts[0] + attenuation_consts[1] * distance + attenuation_consts[2] * distance * distance;
+    var intensity = light_color / attenuation;
+
+    if distance > range {
+        intensity = vec3f(0.0);
+    }
+    return  intensity;
+}
+fn spot_light(light_direction: vec3f, light: Light) -> vec3f {
+    let L = normalize(light_direction);
+    let light_color = light.color_range.rgb;
+
+    let range = light.color_range.w;
+    let distance = length(light_direction);
+    let attenuation_consts = light.custom_data.xyz;
+    let direction = vec4f(light.custom_data.xyz, 0.0);
+    let outer_cutoff = light.custom_data.w;
+    let spot_direction = normalize((-direction).xyz);
+    let dot = saturate(dot(L, spot_direction));
+    let delta = 1.0 - outer_cutoff;
+    var intensity = light_color * saturate((dot - outer_cutoff) / delta);
+
+    if distance > range || dot < outer_cutoff {
+        intensity = vec3f(0.0);
+    }
+    return intensity;
+}
+
+// Intensity of the light reaching P from `light_direction`
+fn light_intensity(light_direction: vec3f, light: Light) -> vec3f {
+    if light.position.w == 0.0 {
+        return directional_light(light_direction, light);
+    } else if light.custom_data.w == -1.0 {
+        return point_light(light_direction, light);
+    }
+    return spot_light(light_direction, light);
+}
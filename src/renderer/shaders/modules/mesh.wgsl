@@ -0,0 +1,20 @@
+// Vertex buffers of `Vertex` and `Instance`, drawn by the `Batcher`
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(5) x_axis: vec4<f32>,
+    @location(6) y_axis: vec4<f32>,
+    @location(7) z_axis: vec4<f32>,
+    @location(8) w_axis: vec4<f32>,
+};
+
+fn instance_transform(instance: InstanceInput) -> mat4x4f {
+    return mat4x4f(instance.x_axis, instance.y_axis, instance.z_axis, instance.w_axis);
+}
@@ -0,0 +1,51 @@
+// Lighting of the pbr material, shared by pbr.wgsl and the deferred lighting pass.
+// Includers declare the `specular`, `ior` and `roughness` of the shaded surface.
+
+#import scene
+#import shadows
+#import brdf
+
+// Binding 4 and 5 are the skybox, only used by skybox.wgsl
+@group(1) @binding(6) var irradiance_map: texture_cube<f32>;
+@group(1) @binding(7) var s_irradiance_map: sampler;
+@group(1) @binding(8) var prefiltered_map: texture_cube<f32>;
+@group(1) @binding(9) var s_prefiltered_map: sampler;
+@group(1) @binding(10) var brdf_lut: texture_2d<f32>;
+@group(1) @binding(11) var s_brdf_lut: sampler;
+
+// Light reaching V from the surface at P, from the lights of its cluster and the environment.
+// The geometric normal offsets the shadow lookups.
+fn shade(P: vec3f, N: vec3f, V: vec3f, diffuse: vec3f, frag_coord: vec2f, geometric_normal: vec3f) -> vec3f {
+    var lighting = vec3f(0.0);
+    let first = cluster_index(frag_coord, P) * (clusters.max_cluster_lights + 1u);
+    let count = cluster_lights[first];
+    for (var j = 0u; j < count; j++) {
+        let i = cluster_lights[first + 1u + j];
+        let light = lights[i];
+        let light_direction = (light.position).xyz - P * light.position.w;
+        let intensity = light_intensity(light_direction, light) * shadow_visibility(i, light, P, geometric_normal);
+        let L = normalize(light_direction);
+
+        lighting += brdf(V, L, N, diffuse) * intensity;
+    }
+    return lighting + image_based_lighting(V, N, diffuse);
+}
+
+// Last mip of Environment::PREFILTERED_MIP_LEVELS, GLSL ES has no textureQueryLevels
+const PREFILTERED_MAX_LOD: f32 = 4.0;
+
+// Ambient light from the environment, using the split sum approximation for specular
+fn image_based_lighting(V: vec3f, N: vec3f, diffuse: vec3f) -> vec3f {
+    let NdotV = saturate(dot(N, V));
+    let R = reflect(-V, N);
+    let f0 = vec3f(calculate_f0(ior));
+
+    let irradiance = textureSampleLevel(irradiance_map, s_irradiance_map, N, 0.0).rgb;
+    let lod = roughness * PREFILTERED_MAX_LOD;
+    let prefiltered = textureSampleLevel(prefiltered_map, s_prefiltered_map, R, lod).rgb;
+    let brdf = textureSampleLevel(brdf_lut, s_brdf_lut, vec2f(NdotV, roughness), 0.0).rg;
+
+    let diffuse_ibl = irradiance * diffuse;
+    let specular_ibl = prefiltered * (f0 * brdf.x + brdf.y);
+    return (1.0 - specular) * diffuse_ibl + specular * specular_ibl;
+}
@@ -0,0 +1,21 @@
+// Camera and clustered lights of the scene bind group, see `material::SCENE_GROUP`
+
+#import camera
+#import lights
+#import clusters
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(1) var<storage,read> lights: array<Light>;
+@group(1) @binding(2) var<uniform> clusters: Clusters;
+@group(1) @binding(3) var<storage,read> cluster_lights: array<u32>;
+
+// Light list of the cluster containing the fragment, the list starts with its light count
+fn cluster_index(frag_coord: vec2f, P: vec3f) -> u32 {
+    let grid = clusters.grid;
+    let tile = vec2u(clamp(frag_coord / clusters.screen_size * vec2f(grid.xy), vec2f(0.0), vec2f(grid.xy - 1u)));
+    // Slices are spaced logarithmically in depth
+    let depth = -(camera.view * vec4f(P, 1.0)).z;
+    let slice = log(depth / clusters.near) / log(clusters.far / clusters.near) * f32(grid.z);
+    let z = u32(clamp(slice, 0.0, f32(grid.z - 1u)));
+    return tile.x + grid.x * (tile.y + grid.y * z);
+}
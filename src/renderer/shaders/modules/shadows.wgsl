@@ -0,0 +1,104 @@
+// Shadow maps of the shadow bind group, see `material::SHADOW_GROUP`
+
+#import scene
+
+struct Shadow {
+    // First layer in shadow_maps, or the cube in point_shadow_maps for point lights.
+    // Negative when the light casts no shadows
+    first_layer: i32,
+    // First of the matrices of the layers or cube faces in shadow_matrices
+    first_matrix: u32,
+    pcf_radius: u32,
+    depth_bias: f32,
+    normal_bias: f32,
+    // View space depth where each directional light cascade ends
+    cascade_splits: vec4f,
+}
+
+@group(2) @binding(0) var shadow_maps: texture_depth_2d_array;
+@group(2) @binding(1) var s_shadow_maps: sampler_comparison;
+@group(2) @binding(2) var<storage,read> shadows: array<Shadow>;
+@group(2) @binding(3) var<storage,read> shadow_matrices: array<mat4x4f>;
+@group(2) @binding(4) var point_shadow_maps: texture_depth_cube_array;
+@group(2) @binding(5) var s_point_shadow_maps: sampler_comparison;
+
+// Offset of the cascade of directional lights, the layer of the other lights is the first one
+fn shadow_cascade(shadow: Shadow, light: Light, P: vec3f) -> i32 {
+    if light.position.w == 0.0 {
+        let depth = -(camera.view * vec4f(P, 1.0)).z;
+        var cascade = 0;
+        for (var i = 0; i < 3; i++) {
+            if depth > shadow.cascade_splits[i] {
+                cascade = i + 1;
+            }
+        }
+        return cascade;
+    }
+    return 0;
+}
+
+// Cube face the direction d points to, in +X, -X, +Y, -Y, +Z, -Z order
+fn cube_face(d: vec3f) -> i32 {
+    let a = abs(d);
+    if a.x >= a.y && a.x >= a.z {
+        return select(1, 0, d.x > 0.0);
+    } else if a.y >= a.z {
+        return select(3, 2, d.y > 0.0);
+    }
+    return select(5, 4, d.z > 0.0);
+}
+
+fn point_shadow_visibility(shadow: Shadow, light: Light, P: vec3f) -> f32 {
+    let d = P - light.position.xyz;
+    let position = shadow_matrices[shadow.first_matrix + u32(cube_face(d))] * vec4f(P, 1.0);
+    let depth = position.z / position.w;
+    if depth > 1.0 {
+        return 1.0;
+    }
+    // Offsets of one texel on the face, perpendicular to d
+    let a = abs(d);
+    let texel = 2.0 * max(a.x, max(a.y, a.z)) / f32(textureDimensions(point_shadow_maps).x);
+    let up = select(vec3f(0.0, 1.0, 0.0), vec3f(1.0, 0.0, 0.0), a.y > a.x && a.y > a.z);
+    let tangent = normalize(cross(d, up));
+    let bitangent = cross(normalize(d), tangent);
+    let radius = i32(shadow.pcf_radius);
+    var visibility = 0.0;
+    for (var x = -radius; x <= radius; x++) {
+        for (var y = -radius; y <= radius; y++) {
+            let offset = (f32(x) * tangent + f32(y) * bitangent) * texel;
+            visibility += textureSampleCompareLevel(point_shadow_maps, s_point_shadow_maps, d + offset, shadow.first_layer, depth - shadow.depth_bias);
+        }
+    }
+    let side = f32(2 * radius + 1);
+    return visibility / (side * side);
+}
+
+// Fraction of light i that reaches P, filtered with PCF
+fn shadow_visibility(i: u32, light: Light, P: vec3f, N: vec3f) -> f32 {
+    if i >= arrayLength(&shadows) || shadows[i].first_layer < 0 {
+        return 1.0;
+    }
+    let shadow = shadows[i];
+    if light.position.w != 0.0 && light.custom_data.w == -1.0 {
+        return point_shadow_visibility(shadow, light, P + N * shadow.normal_bias);
+    }
+    let cascade = shadow_cascade(shadow, light, P);
+    let layer = shadow.first_layer + cascade;
+    let position = shadow_matrices[shadow.first_matrix + u32(cascade)] * vec4f(P + N * shadow.normal_bias, 1.0);
+    let ndc = position.xyz / position.w;
+    let uv = ndc.xy * vec2f(0.5, -0.5) + 0.5;
+    if any(uv < vec2f(0.0)) || any(uv > vec2f(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let texel = 1.0 / vec2f(textureDimensions(shadow_maps));
+    let radius = i32(shadow.pcf_radius);
+    var visibility = 0.0;
+    for (var x = -radius; x <= radius; x++) {
+        for (var y = -radius; y <= radius; y++) {
+            let offset = vec2f(f32(x), f32(y)) * texel;
+            visibility += textureSampleCompareLevel(shadow_maps, s_shadow_maps, uv + offset, layer, ndc.z - shadow.depth_bias);
+        }
+    }
+    let side = f32(2 * radius + 1);
+    return visibility / (side * side);
+}
@@ -0,0 +1,12 @@
+#import mesh
+
+@group(0) @binding(0) var<uniform> light_view_projection: mat4x4<f32>;
+
+@vertex
+fn vs_main(
+    in: VertexInput,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let transform = instance_transform(instance);
+    return light_view_projection * transform * vec4f(in.position, 1.0);
+}
@@ -0,0 +1,28 @@
+#import camera
+
+// Same group as the camera and lights of the lit and pbr shaders
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(4) var skybox: texture_cube<f32>;
+@group(0) @binding(5) var s_skybox: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// Fullscreen triangle on the far plane
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var result: VertexOutput;
+    let ndc = vec2f(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    result.clip_position = vec4f(ndc, 1.0, 1.0);
+    result.ndc = ndc;
+    return result;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let view_direction = vec3f(in.ndc.x / camera.proj[0][0], in.ndc.y / camera.proj[1][1], -1.0);
+    let direction = (camera.inv_view * vec4f(view_direction, 0.0)).xyz;
+    return vec4f(textureSampleLevel(skybox, s_skybox, direction, 0.0).rgb, 1.0);
+}
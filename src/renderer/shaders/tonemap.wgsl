@@ -0,0 +1,81 @@
+// Last pass of the post-process chain, maps the HDR color to the output range
+
+const TONEMAPPING_NONE: u32 = 0u;
+const TONEMAPPING_REINHARD: u32 = 1u;
+const TONEMAPPING_ACES: u32 = 2u;
+const TONEMAPPING_AGX: u32 = 3u;
+
+struct Settings {
+    // In stops
+    exposure: f32,
+    tonemapping: u32,
+    bloom_threshold: f32,
+    // Zero when bloom is disabled
+    bloom_intensity: f32,
+}
+
+@group(0) @binding(0) var hdr: texture_2d<f32>;
+@group(0) @binding(1) var s_hdr: sampler;
+@group(0) @binding(2) var bloom: texture_2d<f32>;
+@group(0) @binding(3) var s_bloom: sampler;
+@group(0) @binding(4) var<uniform> settings: Settings;
+
+fn reinhard(color: vec3f) -> vec3f {
+    return color / (1.0 + color);
+}
+
+// Fit of the ACES reference rendering and output transforms by Stephen Hill
+fn aces(color: vec3f) -> vec3f {
+    let input = mat3x3f(
+        vec3f(0.59719, 0.07600, 0.02840),
+        vec3f(0.35458, 0.90834, 0.13383),
+        vec3f(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3f(
+        vec3f(1.60475, -0.10208, -0.00327),
+        vec3f(-0.53108, 1.10813, -0.07276),
+        vec3f(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return saturate(output * (a / b));
+}
+
+// Minimal AgX by Benjamin Wrensch, with the default look
+fn agx(color: vec3f) -> vec3f {
+    let inset = mat3x3f(
+        vec3f(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3f(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3f(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3f(
+        vec3f(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3f(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3f(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    var v = clamp(log2(max(inset * color, vec3f(1e-10))), vec3f(min_ev), vec3f(max_ev));
+    v = (v - min_ev) / (max_ev - min_ev);
+    // Polynomial approximation of the contrast curve
+    let v2 = v * v;
+    let v4 = v2 * v2;
+    v = 15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v - 0.00232;
+    // Back to linear, the output view encodes it
+    return pow(saturate(outset * v), vec3f(2.2));
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(hdr, s_hdr, in.uv).rgb;
+    color += textureSample(bloom, s_bloom, in.uv).rgb * settings.bloom_intensity;
+    color *= exp2(settings.exposure);
+    switch settings.tonemapping {
+        case TONEMAPPING_REINHARD: { color = reinhard(color); }
+        case TONEMAPPING_ACES: { color = aces(color); }
+        case TONEMAPPING_AGX: { color = agx(color); }
+        default: {}
+    }
+    return vec4f(saturate(color), 1.0);
+}
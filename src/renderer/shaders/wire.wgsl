@@ -1,27 +1,18 @@
-
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-};
+#import mesh
+#import camera
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
 };
 
-
-struct Camera {
-    proj: mat4x4<f32>,
-    view: mat4x4<f32>,
-    position: vec3f,
-}
-@group(1) @binding(0) var<uniform> transform: mat4x4<f32>;
-
-@group(2) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(0) var<uniform> camera: Camera;
 
 @vertex
 fn vs_main(
     in: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
+    let transform = instance_transform(instance);
     var result: VertexOutput;
     result.clip_position = camera.proj * camera.view * transform * vec4f(in.position, 1.0);
     return result;
@@ -32,4 +23,4 @@ fn vs_main(
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4f {
     return vec4f(0.0, 0.5, 0.0, 0.5);
-}
\ No newline at end of file
+}
@@ -0,0 +1,651 @@
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use egui::{Slider, Ui};
+use glam::{Mat4, Vec4};
+use serde::{Deserialize, Serialize};
+
+use crate::core::shader_type::ShaderType;
+
+use super::{
+    bind_group::{BindGroup, BindGroupBuilder},
+    buffer::{Buffer, IndexBuffer, UniformBuffer, VertexBuffer},
+    camera::PerspectiveCamera,
+    gui::float_edit,
+    light::Light,
+    mesh::Vertex,
+    model::Instance,
+    render_graph::{RenderGraph, ResourceHandle},
+    resources::VertexAttributeLayout,
+    shader::{Shader, ShaderDefs},
+    texture::Texture,
+};
+
+const SHADOW_SHADER: Shader =
+    Shader::new("shaders/shadow.wgsl", include_str!("shaders/shadow.wgsl"));
+
+/// Number of shadow maps a directional light splits the view frustum into
+pub const CASCADE_COUNT: usize = 4;
+
+/// Per light shadow parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ShadowSettings {
+    pub cast_shadows: bool,
+    /// Subtracted from the depth of the shaded point before the comparison
+    pub depth_bias: f32,
+    /// World space offset of the shaded point along its normal
+    pub normal_bias: f32,
+    /// Percentage closer filtering samples a (2r+1)x(2r+1) texel grid
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            cast_shadows: true,
+            depth_bias: 0.0005,
+            normal_bias: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+impl ShadowSettings {
+    pub fn gui(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = ui
+            .checkbox(&mut self.cast_shadows, "Cast Shadows")
+            .changed();
+        if self.cast_shadows {
+            changed |= float_edit(ui, &mut self.depth_bias, "Depth Bias", 0.0..=0.01);
+            changed |= float_edit(ui, &mut self.normal_bias, "Normal Bias", 0.0..=0.2);
+            changed |= ui
+                .add(Slider::new(&mut self.pcf_radius, 0..=4).text("PCF Radius"))
+                .changed();
+        }
+        changed
+    }
+}
+
+/// View space depth where each cascade ends, mixing logarithmic and uniform splits.
+pub fn cascade_splits(near: f32, far: f32) -> [f32; CASCADE_COUNT] {
+    const LAMBDA: f32 = 0.5;
+    std::array::from_fn(|i| {
+        let t = (i + 1) as f32 / CASCADE_COUNT as f32;
+        let logarithmic = near * (far / near).powf(t);
+        let uniform = (far - near).mul_add(t, near);
+        LAMBDA.mul_add(logarithmic, (1.0 - LAMBDA) * uniform)
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, ShaderType)]
+pub struct GpuShadow {
+    // Cube index for point lights, negative when the light has no shadow map
+    first_layer: i32,
+    first_matrix: u32,
+    pcf_radius: u32,
+    depth_bias: f32,
+    normal_bias: f32,
+    #[padding]
+    _padding: [u32; 3],
+    cascade_splits: Vec4,
+}
+
+impl GpuShadow {
+    const NONE: Self = Self {
+        first_layer: -1,
+        first_matrix: 0,
+        pcf_radius: 0,
+        depth_bias: 0.0,
+        normal_bias: 0.0,
+        _padding: [0; 3],
+        cascade_splits: Vec4::ZERO,
+    };
+}
+
+/// Geometry drawn into the shadow maps, as `instances` of an [`Instance`] buffer.
+#[derive(Debug, Clone)]
+pub struct ShadowCaster<'a> {
+    pub vertex_buffer: &'a VertexBuffer<Vertex>,
+    pub index_buffer: &'a IndexBuffer,
+    pub indices: Range<u32>,
+    pub base_vertex: i32,
+    pub instance_buffer: &'a wgpu::Buffer,
+    pub instances: Range<u32>,
+}
+
+/// Depth maps of every shadow casting light.
+///
+/// Directional lights use [`CASCADE_COUNT`] layers of a texture array and spot lights a single layer.
+/// Point lights use a cube of a cube texture array, which needs [`wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES`].
+/// Lights that do not fit in the remaining layers or cubes are left unshadowed.
+#[derive(Debug)]
+pub struct ShadowMaps {
+    max_lights: usize,
+    texture: Texture,
+    cube_texture: Texture,
+    /// The layers of `texture` followed by the faces of `cube_texture`
+    layer_views: Vec<wgpu::TextureView>,
+    layers: Vec<(UniformBuffer<Mat4>, BindGroup)>,
+    used_layers: usize,
+    used_cubes: usize,
+    shadows: Buffer,
+    matrices: Buffer,
+    /// Bound by the lit and pbr shaders at group 3
+    pub bind_group: BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ShadowMaps {
+    pub const DEFAULT_RESOLUTION: u32 = 1024;
+    pub const DEFAULT_LAYERS: u32 = 16;
+    pub const DEFAULT_CUBES: u32 = 4;
+
+    /// `max_lights` should match the capacity of the light storage buffer.
+    pub fn new(
+        device: &wgpu::Device,
+        resolution: u32,
+        layer_count: u32,
+        cube_count: u32,
+        max_lights: usize,
+    ) -> Self {
+        let texture = Texture::depth_array(device, resolution, layer_count);
+        let cube_texture = Texture::depth_cube_array(device, resolution, cube_count);
+        let layer_view = |texture: &Texture, layer| {
+            texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Shadow Map Layer"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+        let layer_views: Vec<_> = (0..layer_count)
+            .map(|layer| layer_view(&texture, layer))
+            .chain((0..6 * cube_count).map(|face| layer_view(&cube_texture, face)))
+            .collect();
+        let layers: Vec<_> = (0..layer_views.len())
+            .map(|_| {
+                let uniform = UniformBuffer::new(Mat4::IDENTITY, device);
+                let bind_group = BindGroupBuilder::new()
+                    .uniform(&uniform.buffer)
+                    .build(device);
+                (uniform, bind_group)
+            })
+            .collect();
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let shadows = Buffer::new(
+            device,
+            (size_of::<GpuShadow>() * max_lights.max(1)) as u64,
+            storage,
+        );
+        let matrices = Buffer::new(
+            device,
+            (size_of::<Mat4>() * layers.len().max(1)) as u64,
+            storage,
+        );
+        let bind_group = BindGroupBuilder::new()
+            .depth_texture_array(&texture)
+            .storage_buffer(&shadows.buffer)
+            .storage_buffer(&matrices.buffer)
+            .depth_texture_cube_array(&cube_texture)
+            .build(device);
+
+        let shader = device.create_shader_module(SHADOW_SHADER.variant(&ShaderDefs::new()));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&layers[0].1.layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout(), Instance::layout()],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture.texture.format(),
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                // Slope scaled bias removes most acne on surfaces at grazing angles
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            max_lights,
+            texture,
+            cube_texture,
+            layer_views,
+            layers,
+            used_layers: 0,
+            used_cubes: 0,
+            shadows,
+            matrices,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    pub const fn texture(&self) -> &Texture {
+        &self.texture
+    }
+    pub const fn cube_texture(&self) -> &Texture {
+        &self.cube_texture
+    }
+    pub const fn used_layers(&self) -> usize {
+        self.used_layers
+    }
+    pub const fn used_cubes(&self) -> usize {
+        self.used_cubes
+    }
+
+    fn layer_count(&self) -> usize {
+        self.texture.texture.depth_or_array_layers() as usize
+    }
+
+    /// Indices into `layers` of the layers and cube faces assigned by the last [`ShadowMaps::update`]
+    fn used(&self) -> impl Iterator<Item = usize> {
+        let first_face = self.layer_count();
+        (0..self.used_layers).chain(first_face..first_face + 6 * self.used_cubes)
+    }
+
+    /// Assigns shadow map layers and cubes to `lights` and computes their view projections.
+    ///
+    /// Directional light cascades are fitted to the frustum of `camera` seen from `view`.
+    pub fn update(
+        &mut self,
+        lights: &[Light],
+        view: Mat4,
+        camera: &PerspectiveCamera,
+        queue: &wgpu::Queue,
+    ) {
+        let splits = cascade_splits(camera.near, camera.far);
+        let layer_count = self.layer_count();
+        let cube_count = (self.layers.len() - layer_count) / 6;
+        let mut shadows = vec![GpuShadow::NONE; self.max_lights.max(1)];
+        // The matrices of the layers, then of the cube faces
+        let mut matrices = vec![Mat4::IDENTITY; self.layers.len()];
+        let (mut used_layers, mut used_cubes) = (0, 0);
+        for (shadow, light) in shadows.iter_mut().zip(lights) {
+            let (settings, light_matrices) = match *light {
+                Light::DirectionalLight(light) => (
+                    light.shadow,
+                    (0..CASCADE_COUNT)
+                        .map(|i| {
+                            let near = if i == 0 { camera.near } else { splits[i - 1] };
+                            light.cascade_view_projection(view, camera, near, splits[i])
+                        })
+                        .collect(),
+                ),
+                Light::PointLight(light) => {
+                    (light.shadow, light.shadow_view_projections().to_vec())
+                }
+                Light::SpotLight(light) => (light.shadow, vec![light.shadow_view_projection()]),
+            };
+            if !settings.cast_shadows {
+                continue;
+            }
+            let (first_layer, first_matrix) = if let Light::PointLight(_) = light {
+                if used_cubes == cube_count {
+                    tracing::warn!("Out of shadow cube maps, {light:?} casts no shadows");
+                    continue;
+                }
+                used_cubes += 1;
+                (used_cubes - 1, layer_count + 6 * (used_cubes - 1))
+            } else {
+                if used_layers + light_matrices.len() > layer_count {
+                    tracing::warn!("Out of shadow map layers, {light:?} casts no shadows");
+                    continue;
+                }
+                used_layers += light_matrices.len();
+                let first_layer = used_layers - light_matrices.len();
+                (first_layer, first_layer)
+            };
+            matrices[first_matrix..first_matrix + light_matrices.len()]
+                .copy_from_slice(&light_matrices);
+            *shadow = GpuShadow {
+                first_layer: i32::try_from(first_layer).unwrap_or(i32::MAX),
+                first_matrix: u32::try_from(first_matrix).unwrap_or(u32::MAX),
+                pcf_radius: settings.pcf_radius,
+                depth_bias: settings.depth_bias,
+                normal_bias: settings.normal_bias,
+                _padding: [0; 3],
+                cascade_splits: Vec4::from_array(splits),
+            };
+        }
+        self.used_layers = used_layers;
+        self.used_cubes = used_cubes;
+
+        queue.write_buffer(&self.shadows.buffer, 0, bytemuck::cast_slice(&shadows));
+        if !matrices.is_empty() {
+            queue.write_buffer(&self.matrices.buffer, 0, bytemuck::cast_slice(&matrices));
+        }
+        for index in (0..used_layers).chain(layer_count..layer_count + 6 * used_cubes) {
+            let uniform = &mut self.layers[index].0;
+            uniform.data = matrices[index];
+            uniform.update(queue);
+        }
+    }
+
+    /// Adds the [`Self::render`] pass, returns the shadow maps for the passes sampling them.
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        casters: Vec<ShadowCaster<'a>>,
+    ) -> ResourceHandle {
+        let texture = graph.import_texture(&self.texture);
+        graph
+            .add_pass("Shadows")
+            .write(texture)
+            .run(move |ctx| self.render(ctx.encoder, &casters));
+        texture
+    }
+
+    /// Renders `casters` into every layer and cube face assigned by the last [`ShadowMaps::update`].
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, casters: &[ShadowCaster]) {
+        for index in self.used() {
+            let (view, (_, layer)) = (&self.layer_views[index], &self.layers[index]);
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, &layer.bind_group, &[]);
+            for caster in casters {
+                rpass.set_vertex_buffer(0, caster.vertex_buffer.buffer.slice(..));
+                rpass.set_vertex_buffer(1, caster.instance_buffer.slice(..));
+                rpass.set_index_buffer(
+                    caster.index_buffer.buffer.slice(..),
+                    wgpu::IndexFormat::Uint32,
+                );
+                rpass.draw_indexed(
+                    caster.indices.clone(),
+                    caster.base_vertex,
+                    caster.instances.clone(),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use approx::assert_relative_eq;
+    use glam::{Affine3A, Mat4, Vec3};
+    use image::RgbaImage;
+
+    use super::{cascade_splits, ShadowMaps, CASCADE_COUNT};
+    use crate::{
+        collision::shapes::Cuboid,
+        renderer::{
+            batch::Batcher,
+            bind_group::BindGroupBuilder,
+            buffer::UniformBuffer,
+            camera::{OrbitCamera, PerspectiveCamera},
+            cluster::LightClusters,
+            color::Color,
+            environment::Environment,
+            light::{DirectionalLight, Light, PointLight, SpotLight},
+            material::{PbrMaterialBuilder, SCENE_GROUP, SHADOW_GROUP},
+            mesh::{Meshable, Vertex},
+            model::{Instance, Model},
+            render_graph::{RenderGraph, TexturePool},
+            render_pipeline::{RenderPassBuilder, RenderPipelineBuilder},
+            resources::get_texture_data,
+            texture::Texture,
+            wgpu_renderer::ShadingPath,
+        },
+        tests::device,
+    };
+
+    const SIZE: u32 = 64;
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+    /// Center of the cube between the lights and the wall at z = 0
+    const CASTER: Vec3 = Vec3::new(-0.5, 0.0, 0.6);
+
+    /// A white wall facing the camera and a cube in front of it, lit only by `light`.
+    fn render(light: Light, device: &wgpu::Device, queue: &wgpu::Queue) -> RgbaImage {
+        let camera = UniformBuffer::new(OrbitCamera::new(3.0, 1.0), device);
+        let mut light_clusters = LightClusters::new(&[light], device, queue);
+        let environment = Environment::from_color(Color::BLACK, device, queue);
+        let scene = environment
+            .bind(light_clusters.bind(BindGroupBuilder::new().uniform(&camera.buffer)))
+            .build(device);
+        let mut shadow_maps = ShadowMaps::new(device, 256, 4, 1, 1);
+        let cuboid = |center: Vec3, half_extents: Vec3| {
+            Model::new(
+                Affine3A::from_translation(center),
+                Rc::new(Cuboid::new(half_extents).mesh()),
+                PbrMaterialBuilder::new().build(device, queue),
+            )
+        };
+        let wall = cuboid(Vec3::new(0.0, 0.0, -0.05), Vec3::new(1.5, 1.5, 0.05));
+        let caster = cuboid(CASTER, Vec3::splat(0.15));
+
+        light_clusters.update(
+            camera.data.view(),
+            camera.data.perspective(),
+            SIZE,
+            SIZE,
+            queue,
+        );
+        shadow_maps.update(
+            &light_clusters.lights.data,
+            camera.data.view(),
+            camera.data.perspective(),
+            queue,
+        );
+        let mut batcher = Batcher::new(device, false, ShadingPath::Forward);
+        batcher.prepare(
+            [&wall, &caster],
+            None,
+            camera.data.position(),
+            device,
+            queue,
+            |builder: RenderPipelineBuilder| {
+                builder
+                    .bind_group(&scene)
+                    .bind_group(&shadow_maps.bind_group)
+                    .depth(Texture::DEPTH_FORMAT)
+                    .build_with_instancing::<Vertex, Instance>(device, FORMAT)
+            },
+        );
+
+        let mut graph = RenderGraph::new(SIZE, SIZE, 1);
+        let cluster_lights = light_clusters.add_to_graph(&mut graph);
+        let instances = batcher.add_to_graph(&mut graph);
+        let shadows = shadow_maps.add_to_graph(&mut graph, batcher.shadow_casters());
+        let depth = graph.create_depth_texture();
+        let (batcher, scene, shadow_maps) = (&batcher, &scene, &shadow_maps);
+        graph
+            .add_pass("Forward")
+            .read(cluster_lights)
+            .read(instances)
+            .read(shadows)
+            .write(RenderGraph::SURFACE)
+            .write(depth)
+            .run(move |ctx| {
+                let mut rpass = RenderPassBuilder::new()
+                    .depth(ctx.view(depth))
+                    .build(ctx.encoder, ctx.view(RenderGraph::SURFACE));
+                rpass.set_bind_group(SCENE_GROUP, &scene.bind_group, &[]);
+                rpass.set_bind_group(SHADOW_GROUP, &shadow_maps.bind_group.bind_group, &[]);
+                batcher.draw(&mut rpass);
+            });
+
+        let output = Texture::render_target(device, SIZE, SIZE, FORMAT, FORMAT);
+        graph.execute(device, queue, &mut TexturePool::default(), &output.view);
+        let (pixels, ..) = get_texture_data(&output.texture, device, queue, 0);
+        RgbaImage::from_raw(SIZE, SIZE, pixels.samples).expect("Readback has the target size")
+    }
+
+    /// Red channel of the pixel showing `point`
+    fn brightness(image: &RgbaImage, point: Vec3) -> u8 {
+        let camera = OrbitCamera::new(3.0, 1.0);
+        let ndc = (camera.perspective().matrix_rh() * camera.view()).project_point3(point);
+        let x = (ndc.x + 1.0) * 0.5 * SIZE as f32;
+        let y = (1.0 - ndc.y) * 0.5 * SIZE as f32;
+        image.get_pixel(x as u32, y as u32).0[0]
+    }
+
+    fn in_clip_space(view_projection: Mat4, point: Vec3) -> bool {
+        let clip = view_projection.project_point3(point);
+        clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0 && (0.0..=1.0).contains(&clip.z)
+    }
+
+    #[test]
+    fn cascades_cover_the_frustum() {
+        let camera = PerspectiveCamera::default();
+        let splits = cascade_splits(camera.near, camera.far);
+        assert_relative_eq!(splits[CASCADE_COUNT - 1], camera.far, max_relative = 1e-5);
+        assert!(
+            splits.windows(2).all(|w| w[0] < w[1]),
+            "Splits should be increasing"
+        );
+
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 2.0, 5.0), Vec3::ZERO, Vec3::Y);
+        let light = DirectionalLight::new(crate::renderer::color::Color::WHITE, Vec3::NEG_ONE);
+        let near_cascade = light.cascade_view_projection(view, &camera, camera.near, splits[0]);
+        // Point in front of the camera, inside the first cascade
+        let point = view
+            .inverse()
+            .transform_point3(Vec3::new(0.0, 0.0, -0.5 * splits[0]));
+        assert!(
+            in_clip_space(near_cascade, point),
+            "First cascade contains points at its depth"
+        );
+    }
+
+    #[test]
+    fn point_light_faces_cover_every_direction() {
+        let light = PointLight {
+            position: Vec3::new(1.0, 2.0, 3.0),
+            range: 10.0,
+            ..Default::default()
+        };
+        let faces = light.shadow_view_projections();
+        for (i, direction) in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let point = light.position + direction * 5.0;
+            assert!(
+                in_clip_space(faces[i], point),
+                "Face {i} should contain points along its axis"
+            );
+        }
+    }
+
+    #[test]
+    fn spot_light_projection_matches_the_cone() {
+        let light = SpotLight {
+            position: Vec3::ZERO,
+            direction: Vec3::NEG_Y,
+            range: 10.0,
+            outer_cutoff: 30_f32.to_radians(),
+            ..Default::default()
+        };
+        let view_projection = light.shadow_view_projection();
+        let inside = Vec3::new(0.0, -5.0, 5.0 * 25_f32.to_radians().tan());
+        let outside = Vec3::new(0.0, -5.0, 5.0 * 35_f32.to_radians().tan());
+        assert!(
+            in_clip_space(view_projection, inside),
+            "Inside the cutoff angle"
+        );
+        assert!(
+            !in_clip_space(view_projection, outside),
+            "Outside the cutoff angle"
+        );
+    }
+
+    #[test]
+    fn spot_light_texture_projection_matches_its_shadow_map() {
+        let light = SpotLight::new(
+            Color::WHITE,
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            10.0,
+            30_f32.to_radians(),
+        );
+        let uv = |point: Vec3| light.project_texture_matrix(256, 256).project_point3(point);
+        let center = uv(light.position + light.direction * 4.0);
+        assert_relative_eq!(center.x, 0.5, epsilon = 1e-5);
+        assert_relative_eq!(center.y, 0.5, epsilon = 1e-5);
+        let clip = light
+            .shadow_view_projection()
+            .project_point3(Vec3::new(2.0, 0.5, 3.5));
+        let texture = uv(Vec3::new(2.0, 0.5, 3.5));
+        assert_relative_eq!(texture.x, 0.5 * clip.x + 0.5, epsilon = 1e-5);
+        assert_relative_eq!(texture.y, 0.5 - 0.5 * clip.y, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn shadows_fall_on_the_receiver() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let color = Color::WHITE * 4.0;
+        let position = Vec3::new(-1.1, 0.0, 1.3);
+        let direction = (CASTER - position).normalize();
+        // Where the ray from the light through the caster meets the wall
+        let behind = |origin: Vec3, direction: Vec3| origin - direction * (origin.z / direction.z);
+        let lights: [(Light, Vec3); 3] = [
+            (
+                DirectionalLight::new(color, direction).into(),
+                behind(CASTER, direction),
+            ),
+            (
+                SpotLight::new(color, position, direction, 5.0, 0.6).into(),
+                behind(position, direction),
+            ),
+            (
+                PointLight::new(color, position, 5.0, [0.0, 0.0, 1.0]).into(),
+                behind(position, direction),
+            ),
+        ];
+        for (light, shadow) in lights {
+            let image = render(light, &device, &queue);
+            let shadowed = brightness(&image, shadow);
+            let lit = brightness(&image, shadow + Vec3::Y * 0.7);
+            assert!(
+                u32::from(lit) > 2 * u32::from(shadowed) + 10,
+                "{light:?} casts a shadow on the wall, {shadowed} in the shadow and {lit} next to it"
+            );
+        }
+    }
+}
@@ -5,13 +5,14 @@ use crate::renderer::resources::get_max_mip_level_count;
 
 use super::{compute, resources::load_texture};
 
-use std::path::Path;
+use std::{path::Path, rc::Rc};
 
-#[derive(Debug)]
+/// Clones share the texture on the GPU, like materials using the same image.
+#[derive(Debug, Clone)]
 pub struct Texture {
-    pub texture: wgpu::Texture,
-    pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler,
+    pub texture: Rc<wgpu::Texture>,
+    pub view: Rc<wgpu::TextureView>,
+    pub sampler: Rc<wgpu::Sampler>,
     pub egui_id: Option<TextureId>,
 }
 
@@ -34,9 +35,9 @@ impl Texture {
             border_color: None,
         });
         Self {
-            texture,
-            view,
-            sampler,
+            texture: Rc::new(texture),
+            view: Rc::new(view),
+            sampler: Rc::new(sampler),
             egui_id: None,
         }
     }
@@ -65,9 +66,9 @@ impl Texture {
             border_color: None,
         });
         Ok(Self {
-            texture,
-            view,
-            sampler,
+            texture: Rc::new(texture),
+            view: Rc::new(view),
+            sampler: Rc::new(sampler),
             egui_id: None,
         })
     }
@@ -129,8 +130,12 @@ impl Texture {
                 bytes_per_row: Some(4 * texture.size().width),
                 rows_per_image: Some(texture.size().height),
             };
-            let data = image.as_rgba8().unwrap().as_raw();
-            queue.write_texture(destination, data, source, texture.size());
+            let data = image.to_rgba8();
+            let face_size = wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                ..texture.size()
+            };
+            queue.write_texture(destination, &data, source, face_size);
             compute::generate_mipmaps(&texture, device, queue, layer as u32);
         }
 
@@ -159,14 +164,17 @@ impl Texture {
             border_color: None,
         });
         Self {
-            texture,
-            view,
-            sampler,
+            texture: Rc::new(texture),
+            view: Rc::new(view),
+            sampler: Rc::new(sampler),
             egui_id: None,
         }
     }
-    pub fn depth(device: &wgpu::Device, width: u32, height: u32) -> Self {
-        let depth_texture_format = wgpu::TextureFormat::Depth24Plus;
+    /// Format of [`Texture::depth`]
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+
+    pub fn depth(device: &wgpu::Device, width: u32, height: u32, sample_count: u32) -> Self {
+        let depth_texture_format = Self::DEPTH_FORMAT;
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Depth Texture"),
             size: wgpu::Extent3d {
@@ -175,7 +183,7 @@ impl Texture {
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: depth_texture_format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -205,9 +213,118 @@ impl Texture {
             ..Default::default()
         });
         Self {
-            texture,
-            view,
-            sampler,
+            texture: Rc::new(texture),
+            view: Rc::new(view),
+            sampler: Rc::new(sampler),
+            egui_id: None,
+        }
+    }
+
+    /// Depth texture with `layers` array layers, sampled with a comparison sampler.
+    pub fn depth_array(device: &wgpu::Device, size: u32, layers: u32) -> Self {
+        Self::depth_layers(device, size, layers, wgpu::TextureViewDimension::D2Array)
+    }
+
+    /// Depth texture with `cubes` cube maps, sampled with a comparison sampler.
+    /// Needs [`wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES`].
+    pub fn depth_cube_array(device: &wgpu::Device, size: u32, cubes: u32) -> Self {
+        // The GL backend makes a single cube a cube map, which can't be viewed as an array
+        Self::depth_layers(
+            device,
+            size,
+            6 * cubes.max(2),
+            wgpu::TextureViewDimension::CubeArray,
+        )
+    }
+
+    fn depth_layers(
+        device: &wgpu::Device,
+        size: u32,
+        layers: u32,
+        dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        let format = wgpu::TextureFormat::Depth32Float;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture Array"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[format],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Depth Texture Array View"),
+            format: Some(format),
+            dimension: Some(dimension),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            base_mip_level: 0,
+            mip_level_count: Some(1),
+            base_array_layer: 0,
+            array_layer_count: Some(layers),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Depth Texture Array"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        Self {
+            texture: Rc::new(texture),
+            view: Rc::new(view),
+            sampler: Rc::new(sampler),
+            egui_id: None,
+        }
+    }
+
+    pub fn render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        view_format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[view_format],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Render Target View"),
+            format: Some(view_format),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Render Target"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture: Rc::new(texture),
+            view: Rc::new(view),
+            sampler: Rc::new(sampler),
             egui_id: None,
         }
     }
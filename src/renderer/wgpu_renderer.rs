@@ -1,8 +1,51 @@
 use std::sync::Arc;
 
+use image::RgbaImage;
 use winit::dpi::PhysicalSize;
 
-use super::app::App;
+use super::{
+    app::App,
+    culling::GpuCulling,
+    post_process::HDR_FORMAT,
+    render_graph::{RenderGraph, TexturePool},
+    resources::get_texture_data,
+    texture::Texture,
+};
+
+/// How the materials of a scene are shaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShadingPath {
+    /// Every material computes its lighting as it is drawn
+    #[default]
+    Forward,
+    /// Materials with a [`Material::gbuffer_entry`](super::material::Material::gbuffer_entry) write a
+    /// [`GBuffer`](super::deferred::GBuffer), lit once per pixel by [`DeferredLighting`](super::deferred::DeferredLighting).
+    /// The other materials are drawn forward over it.
+    Deferred,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RendererSettings {
+    /// MSAA samples per pixel: 1, 2, 4 or 8.
+    /// Lowered to the highest count the adapter supports for the surface, [`HDR_FORMAT`] and depth textures,
+    /// and to 1 by the deferred path.
+    pub sample_count: u32,
+    pub shading: ShadingPath,
+    /// Dev mode reading the engine shaders from the source tree and watching them,
+    /// [`App::shaders_changed`] is called after an edit. The files of [`App::resources`] are reloaded too,
+    /// see [`App::resources_changed`]. Not available headless.
+    pub hot_reload: bool,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            sample_count: 1,
+            shading: ShadingPath::default(),
+            hot_reload: false,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Renderer {
@@ -10,24 +53,46 @@ pub struct Renderer {
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface<'static>,
+    pub surface: Option<wgpu::Surface<'static>>,
     pub config: wgpu::SurfaceConfiguration,
+    /// Settings of the [`App`], with the sample count the adapter supports
+    pub settings: RendererSettings,
+    pub offscreen: Option<OffscreenTarget>,
+    /// Transient textures of the [`RenderGraph`]s run with [`Renderer::execute`]
+    pub transient_textures: TexturePool,
+}
+
+/// Color and depth textures a headless [`Renderer`] draws into instead of a swapchain.
+#[derive(Debug)]
+pub struct OffscreenTarget {
+    pub color: Texture,
+    pub depth: Texture,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Self {
+        Self {
+            color: Texture::render_target(
+                device,
+                config.width,
+                config.height,
+                config.format,
+                config.view_formats[0],
+            ),
+            depth: Texture::depth(device, config.width, config.height, sample_count),
+        }
+    }
 }
 
 impl Renderer {
     pub async fn new<A: App>(window: Arc<winit::window::Window>, is_srgb: bool) -> Self {
         tracing::info!("Initializing wgpu...");
 
-        let backends = wgpu::util::backend_bits_from_env().unwrap_or_default();
-        let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
-        let gles_minor_version = wgpu::util::gles_minor_version_from_env().unwrap_or_default();
-
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends,
-            flags: wgpu::InstanceFlags::from_build_config().with_env(),
-            dx12_shader_compiler,
-            gles_minor_version,
-        });
+        let instance = create_instance();
 
         // Window size is only actually valid after we enter the event loop.
         let window_size = window.inner_size();
@@ -56,72 +121,113 @@ impl Renderer {
             config.format = format;
             config.view_formats.push(format);
         };
-        let adapter_info = adapter.get_info();
-        tracing::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
-
-        let optional_features = A::optional_features();
-        let required_features = A::required_features();
-        let adapter_features = adapter.features();
-        assert!(
-            adapter_features.contains(required_features),
-            "Adapter does not support required features for this example: {:?}",
-            required_features - adapter_features
-        );
-
-        let required_downlevel_capabilities = A::required_downlevel_capabilities();
-        let downlevel_capabilities = adapter.get_downlevel_capabilities();
-        assert!(
-            downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
-            "Adapter does not support the minimum shader model required to run this example: {:?}",
-            required_downlevel_capabilities.shader_model
-        );
-        assert!(
-            downlevel_capabilities
-                .flags
-                .contains(required_downlevel_capabilities.flags),
-            "Adapter does not support the downlevel capabilities required to run this example: {:?}",
-            required_downlevel_capabilities.flags - downlevel_capabilities.flags
-        );
-
-        // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the surface.
-        let needed_limits = A::required_limits().using_resolution(adapter.limits());
-
-        let trace_dir = std::env::var("WGPU_TRACE");
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: (optional_features & adapter_features) | required_features,
-                    required_limits: needed_limits,
-                },
-                trace_dir.ok().as_ref().map(std::path::Path::new),
-            )
-            .await
-            .expect("Unable to find a suitable GPU adapter!");
+        let settings = supported_settings::<A>(&adapter, config.view_formats[0]);
+        let (device, queue) = request_device::<A>(&adapter, settings).await;
 
         Self {
             instance,
             adapter,
             device,
             queue,
-            surface,
+            surface: Some(surface),
             config,
+            settings,
+            offscreen: None,
+            transient_textures: TexturePool::default(),
         }
     }
+
+    /// Creates a renderer without a window, drawing into an [`OffscreenTarget`].
+    ///
+    /// Returns `None` if no adapter is available, so callers on machines without a GPU can skip.
+    /// With `force_fallback_adapter` a software adapter (e.g. llvmpipe or WARP) is requested.
+    pub async fn headless<A: App>(
+        width: u32,
+        height: u32,
+        is_srgb: bool,
+        force_fallback_adapter: bool,
+    ) -> Option<Self> {
+        tracing::info!("Initializing headless wgpu...");
+
+        let instance = create_instance();
+        let adapter = match wgpu::util::initialize_adapter_from_env(&instance, None) {
+            Some(adapter) => adapter,
+            None => {
+                instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference: wgpu::util::power_preference_from_env()
+                            .unwrap_or_default(),
+                        force_fallback_adapter,
+                        compatible_surface: None,
+                    })
+                    .await?
+            }
+        };
+
+        // Readback in `render_to_image` expects 4 bytes per pixel in RGBA order
+        let format = if is_srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::AutoNoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![format],
+        };
+        let settings = supported_settings::<A>(&adapter, format);
+        let (device, queue) = request_device::<A>(&adapter, settings).await;
+        let offscreen = OffscreenTarget::new(&device, &config, settings.sample_count);
+
+        Some(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            surface: None,
+            config,
+            settings,
+            offscreen: Some(offscreen),
+            transient_textures: TexturePool::default(),
+        })
+    }
+
+    pub const fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
     /// Resize the surface, making sure to not resize to zero.
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
         tracing::info!("Surface resize {size:?}");
 
         self.config.width = size.width.max(1);
         self.config.height = size.height.max(1);
-        self.surface.configure(&self.device, &self.config);
+        if let Some(ref surface) = self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+        if self.offscreen.is_some() {
+            self.offscreen = Some(OffscreenTarget::new(
+                &self.device,
+                &self.config,
+                self.settings.sample_count,
+            ));
+        }
     }
     /// Acquire the next surface texture.
     pub fn acquire(&mut self) -> wgpu::SurfaceTexture {
-        match self.surface.get_current_texture() {
+        let surface = self
+            .surface
+            .as_ref()
+            .expect("Headless renderers have no surface to acquire from!");
+        match surface.get_current_texture() {
             Ok(frame) => frame,
             // If we timed out, just try again
-            Err(wgpu::SurfaceError::Timeout) => self.surface
+            Err(wgpu::SurfaceError::Timeout) => surface
                 .get_current_texture()
                 .expect("Failed to acquire next surface texture!"),
             Err(
@@ -131,11 +237,169 @@ impl Renderer {
                 // If OutOfMemory happens, reconfiguring may not help, but we might as well try
                 | wgpu::SurfaceError::OutOfMemory,
             ) => {
-                self.surface.configure(&self.device, &self.config);
-                self.surface
+                surface.configure(&self.device, &self.config);
+                surface
                     .get_current_texture()
                     .expect("Failed to acquire next surface texture!")
             }
         }
     }
+
+    /// Starts the graph of a frame, sized like the surface.
+    pub fn graph<'a>(&self) -> RenderGraph<'a> {
+        RenderGraph::new(
+            self.config.width,
+            self.config.height,
+            self.settings.sample_count,
+        )
+    }
+
+    /// Runs the passes of `graph`, [`RenderGraph::SURFACE`] being `surface`.
+    pub fn execute(&mut self, graph: RenderGraph<'_>, surface: &wgpu::TextureView) {
+        graph.execute(
+            &self.device,
+            &self.queue,
+            &mut self.transient_textures,
+            surface,
+        );
+    }
+
+    /// View of the offscreen color target, in the same format a swapchain view would have.
+    pub fn offscreen_view(&self) -> Option<wgpu::TextureView> {
+        let offscreen = self.offscreen.as_ref()?;
+        Some(
+            offscreen
+                .color
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor {
+                    format: Some(self.config.view_formats[0]),
+                    ..wgpu::TextureViewDescriptor::default()
+                }),
+        )
+    }
+
+    /// Reads the offscreen color target back to the CPU.
+    pub fn render_to_image(&self) -> Option<RgbaImage> {
+        let offscreen = self.offscreen.as_ref()?;
+        let (buffer, height, width) =
+            get_texture_data(&offscreen.color.texture, &self.device, &self.queue, 0);
+        RgbaImage::from_raw(width, height, buffer.samples)
+    }
+}
+
+fn create_instance() -> wgpu::Instance {
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or_default();
+    let dx12_shader_compiler = wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default();
+    let gles_minor_version = wgpu::util::gles_minor_version_from_env().unwrap_or_default();
+
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        flags: wgpu::InstanceFlags::from_build_config().with_env(),
+        dx12_shader_compiler,
+        gles_minor_version,
+    })
+}
+
+/// [`App::renderer_settings`], with the highest sample count up to the requested one
+/// that `surface_format`, [`HDR_FORMAT`] and [`Texture::DEPTH_FORMAT`] all support.
+/// The deferred path renders without MSAA.
+fn supported_settings<A: App>(
+    adapter: &wgpu::Adapter,
+    surface_format: wgpu::TextureFormat,
+) -> RendererSettings {
+    let settings = A::renderer_settings();
+    let requested = settings.sample_count;
+    assert!(
+        matches!(requested, 1 | 2 | 4 | 8),
+        "Sample count must be 1, 2, 4 or 8, got {requested}"
+    );
+    if settings.shading == ShadingPath::Deferred {
+        if requested > 1 {
+            tracing::warn!("MSAA is not supported by the deferred path, using 1x");
+        }
+        return RendererSettings {
+            sample_count: 1,
+            ..settings
+        };
+    }
+    let adapter_specific = adapter
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let supported = |format: wgpu::TextureFormat, count| {
+        let features = if adapter_specific {
+            adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(adapter.features())
+        };
+        features.flags.sample_count_supported(count)
+    };
+    let sample_count = [8, 4, 2, 1]
+        .into_iter()
+        .filter(|&count| count <= requested)
+        .find(|&count| {
+            [surface_format, HDR_FORMAT, Texture::DEPTH_FORMAT]
+                .into_iter()
+                .all(|format| supported(format, count))
+        })
+        .unwrap_or(1);
+    if sample_count != requested {
+        tracing::warn!("{requested}x MSAA is not supported, using {sample_count}x");
+    }
+    RendererSettings {
+        sample_count,
+        ..settings
+    }
+}
+
+async fn request_device<A: App>(
+    adapter: &wgpu::Adapter,
+    settings: RendererSettings,
+) -> (wgpu::Device, wgpu::Queue) {
+    let adapter_info = adapter.get_info();
+    tracing::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
+
+    // The batcher merges indirect draws when they are available
+    let mut optional_features = A::optional_features() | GpuCulling::MULTI_DRAW_FEATURES;
+    if settings.sample_count > 1 {
+        // Sample counts other than 4 are adapter specific
+        optional_features |= wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+    }
+    let required_features = A::required_features();
+    let adapter_features = adapter.features();
+    assert!(
+        adapter_features.contains(required_features),
+        "Adapter does not support required features for this example: {:?}",
+        required_features - adapter_features
+    );
+
+    let required_downlevel_capabilities = A::required_downlevel_capabilities();
+    let downlevel_capabilities = adapter.get_downlevel_capabilities();
+    assert!(
+        downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
+        "Adapter does not support the minimum shader model required to run this example: {:?}",
+        required_downlevel_capabilities.shader_model
+    );
+    assert!(
+        downlevel_capabilities
+            .flags
+            .contains(required_downlevel_capabilities.flags),
+        "Adapter does not support the downlevel capabilities required to run this example: {:?}",
+        required_downlevel_capabilities.flags - downlevel_capabilities.flags
+    );
+
+    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the surface.
+    let needed_limits = A::required_limits().using_resolution(adapter.limits());
+
+    let trace_dir = std::env::var("WGPU_TRACE");
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: (optional_features & adapter_features) | required_features,
+                required_limits: needed_limits,
+            },
+            trace_dir.ok().as_ref().map(std::path::Path::new),
+        )
+        .await
+        .expect("Unable to find a suitable GPU adapter!")
 }
@@ -28,3 +28,34 @@ prop_compose! {
         Quat::from_axis_angle(axis, angle)
     }
 }
+
+/// Adapter and device for GPU tests, with downlevel limits and the `optional_features` the adapter supports.
+/// Warns and returns `None` when there is no adapter, the test should then be skipped.
+pub(crate) fn adapter_device(
+    optional_features: wgpu::Features,
+) -> Option<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+    let adapter =
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()));
+    let device = adapter.and_then(|adapter| {
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: adapter.features() & optional_features,
+                required_limits: wgpu::Limits::downlevel_defaults(),
+            },
+            None,
+        ))
+        .ok()?;
+        Some((adapter, device, queue))
+    });
+    if device.is_none() {
+        tracing::warn!("No adapter available, skipping");
+    }
+    device
+}
+
+/// [`adapter_device`] without optional features.
+pub(crate) fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    adapter_device(wgpu::Features::empty()).map(|(_, device, queue)| (device, queue))
+}